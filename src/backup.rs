@@ -0,0 +1,584 @@
+//! Backends used to back up files before they are modified and to restore them afterwards.
+//!
+//! Two backends are provided:
+//!
+//! - [`FileBackupBackend`]: copies files next to the originals, adding a suffix to their names
+//! - [`GitBackupBackend`]: stores files in a commit object referenced by a Git ref
+
+use std::ffi::OsStr;
+use std::fs;
+use std::process::Command;
+
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, error, info, trace};
+
+#[mockall_double::double]
+use crate::mockable::fs as mockable_fs;
+use crate::result::IoErrorContext;
+use crate::{Error, Result, LOCKFILE_EXT};
+
+/// Default Git ref used by [`GitBackupBackend`] to store backups.
+pub const DEFAULT_GIT_BACKUP_REF: &str = "refs/msrv-prep/backup";
+
+/// Content of the backup stored for a file that did not exist when it was backed up.
+///
+/// When such a backup is restored, the file is deleted.
+pub const ABSENT_FILE_MARKER: &str = "# cargo-msrv-prep: file did not exist before preparation\n";
+
+/// Name and email used for commits created by [`GitBackupBackend`].
+const GIT_BACKUP_IDENTITY: (&str, &str) = ("cargo-msrv-prep", "cargo-msrv-prep@localhost");
+
+/// Trait implemented by backends that can back up and restore files.
+pub trait BackupBackend {
+    /// Checks whether a backup exists for the given file.
+    fn has_backup(&self, file_path: &Utf8Path) -> Result<bool>;
+
+    /// Returns the content of the backup for the given file, if there is one.
+    fn read_backup(&self, file_path: &Utf8Path) -> Result<Option<Vec<u8>>>;
+
+    /// Backs up the given files.
+    ///
+    /// If a backup already exists for one of the files, an error is returned
+    /// and no file is backed up, unless `force` is `true`.
+    ///
+    /// Files that do not exist can also be backed up; restoring them will delete
+    /// the file (see [`ABSENT_FILE_MARKER`]).
+    fn backup_files(&self, file_paths: &[&Utf8Path], force: bool) -> Result<()>;
+
+    /// If a backup exists for the given file, restores it and removes the backup.
+    ///
+    /// Returns `Ok(true)` if the file was restored.
+    fn maybe_restore_file(&self, file_path: &Utf8Path) -> Result<bool>;
+
+    /// Returns a description of where the backup of the given file is stored (e.g. a file path).
+    fn backup_location(&self, file_path: &Utf8Path) -> Result<String>;
+
+    /// Backs up a manifest file.
+    ///
+    /// If a lockfile exists next to the manifest, it is also backed up.
+    fn backup_manifest(&self, manifest_path: &Utf8Path, force: bool) -> Result<()> {
+        trace!("Entering `backup_manifest` (manifest_path: '{manifest_path}', force: {force})");
+
+        let lockfile_path = manifest_path.with_extension(LOCKFILE_EXT);
+
+        let mut file_paths = vec![manifest_path];
+        if lockfile_path.is_file() {
+            file_paths.push(&lockfile_path);
+        }
+        self.backup_files(&file_paths, force)?;
+
+        trace!("Exiting `backup_manifest`");
+        Ok(())
+    }
+
+    /// If a backup exists for the given manifest, restores it.
+    ///
+    /// If a lockfile was also backed up next to the manifest, it is also restored.
+    ///
+    /// Returns `Ok(true)` if the manifest was restored.
+    fn maybe_restore_manifest(&self, manifest_path: &Utf8Path) -> Result<bool> {
+        trace!("Entering `maybe_restore_manifest` (manifest_path: '{manifest_path}')");
+
+        let lockfile_path = manifest_path.with_extension(LOCKFILE_EXT);
+
+        let restored = self.maybe_restore_file(manifest_path)?;
+
+        if lockfile_path.is_file() {
+            self.maybe_restore_file(&lockfile_path)?;
+        }
+
+        trace!("Exiting `maybe_restore_manifest` (restored: {restored})");
+        Ok(restored)
+    }
+}
+
+/// Backup backend that copies files next to the originals.
+///
+/// The name of a backup file is the same as the original, with a suffix appended.
+#[derive(Debug, Clone)]
+pub struct FileBackupBackend {
+    backup_suffix: String,
+}
+
+impl FileBackupBackend {
+    /// Creates a backend using the given suffix for backup files.
+    pub fn new<S>(backup_suffix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self { backup_suffix: backup_suffix.into() }
+    }
+
+    /// Returns the path of the backup file for the given file.
+    pub fn backup_path(&self, file_path: &Utf8Path) -> Result<Utf8PathBuf> {
+        file_path
+            .file_name()
+            .map(|name| name.to_string() + &self.backup_suffix)
+            .and_then(|name| file_path.parent().map(|par| par.join(name)))
+            .ok_or_else(|| Error::InvalidPath(file_path.into()))
+    }
+}
+
+impl BackupBackend for FileBackupBackend {
+    fn has_backup(&self, file_path: &Utf8Path) -> Result<bool> {
+        Ok(self.backup_path(file_path)?.is_file())
+    }
+
+    fn read_backup(&self, file_path: &Utf8Path) -> Result<Option<Vec<u8>>> {
+        let backup_path = self.backup_path(file_path)?;
+        if !backup_path.is_file() {
+            return Ok(None);
+        }
+
+        fs::read(&backup_path)
+            .map(Some)
+            .with_io_context(|| format!("reading backup file '{backup_path}'"))
+    }
+
+    fn backup_files(&self, file_paths: &[&Utf8Path], force: bool) -> Result<()> {
+        let backup_paths = file_paths
+            .iter()
+            .map(|file_path| self.backup_path(file_path))
+            .collect::<Result<Vec<_>>>()?;
+
+        for backup_path in &backup_paths {
+            validate_backup(backup_path.is_file(), backup_path, force)?;
+        }
+
+        for (file_path, backup_path) in file_paths.iter().zip(&backup_paths) {
+            info!("Backing up '{file_path}' to '{backup_path}'");
+            if file_path.is_file() {
+                mockable_fs::copy(file_path, backup_path).map(|_| ())
+            } else {
+                mockable_fs::write(backup_path, ABSENT_FILE_MARKER)
+            }
+            .with_io_context(|| format!("backing up '{file_path}' to '{backup_path}'"))?;
+        }
+
+        Ok(())
+    }
+
+    fn maybe_restore_file(&self, file_path: &Utf8Path) -> Result<bool> {
+        trace!("Entering `maybe_restore_file` (file_path: '{file_path}')");
+
+        let backup_path = self.backup_path(file_path)?;
+        debug!("Backup path: {backup_path}");
+
+        let restored = backup_path.is_file();
+        if restored {
+            info!("Backup file found at '{backup_path}'; restoring to '{file_path}'");
+
+            if is_absent_file_marker(&backup_path)? {
+                remove_restored_file(file_path)?;
+                mockable_fs::remove_file(&backup_path)
+            } else {
+                mockable_fs::rename(&backup_path, file_path)
+            }
+            .with_io_context(|| {
+                format!("restoring backup from '{backup_path}' to '{file_path}'")
+            })?;
+        }
+
+        trace!("Exiting `maybe_restore_file` (restored: {restored})");
+        Ok(restored)
+    }
+
+    fn backup_location(&self, file_path: &Utf8Path) -> Result<String> {
+        Ok(self.backup_path(file_path)?.into_string())
+    }
+}
+
+/// Backup backend that stores files in a Git repository, without creating any file.
+///
+/// Backed up files are stored in the tree of a commit object that is referenced by a Git ref
+/// (see [`DEFAULT_GIT_BACKUP_REF`]). Each backup or restore operation creates a new commit
+/// on top of the previous one; when the last file is restored, the ref is deleted.
+///
+/// This backend uses the `git` command-line tool.
+#[derive(Debug, Clone)]
+pub struct GitBackupBackend {
+    repo_root: Utf8PathBuf,
+    git_dir: Utf8PathBuf,
+    ref_name: String,
+}
+
+impl GitBackupBackend {
+    /// Creates a backend storing backups in the Git repository containing `path`,
+    /// using the given ref.
+    pub fn new<S>(path: &Utf8Path, ref_name: S) -> Result<Self>
+    where
+        S: Into<String>,
+    {
+        let output = run_git(path, ["rev-parse", "--show-toplevel", "--absolute-git-dir"], None)?;
+        let mut lines = output.lines();
+        let (Some(repo_root), Some(git_dir)) = (lines.next(), lines.next()) else {
+            return Err(Error::Git {
+                command: "git rev-parse".into(),
+                message: format!("could not determine Git repository containing '{path}'"),
+            });
+        };
+
+        let repo_root = canonicalize(repo_root.into())?;
+        debug!("Git repository root: {repo_root}");
+
+        Ok(Self { repo_root, git_dir: git_dir.into(), ref_name: ref_name.into() })
+    }
+
+    fn repo_path(&self, file_path: &Utf8Path) -> Result<String> {
+        let (Some(parent), Some(file_name)) = (file_path.parent(), file_path.file_name()) else {
+            return Err(Error::InvalidPath(file_path.into()));
+        };
+        let parent = if parent.as_str().is_empty() { Utf8Path::new(".") } else { parent };
+
+        // Parent directory might not exist (e.g. when backing up an absent file),
+        // so we need to canonicalize its closest existing ancestor instead.
+        let existing_ancestor = parent
+            .ancestors()
+            .find(|ancestor| ancestor.is_dir())
+            .unwrap_or(parent);
+        let path = canonicalize(existing_ancestor)?
+            .join(
+                parent
+                    .strip_prefix(existing_ancestor)
+                    .unwrap_or(Utf8Path::new("")),
+            )
+            .join(file_name);
+        let relative_path = path
+            .strip_prefix(&self.repo_root)
+            .map_err(|_| Error::InvalidPath(file_path.into()))?;
+
+        Ok(relative_path
+            .components()
+            .map(|component| component.as_str())
+            .collect::<Vec<_>>()
+            .join("/"))
+    }
+
+    fn git<I, S>(&self, args: I) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        run_git(&self.repo_root, args, Some(&self.index_path()))
+    }
+
+    fn index_path(&self) -> Utf8PathBuf {
+        self.git_dir.join("msrv-prep.index")
+    }
+
+    fn ref_exists(&self) -> Result<bool> {
+        Ok(git_succeeds(
+            &self.repo_root,
+            ["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", self.ref_name)],
+        ))
+    }
+
+    fn update_tree<F>(&self, update_fn: F) -> Result<()>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let parent = self.ref_exists()?.then(|| self.ref_name.clone());
+        match &parent {
+            Some(parent) => self.git(["read-tree", &format!("{parent}^{{tree}}")])?,
+            None => self.git(["read-tree", "--empty"])?,
+        };
+
+        let result = update_fn().and_then(|_| {
+            if self.git(["ls-files"])?.trim().is_empty() {
+                info!("No more backups in '{}'; deleting ref", self.ref_name);
+                if parent.is_some() {
+                    self.git(["update-ref", "-d", &self.ref_name])?;
+                }
+            } else {
+                let tree = self.git(["write-tree"])?;
+                let mut args = vec!["commit-tree", tree.trim(), "-m", "cargo-msrv-prep backup"];
+                if let Some(parent) = &parent {
+                    args.extend(["-p", parent]);
+                }
+                let commit = self.git(args)?;
+                self.git(["update-ref", &self.ref_name, commit.trim()])?;
+            }
+
+            Ok(())
+        });
+
+        let _ = fs::remove_file(self.index_path());
+        result
+    }
+}
+
+impl BackupBackend for GitBackupBackend {
+    fn has_backup(&self, file_path: &Utf8Path) -> Result<bool> {
+        let location = self.backup_location(file_path)?;
+
+        Ok(git_succeeds(&self.repo_root, ["cat-file", "-e", &location]))
+    }
+
+    fn read_backup(&self, file_path: &Utf8Path) -> Result<Option<Vec<u8>>> {
+        if !self.has_backup(file_path)? {
+            return Ok(None);
+        }
+
+        let location = self.backup_location(file_path)?;
+        git_output(&self.repo_root, ["cat-file", "blob", &location], None).map(Some)
+    }
+
+    fn backup_files(&self, file_paths: &[&Utf8Path], force: bool) -> Result<()> {
+        for file_path in file_paths {
+            let location = self.backup_location(file_path)?;
+            validate_backup(self.has_backup(file_path)?, Utf8Path::new(&location), force)?;
+        }
+
+        self.update_tree(|| {
+            for file_path in file_paths {
+                info!("Backing up '{file_path}' to '{}'", self.backup_location(file_path)?);
+
+                let blob = if file_path.is_file() {
+                    self.git(["hash-object", "-w", "--", file_path.as_str()])?
+                } else {
+                    let marker_path = self.git_dir.join("msrv-prep.absent");
+                    fs::write(&marker_path, ABSENT_FILE_MARKER)
+                        .with_io_context(|| format!("writing '{marker_path}'"))?;
+                    let blob = self.git(["hash-object", "-w", "--", marker_path.as_str()]);
+                    let _ = fs::remove_file(&marker_path);
+                    blob?
+                };
+                let cache_info = format!("100644,{},{}", blob.trim(), self.repo_path(file_path)?);
+                self.git(["update-index", "--add", "--cacheinfo", &cache_info])?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn maybe_restore_file(&self, file_path: &Utf8Path) -> Result<bool> {
+        trace!("Entering `maybe_restore_file` (file_path: '{file_path}')");
+
+        let backup = self.read_backup(file_path)?;
+        let restored = backup.is_some();
+        if let Some(content) = backup {
+            let location = self.backup_location(file_path)?;
+            info!("Backup found at '{location}'; restoring to '{file_path}'");
+
+            if content == ABSENT_FILE_MARKER.as_bytes() {
+                remove_restored_file(file_path)?;
+            } else {
+                fs::write(file_path, content).with_io_context(|| {
+                    format!("restoring backup from '{location}' to '{file_path}'")
+                })?;
+            }
+
+            self.update_tree(|| {
+                self.git(["update-index", "--force-remove", "--", &self.repo_path(file_path)?])
+                    .map(|_| ())
+            })?;
+        }
+
+        trace!("Exiting `maybe_restore_file` (restored: {restored})");
+        Ok(restored)
+    }
+
+    /// Returns the location of the backup for the given file (e.g. `refs/msrv-prep/backup:Cargo.toml`).
+    fn backup_location(&self, file_path: &Utf8Path) -> Result<String> {
+        Ok(format!("{}:{}", self.ref_name, self.repo_path(file_path)?))
+    }
+}
+
+/// Checks whether the given backup file contains the [`ABSENT_FILE_MARKER`].
+pub fn is_absent_file_marker(backup_path: &Utf8Path) -> Result<bool> {
+    let len = backup_path
+        .metadata()
+        .with_io_context(|| format!("reading metadata of '{backup_path}'"))?
+        .len();
+    if len != ABSENT_FILE_MARKER.len() as u64 {
+        return Ok(false);
+    }
+
+    let content =
+        fs::read(backup_path).with_io_context(|| format!("reading backup file '{backup_path}'"))?;
+    Ok(content == ABSENT_FILE_MARKER.as_bytes())
+}
+
+fn remove_restored_file(file_path: &Utf8Path) -> Result<()> {
+    if file_path.is_file() {
+        info!("'{file_path}' did not exist before preparation; removing");
+
+        fs::remove_file(file_path).with_io_context(|| format!("removing '{file_path}'"))?;
+    }
+
+    Ok(())
+}
+
+fn validate_backup(exists: bool, backup_path: &Utf8Path, force: bool) -> Result<()> {
+    match (exists, force) {
+        (true, true) => {
+            info!(
+                "Backup file already exists at '{backup_path}'; will be overwritten (forced backup)"
+            );
+            Ok(())
+        },
+        (true, false) => {
+            error!("Backup file already exists at '{backup_path}'; aborting");
+
+            Err(Error::BackupFileAlreadyExists(backup_path.into()))
+        },
+        (false, _) => Ok(()),
+    }
+}
+
+fn canonicalize(path: &Utf8Path) -> Result<Utf8PathBuf> {
+    path.canonicalize_utf8()
+        .with_io_context(|| format!("resolving path '{path}'"))
+}
+
+fn git_command<I, S>(dir: &Utf8Path, args: I, index_path: Option<&Utf8Path>) -> Command
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = Command::new("git");
+    command
+        .current_dir(dir)
+        .args(args)
+        .env("GIT_AUTHOR_NAME", GIT_BACKUP_IDENTITY.0)
+        .env("GIT_AUTHOR_EMAIL", GIT_BACKUP_IDENTITY.1)
+        .env("GIT_COMMITTER_NAME", GIT_BACKUP_IDENTITY.0)
+        .env("GIT_COMMITTER_EMAIL", GIT_BACKUP_IDENTITY.1);
+    if let Some(index_path) = index_path {
+        command.env("GIT_INDEX_FILE", index_path);
+    }
+
+    command
+}
+
+fn git_output<I, S>(dir: &Utf8Path, args: I, index_path: Option<&Utf8Path>) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = git_command(dir, args, index_path);
+    let command_line = format!("{command:?}");
+    debug!("Running {command_line}");
+
+    let output = command
+        .output()
+        .with_io_context(|| format!("running {command_line}"))?;
+    if !output.status.success() {
+        return Err(Error::Git {
+            command: command_line,
+            message: String::from_utf8_lossy(&output.stderr).trim().into(),
+        });
+    }
+
+    Ok(output.stdout)
+}
+
+fn run_git<I, S>(dir: &Utf8Path, args: I, index_path: Option<&Utf8Path>) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    git_output(dir, args, index_path).map(|output| String::from_utf8_lossy(&output).into_owned())
+}
+
+fn git_succeeds<I, S>(dir: &Utf8Path, args: I) -> bool
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    git_output(dir, args, None).is_ok()
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod git_backup_backend {
+        use assert_fs::fixture::{FileWriteStr, PathChild};
+        use assert_fs::TempDir;
+        use assert_matches::assert_matches;
+
+        use super::*;
+
+        fn init_repo() -> (TempDir, Utf8PathBuf) {
+            let temp = TempDir::new().unwrap();
+            let root = Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+            run_git(&root, ["init", "--quiet"], None).unwrap();
+
+            (temp, root)
+        }
+
+        #[test_log::test]
+        fn backup_and_restore() {
+            let (temp, root) = init_repo();
+            temp.child("Cargo.toml").write_str("[package]\n").unwrap();
+            temp.child("member")
+                .child("Cargo.toml")
+                .write_str("[package]\nname = \"member\"\n")
+                .unwrap();
+            let manifest_path = root.join("Cargo.toml");
+            let member_manifest_path = root.join("member").join("Cargo.toml");
+
+            let backend = GitBackupBackend::new(&root, DEFAULT_GIT_BACKUP_REF).unwrap();
+            assert!(!backend.has_backup(&manifest_path).unwrap());
+
+            backend
+                .backup_files(&[&manifest_path, &member_manifest_path], false)
+                .unwrap();
+            assert!(backend.has_backup(&manifest_path).unwrap());
+            assert!(backend.has_backup(&member_manifest_path).unwrap());
+            assert_eq!(
+                "refs/msrv-prep/backup:member/Cargo.toml",
+                backend.backup_location(&member_manifest_path).unwrap()
+            );
+
+            assert_matches!(
+                backend.backup_files(&[&manifest_path], false),
+                Err(Error::BackupFileAlreadyExists(_))
+            );
+
+            fs::write(&manifest_path, "[workspace]\n").unwrap();
+            fs::write(&member_manifest_path, "[workspace]\n").unwrap();
+
+            assert!(backend.maybe_restore_file(&manifest_path).unwrap());
+            assert_eq!("[package]\n", fs::read_to_string(&manifest_path).unwrap());
+            assert!(!backend.has_backup(&manifest_path).unwrap());
+            assert!(!backend.maybe_restore_file(&manifest_path).unwrap());
+            assert!(backend.ref_exists().unwrap());
+
+            assert!(backend.maybe_restore_file(&member_manifest_path).unwrap());
+            assert_eq!(
+                "[package]\nname = \"member\"\n",
+                fs::read_to_string(&member_manifest_path).unwrap()
+            );
+            assert!(!backend.ref_exists().unwrap());
+        }
+
+        #[test_log::test]
+        fn absent_file() {
+            let (_temp, root) = init_repo();
+            let config_path = root.join("config.toml");
+
+            let backend = GitBackupBackend::new(&root, DEFAULT_GIT_BACKUP_REF).unwrap();
+            backend.backup_files(&[&config_path], false).unwrap();
+            assert!(backend.has_backup(&config_path).unwrap());
+
+            fs::write(&config_path, "[net]\noffline = true\n").unwrap();
+            assert!(backend.maybe_restore_file(&config_path).unwrap());
+            assert!(!config_path.exists());
+            assert!(!backend.ref_exists().unwrap());
+        }
+
+        #[test_log::test]
+        fn outside_repository() {
+            let temp = TempDir::new().unwrap();
+            let root = Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+
+            assert_matches!(
+                GitBackupBackend::new(&root, DEFAULT_GIT_BACKUP_REF),
+                Err(Error::Git { .. })
+            );
+        }
+    }
+}
@@ -1,13 +1,31 @@
 //! Restores manifests backed up by `cargo msrv-prep` (see `cargo-msrv-prep` crate).
-
-use cargo_msrv_prep::common_args::CommonArgs;
-use cargo_msrv_prep::metadata::Metadata;
-use cargo_msrv_prep::{maybe_restore_manifest, DEFAULT_MANIFEST_FILE_NAME};
-use clap::{Args, Parser};
+//!
+//! Each restored manifest is verified after being restored. The result of restoring each
+//! package's manifest is summarized in a table printed to stderr (unless `--quiet` is used);
+//! packages that are expected to have been prepped can be specified via `--expect-prepped`.
+//! With `--message-format github`, errors are reported as GitHub Actions annotations and the
+//! table is appended to the job summary.
+//!
+//! If the workspace's toolchain file or Cargo configuration file was modified by
+//! `cargo msrv-prep`, it is also restored (or deleted if it was created).
+
+use std::process::ExitCode;
+
+use cargo_msrv_prep::clean::{delete_backup_file, find_backup_files, BackupFileStatus};
+use cargo_msrv_prep::common_args::{BackupBackendKind, CommonArgs, MessageFormat};
+use cargo_msrv_prep::github::{error_annotation, maybe_append_step_summary};
+use cargo_msrv_prep::Preparer;
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser};
 use log::{debug, info, trace};
 
-fn main() -> cargo_msrv_prep::Result<()> {
+fn main() -> ExitCode {
     let Cli::MsrvUnprep(args) = Cli::parse();
+    if args.clean && args.common.backup_backend != BackupBackendKind::File {
+        Cli::command()
+            .error(ErrorKind::ArgumentConflict, "`--clean` only supports the `file` backup backend")
+            .exit();
+    }
 
     env_logger::Builder::new()
         .filter_level(args.common.verbose.log_level_filter())
@@ -15,10 +33,17 @@ fn main() -> cargo_msrv_prep::Result<()> {
 
     info!("{} started", env!("CARGO_BIN_NAME"));
 
-    unprep_from_msrv(&args)?;
+    let result = if args.clean { clean_backup_files(&args) } else { unprep_from_msrv(&args) };
+    if let Err(err) = result {
+        if args.common.message_format == MessageFormat::Github {
+            println!("{}", error_annotation(&err));
+        }
+        eprintln!("error: {err}");
+        return ExitCode::from(err.exit_code());
+    }
 
     info!("{} finished", env!("CARGO_BIN_NAME"));
-    Ok(())
+    ExitCode::SUCCESS
 }
 
 #[derive(Debug, Parser)]
@@ -32,46 +57,141 @@ enum Cli {
 struct MsrvUnprepArgs {
     #[command(flatten)]
     common: CommonArgs,
+
+    /// Name of a package that is expected to have been prepped (can be specified multiple times)
+    ///
+    /// If no backup is found for such a package, a warning is reported (or an error with `--strict`)
+    #[arg(long, value_name = "SPEC", conflicts_with = "clean")]
+    pub expect_prepped: Vec<String>,
+
+    /// Fail if a package specified via `--expect-prepped` has no backup
+    #[arg(long, default_value_t = false, conflicts_with = "clean")]
+    pub strict: bool,
+
+    /// Look for leftover backup files instead of restoring manifests
+    ///
+    /// Backup files found under the workspace root are compared with the current files
+    /// and reported as identical, stale (content differs) or orphaned (file is missing).
+    /// Backup files are only deleted if `--yes` is also specified; no file is restored.
+    /// Only supported with the `file` backup backend.
+    #[arg(long, default_value_t = false)]
+    pub clean: bool,
+
+    /// Additional backup file suffix to look for when cleaning (must end with `.bak`)
+    #[arg(long = "clean-suffix", value_name = "SUFFIX", requires = "clean", value_parser = parse_bak_suffix)]
+    pub clean_suffixes: Vec<String>,
+
+    /// Delete backup files found when cleaning
+    ///
+    /// Stale and orphaned backup files are not deleted unless `--include-stale`
+    /// or `--include-orphaned` is also specified
+    #[arg(short, long, requires = "clean", default_value_t = false)]
+    pub yes: bool,
+
+    /// Also delete stale backup files when cleaning
+    ///
+    /// A stale backup file is usually the backup of a manifest that is currently prepped,
+    /// making it the only copy of the original; consider restoring it instead
+    #[arg(long, requires = "yes", default_value_t = false)]
+    pub include_stale: bool,
+
+    /// Also delete orphaned backup files when cleaning
+    ///
+    /// An orphaned backup file is usually the backup of a file removed during preparation
+    /// (e.g. with `--remove-toolchain-file`), making it the only copy of the original;
+    /// consider restoring it instead
+    #[arg(long, requires = "yes", default_value_t = false)]
+    pub include_orphaned: bool,
+}
+
+fn parse_bak_suffix(suffix: &str) -> Result<String, String> {
+    if suffix.len() > ".bak".len() && suffix.ends_with(".bak") {
+        Ok(suffix.into())
+    } else {
+        Err(format!("backup suffix must end with `.bak`: {suffix}"))
+    }
 }
 
 fn unprep_from_msrv(args: &MsrvUnprepArgs) -> cargo_msrv_prep::Result<()> {
     trace!("Entering `unprep_from_msrv` (args: {args:?})");
 
-    let metadata: Metadata = (&args.common).try_into()?;
-    debug!("Workspace root: {}", metadata.cargo_metadata.workspace_root);
-    debug!("Selected packages: {}", metadata.selected_package_names());
+    let metadata = args.common.metadata()?;
+    let backup_backend = args
+        .common
+        .backup_backend(&metadata.cargo_metadata().workspace_root)?;
+
+    let mut preparer = Preparer::new()
+        .backup_root_manifest(args.common.backup_root_manifest)
+        .strict(args.strict)
+        .backup_backend(backup_backend);
+    for package_name in &args.expect_prepped {
+        preparer = preparer.expect_prepped(package_name.as_str());
+    }
+    let report = preparer.unprepare(&metadata)?;
+    if !args.common.verbose.is_silent() {
+        eprint!("{report}");
+    }
+    if args.common.message_format == MessageFormat::Github {
+        maybe_append_step_summary(&format!("### `cargo msrv-unprep`\n\n{}", report.to_markdown()))?;
+    }
 
-    let mut root_manifest_restored = false;
-    for package in &metadata.selected_packages {
-        info!("Restoring manifest '{}' (at '{}')", package.name, package.manifest_path);
+    trace!("Exiting `unprep_from_msrv`");
+    Ok(())
+}
 
-        maybe_restore_manifest(&package.manifest_path, &args.common.manifest_backup_suffix)?;
+fn clean_backup_files(args: &MsrvUnprepArgs) -> cargo_msrv_prep::Result<()> {
+    trace!("Entering `clean_backup_files` (args: {args:?})");
 
-        root_manifest_restored = root_manifest_restored
-            || package.manifest_path
-                == metadata
-                    .cargo_metadata
-                    .workspace_root
-                    .join(DEFAULT_MANIFEST_FILE_NAME);
+    // Note: we skip dependency resolution here, since it could end up modifying the lockfile.
+    let metadata = args.common.manifest.metadata().no_deps().exec()?;
+    debug!("Workspace root: {}", metadata.workspace_root);
+
+    let mut backup_suffixes = vec![args.common.manifest_backup_suffix.clone()];
+    backup_suffixes.extend(args.clean_suffixes.iter().cloned());
+    debug!("Backup suffixes: {}", backup_suffixes.join(", "));
+
+    let backup_files = find_backup_files(&metadata.workspace_root, &backup_suffixes)?;
+    for backup_file in &backup_files {
+        println!("{}: {}", backup_file.status, backup_file.backup_path);
     }
 
-    if args.common.backup_root_manifest {
-        if !root_manifest_restored {
-            info!("Restoring root manifest (at '{}')", metadata.cargo_metadata.workspace_root);
-
-            // Note: we do the same assumption here as in `cargo-msrv-prep` (see the corresponding note).
-            maybe_restore_manifest(
-                &metadata
-                    .cargo_metadata
-                    .workspace_root
-                    .join(DEFAULT_MANIFEST_FILE_NAME),
-                &args.common.manifest_backup_suffix,
-            )?;
-        } else {
-            info!("Root manifest already restored; skipping");
+    if backup_files.is_empty() {
+        println!("No backup files found");
+    } else if args.yes {
+        let (to_delete, kept): (Vec<_>, Vec<_>) =
+            backup_files
+                .iter()
+                .partition(|backup_file| match backup_file.status {
+                    BackupFileStatus::Identical => true,
+                    BackupFileStatus::Stale => args.include_stale,
+                    BackupFileStatus::Orphaned => args.include_orphaned,
+                });
+        for backup_file in &to_delete {
+            delete_backup_file(backup_file)?;
         }
+        println!("Deleted {} backup file(s)", to_delete.len());
+        for (status, flag) in [
+            (BackupFileStatus::Stale, "--include-stale"),
+            (BackupFileStatus::Orphaned, "--include-orphaned"),
+        ] {
+            let count = kept
+                .iter()
+                .filter(|backup_file| backup_file.status == status)
+                .count();
+            if count > 0 {
+                println!(
+                    "Kept {count} {status} backup file(s); run `cargo msrv-unprep` to restore them, \
+                     or run again with `{flag}` to delete them"
+                );
+            }
+        }
+    } else {
+        println!(
+            "Found {} backup file(s); run again with `--yes` to delete them",
+            backup_files.len()
+        );
     }
 
-    trace!("Exiting `unprep_from_msrv`");
+    trace!("Exiting `clean_backup_files`");
     Ok(())
 }
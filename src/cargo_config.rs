@@ -0,0 +1,397 @@
+//! Helpers to prepare the Cargo configuration file of a workspace (e.g. `.cargo/config.toml`).
+//!
+//! Some Cargo settings are useful when determining/verifying MSRV, like enabling MSRV-aware
+//! dependency resolution. These can be set in the workspace's configuration file, which is
+//! created if needed. The original file is backed up so that it can be restored (or deleted
+//! if it did not exist).
+
+use std::fs;
+
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, info, trace};
+use toml_edit::{Array, DocumentMut, Item, Table, Value};
+
+use crate::backup::BackupBackend;
+use crate::detail::unformatted;
+#[mockall_double::double]
+use crate::mockable::fs as mockable_fs;
+use crate::result::{IoErrorContext, TomlErrorContext};
+use crate::Result;
+
+/// Name of the directory storing Cargo configuration files.
+pub const CARGO_CONFIG_DIR_NAME: &str = ".cargo";
+
+/// Name of the Cargo configuration file.
+pub const CARGO_CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Legacy name of the Cargo configuration file (without extension).
+pub const LEGACY_CARGO_CONFIG_FILE_NAME: &str = "config";
+
+/// Name of the source used to replace `crates-io` when using vendored sources.
+const VENDORED_SOURCES_NAME: &str = "vendored-sources";
+
+/// Changes to apply to a workspace's Cargo configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CargoConfigChanges {
+    /// Enable MSRV-aware dependency resolution
+    /// (sets `resolver.incompatible-rust-versions` to `"fallback"`).
+    pub msrv_aware_resolver: bool,
+
+    /// Make Cargo run in offline mode (sets `net.offline` to `true`).
+    pub offline: bool,
+
+    /// Replace the `crates-io` source with vendored sources stored in the given directory
+    /// (e.g. the directory created by `cargo vendor`).
+    pub vendored_sources: Option<Utf8PathBuf>,
+
+    /// Flags to append to `build.rustflags` (e.g. `-Dunsafe_code`).
+    ///
+    /// Note that Cargo ignores `build.rustflags` if the `RUSTFLAGS` environment variable is set.
+    pub rustflags: Vec<String>,
+}
+
+impl CargoConfigChanges {
+    /// Checks whether there are no changes to apply.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Returns the path of the Cargo configuration file for the given workspace root.
+///
+/// If the workspace only has a legacy configuration file (`.cargo/config`), its path is
+/// returned; otherwise, the path to `.cargo/config.toml` is returned, whether it exists or not.
+pub fn cargo_config_path(workspace_root: &Utf8Path) -> Utf8PathBuf {
+    let config_dir = workspace_root.join(CARGO_CONFIG_DIR_NAME);
+    let config_path = config_dir.join(CARGO_CONFIG_FILE_NAME);
+    let legacy_config_path = config_dir.join(LEGACY_CARGO_CONFIG_FILE_NAME);
+
+    if !config_path.is_file() && legacy_config_path.is_file() {
+        legacy_config_path
+    } else {
+        config_path
+    }
+}
+
+/// Applies changes to the content of a Cargo configuration file.
+///
+/// Returns `true` if the configuration was modified.
+pub fn apply_cargo_config_changes(config: &mut Table, changes: &CargoConfigChanges) -> bool {
+    trace!("Entering `apply_cargo_config_changes` (changes: {changes:?})");
+
+    let mut changed = false;
+
+    if changes.msrv_aware_resolver {
+        changed = set_config_value(
+            config,
+            &["resolver", "incompatible-rust-versions"],
+            "fallback".into(),
+        ) || changed;
+    }
+    if changes.offline {
+        changed = set_config_value(config, &["net", "offline"], true.into()) || changed;
+    }
+    if let Some(vendored_sources) = &changes.vendored_sources {
+        changed = set_config_value(
+            config,
+            &["source", "crates-io", "replace-with"],
+            VENDORED_SOURCES_NAME.into(),
+        ) || changed;
+        changed = set_config_value(
+            config,
+            &["source", VENDORED_SOURCES_NAME, "directory"],
+            vendored_sources.as_str().into(),
+        ) || changed;
+    }
+
+    if !changes.rustflags.is_empty() {
+        changed = append_rustflags(config, &changes.rustflags) || changed;
+    }
+
+    trace!("Exiting `apply_cargo_config_changes` (changed: {changed})");
+    changed
+}
+
+/// Applies changes to the Cargo configuration file of the given workspace,
+/// creating it if needed.
+///
+/// The configuration file is backed up using the given backend before being modified.
+/// If it did not exist, restoring the backup will delete it.
+/// If `dry_run` is `true`, the configuration file is not actually modified.
+///
+/// Returns `Ok(true)` if the configuration file needed to be changed.
+pub fn prep_cargo_config(
+    workspace_root: &Utf8Path,
+    changes: &CargoConfigChanges,
+    backup_backend: &dyn BackupBackend,
+    force: bool,
+    dry_run: bool,
+) -> Result<bool> {
+    trace!("Entering `prep_cargo_config` (workspace_root: '{workspace_root}')");
+
+    let config_path = cargo_config_path(workspace_root);
+    debug!("Cargo configuration file path: {config_path}");
+
+    let config_text = if config_path.is_file() {
+        fs::read_to_string(&config_path)
+            .with_io_context(|| format!("reading Cargo configuration file '{config_path}'"))?
+    } else {
+        String::new()
+    };
+    let mut config = config_text
+        .parse::<DocumentMut>()
+        .with_toml_file(&config_path, &config_text)?;
+
+    let changed = apply_cargo_config_changes(&mut config, changes);
+    if !changed {
+        info!("Cargo configuration file '{config_path}' does not need changes; skipping");
+    } else if dry_run {
+        info!(
+            "Cargo configuration file '{config_path}' needs changes; not persisting (dry-run mode)"
+        );
+    } else {
+        info!("Cargo configuration file '{config_path}' changed after preparation; persisting");
+
+        if let Some(config_dir) = config_path.parent() {
+            fs::create_dir_all(config_dir)
+                .with_io_context(|| format!("creating directory '{config_dir}'"))?;
+        }
+        backup_backend.backup_files(&[&config_path], force)?;
+        mockable_fs::write(&config_path, config.to_string()).with_io_context(|| {
+            format!("saving updated Cargo configuration file to '{config_path}'")
+        })?;
+    }
+
+    trace!("Exiting `prep_cargo_config` (changed: {changed})");
+    Ok(changed)
+}
+
+/// Restores the Cargo configuration file of the given workspace, if it was backed up
+/// by [`prep_cargo_config`].
+///
+/// If the configuration file did not exist before preparation, it is deleted.
+///
+/// Returns `Ok(true)` if the configuration file was restored.
+pub fn maybe_restore_cargo_config(
+    workspace_root: &Utf8Path,
+    backup_backend: &dyn BackupBackend,
+) -> Result<bool> {
+    trace!("Entering `maybe_restore_cargo_config` (workspace_root: '{workspace_root}')");
+
+    let config_dir = workspace_root.join(CARGO_CONFIG_DIR_NAME);
+    let mut restored = false;
+    for name in [CARGO_CONFIG_FILE_NAME, LEGACY_CARGO_CONFIG_FILE_NAME] {
+        restored = backup_backend.maybe_restore_file(&config_dir.join(name))? || restored;
+    }
+
+    trace!("Exiting `maybe_restore_cargo_config` (restored: {restored})");
+    Ok(restored)
+}
+
+fn append_rustflags(config: &mut Table, rustflags: &[String]) -> bool {
+    let existing = match config
+        .get("build")
+        .and_then(|build| build.get("rustflags"))
+        .and_then(Item::as_value)
+    {
+        Some(Value::Array(existing)) => existing.clone(),
+        Some(Value::String(existing)) => existing.value().split_whitespace().collect(),
+        _ => Array::new(),
+    };
+
+    let mut updated = existing.clone();
+    for flag in rustflags {
+        if !updated
+            .iter()
+            .any(|existing| existing.as_str() == Some(flag))
+        {
+            updated.push(flag.as_str());
+        }
+    }
+    if updated.len() == existing.len() {
+        return false;
+    }
+
+    set_config_value(config, &["build", "rustflags"], Value::Array(updated))
+}
+
+fn set_config_value(config: &mut Table, path: &[&str], value: Value) -> bool {
+    let (key, parents) = path
+        .split_last()
+        .expect("config value path should not be empty");
+
+    let mut table = config;
+    for parent in parents {
+        let item = table.entry(parent).or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        });
+        if !item.is_table() {
+            *item = Item::Table(std::mem::take(item).into_table().unwrap_or_default());
+            if let Some(mut key) = table.key_mut(parent) {
+                key.fmt();
+            }
+        }
+        table = table[parent]
+            .as_table_mut()
+            .expect("item should be a table");
+    }
+
+    let changed = table
+        .get(key)
+        .and_then(Item::as_value)
+        .is_none_or(|existing| unformatted(existing) != unformatted(&value));
+    if changed {
+        debug!("Setting Cargo configuration value '{}' to {value}", path.join("."));
+        table[key] = Item::Value(value);
+    }
+
+    changed
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod apply_cargo_config_changes {
+        use indoc::indoc;
+
+        use super::*;
+
+        #[test]
+        fn empty_config() {
+            let mut config = DocumentMut::new();
+            let changes = CargoConfigChanges {
+                msrv_aware_resolver: true,
+                offline: true,
+                vendored_sources: Some("vendor".into()),
+                rustflags: vec!["-Dunsafe_code".into()],
+            };
+
+            assert!(apply_cargo_config_changes(&mut config, &changes));
+
+            let expected = indoc! {r#"
+                [resolver]
+                incompatible-rust-versions = "fallback"
+
+                [net]
+                offline = true
+
+                [source.crates-io]
+                replace-with = "vendored-sources"
+
+                [source.vendored-sources]
+                directory = "vendor"
+
+                [build]
+                rustflags = ["-Dunsafe_code"]
+            "#};
+            assert_eq!(expected, config.to_string());
+        }
+
+        #[test]
+        fn existing_config() {
+            let mut config = indoc! {r#"
+                # Our config
+                [build]
+                rustflags = ["-Dwarnings"]
+
+                [resolver]
+                incompatible-rust-versions = "allow" # default
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+            let changes = CargoConfigChanges { msrv_aware_resolver: true, ..Default::default() };
+
+            assert!(apply_cargo_config_changes(&mut config, &changes));
+
+            let expected = indoc! {r#"
+                # Our config
+                [build]
+                rustflags = ["-Dwarnings"]
+
+                [resolver]
+                incompatible-rust-versions = "fallback"
+            "#};
+            assert_eq!(expected, config.to_string());
+        }
+
+        #[test]
+        fn existing_rustflags() {
+            let mut config = indoc! {r#"
+                [build]
+                rustflags = ["-Dwarnings"] # strict
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+            let changes = CargoConfigChanges {
+                rustflags: vec!["-Dwarnings".into(), "-Aclippy::all".into()],
+                ..Default::default()
+            };
+
+            assert!(apply_cargo_config_changes(&mut config, &changes));
+            assert!(!apply_cargo_config_changes(&mut config, &changes));
+
+            let expected = indoc! {r#"
+                [build]
+                rustflags = ["-Dwarnings", "-Aclippy::all"] # strict
+            "#};
+            assert_eq!(expected, config.to_string());
+        }
+
+        #[test]
+        fn inline_table() {
+            let mut config = indoc! {r#"
+                net = { retry = 3 }
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+            let changes = CargoConfigChanges { offline: true, ..Default::default() };
+
+            assert!(apply_cargo_config_changes(&mut config, &changes));
+
+            let expected = indoc! {r#"
+                [net]
+                retry = 3
+                offline = true
+            "#};
+            assert_eq!(expected, config.to_string());
+        }
+
+        #[test]
+        fn formatting_ignored() {
+            let config_text = indoc! {r#"
+                [resolver]
+                incompatible-rust-versions = 'fallback' # MSRV-aware
+
+                [net]
+                offline =   true
+            "#};
+            let mut config = config_text.parse::<DocumentMut>().unwrap();
+            let changes = CargoConfigChanges {
+                msrv_aware_resolver: true,
+                offline: true,
+                ..Default::default()
+            };
+
+            assert!(!apply_cargo_config_changes(&mut config, &changes));
+            assert_eq!(config_text, config.to_string());
+        }
+
+        #[test]
+        fn no_changes_needed() {
+            let mut config = indoc! {r#"
+                [net]
+                offline = true
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+            let changes = CargoConfigChanges { offline: true, ..Default::default() };
+
+            assert!(!apply_cargo_config_changes(&mut config, &changes));
+            assert!(!apply_cargo_config_changes(&mut config, &CargoConfigChanges::default()));
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! Helpers to find and clean up stale backup files left behind by `cargo msrv-prep`.
+
+use std::fmt::{Display, Formatter};
+use std::fs;
+
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, info, trace};
+
+use crate::backup::is_absent_file_marker;
+#[mockall_double::double]
+use crate::mockable::fs as mockable_fs;
+use crate::result::IoErrorContext;
+use crate::Result;
+
+/// Name of directories that are never scanned when looking for backup files.
+const IGNORED_DIR_NAMES: &[&str] = &["target"];
+
+/// Status of a backup file when compared to the file it was created from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BackupFileStatus {
+    /// Backup file has the same content as the original file
+    /// (or the original file did not exist and still doesn't).
+    Identical,
+
+    /// Backup file's content differs from the original file's.
+    Stale,
+
+    /// The original file does not exist anymore.
+    Orphaned,
+}
+
+impl Display for BackupFileStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Identical => write!(f, "identical"),
+            Self::Stale => write!(f, "stale"),
+            Self::Orphaned => write!(f, "orphaned"),
+        }
+    }
+}
+
+/// Backup file found by [`find_backup_files`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupFile {
+    /// Path to the backup file itself.
+    pub backup_path: Utf8PathBuf,
+
+    /// Path to the file that was backed up.
+    pub original_path: Utf8PathBuf,
+
+    /// Status of the backup file when compared to the original.
+    pub status: BackupFileStatus,
+}
+
+/// Recursively looks for backup files under the given root directory.
+///
+/// A file is considered a backup file if its name ends with one of the given suffixes.
+/// Hidden directories (like `.git`) and `target` directories are not scanned.
+///
+/// Returned backup files are sorted by path.
+pub fn find_backup_files<S>(root: &Utf8Path, backup_suffixes: &[S]) -> Result<Vec<BackupFile>>
+where
+    S: AsRef<str>,
+{
+    trace!("Entering `find_backup_files` (root: '{root}')");
+
+    let mut backup_files = Vec::new();
+    scan_dir(root, backup_suffixes, &mut backup_files)?;
+    backup_files.sort_by(|a, b| a.backup_path.cmp(&b.backup_path));
+
+    trace!("Exiting `find_backup_files` (found: {})", backup_files.len());
+    Ok(backup_files)
+}
+
+/// Deletes a backup file found by [`find_backup_files`].
+///
+/// The original file is left untouched.
+pub fn delete_backup_file(backup_file: &BackupFile) -> Result<()> {
+    info!("Deleting {} backup file '{}'", backup_file.status, backup_file.backup_path);
+
+    mockable_fs::remove_file(&backup_file.backup_path)
+        .with_io_context(|| format!("deleting backup file '{}'", backup_file.backup_path))
+}
+
+fn scan_dir<S>(
+    dir: &Utf8Path,
+    backup_suffixes: &[S],
+    backup_files: &mut Vec<BackupFile>,
+) -> Result<()>
+where
+    S: AsRef<str>,
+{
+    debug!("Scanning '{dir}' for backup files");
+
+    for entry in dir
+        .read_dir_utf8()
+        .with_io_context(|| format!("listing content of '{dir}'"))?
+    {
+        let entry = entry.with_io_context(|| format!("listing content of '{dir}'"))?;
+        let file_type = entry
+            .file_type()
+            .with_io_context(|| format!("fetching file type of '{}'", entry.path()))?;
+
+        if file_type.is_dir() {
+            if !entry.file_name().starts_with('.')
+                && !IGNORED_DIR_NAMES.contains(&entry.file_name())
+            {
+                scan_dir(entry.path(), backup_suffixes, backup_files)?;
+            }
+        } else if file_type.is_file() {
+            let original_name = backup_suffixes.iter().find_map(|suffix| {
+                entry
+                    .file_name()
+                    .strip_suffix(suffix.as_ref())
+                    .filter(|name| !name.is_empty())
+            });
+
+            if let Some(original_name) = original_name {
+                let backup_path = entry.path().to_path_buf();
+                let original_path = dir.join(original_name);
+                let status = get_backup_file_status(&backup_path, &original_path)?;
+                debug!("Found {status} backup file '{backup_path}'");
+
+                backup_files.push(BackupFile { backup_path, original_path, status });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn get_backup_file_status(
+    backup_path: &Utf8Path,
+    original_path: &Utf8Path,
+) -> Result<BackupFileStatus> {
+    // Backups of files that did not exist are "identical" if the file still doesn't exist.
+    if is_absent_file_marker(backup_path)? {
+        return Ok(if original_path.is_file() {
+            BackupFileStatus::Stale
+        } else {
+            BackupFileStatus::Identical
+        });
+    }
+    if !original_path.is_file() {
+        return Ok(BackupFileStatus::Orphaned);
+    }
+
+    let backup_content =
+        fs::read(backup_path).with_io_context(|| format!("reading backup file '{backup_path}'"))?;
+    let original_content =
+        fs::read(original_path).with_io_context(|| format!("reading file '{original_path}'"))?;
+
+    Ok(if backup_content == original_content {
+        BackupFileStatus::Identical
+    } else {
+        BackupFileStatus::Stale
+    })
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use assert_fs::fixture::{FileWriteStr, PathChild};
+    use assert_fs::TempDir;
+
+    use super::*;
+    use crate::DEFAULT_MANIFEST_BACKUP_SUFFIX;
+
+    fn temp_root(temp: &TempDir) -> Utf8PathBuf {
+        Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap()
+    }
+
+    mod find_backup_files {
+        use super::*;
+
+        #[test_log::test]
+        fn statuses() {
+            let temp = TempDir::new().unwrap();
+            temp.child("Cargo.toml").write_str("[package]").unwrap();
+            temp.child("Cargo.toml.msrv-prep.bak")
+                .write_str("[package]")
+                .unwrap();
+            temp.child("Cargo.lock").write_str("version = 4").unwrap();
+            temp.child("Cargo.lock.msrv-prep.bak")
+                .write_str("version = 3")
+                .unwrap();
+            temp.child("member")
+                .child("Cargo.toml.msrv-prep.bak")
+                .write_str("[package]")
+                .unwrap();
+            temp.child("target")
+                .child("Cargo.toml.msrv-prep.bak")
+                .write_str("[package]")
+                .unwrap();
+            temp.child(".git")
+                .child("Cargo.toml.msrv-prep.bak")
+                .write_str("[package]")
+                .unwrap();
+
+            let root = temp_root(&temp);
+            let backup_files = find_backup_files(&root, &[DEFAULT_MANIFEST_BACKUP_SUFFIX]).unwrap();
+
+            assert_eq!(
+                vec![
+                    BackupFile {
+                        backup_path: root.join("Cargo.lock.msrv-prep.bak"),
+                        original_path: root.join("Cargo.lock"),
+                        status: BackupFileStatus::Stale,
+                    },
+                    BackupFile {
+                        backup_path: root.join("Cargo.toml.msrv-prep.bak"),
+                        original_path: root.join("Cargo.toml"),
+                        status: BackupFileStatus::Identical,
+                    },
+                    BackupFile {
+                        backup_path: root.join("member").join("Cargo.toml.msrv-prep.bak"),
+                        original_path: root.join("member").join("Cargo.toml"),
+                        status: BackupFileStatus::Orphaned,
+                    },
+                ],
+                backup_files
+            );
+        }
+
+        #[test_log::test]
+        fn multiple_suffixes() {
+            let temp = TempDir::new().unwrap();
+            temp.child("Cargo.toml").write_str("[package]").unwrap();
+            temp.child("Cargo.toml.msrv-prep.bak")
+                .write_str("[package]")
+                .unwrap();
+            temp.child("Cargo.toml.old.bak")
+                .write_str("[package]")
+                .unwrap();
+            temp.child(".bak").write_str("").unwrap();
+
+            let root = temp_root(&temp);
+            let backup_files = find_backup_files(&root, &[".old.bak"]).unwrap();
+            assert_eq!(1, backup_files.len());
+            assert_eq!(root.join("Cargo.toml.old.bak"), backup_files[0].backup_path);
+
+            let backup_files =
+                find_backup_files(&root, &[DEFAULT_MANIFEST_BACKUP_SUFFIX, ".bak"]).unwrap();
+            assert_eq!(2, backup_files.len());
+        }
+    }
+
+    mod delete_backup_file {
+        use std::io;
+
+        use assert_matches::assert_matches;
+
+        use super::*;
+        use crate::Error;
+
+        #[test_log::test]
+        fn remove_error() {
+            let _lock = crate::mockable::lock_fs_mock();
+            let ctx = mockable_fs::remove_file_context();
+            ctx.expect().returning(|_| {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, "permission denied"))
+            });
+
+            let result = delete_backup_file(&BackupFile {
+                backup_path: "Cargo.toml.msrv-prep.bak".into(),
+                original_path: "Cargo.toml".into(),
+                status: BackupFileStatus::Identical,
+            });
+            assert_matches!(result, Err(Error::Io { source, .. }) => {
+                assert_eq!(io::ErrorKind::PermissionDenied, source.kind());
+            });
+        }
+    }
+}
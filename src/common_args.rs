@@ -1,7 +1,10 @@
-use clap::Args;
+use cargo_metadata::camino::Utf8Path;
+use clap::{Args, ValueEnum};
 use clap_cargo::{Manifest, Workspace};
 use clap_verbosity_flag::Verbosity;
 
+use crate::backup::{BackupBackend, FileBackupBackend, GitBackupBackend, DEFAULT_GIT_BACKUP_REF};
+use crate::metadata::Metadata;
 use crate::DEFAULT_MANIFEST_BACKUP_SUFFIX;
 
 #[derive(Debug, Args)]
@@ -23,4 +26,67 @@ pub struct CommonArgs {
     /// Use to back up the `Cargo.lock` of a workspace without a root package
     #[arg(long, default_value_t = false)]
     pub backup_root_manifest: bool,
+
+    /// Where to store backups of modified files
+    #[arg(long, value_enum, default_value_t = BackupBackendKind::File)]
+    pub backup_backend: BackupBackendKind,
+
+    /// Git ref used to store backups when using the `git` backup backend
+    #[arg(long, default_value = DEFAULT_GIT_BACKUP_REF)]
+    pub git_backup_ref: String,
+
+    /// Format used to report errors and summaries
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+    pub message_format: MessageFormat,
+}
+
+impl CommonArgs {
+    /// Loads the workspace's metadata, selecting packages via command-line arguments
+    /// (e.g. `--package`, `--workspace`).
+    pub fn metadata(&self) -> crate::Result<Metadata> {
+        let metadata = self.manifest.metadata().exec()?;
+
+        let (selected_packages, _) = self.workspace.partition_packages(&metadata);
+        let selected_packages: Vec<_> = selected_packages.into_iter().cloned().collect();
+
+        Ok(Metadata { cargo_metadata: metadata, selected_packages })
+    }
+
+    /// Creates the backup backend selected via command-line arguments.
+    ///
+    /// The workspace root is used to locate the Git repository when using the `git` backend.
+    pub fn backup_backend(
+        &self,
+        workspace_root: &Utf8Path,
+    ) -> crate::Result<Box<dyn BackupBackend>> {
+        Ok(match self.backup_backend {
+            BackupBackendKind::File => {
+                Box::new(FileBackupBackend::new(self.manifest_backup_suffix.as_str()))
+            },
+            BackupBackendKind::Git => {
+                Box::new(GitBackupBackend::new(workspace_root, self.git_backup_ref.as_str())?)
+            },
+        })
+    }
+}
+
+/// Kind of backend used to store backups of modified files.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum BackupBackendKind {
+    /// Copy files next to the originals, adding the manifest backup suffix
+    File,
+
+    /// Store files in a commit referenced by a Git ref (see `--git-backup-ref`)
+    Git,
+}
+
+/// Format used to report errors and summaries.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum MessageFormat {
+    /// Human-readable messages
+    Human,
+
+    /// GitHub Actions workflow commands (errors are reported as annotations); summaries are
+    /// also appended to the job summary (`$GITHUB_STEP_SUMMARY`), if set
+    Github,
 }
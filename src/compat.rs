@@ -0,0 +1,396 @@
+//! Helpers to down-level manifest syntax for older versions of Cargo.
+//!
+//! When determining/verifying MSRV using an old toolchain, Cargo can fail to parse manifests
+//! using newer syntax. Given a target Rust version, the following constructs are down-leveled
+//! if the target Rust version does not support them:
+//!
+//! - Workspace inheritance (`workspace = true`, Rust 1.64+): inherited values are inlined from
+//!   the root manifest (see [`inline_workspace_inheritance`])
+//! - `lints` sections (Rust 1.74+): removed (along with the `workspace.lints` section)
+//! - Namespaced (`dep:`) dependency features (Rust 1.60+): rewritten to use implicit features
+//! - `resolver = "3"` (Rust 1.84+): replaced with `resolver = "2"`
+//!
+//! Constructs that cannot be down-leveled safely (like an edition that is not supported by the
+//! target Rust version, or weak (`?/`) dependency features, which would enable optional
+//! dependencies if rewritten) result in an [`Error::IncompatibleManifest`].
+
+use std::collections::HashSet;
+
+use cargo_metadata::camino::Utf8Path;
+use cargo_metadata::semver::Version;
+use log::{debug, trace};
+use toml_edit::{Item, Table, Value};
+
+use crate::detail::{
+    is_workspace_inherited, FEATURES_SECTION_NAME, PACKAGE_SECTION_NAME, WORKSPACE_SECTION_NAME,
+};
+use crate::inheritance::{inline_workspace_inheritance, RootWorkspace};
+use crate::lints::strip_lints;
+use crate::{Error, Result};
+
+/// First Rust version supporting workspace inheritance.
+pub const WORKSPACE_INHERITANCE_RUST_VERSION: Version = Version::new(1, 64, 0);
+
+/// First Rust version supporting `lints` sections.
+pub const LINTS_RUST_VERSION: Version = Version::new(1, 74, 0);
+
+/// First Rust version supporting namespaced (`dep:`) and weak (`?/`) dependency features.
+pub const NAMESPACED_FEATURES_RUST_VERSION: Version = Version::new(1, 60, 0);
+
+/// First Rust version supporting `resolver = "3"`.
+pub const RESOLVER_3_RUST_VERSION: Version = Version::new(1, 84, 0);
+
+/// Editions and the first Rust version supporting them.
+const EDITION_RUST_VERSIONS: &[(&str, Version)] = &[
+    ("2018", Version::new(1, 31, 0)),
+    ("2021", Version::new(1, 56, 0)),
+    ("2024", Version::new(1, 85, 0)),
+];
+
+const EDITION_FIELD: &str = "edition";
+const RESOLVER_FIELD: &str = "resolver";
+
+/// Down-levels the syntax of a manifest so that it can be parsed by the given version of Cargo.
+///
+/// `manifest_dir` is the directory containing the manifest and `root_workspace` is the `workspace`
+/// section of the workspace's root manifest; these are used to inline values inherited from the
+/// workspace. (See [module documentation](self) for details.)
+///
+/// Returns `Ok(true)` if the manifest was modified.
+pub fn downgrade_manifest(
+    manifest: &mut Table,
+    manifest_dir: &Utf8Path,
+    root_workspace: Option<&RootWorkspace>,
+    target_rust_version: &Version,
+) -> Result<bool> {
+    trace!("Entering `downgrade_manifest` (target_rust_version: {target_rust_version})");
+
+    let mut changed = false;
+    if *target_rust_version < WORKSPACE_INHERITANCE_RUST_VERSION {
+        changed = inline_workspace_inheritance(manifest, manifest_dir, root_workspace)? || changed;
+    }
+    if *target_rust_version < LINTS_RUST_VERSION {
+        changed = strip_lints(manifest) || changed;
+    }
+    if *target_rust_version < NAMESPACED_FEATURES_RUST_VERSION {
+        changed = downgrade_features(manifest, target_rust_version)? || changed;
+    }
+    if *target_rust_version < RESOLVER_3_RUST_VERSION {
+        changed = downgrade_resolver(manifest) || changed;
+    }
+    check_edition(manifest, root_workspace, target_rust_version)?;
+
+    trace!("Exiting `downgrade_manifest` (changed: {changed})");
+    Ok(changed)
+}
+
+fn incompatible(construct: String, rust_version: &Version) -> Error {
+    Error::IncompatibleManifest { construct, rust_version: rust_version.clone() }
+}
+
+fn downgrade_features(manifest: &mut Table, rust_version: &Version) -> Result<bool> {
+    let Some(features) = manifest
+        .get_mut(FEATURES_SECTION_NAME)
+        .and_then(Item::as_table_like_mut)
+    else {
+        return Ok(false);
+    };
+
+    // A feature only enabling an optional dependency of the same name (e.g. `foo = ["dep:foo"]`)
+    // is equivalent to the dependency's implicit feature, so it can be removed.
+    let redundant: Vec<_> = features
+        .iter()
+        .filter(|(name, enabled)| {
+            enabled.as_array().is_some_and(|enabled| {
+                enabled.len() == 1
+                    && enabled.get(0).and_then(Value::as_str) == Some(&format!("dep:{name}"))
+            })
+        })
+        .map(|(name, _)| name.to_string())
+        .collect();
+    let mut changed = false;
+    for name in &redundant {
+        debug!("Removing feature '{name}' (replaced by implicit feature)");
+        features.remove(name);
+        changed = true;
+    }
+
+    let feature_names: HashSet<_> = features.iter().map(|(name, _)| name.to_string()).collect();
+    for (name, enabled) in features.iter_mut() {
+        let Some(enabled) = enabled.as_array_mut() else {
+            continue;
+        };
+        for value in enabled.iter_mut() {
+            let Some(feature) = value.as_str() else {
+                continue;
+            };
+
+            let downgraded = if let Some(dependency) = feature.strip_prefix("dep:") {
+                if feature_names.contains(dependency) {
+                    return Err(incompatible(
+                        format!("feature `{dependency}` shadowing optional dependency `{feature}`"),
+                        rust_version,
+                    ));
+                }
+                dependency.to_string()
+            } else if feature.contains("?/") {
+                // Rewriting `foo?/bar` to `foo/bar` would enable optional dependency `foo`,
+                // changing the package's dependency graph.
+                return Err(incompatible(
+                    format!("weak dependency feature `{feature}` (in feature `{name}`)"),
+                    rust_version,
+                ));
+            } else {
+                continue;
+            };
+
+            debug!("Replacing '{feature}' with '{downgraded}' in feature '{name}'");
+            let decor = value.decor().clone();
+            *value = downgraded.into();
+            *value.decor_mut() = decor;
+            changed = true;
+        }
+    }
+
+    Ok(changed)
+}
+
+fn downgrade_resolver(manifest: &mut Table) -> bool {
+    let mut changed = false;
+
+    for section in [PACKAGE_SECTION_NAME, WORKSPACE_SECTION_NAME] {
+        if let Some(resolver) = manifest
+            .get_mut(section)
+            .and_then(|section| section.get_mut(RESOLVER_FIELD))
+            .and_then(Item::as_value_mut)
+            .filter(|resolver| resolver.as_str() == Some("3"))
+        {
+            debug!("Replacing '{section}.{RESOLVER_FIELD}' \"3\" with \"2\"");
+
+            let decor = resolver.decor().clone();
+            *resolver = "2".into();
+            *resolver.decor_mut() = decor;
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+fn check_edition(
+    manifest: &Table,
+    root_workspace: Option<&RootWorkspace>,
+    rust_version: &Version,
+) -> Result<()> {
+    let edition = manifest
+        .get(PACKAGE_SECTION_NAME)
+        .and_then(|package| package.get(EDITION_FIELD))
+        .and_then(|edition| match edition {
+            edition if is_workspace_inherited(edition) => root_workspace
+                .and_then(|ws| ws.workspace.get(PACKAGE_SECTION_NAME))
+                .and_then(|package| package.get(EDITION_FIELD)),
+            edition => Some(edition),
+        })
+        .and_then(Item::as_str);
+
+    match edition.and_then(|edition| {
+        EDITION_RUST_VERSIONS
+            .iter()
+            .find(|(known_edition, _)| *known_edition == edition)
+    }) {
+        Some((edition, required)) if rust_version < required => {
+            Err(incompatible(format!("edition {edition}"), rust_version))
+        },
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod downgrade_manifest {
+        use assert_matches::assert_matches;
+        use indoc::indoc;
+        use toml_edit::DocumentMut;
+
+        use super::*;
+
+        const ROOT_MANIFEST: &str = indoc! {r#"
+            [workspace]
+            members = ["member"]
+            resolver = "3"
+
+            [workspace.package]
+            version = "1.2.3"
+            edition = "2021" # edition
+
+            [workspace.dependencies]
+            either = "1.10.0"
+            mockall = "0.12.1"
+            serde = { version = "1.0.197", features = ["derive"], default-features = false }
+
+            [workspace.lints.rust]
+            unsafe_code = "deny"
+        "#};
+
+        fn perform_test(manifest_text: &str, target_rust_version: Version) -> Result<String> {
+            let root_manifest = ROOT_MANIFEST.parse::<DocumentMut>().unwrap();
+            let root_workspace =
+                RootWorkspace::from_manifest(Utf8Path::new("/ws/Cargo.toml"), &root_manifest);
+
+            let mut manifest = manifest_text.parse::<DocumentMut>().unwrap();
+            downgrade_manifest(
+                &mut manifest,
+                Utf8Path::new("/ws/member"),
+                root_workspace.as_ref(),
+                &target_rust_version,
+            )
+            .map(|_| manifest.to_string())
+        }
+
+        #[test_log::test]
+        fn workspace_inheritance() {
+            let manifest_text = indoc! {r#"
+                [package]
+                name = "member"
+                version.workspace = true
+                edition = { workspace = true }
+
+                [dependencies]
+                either = { workspace = true, optional = true }
+                mockall.workspace = true
+                serde = { workspace = true, features = ["rc", "derive"] } # serialization
+
+                [target.'cfg(unix)'.dev-dependencies.either]
+                workspace = true
+            "#};
+
+            let expected = indoc! {r#"
+                [package]
+                name = "member"
+                version = "1.2.3"
+                edition = "2021"
+
+                [dependencies]
+                either = { version = "1.10.0", optional = true }
+                mockall = { version = "0.12.1" }
+                serde = { version = "1.0.197", features = ["derive", "rc"], default-features = false } # serialization
+
+                [target.'cfg(unix)'.dev-dependencies.either]
+                version = "1.10.0"
+            "#};
+            assert_eq!(expected, perform_test(manifest_text, Version::new(1, 63, 0)).unwrap());
+        }
+
+        #[test_log::test]
+        fn missing_workspace_value() {
+            let manifest_text = "[dependencies]\nfoo.workspace = true\n";
+
+            assert_matches!(
+                perform_test(manifest_text, Version::new(1, 63, 0)),
+                Err(Error::MissingInheritedValue(what)) => {
+                    assert_eq!("dependency `foo`", what);
+                }
+            );
+        }
+
+        #[test_log::test]
+        fn lints() {
+            let manifest_text = indoc! {r#"
+                [package]
+                name = "member"
+
+                [lints]
+                workspace = true
+            "#};
+
+            assert_eq!(
+                "[package]\nname = \"member\"\n",
+                perform_test(manifest_text, Version::new(1, 73, 0)).unwrap()
+            );
+            assert_eq!(manifest_text, perform_test(manifest_text, Version::new(1, 74, 0)).unwrap());
+        }
+
+        #[test_log::test]
+        fn features() {
+            let manifest_text = indoc! {r#"
+                [features]
+                default = ["std"]
+                std = ["dep:either", "serde/std"]
+                serde = ["dep:serde"]
+            "#};
+
+            let expected = indoc! {r#"
+                [features]
+                default = ["std"]
+                std = ["either", "serde/std"]
+            "#};
+            assert_eq!(expected, perform_test(manifest_text, Version::new(1, 59, 0)).unwrap());
+        }
+
+        #[test_log::test]
+        fn weak_dependency_feature() {
+            let manifest_text = indoc! {r#"
+                [features]
+                std = ["serde?/std"]
+            "#};
+
+            assert_matches!(
+                perform_test(manifest_text, Version::new(1, 59, 0)),
+                Err(Error::IncompatibleManifest { construct, .. }) => {
+                    assert_eq!("weak dependency feature `serde?/std` (in feature `std`)", construct);
+                }
+            );
+            assert_eq!(manifest_text, perform_test(manifest_text, Version::new(1, 60, 0)).unwrap());
+        }
+
+        #[test_log::test]
+        fn shadowed_optional_dependency() {
+            let manifest_text = indoc! {r#"
+                [features]
+                serde = ["dep:serde", "either/serde"]
+            "#};
+
+            assert_matches!(
+                perform_test(manifest_text, Version::new(1, 59, 0)),
+                Err(Error::IncompatibleManifest { .. })
+            );
+        }
+
+        #[test_log::test]
+        fn resolver() {
+            let manifest_text = "[workspace]\nresolver = \"3\" # MSRV-aware\n";
+
+            assert_eq!(
+                "[workspace]\nresolver = \"2\" # MSRV-aware\n",
+                perform_test(manifest_text, Version::new(1, 83, 0)).unwrap()
+            );
+        }
+
+        #[test_log::test]
+        fn unsupported_edition() {
+            let manifest_text = "[package]\nname = \"member\"\nedition = \"2024\"\n";
+
+            assert_matches!(
+                perform_test(manifest_text, Version::new(1, 84, 0)),
+                Err(Error::IncompatibleManifest { construct, rust_version }) => {
+                    assert_eq!("edition 2024", construct);
+                    assert_eq!(Version::new(1, 84, 0), rust_version);
+                }
+            );
+        }
+
+        #[test_log::test]
+        fn unsupported_inherited_edition() {
+            let manifest_text = "[package]\nname = \"member\"\nedition.workspace = true\n";
+
+            assert_matches!(perform_test(manifest_text, Version::new(1, 70, 0)), Ok(_));
+            assert_matches!(
+                perform_test(manifest_text, Version::new(1, 55, 0)),
+                Err(Error::IncompatibleManifest { .. })
+            );
+        }
+    }
+}
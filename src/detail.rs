@@ -1,84 +1,156 @@
+mod snippet;
+mod table;
 mod toml;
 
 use log::{info, trace};
-use toml_edit::{table, Item, Table};
+use toml_edit::{value, Formatted, Item, Key, Table, TableLike, Value};
 
+pub use crate::detail::snippet::{line_column, source_snippet};
+pub use crate::detail::table::{markdown_table, write_table};
 use crate::detail::toml::merge_toml;
+pub use crate::detail::toml::unformatted;
+use crate::strip_keys::MSRV_PREP_SECTION_NAME;
 
 pub const PACKAGE_SECTION_NAME: &str = "package";
+pub const WORKSPACE_SECTION_NAME: &str = "workspace";
 pub const DEPENDENCIES_SECTION_NAME: &str = "dependencies";
+pub const DEV_DEPENDENCIES_SECTION_NAME: &str = "dev-dependencies";
 pub const BUILD_DEPENDENCIES_SECTION_NAME: &str = "build-dependencies";
 pub const TARGET_SECTION_NAME: &str = "target";
+pub const FEATURES_SECTION_NAME: &str = "features";
+pub const LINTS_SECTION_NAME: &str = "lints";
 
-pub fn merge_msrv_dependencies(manifest: &mut Table, msrv_dependencies: &Table) -> bool {
+/// Fields of a `package` section after which new fields are inserted, in order of preference.
+const PACKAGE_FIELD_ANCHORS: &[&str] = &["edition", "version", "name"];
+
+pub fn is_workspace_inherited(item: &Item) -> bool {
+    item.get(WORKSPACE_SECTION_NAME)
+        .and_then(Item::as_bool)
+        .unwrap_or(false)
+}
+
+pub fn set_package_field(package: &mut Table, key: &str, new_value: &str) -> bool {
+    match package.get_mut(key) {
+        Some(Item::Value(Value::String(existing))) if existing.value() == new_value => false,
+        Some(Item::Value(existing)) => {
+            let decor = existing.decor().clone();
+            *existing = Value::String(Formatted::new(new_value.into()));
+            *existing.decor_mut() = decor;
+            true
+        },
+        _ => {
+            // Keep the order of existing fields by moving those after the anchor to the end.
+            let anchor_index = PACKAGE_FIELD_ANCHORS
+                .iter()
+                .find_map(|anchor| package.iter().position(|(k, _)| k == *anchor));
+            let moved_keys: Vec<_> = match anchor_index {
+                Some(index) => package
+                    .iter()
+                    .skip(index + 1)
+                    .map(|(k, _)| k.to_string())
+                    .filter(|k| k != key)
+                    .collect(),
+                None => Vec::new(),
+            };
+            let moved: Vec<_> = moved_keys
+                .iter()
+                .filter_map(|k| package.remove_entry(k))
+                .collect();
+
+            package.insert(key, value(new_value));
+            for (moved_key, moved_item) in moved {
+                package.insert_formatted(&moved_key, moved_item);
+            }
+            true
+        },
+    }
+}
+
+/// Merges the dependencies sections of a pinned MSRV dependencies file in a manifest.
+///
+/// Returns the number of entries merged per section, in order. Sections are returned as dotted
+/// keys (e.g. `target."cfg(unix)".dependencies`); other sections (e.g. `lock`) are not merged.
+pub fn merge_msrv_dependencies(
+    manifest: &mut Table,
+    msrv_dependencies: &Table,
+) -> Vec<(String, usize)> {
     trace!("Entering `merge_msrv_dependencies`");
 
-    let mut changed = merge_dependencies_sections(manifest, msrv_dependencies, None);
+    let mut merged = Vec::new();
+    merge_dependencies_sections(manifest, msrv_dependencies, "", &mut merged);
 
     if let Some(Item::Table(msrv_target_table)) = msrv_dependencies.get(TARGET_SECTION_NAME) {
         info!("MSRV dependencies found in '{TARGET_SECTION_NAME}'; merging");
 
-        changed = merge_table(manifest, TARGET_SECTION_NAME, msrv_target_table, |dest, src| {
-            let mut changed = false;
-
-            for (msrv_key, msrv_value) in src.into_iter() {
-                if let Item::Table(msrv_table) = msrv_value {
-                    changed = merge_table(dest, msrv_key, msrv_table, |dest, src| {
-                        info!(
-                            "MSRV dependencies found in '{TARGET_SECTION_NAME}.{msrv_key}'; merging"
-                        );
-
-                        merge_dependencies_sections(
-                            dest,
-                            src,
-                            Some(format!("{TARGET_SECTION_NAME}.{msrv_key}.")),
-                        )
-                    }) || changed;
-                }
+        let target_table = table_entry(manifest, TARGET_SECTION_NAME);
+        for (msrv_key, msrv_value) in msrv_target_table {
+            if let Item::Table(msrv_table) = msrv_value {
+                info!("MSRV dependencies found in '{TARGET_SECTION_NAME}.{msrv_key}'; merging");
+
+                merge_dependencies_sections(
+                    table_entry(target_table, msrv_key),
+                    msrv_table,
+                    &format!("{TARGET_SECTION_NAME}.{}.", Key::new(msrv_key)),
+                    &mut merged,
+                );
             }
-
-            changed
-        }) || changed;
+        }
     }
 
-    trace!("Exiting `merge_msrv_dependencies` (changed: {changed})");
-    changed
+    trace!("Exiting `merge_msrv_dependencies` (merged: {merged:?})");
+    merged
 }
 
-fn merge_table<F>(destination: &mut Table, key: &str, source: &Table, merge_fn: F) -> bool
-where
-    F: FnOnce(&mut Table, &Table) -> bool,
-{
-    match destination.entry(key).or_insert_with(table) {
-        Item::Table(dest_table) => merge_fn(dest_table, source),
-        dest_item => {
-            *dest_item = Item::Table(source.clone());
-            true
-        },
+/// Returns the table stored at the given key, inserting (or replacing a non-table value with)
+/// an empty implicit table if needed.
+fn table_entry<'a>(destination: &'a mut Table, key: &str) -> &'a mut Table {
+    let item = destination.entry(key).or_insert(Item::None);
+    if !item.is_table() {
+        let mut new_table = Table::new();
+        new_table.set_implicit(true);
+        *item = Item::Table(new_table);
     }
+
+    item.as_table_mut().expect("item should be a table")
 }
 
 fn merge_dependencies_sections(
     manifest: &mut Table,
     msrv_dependencies: &Table,
-    key_prefix: Option<String>,
-) -> bool {
-    trace!("Entering `merge_dependencies_section` (key_prefix: '{key_prefix:?}'");
-
-    let mut changed = false;
-    let key_prefix = key_prefix.as_deref().unwrap_or("");
-
-    for name in [DEPENDENCIES_SECTION_NAME, BUILD_DEPENDENCIES_SECTION_NAME] {
+    key_prefix: &str,
+    merged: &mut Vec<(String, usize)>,
+) {
+    trace!("Entering `merge_dependencies_sections` (key_prefix: '{key_prefix}')");
+
+    for name in
+        [DEPENDENCIES_SECTION_NAME, DEV_DEPENDENCIES_SECTION_NAME, BUILD_DEPENDENCIES_SECTION_NAME]
+    {
         if let Some(src_section) = msrv_dependencies.get(name) {
             info!("MSRV dependencies found in section '{key_prefix}{name}'; merging");
 
-            merge_toml(manifest.entry(name), src_section);
-            changed = true;
+            let mut src_section = src_section.clone();
+            strip_pin_metadata(&mut src_section);
+            merge_toml(manifest.entry(name), &src_section);
+            merged.push((
+                format!("{key_prefix}{name}"),
+                src_section.as_table_like().map_or(0, TableLike::len),
+            ));
         }
     }
 
-    trace!("Exiting `merge_dependencies_section` (changed: {changed})");
-    changed
+    trace!("Exiting `merge_dependencies_sections`");
+}
+
+/// Removes the metadata of pins (stored in their `msrv-prep` field) from a dependencies
+/// section, since Cargo doesn't know about it.
+fn strip_pin_metadata(section: &mut Item) {
+    if let Some(section) = section.as_table_like_mut() {
+        for (_, pin) in section.iter_mut() {
+            if let Some(pin) = pin.as_table_like_mut() {
+                pin.remove(MSRV_PREP_SECTION_NAME);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -114,7 +186,10 @@ mod tests {
             "#};
             let msrv_dependencies = Document::parse(msrv_dependencies).unwrap();
 
-            assert!(merge_msrv_dependencies(&mut manifest, &msrv_dependencies));
+            assert_eq!(
+                vec![("dependencies".to_string(), 2)],
+                merge_msrv_dependencies(&mut manifest, &msrv_dependencies)
+            );
 
             let expected = indoc! {r#"
                 [dependencies]
@@ -152,7 +227,10 @@ mod tests {
             "#};
             let msrv_dependencies = Document::parse(msrv_dependencies).unwrap();
 
-            assert!(merge_msrv_dependencies(&mut manifest, &msrv_dependencies));
+            assert_eq!(
+                vec![("build-dependencies".to_string(), 1)],
+                merge_msrv_dependencies(&mut manifest, &msrv_dependencies)
+            );
 
             let expected = indoc! {r#"
                 [dependencies]
@@ -198,7 +276,13 @@ mod tests {
             "#};
             let msrv_dependencies = Document::parse(msrv_dependencies).unwrap();
 
-            assert!(merge_msrv_dependencies(&mut manifest, &msrv_dependencies));
+            assert_eq!(
+                vec![
+                    ("target.\"cfg(unix)\".dependencies".to_string(), 1),
+                    ("target.\"cfg(unix)\".build-dependencies".to_string(), 1),
+                ],
+                merge_msrv_dependencies(&mut manifest, &msrv_dependencies)
+            );
 
             let expected = indoc! {r#"
                 [dependencies]
@@ -236,7 +320,10 @@ mod tests {
             "#};
             let msrv_dependencies = Document::parse(msrv_dependencies).unwrap();
 
-            assert!(merge_msrv_dependencies(&mut manifest, &msrv_dependencies));
+            assert_eq!(
+                vec![("target.\"cfg(unix)\".dependencies".to_string(), 1)],
+                merge_msrv_dependencies(&mut manifest, &msrv_dependencies)
+            );
 
             let expected = indoc! {r#"
                 [target."cfg(unix)".dependencies]
@@ -244,5 +331,35 @@ mod tests {
             "#};
             assert_eq!(manifest.to_string(), expected);
         }
+
+        #[test_log::test]
+        fn test_pin_metadata_stripping() {
+            let mut manifest = indoc! {r#"
+                [dependencies]
+                memchr = { version = "2.7.0", features = ["std"] }
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            let msrv_dependencies = indoc! {r#"
+                [dependencies]
+                memchr = { version = "=2.5.0", msrv-prep.reason = "2.6 needs 1.61", msrv-prep.until-rust-version = "1.61" }
+
+                [build-dependencies.cc]
+                version = "=1.0.83"
+                msrv-prep = { reason = "1.0.84 needs 1.63" }
+            "#};
+            let msrv_dependencies = Document::parse(msrv_dependencies).unwrap();
+
+            assert_eq!(
+                vec![("dependencies".to_string(), 1), ("build-dependencies".to_string(), 1)],
+                merge_msrv_dependencies(&mut manifest, &msrv_dependencies)
+            );
+
+            let manifest = manifest.to_string();
+            assert!(!manifest.contains("msrv-prep"), "{manifest}");
+            assert!(manifest.contains(r#"memchr = { version = "=2.5.0" }"#), "{manifest}");
+            assert!(manifest.contains(r#"version = "=1.0.83""#), "{manifest}");
+        }
     }
 }
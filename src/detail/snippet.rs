@@ -0,0 +1,96 @@
+use std::fmt::Write;
+use std::ops::Range;
+
+use cargo_metadata::camino::Utf8Path;
+
+/// Returns the (1-based) line and column of a byte offset in a text.
+///
+/// Offsets past the end of the text (or not on a character boundary) are clamped.
+pub fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let offset = floor_char_boundary(text, offset);
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+/// Returns a snippet of a source file pointing to a span, e.g.:
+///
+/// ```text
+///  --> msrv-pins.toml:9:10
+///   |
+/// 9 | foo = 1.0.0
+///   |          ^^
+/// ```
+///
+/// The span is underlined with carets (at least one, even for an empty span). The returned
+/// snippet does not end with a newline.
+pub fn source_snippet(path: &Utf8Path, text: &str, span: Range<usize>) -> String {
+    let start = floor_char_boundary(text, span.start);
+    let (line, column) = line_column(text, start);
+
+    let line_start = text[..start].rfind('\n').map_or(0, |index| index + 1);
+    let line_end = text[start..]
+        .find('\n')
+        .map_or(text.len(), |index| start + index);
+    let content = text[line_start..line_end].trim_end_matches('\r');
+
+    let end = floor_char_boundary(text, span.end.clamp(start, line_end));
+    let highlight_len = text[start..end].chars().count().max(1);
+
+    let gutter = line.to_string().len();
+    let mut snippet = String::new();
+    // Note: writing to a `String` cannot fail.
+    let _ = writeln!(snippet, "{:gutter$}--> {path}:{line}:{column}", "");
+    let _ = writeln!(snippet, "{:gutter$} |", "");
+    let _ = writeln!(snippet, "{line} | {content}");
+    let _ = write!(
+        snippet,
+        "{:gutter$} | {:offset$}{}",
+        "",
+        "",
+        "^".repeat(highlight_len),
+        offset = column - 1
+    );
+
+    snippet
+}
+
+fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    (0..=offset.min(text.len()))
+        .rev()
+        .find(|&index| text.is_char_boundary(index))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "[dependencies]\nserde = \"1.0.197\"\nfoo = 1.0.0\n";
+
+    #[test]
+    fn test_line_column() {
+        assert_eq!((1, 1), line_column(TEXT, 0));
+        assert_eq!((2, 9), line_column(TEXT, 23));
+        assert_eq!((3, 10), line_column(TEXT, 42));
+        assert_eq!((4, 1), line_column(TEXT, 1000));
+    }
+
+    #[test]
+    fn test_source_snippet() {
+        assert_eq!(
+            " --> msrv-pins.toml:3:10\n  |\n3 | foo = 1.0.0\n  |          ^^",
+            source_snippet("msrv-pins.toml".into(), TEXT, 42..44)
+        );
+        assert_eq!(
+            " --> msrv-pins.toml:3:7\n  |\n3 | foo = 1.0.0\n  |       ^^^^^",
+            source_snippet("msrv-pins.toml".into(), TEXT, 39..1000)
+        );
+        assert_eq!(
+            " --> msrv-pins.toml:4:1\n  |\n4 | \n  | ^",
+            source_snippet("msrv-pins.toml".into(), TEXT, 1000..1000)
+        );
+    }
+}
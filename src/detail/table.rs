@@ -0,0 +1,89 @@
+use std::fmt::{Formatter, Write};
+
+/// Writes a table with left-aligned columns separated by two spaces.
+///
+/// Rows are expected to have as many cells as the header. Trailing whitespace is not written.
+pub fn write_table(
+    f: &mut Formatter<'_>,
+    header: &[&str],
+    rows: &[Vec<String>],
+) -> std::fmt::Result {
+    let mut widths: Vec<_> = header.iter().map(|cell| cell.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header: Vec<_> = header.iter().map(|cell| cell.to_string()).collect();
+    for row in [&header].into_iter().chain(rows) {
+        let mut line = String::new();
+        for (cell, width) in row.iter().zip(&widths) {
+            write!(line, "{cell:width$}  ")?;
+        }
+        writeln!(f, "{}", line.trim_end())?;
+    }
+
+    Ok(())
+}
+
+/// Returns a Markdown table (e.g. for GitHub job summaries).
+///
+/// Pipes in cells are escaped.
+pub fn markdown_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+    let escape = |cell: &str| cell.replace('|', "\\|");
+
+    let mut table = line(header.iter().map(|cell| escape(cell)).collect());
+    table.push_str(&line(header.iter().map(|_| "---".into()).collect()));
+    for row in rows {
+        table.push_str(&line(row.iter().map(|cell| escape(cell)).collect()));
+    }
+
+    table
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::fmt::Display;
+
+    use indoc::indoc;
+
+    use super::*;
+
+    struct Table(Vec<Vec<String>>);
+
+    impl Display for Table {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write_table(f, &["Package", "Status"], &self.0)
+        }
+    }
+
+    #[test]
+    fn test_write_table() {
+        let table = Table(vec![
+            vec!["foo".into(), "restored".into()],
+            vec!["longer-name".into(), "-".into()],
+        ]);
+
+        let expected = indoc! {"
+            Package      Status
+            foo          restored
+            longer-name  -
+        "};
+        assert_eq!(expected, table.to_string());
+    }
+
+    #[test]
+    fn test_markdown_table() {
+        let rows = vec![vec!["foo".into(), "a|b".into()]];
+
+        let expected = indoc! {r"
+            | Package | Status |
+            | --- | --- |
+            | foo | a\|b |
+        "};
+        assert_eq!(expected, markdown_table(&["Package", "Status"], &rows));
+    }
+}
@@ -1,4 +1,4 @@
-use toml_edit::{ArrayOfTables, Entry, Item, Table};
+use toml_edit::{ArrayOfTables, Entry, Item, Table, Value};
 
 /// Merges a source TOML [`Item`] in a destination TOML table (via an [`Entry`]).
 ///
@@ -39,6 +39,37 @@ fn merge_toml_array(destination: &mut ArrayOfTables, source: &ArrayOfTables) {
     }
 }
 
+/// Returns the representation of a value without its formatting (e.g. whitespace, comments or
+/// quoting style), so that values can be compared.
+pub fn unformatted(value: &Value) -> String {
+    fn clear_formatting(value: &mut Value) {
+        match value {
+            Value::String(formatted) => formatted.fmt(),
+            Value::Integer(formatted) => formatted.fmt(),
+            Value::Float(formatted) => formatted.fmt(),
+            Value::Boolean(formatted) => formatted.fmt(),
+            Value::Datetime(formatted) => formatted.fmt(),
+            Value::Array(array) => {
+                array.iter_mut().for_each(clear_formatting);
+                array.set_trailing_comma(false);
+                array.set_trailing("");
+                array.fmt();
+            },
+            Value::InlineTable(inline_table) => {
+                inline_table
+                    .iter_mut()
+                    .for_each(|(_, value)| clear_formatting(value));
+                inline_table.fmt();
+            },
+        }
+        value.decor_mut().clear();
+    }
+
+    let mut value = value.clone();
+    clear_formatting(&mut value);
+    value.to_string()
+}
+
 #[cfg(test)]
 #[cfg_attr(coverage_nightly, coverage(off))]
 mod tests {
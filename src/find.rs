@@ -0,0 +1,346 @@
+//! Helpers to determine the MSRV of packages by bisecting installed toolchains.
+//!
+//! Manifests are prepared once (see [`prep_for_msrv`](crate::prep::prep_for_msrv)), then a check
+//! command is run using a binary search over candidate toolchains to find the lowest toolchain
+//! for which the command succeeds. Manifests are then restored (see [`unprep_from_msrv`]).
+
+use std::fmt::{Display, Formatter};
+use std::process::Command;
+
+use cargo_metadata::semver::Version;
+use log::{debug, error, info, trace};
+
+use crate::backup::BackupBackend;
+use crate::metadata::Metadata;
+use crate::prep::PrepOptions;
+use crate::result::IoErrorContext;
+use crate::unprep::{unprep_from_msrv, UnprepOptions};
+use crate::verify::{prep_for_check, CheckCommand, PACKAGE_PLACEHOLDER};
+use crate::{Error, Result};
+
+/// A Rust toolchain that can be used to run a check command (e.g. via `cargo +<name>`).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Toolchain {
+    /// Version of the toolchain.
+    pub version: Version,
+
+    /// Name of the toolchain (e.g. `1.70-x86_64-unknown-linux-gnu`).
+    pub name: String,
+}
+
+impl From<Version> for Toolchain {
+    fn from(version: Version) -> Self {
+        Self { name: version.to_string(), version }
+    }
+}
+
+impl Display for Toolchain {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Parses a toolchain version (e.g. `1.70.0` or `1.70`).
+///
+/// A missing patch version is assumed to be `0`.
+pub fn parse_toolchain_version(toolchain: &str) -> Option<Version> {
+    let parts: Vec<_> = toolchain.split('.').collect();
+    if !(2..=3).contains(&parts.len())
+        || parts
+            .iter()
+            .any(|part| part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return None;
+    }
+
+    let part = |i: usize| parts.get(i).map_or(Ok(0), |part| part.parse());
+    Some(Version::new(part(0).ok()?, part(1).ok()?, part(2).ok()?))
+}
+
+/// Parses the output of `rustup toolchain list`, returning installed stable toolchains
+/// (sorted by version, without duplicate versions).
+///
+/// Each toolchain keeps its name as listed (e.g. `1.70-x86_64-unknown-linux-gnu`), so that it
+/// can be used as is to select it. Toolchains that are not versioned (like `stable` or
+/// `nightly`) are ignored.
+pub fn parse_toolchain_list(output: &str) -> Vec<Toolchain> {
+    let mut toolchains: Vec<_> = output
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter_map(|name| {
+            let version = name.split('-').next().unwrap_or(name);
+            Some(Toolchain { version: parse_toolchain_version(version)?, name: name.into() })
+        })
+        .collect();
+
+    toolchains.sort();
+    toolchains.dedup_by(|a, b| a.version == b.version);
+    toolchains
+}
+
+/// Returns the stable toolchains installed via `rustup`.
+pub fn installed_toolchains() -> Result<Vec<Toolchain>> {
+    trace!("Entering `installed_toolchains`");
+
+    let output = Command::new("rustup")
+        .args(["toolchain", "list"])
+        .env_remove("RUSTUP_TOOLCHAIN")
+        .output()
+        .with_io_context(|| "listing installed toolchains via `rustup toolchain list`")?;
+    if !output.status.success() {
+        return Err(Error::ToolchainList(String::from_utf8_lossy(&output.stderr).trim().into()));
+    }
+
+    let toolchains = parse_toolchain_list(&String::from_utf8_lossy(&output.stdout));
+    debug!("Installed toolchains: {toolchains:?}");
+
+    trace!("Exiting `installed_toolchains`");
+    Ok(toolchains)
+}
+
+/// Finds the lowest toolchain for which the check command succeeds.
+///
+/// `toolchains` must be sorted; it is assumed that if the check command succeeds for a
+/// toolchain, it will also succeed for all newer toolchains. For each toolchain tested, a
+/// check command is built from `check_command_template` (replacing
+/// [`MSRV_PLACEHOLDER`](crate::verify::MSRV_PLACEHOLDER) with the toolchain's name) and
+/// run via `runner`. Manifests are always restored afterwards, even if preparing them or running
+/// a command fails.
+///
+/// Returns `Ok(None)` if the check command did not succeed for any toolchain.
+pub fn find_msrv<R>(
+    metadata: &Metadata,
+    backup_backend: &dyn BackupBackend,
+    prep_options: &PrepOptions,
+    unprep_options: &UnprepOptions,
+    check_command_template: &str,
+    toolchains: &[Toolchain],
+    mut runner: R,
+) -> Result<Option<Version>>
+where
+    R: FnMut(&CheckCommand) -> Result<bool>,
+{
+    trace!("Entering `find_msrv` (check_command_template: '{check_command_template}')");
+
+    if check_command_template.contains(PACKAGE_PLACEHOLDER) {
+        return Err(Error::InvalidCheckCommand(check_command_template.into()));
+    }
+    let commands = toolchains
+        .iter()
+        .map(|toolchain| CheckCommand::from_template(check_command_template, &toolchain.name, ""))
+        .collect::<Result<Vec<_>>>()?;
+
+    prep_for_check(metadata, backup_backend, prep_options, unprep_options)?;
+
+    let mut lowest_passing = None;
+    let mut run_result = Ok(());
+    let (mut low, mut high) = (0, commands.len());
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let command = &commands[mid];
+
+        info!("Checking toolchain {} ('{command}')", toolchains[mid]);
+        match runner(command) {
+            Ok(true) => {
+                info!("Toolchain {}: passed", toolchains[mid]);
+                lowest_passing = Some(toolchains[mid].version.clone());
+                high = mid;
+            },
+            Ok(false) => {
+                info!("Toolchain {}: failed", toolchains[mid]);
+                low = mid + 1;
+            },
+            Err(err) => {
+                error!("Failed to run check command '{command}': {err}");
+                run_result = Err(err);
+                break;
+            },
+        }
+    }
+
+    let unprep_result = unprep_from_msrv(metadata, backup_backend, unprep_options);
+    run_result?;
+    unprep_result?;
+
+    trace!("Exiting `find_msrv` (lowest_passing: {lowest_passing:?})");
+    Ok(lowest_passing)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod parse_toolchain_version {
+        use super::*;
+
+        #[test]
+        fn valid() {
+            assert_eq!(Some(Version::new(1, 70, 0)), parse_toolchain_version("1.70.0"));
+            assert_eq!(Some(Version::new(1, 70, 0)), parse_toolchain_version("1.70"));
+        }
+
+        #[test]
+        fn invalid() {
+            assert_eq!(None, parse_toolchain_version("stable"));
+            assert_eq!(None, parse_toolchain_version("1"));
+            assert_eq!(None, parse_toolchain_version("1.70.0.1"));
+            assert_eq!(None, parse_toolchain_version("1.70.x"));
+        }
+    }
+
+    mod parse_toolchain_list {
+        use indoc::indoc;
+
+        use super::*;
+
+        #[test]
+        fn rustup_output() {
+            let output = indoc! {"
+                stable-x86_64-unknown-linux-gnu (default)
+                nightly-x86_64-unknown-linux-gnu
+                1.85.1-x86_64-unknown-linux-gnu
+                1.70.0-x86_64-unknown-linux-gnu (active)
+                my-custom-toolchain
+            "};
+
+            assert_eq!(
+                vec![
+                    Toolchain {
+                        version: Version::new(1, 70, 0),
+                        name: "1.70.0-x86_64-unknown-linux-gnu".into()
+                    },
+                    Toolchain {
+                        version: Version::new(1, 85, 1),
+                        name: "1.85.1-x86_64-unknown-linux-gnu".into()
+                    },
+                ],
+                parse_toolchain_list(output)
+            );
+        }
+
+        #[test]
+        fn short_name() {
+            let output = "1.70-x86_64-unknown-linux-gnu\n1.75-x86_64-unknown-linux-gnu\n";
+
+            let toolchains = parse_toolchain_list(output);
+
+            assert_eq!(
+                vec![Version::new(1, 70, 0), Version::new(1, 75, 0)],
+                toolchains
+                    .iter()
+                    .map(|toolchain| toolchain.version.clone())
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!("1.70-x86_64-unknown-linux-gnu", toolchains[0].name);
+        }
+    }
+
+    mod find_msrv {
+        use std::path::PathBuf;
+
+        use assert_fs::fixture::{PathChild, PathCopy};
+        use assert_fs::TempDir;
+        use assert_matches::assert_matches;
+
+        use super::*;
+        use crate::backup::FileBackupBackend;
+        use crate::verify::DEFAULT_CHECK_COMMAND;
+        use crate::DEFAULT_MANIFEST_BACKUP_SUFFIX;
+
+        fn perform_test<R>(
+            check_command_template: &str,
+            toolchains: &[Toolchain],
+            runner: R,
+        ) -> Result<Option<Version>>
+        where
+            R: FnMut(&CheckCommand) -> Result<bool>,
+        {
+            let project_path: PathBuf = [
+                env!("CARGO_MANIFEST_DIR"),
+                "resources",
+                "tests",
+                "cargo-msrv-prep",
+                "simple_project",
+            ]
+            .iter()
+            .collect();
+            let temp = TempDir::new().unwrap();
+            temp.copy_from(project_path, &["*.rs", "*.toml", "*.lock"])
+                .unwrap();
+
+            let metadata = Metadata::for_manifest(temp.child("Cargo.toml").path()).unwrap();
+            let backup_backend = FileBackupBackend::new(DEFAULT_MANIFEST_BACKUP_SUFFIX);
+
+            // Note: files are not actually modified here (see `dry_run`), since our `fs`
+            // functions are mocked in unit tests.
+            find_msrv(
+                &metadata,
+                &backup_backend,
+                &PrepOptions { dry_run: true, ..Default::default() },
+                &UnprepOptions::default(),
+                check_command_template,
+                toolchains,
+                runner,
+            )
+        }
+
+        fn toolchains() -> Vec<Toolchain> {
+            (60..=80)
+                .map(|minor| Version::new(1, minor, 0).into())
+                .collect()
+        }
+
+        #[test_log::test]
+        fn bisects() {
+            let mut checked = Vec::new();
+            let result = perform_test(DEFAULT_CHECK_COMMAND, &toolchains(), |command| {
+                let toolchain = command.args[0].trim_start_matches('+');
+                checked.push(toolchain.to_string());
+                Ok(parse_toolchain_version(toolchain).unwrap() >= Version::new(1, 67, 0))
+            });
+
+            assert_matches!(result, Ok(Some(version)) => {
+                assert_eq!(Version::new(1, 67, 0), version);
+            });
+            assert!(checked.len() <= 5, "too many toolchains checked: {checked:?}");
+        }
+
+        #[test_log::test]
+        fn short_toolchain_names() {
+            let toolchains = parse_toolchain_list(
+                "1.65-x86_64-unknown-linux-gnu\n1.70-x86_64-unknown-linux-gnu\n",
+            );
+
+            let mut checked = Vec::new();
+            let result = perform_test(DEFAULT_CHECK_COMMAND, &toolchains, |command| {
+                checked.push(command.args[0].clone());
+                Ok(true)
+            });
+
+            assert_matches!(result, Ok(Some(version)) => {
+                assert_eq!(Version::new(1, 65, 0), version);
+            });
+            assert_eq!(
+                vec!["+1.70-x86_64-unknown-linux-gnu", "+1.65-x86_64-unknown-linux-gnu"],
+                checked
+            );
+        }
+
+        #[test_log::test]
+        fn not_found() {
+            let result = perform_test(DEFAULT_CHECK_COMMAND, &toolchains(), |_| Ok(false));
+
+            assert_matches!(result, Ok(None));
+        }
+
+        #[test_log::test]
+        fn package_placeholder() {
+            let result = perform_test("cargo +{msrv} check -p {package}", &toolchains(), |_| {
+                unreachable!("check command should not be run")
+            });
+
+            assert_matches!(result, Err(Error::InvalidCheckCommand(_)));
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Helpers to report results to GitHub Actions.
+//!
+//! Errors are reported as [workflow commands] (e.g. `::error file=msrv-pins.toml,line=3::...`),
+//! which GitHub Actions shows as annotations on the corresponding files. Summaries can also be
+//! appended to the job summary (see [`GITHUB_STEP_SUMMARY_ENV_VAR`]).
+//!
+//! [workflow commands]: https://docs.github.com/en/actions/reference/workflow-commands-for-github-actions
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, trace};
+
+use crate::detail::line_column;
+use crate::result::IoErrorContext;
+use crate::{Error, Result};
+
+/// Environment variable set by GitHub Actions to the path of the job summary file of the
+/// current step.
+pub const GITHUB_STEP_SUMMARY_ENV_VAR: &str = "GITHUB_STEP_SUMMARY";
+
+/// Returns the workflow command reporting an error as an annotation.
+///
+/// If the error refers to a file, the annotation is attached to it (relative to the current
+/// directory, if possible). For TOML parse errors, the line and column of the error are
+/// included as well.
+pub fn error_annotation(error: &Error) -> String {
+    let mut properties = Vec::new();
+    if let Some(path) = error_file(error) {
+        properties.push(format!("file={}", escape_property(relative_path(path).as_str())));
+
+        if let Error::TomlFile { text, source, .. } = error {
+            if let Some(span) = source.span() {
+                let (line, column) = line_column(text, span.start);
+                properties.push(format!("line={line}"));
+                properties.push(format!("col={column}"));
+            }
+        }
+    }
+
+    let message = match error {
+        Error::TomlFile { path, source, .. } => {
+            format!("invalid TOML file '{path}': {}", source.message())
+        },
+        error => error.to_string(),
+    };
+    let properties =
+        if properties.is_empty() { String::new() } else { format!(" {}", properties.join(",")) };
+
+    format!("::error{properties}::{}", escape_data(&message))
+}
+
+/// Appends Markdown content to the job summary of the current step, if running in GitHub
+/// Actions (see [`GITHUB_STEP_SUMMARY_ENV_VAR`]).
+///
+/// Returns `Ok(true)` if the job summary was updated.
+pub fn maybe_append_step_summary(markdown: &str) -> Result<bool> {
+    trace!("Entering `maybe_append_step_summary`");
+
+    let Some(summary_path) = env::var_os(GITHUB_STEP_SUMMARY_ENV_VAR) else {
+        debug!("'{GITHUB_STEP_SUMMARY_ENV_VAR}' not set; skipping job summary");
+        return Ok(false);
+    };
+    let summary_path = summary_path.to_string_lossy();
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(summary_path.as_ref())
+        .and_then(|mut summary| writeln!(summary, "{markdown}"))
+        .with_io_context(|| format!("appending to job summary '{summary_path}'"))?;
+
+    trace!("Exiting `maybe_append_step_summary` (appended to '{summary_path}')");
+    Ok(true)
+}
+
+fn error_file(error: &Error) -> Option<&Utf8Path> {
+    match error {
+        Error::TomlFile { path, .. }
+        | Error::InvalidPath(path)
+        | Error::BackupFileAlreadyExists(path)
+        | Error::InvalidRestoredManifest { path, .. }
+        | Error::ManifestPrepped(path) => Some(path),
+        _ => None,
+    }
+}
+
+fn relative_path(path: &Utf8Path) -> Utf8PathBuf {
+    env::current_dir()
+        .ok()
+        .and_then(|current_dir| path.as_std_path().strip_prefix(current_dir).ok())
+        .and_then(Utf8Path::from_path)
+        .unwrap_or(path)
+        .to_path_buf()
+}
+
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod error_annotation {
+        use toml_edit::DocumentMut;
+
+        use super::*;
+        use crate::result::TomlErrorContext;
+
+        #[test]
+        fn without_file() {
+            assert_eq!(
+                "::error::check command fails even with all pins applied",
+                error_annotation(&Error::PinsBaselineFailed)
+            );
+        }
+
+        #[test]
+        fn with_file() {
+            assert_eq!(
+                "::error file=/tmp/Cargo.toml.msrv-prep.bak::backup file already exists: \
+                 /tmp/Cargo.toml.msrv-prep.bak",
+                error_annotation(&Error::BackupFileAlreadyExists(
+                    "/tmp/Cargo.toml.msrv-prep.bak".into()
+                ))
+            );
+        }
+
+        #[test]
+        fn toml_file() {
+            let pins_file_text = "[dependencies]\nserde = \"1.0.197\"\nfoo = 1.0.0\n";
+            let error = pins_file_text
+                .parse::<DocumentMut>()
+                .with_toml_file("/tmp/msrv-pins.toml", pins_file_text)
+                .unwrap_err();
+
+            let annotation = error_annotation(&error);
+            assert_eq!(
+                "::error file=/tmp/msrv-pins.toml,line=3,col=10::invalid TOML file \
+                 '/tmp/msrv-pins.toml': invalid float, expected nothing",
+                annotation
+            );
+            assert!(!annotation.contains('\n'), "{annotation}");
+        }
+    }
+
+    mod escape {
+        use super::*;
+
+        #[test]
+        fn all() {
+            assert_eq!("100%25%0Adone", escape_data("100%\ndone"));
+            assert_eq!("C%3A\\a%2Cb", escape_property("C:\\a,b"));
+        }
+    }
+}
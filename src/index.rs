@@ -0,0 +1,350 @@
+//! Helpers to read a local copy of a crate registry index.
+//!
+//! Registry indexes store one file per crate, listing all published versions of the crate
+//! along with their metadata (including their `rust-version`, if any). Two kinds of local
+//! copies are supported:
+//!
+//! - Cargo's index cache (`$CARGO_HOME/registry/index/<registry>/.cache`), which is populated
+//!   for both sparse and git registries whenever Cargo resolves dependencies
+//! - A directory in the index format (e.g. a clone of the `crates.io-index` repository), where
+//!   each crate's file contains one JSON object per version
+//!
+//! No network access is performed; crates that were never resolved by Cargo on this machine
+//! will not be found in its cache.
+
+use std::env;
+use std::fs;
+
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::semver::{Version, VersionReq};
+use log::{debug, trace};
+use serde::Deserialize;
+
+use crate::find::parse_toolchain_version;
+use crate::result::IoErrorContext;
+use crate::{Error, Result};
+
+/// Name of the directory storing Cargo's index cache in a registry's index directory.
+pub const INDEX_CACHE_DIR_NAME: &str = ".cache";
+
+/// Prefixes of the index directories used by Cargo for crates.io, in order of preference
+/// (sparse protocol first, then git protocol).
+const CRATES_IO_INDEX_DIR_PREFIXES: &[&str] = &["index.crates.io-", "github.com-"];
+
+/// A version of a crate, as listed in a registry index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Name of the crate.
+    pub name: String,
+
+    /// Version of the crate.
+    pub version: Version,
+
+    /// Minimum Rust version declared by this version of the crate, if any.
+    pub rust_version: Option<Version>,
+
+    /// Whether this version has been yanked.
+    pub yanked: bool,
+}
+
+impl IndexEntry {
+    /// Returns `true` if this version of the crate can be used with the given Rust version.
+    ///
+    /// Like Cargo's MSRV-aware resolver, versions that do not declare a `rust-version` are
+    /// assumed to be compatible.
+    pub fn is_compatible_with(&self, rust_version: &Version) -> bool {
+        self.rust_version
+            .as_ref()
+            .is_none_or(|entry_rust_version| entry_rust_version <= rust_version)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RawIndexEntry {
+    name: String,
+    vers: String,
+    #[serde(default)]
+    yanked: bool,
+    #[serde(default)]
+    rust_version: Option<String>,
+}
+
+impl RawIndexEntry {
+    fn into_entry(self) -> Option<IndexEntry> {
+        Some(IndexEntry {
+            name: self.name,
+            version: self.vers.parse().ok()?,
+            rust_version: self
+                .rust_version
+                .as_deref()
+                .and_then(parse_toolchain_version),
+            yanked: self.yanked,
+        })
+    }
+}
+
+/// Local copy of a crate registry index (see [module description](self) for details).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrateIndex {
+    root: Utf8PathBuf,
+    cache: bool,
+}
+
+impl CrateIndex {
+    /// Opens a local copy of an index.
+    ///
+    /// `path` can be a registry's index directory (e.g.
+    /// `$CARGO_HOME/registry/index/index.crates.io-1949cf8c6b5b557f`), its cache directory
+    /// or a directory in the index format.
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Utf8Path>,
+    {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Err(Error::IndexNotFound(path.into()));
+        }
+
+        let index = if path.join(INDEX_CACHE_DIR_NAME).is_dir() {
+            Self { root: path.join(INDEX_CACHE_DIR_NAME), cache: true }
+        } else {
+            let cache = path.file_name() == Some(INDEX_CACHE_DIR_NAME);
+            Self { root: path.into(), cache }
+        };
+        debug!("Opened index at '{}' (cache: {})", index.root, index.cache);
+
+        Ok(index)
+    }
+
+    /// Opens Cargo's local cache of the crates.io index (in `$CARGO_HOME/registry/index`).
+    pub fn crates_io() -> Result<Self> {
+        let index_dir = cargo_home()?.join("registry").join("index");
+        let mut index_dirs: Vec<_> = index_dir
+            .read_dir_utf8()
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path().to_path_buf())
+                    .collect()
+            })
+            .unwrap_or_default();
+        index_dirs.sort();
+
+        CRATES_IO_INDEX_DIR_PREFIXES
+            .iter()
+            .find_map(|prefix| {
+                index_dirs
+                    .iter()
+                    .find(|dir| dir.file_name().is_some_and(|name| name.starts_with(prefix)))
+            })
+            .ok_or(Error::IndexNotFound(index_dir.clone()))
+            .and_then(Self::open)
+    }
+
+    /// Returns all versions of a crate listed in the index.
+    ///
+    /// Returns an empty list if the crate is not found in the index.
+    pub fn versions(&self, name: &str) -> Result<Vec<IndexEntry>> {
+        trace!("Entering `CrateIndex::versions` (name: '{name}')");
+
+        let file_path = self.root.join(index_file_path(name));
+        if !file_path.is_file() {
+            debug!("Crate '{name}' not found in index (expected at '{file_path}')");
+            return Ok(Vec::new());
+        }
+
+        let content =
+            fs::read(&file_path).with_io_context(|| format!("reading index file '{file_path}'"))?;
+        let records = if self.cache {
+            cache_records(&content).ok_or_else(|| Error::InvalidIndexFile(file_path.clone()))?
+        } else {
+            content
+                .split(|b| *b == b'\n')
+                .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+                .collect()
+        };
+
+        let entries = records
+            .into_iter()
+            .map(|record| {
+                serde_json::from_slice::<RawIndexEntry>(record)
+                    .ok()
+                    .and_then(RawIndexEntry::into_entry)
+                    .ok_or_else(|| Error::InvalidIndexFile(file_path.clone()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        trace!("Exiting `CrateIndex::versions` (entries: {})", entries.len());
+        Ok(entries)
+    }
+
+    /// Returns the highest version of a crate that can be used with the given Rust version
+    /// (see [`IndexEntry::is_compatible_with`]), optionally restricted to versions matching
+    /// a version requirement.
+    ///
+    /// Yanked versions and pre-releases are ignored.
+    pub fn highest_compatible(
+        &self,
+        name: &str,
+        rust_version: &Version,
+        requirement: Option<&VersionReq>,
+    ) -> Result<Option<IndexEntry>> {
+        let highest = self
+            .versions(name)?
+            .into_iter()
+            .filter(|entry| !entry.yanked && entry.version.pre.is_empty())
+            .filter(|entry| requirement.is_none_or(|req| req.matches(&entry.version)))
+            .filter(|entry| entry.is_compatible_with(rust_version))
+            .max_by(|a, b| a.version.cmp(&b.version));
+        debug!("Highest version of '{name}' compatible with Rust {rust_version}: {highest:?}");
+
+        Ok(highest)
+    }
+}
+
+/// Returns the path of a crate's file in an index, relative to the index's root.
+///
+/// For example, `serde` is stored in `se/rd/serde`.
+pub fn index_file_path(name: &str) -> Utf8PathBuf {
+    let name = name.to_lowercase();
+
+    match name.len() {
+        1 => ["1", &name].iter().collect(),
+        2 => ["2", &name].iter().collect(),
+        3 => ["3", &name[..1], &name].iter().collect(),
+        _ => [&name[..2], &name[2..4], &name].iter().collect(),
+    }
+}
+
+fn cargo_home() -> Result<Utf8PathBuf> {
+    let cargo_home = env::var("CARGO_HOME").ok().or_else(|| {
+        env::var("HOME")
+            .or_else(|_| env::var("USERPROFILE"))
+            .ok()
+            .map(|home| format!("{home}/.cargo"))
+    });
+
+    cargo_home
+        .map(Utf8PathBuf::from)
+        .ok_or_else(|| Error::IndexNotFound("$CARGO_HOME".into()))
+}
+
+/// Splits a file of Cargo's index cache into its JSON records.
+///
+/// Cache files start with a header (a cache version byte, the index format version as a
+/// 32-bit integer and the index revision, followed by a NUL byte), followed by pairs of
+/// NUL-terminated version strings and JSON records.
+fn cache_records(content: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut parts = content.get(5..)?.split(|b| *b == 0);
+    parts.next()?;
+
+    let parts: Vec<_> = parts.filter(|part| !part.is_empty()).collect();
+    if parts.len() % 2 != 0 {
+        return None;
+    }
+    Some(parts.chunks(2).map(|pair| pair[1]).collect())
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    fn fixture_index() -> CrateIndex {
+        let path: Utf8PathBuf = [env!("CARGO_MANIFEST_DIR"), "resources", "tests", "index"]
+            .iter()
+            .collect();
+
+        CrateIndex::open(path).unwrap()
+    }
+
+    mod index_file_path {
+        use super::*;
+
+        #[test]
+        fn all() {
+            assert_eq!("1/a", index_file_path("a"));
+            assert_eq!("2/ab", index_file_path("ab"));
+            assert_eq!("3/a/abc", index_file_path("abc"));
+            assert_eq!("se/rd/serde", index_file_path("serde"));
+            assert_eq!("to/ml/toml_edit", index_file_path("TOML_edit"));
+        }
+    }
+
+    mod crate_index {
+        use super::*;
+
+        mod versions {
+            use assert_fs::fixture::{FileWriteBin, PathChild};
+            use assert_fs::TempDir;
+
+            use super::*;
+
+            #[test]
+            fn directory() {
+                let versions = fixture_index().versions("either").unwrap();
+
+                assert_eq!(5, versions.len());
+                assert_eq!(
+                    IndexEntry {
+                        name: "either".into(),
+                        version: Version::new(1, 10, 0),
+                        rust_version: Some(Version::new(1, 63, 0)),
+                        yanked: false,
+                    },
+                    versions[1]
+                );
+                assert!(versions[0].rust_version.is_none());
+                assert!(versions[2].yanked);
+            }
+
+            #[test]
+            fn cache() {
+                let temp = TempDir::new().unwrap();
+                let mut content = b"\x03\x02\x00\x00\x00etag\x00".to_vec();
+                content.extend(b"1.0.0\x00{\"name\":\"abc\",\"vers\":\"1.0.0\"}\x00");
+                content.extend(
+                    b"1.1.0\x00{\"name\":\"abc\",\"vers\":\"1.1.0\",\"rust_version\":\"1.70\"}\x00",
+                );
+                temp.child(".cache/3/a/abc").write_binary(&content).unwrap();
+
+                let index = CrateIndex::open(Utf8Path::from_path(temp.path()).unwrap()).unwrap();
+                let versions = index.versions("abc").unwrap();
+
+                assert_eq!(2, versions.len());
+                assert_eq!(Some(Version::new(1, 70, 0)), versions[1].rust_version);
+            }
+
+            #[test]
+            fn not_found() {
+                assert!(fixture_index().versions("not-a-crate").unwrap().is_empty());
+            }
+        }
+
+        mod highest_compatible {
+            use super::*;
+
+            #[test]
+            fn all() {
+                let index = fixture_index();
+                let highest = |rust_version: &str, requirement: Option<&str>| {
+                    let requirement = requirement.map(|req| VersionReq::parse(req).unwrap());
+                    index
+                        .highest_compatible(
+                            "either",
+                            &parse_toolchain_version(rust_version).unwrap(),
+                            requirement.as_ref(),
+                        )
+                        .unwrap()
+                        .map(|entry| entry.version.to_string())
+                };
+
+                assert_eq!(Some("1.12.0".into()), highest("1.70", None));
+                assert_eq!(Some("1.13.0".into()), highest("1.74", None));
+                assert_eq!(Some("1.10.0".into()), highest("1.64", None));
+                assert_eq!(Some("1.9.0".into()), highest("1.50", None));
+                assert_eq!(Some("1.9.0".into()), highest("1.70", Some("~1.9")));
+            }
+        }
+    }
+}
@@ -0,0 +1,425 @@
+//! Helpers to inline values inherited from the workspace into manifests.
+//!
+//! Versions of Cargo before 1.64 do not support workspace inheritance (e.g.
+//! `version.workspace = true` or `foo = { workspace = true }`). Inherited values can be
+//! replaced with the concrete values found in the root manifest's `workspace.package` and
+//! `workspace.dependencies` sections, following Cargo's rules:
+//!
+//! - `features` specified in the member manifest are added to those of the workspace dependency
+//! - `optional` is taken from the member manifest
+//! - `default-features = true` in the member manifest overrides `default-features = false`
+//!   in the workspace dependency (`default-features = false` is ignored otherwise)
+//!
+//! Paths (e.g. `path` dependencies or `package.readme`) are relative to the root manifest,
+//! so they are rebased to be relative to the member manifest.
+
+use std::fs;
+
+use cargo_metadata::camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use log::{debug, trace, warn};
+use toml_edit::{DocumentMut, InlineTable, Item, Table, TableLike, Value};
+
+use crate::detail::{
+    is_workspace_inherited, BUILD_DEPENDENCIES_SECTION_NAME, DEPENDENCIES_SECTION_NAME,
+    DEV_DEPENDENCIES_SECTION_NAME, PACKAGE_SECTION_NAME, TARGET_SECTION_NAME,
+    WORKSPACE_SECTION_NAME,
+};
+use crate::result::{IoErrorContext, TomlErrorContext};
+use crate::{Error, Result};
+
+/// Inherited `package` fields storing paths, which are relative to the root manifest.
+const PACKAGE_PATH_FIELDS: &[&str] = &["readme", "license-file"];
+
+const VERSION_FIELD: &str = "version";
+const PATH_FIELD: &str = "path";
+const FEATURES_FIELD: &str = "features";
+const OPTIONAL_FIELD: &str = "optional";
+const DEFAULT_FEATURES_FIELD: &str = "default-features";
+
+/// `workspace` section of a workspace's root manifest.
+#[derive(Debug, Clone)]
+pub struct RootWorkspace {
+    /// Directory containing the root manifest.
+    pub root: Utf8PathBuf,
+
+    /// Content of the root manifest's `workspace` section.
+    pub workspace: Table,
+}
+
+impl RootWorkspace {
+    /// Returns the `workspace` section of the given root manifest, if any.
+    pub fn from_manifest(root_manifest_path: &Utf8Path, manifest: &Table) -> Option<Self> {
+        let workspace = manifest
+            .get(WORKSPACE_SECTION_NAME)
+            .and_then(Item::as_table)?
+            .clone();
+        let root = root_manifest_path
+            .parent()
+            .unwrap_or(Utf8Path::new(""))
+            .into();
+
+        Some(Self { root, workspace })
+    }
+
+    /// Reads the `workspace` section of the given root manifest, if it exists.
+    pub fn read(root_manifest_path: &Utf8Path) -> Result<Option<Self>> {
+        if !root_manifest_path.is_file() {
+            return Ok(None);
+        }
+
+        let manifest_text = fs::read_to_string(root_manifest_path)
+            .with_io_context(|| format!("reading root manifest '{root_manifest_path}'"))?;
+        let manifest = manifest_text
+            .parse::<DocumentMut>()
+            .with_toml_file(root_manifest_path, &manifest_text)?;
+
+        Ok(Self::from_manifest(root_manifest_path, &manifest))
+    }
+}
+
+/// Replaces values inherited from the workspace in a manifest with concrete values
+/// (see [module documentation](self) for details).
+///
+/// `manifest_dir` is the directory containing the manifest, used to rebase paths.
+///
+/// Returns `Ok(true)` if the manifest was modified.
+pub fn inline_workspace_inheritance(
+    manifest: &mut Table,
+    manifest_dir: &Utf8Path,
+    root_workspace: Option<&RootWorkspace>,
+) -> Result<bool> {
+    trace!("Entering `inline_workspace_inheritance` (manifest_dir: '{manifest_dir}')");
+
+    let mut changed = false;
+
+    if let Some(package) = manifest
+        .get_mut(PACKAGE_SECTION_NAME)
+        .and_then(Item::as_table_mut)
+    {
+        let workspace_package =
+            root_workspace.and_then(|ws| ws.workspace.get(PACKAGE_SECTION_NAME));
+        let inherited: Vec<_> = package
+            .iter()
+            .filter(|(_, item)| is_workspace_inherited(item))
+            .map(|(key, _)| key.to_string())
+            .collect();
+
+        for key in inherited {
+            let mut value = workspace_package
+                .and_then(|package| package.get(&key))
+                .and_then(Item::as_value)
+                .cloned()
+                .ok_or_else(|| Error::MissingInheritedValue(format!("`package.{key}`")))?;
+            if let (true, Some(path), Some(root_workspace)) =
+                (PACKAGE_PATH_FIELDS.contains(&key.as_str()), value.as_str(), root_workspace)
+            {
+                value = rebase_path(path, &root_workspace.root, manifest_dir).into();
+            }
+
+            debug!("Inlining inherited field 'package.{key}'");
+            value.decor_mut().clear();
+            package.insert(&key, Item::Value(value));
+            changed = true;
+        }
+    }
+
+    changed = for_each_dependencies_section(manifest, |dependencies| {
+        inline_inherited_dependencies(dependencies, manifest_dir, root_workspace)
+    })? || changed;
+
+    trace!("Exiting `inline_workspace_inheritance` (changed: {changed})");
+    Ok(changed)
+}
+
+fn inline_inherited_dependencies(
+    dependencies: &mut dyn TableLike,
+    manifest_dir: &Utf8Path,
+    root_workspace: Option<&RootWorkspace>,
+) -> Result<bool> {
+    let mut changed = false;
+
+    for (name, dependency) in dependencies.iter_mut() {
+        if !is_workspace_inherited(dependency) {
+            continue;
+        }
+
+        let (workspace_dependency, root_workspace) = root_workspace
+            .and_then(|root_workspace| {
+                let workspace_dependency = root_workspace
+                    .workspace
+                    .get(DEPENDENCIES_SECTION_NAME)?
+                    .get(name.get())?;
+                Some((workspace_dependency, root_workspace))
+            })
+            .ok_or_else(|| Error::MissingInheritedValue(format!("dependency `{name}`")))?;
+
+        let mut inlined = InlineTable::new();
+        match workspace_dependency {
+            Item::Value(Value::String(version)) => {
+                inlined.insert(VERSION_FIELD, version.value().as_str().into());
+            },
+            _ => {
+                if let Some(workspace_dependency) = workspace_dependency.as_table_like() {
+                    for (key, item) in workspace_dependency.iter() {
+                        if let Some(value) = item.as_value() {
+                            inlined.insert(key, value.clone());
+                        }
+                    }
+                }
+            },
+        }
+        if let Some(path) = inlined.get(PATH_FIELD).and_then(Value::as_str) {
+            let rebased = rebase_path(path, &root_workspace.root, manifest_dir);
+            inlined.insert(PATH_FIELD, rebased.into());
+        }
+
+        if let Some(member_dependency) = dependency.as_table_like() {
+            for (key, item) in member_dependency.iter() {
+                let Some(value) = item.as_value() else {
+                    continue;
+                };
+                match (key, value) {
+                    (WORKSPACE_SECTION_NAME, _) => (),
+                    (FEATURES_FIELD, Value::Array(new_features)) => {
+                        let features = inlined
+                            .entry(FEATURES_FIELD)
+                            .or_insert_with(|| Value::Array(Default::default()));
+                        if let Value::Array(features) = features {
+                            for feature in new_features.iter() {
+                                if !features.iter().any(|f| f.as_str() == feature.as_str()) {
+                                    features.push_formatted(feature.clone());
+                                }
+                            }
+                        }
+                    },
+                    (OPTIONAL_FIELD, _) => {
+                        inlined.insert(key, value.clone());
+                    },
+                    (DEFAULT_FEATURES_FIELD, Value::Boolean(default_features)) => {
+                        let workspace_default_features = inlined
+                            .get(DEFAULT_FEATURES_FIELD)
+                            .and_then(Value::as_bool)
+                            .unwrap_or(true);
+                        if *default_features.value() && !workspace_default_features {
+                            inlined.remove(DEFAULT_FEATURES_FIELD);
+                        } else if !*default_features.value() && workspace_default_features {
+                            warn!(
+                                "'{DEFAULT_FEATURES_FIELD} = false' is ignored for inherited dependency '{name}' since it is enabled in the workspace"
+                            );
+                        }
+                    },
+                    (key, _) => {
+                        warn!("Key '{key}' is ignored for inherited dependency '{name}'");
+                    },
+                }
+            }
+        }
+        for (_, value) in inlined.iter_mut() {
+            value.decor_mut().clear();
+            if let Value::Array(array) = value {
+                array.fmt();
+            }
+        }
+        inlined.fmt();
+
+        debug!("Inlining inherited dependency '{name}'");
+        match dependency {
+            Item::Table(table) if !table.is_dotted() => {
+                let mut new_table = inlined.into_table();
+                new_table.set_position(table.position());
+                *new_table.decor_mut() = table.decor().clone();
+                *table = new_table;
+            },
+            Item::Value(value) => {
+                let decor = value.decor().clone();
+                *value = Value::InlineTable(inlined);
+                *value.decor_mut() = decor;
+            },
+            _ => *dependency = Item::Value(Value::InlineTable(inlined)),
+        }
+        changed = true;
+    }
+
+    Ok(changed)
+}
+
+/// Rebases a path relative to `root` so that it is relative to `dir` instead.
+fn rebase_path(path: &str, root: &Utf8Path, dir: &Utf8Path) -> String {
+    let path = Utf8Path::new(path);
+    if path.is_absolute() {
+        return path.as_str().into();
+    }
+
+    let target = root.join(path);
+    let target: Vec<_> = target
+        .components()
+        .filter(|component| *component != Utf8Component::CurDir)
+        .collect();
+    let dir: Vec<_> = dir
+        .components()
+        .filter(|component| *component != Utf8Component::CurDir)
+        .collect();
+    let common = target.iter().zip(&dir).take_while(|(a, b)| a == b).count();
+
+    let mut rebased = Utf8PathBuf::new();
+    for _ in common..dir.len() {
+        rebased.push("..");
+    }
+    for component in &target[common..] {
+        rebased.push(component);
+    }
+
+    if rebased.as_str().is_empty() {
+        ".".into()
+    } else {
+        rebased.as_str().replace('\\', "/")
+    }
+}
+
+fn for_each_dependencies_section<F>(manifest: &mut Table, mut f: F) -> Result<bool>
+where
+    F: FnMut(&mut dyn TableLike) -> Result<bool>,
+{
+    let mut changed = for_each_dependencies_table(manifest, &mut f)?;
+
+    if let Some(Item::Table(targets)) = manifest.get_mut(TARGET_SECTION_NAME) {
+        for (_, target) in targets.iter_mut() {
+            if let Some(target) = target.as_table_like_mut() {
+                changed = for_each_dependencies_table(target, &mut f)? || changed;
+            }
+        }
+    }
+
+    Ok(changed)
+}
+
+fn for_each_dependencies_table<F>(table: &mut dyn TableLike, f: &mut F) -> Result<bool>
+where
+    F: FnMut(&mut dyn TableLike) -> Result<bool>,
+{
+    let mut changed = false;
+
+    for name in
+        [DEPENDENCIES_SECTION_NAME, DEV_DEPENDENCIES_SECTION_NAME, BUILD_DEPENDENCIES_SECTION_NAME]
+    {
+        if let Some(dependencies) = table.get_mut(name).and_then(Item::as_table_like_mut) {
+            changed = f(dependencies)? || changed;
+        }
+    }
+
+    Ok(changed)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod inline_workspace_inheritance {
+        use assert_matches::assert_matches;
+        use indoc::indoc;
+
+        use super::*;
+
+        const ROOT_MANIFEST: &str = indoc! {r#"
+            [workspace]
+            members = ["crates/member"]
+
+            [workspace.package]
+            version = "1.2.3"
+            edition = "2021" # edition
+            readme = "README.md"
+
+            [workspace.dependencies]
+            either = "1.10.0"
+            mockall = "0.12.1"
+            serde = { version = "1.0.197", features = ["derive"], default-features = false }
+            local = { path = "crates/local", version = "0.1.0" }
+        "#};
+
+        fn perform_test(manifest_text: &str) -> Result<String> {
+            let root_manifest = ROOT_MANIFEST.parse::<DocumentMut>().unwrap();
+            let root_workspace =
+                RootWorkspace::from_manifest(Utf8Path::new("/ws/Cargo.toml"), &root_manifest);
+
+            let mut manifest = manifest_text.parse::<DocumentMut>().unwrap();
+            inline_workspace_inheritance(
+                &mut manifest,
+                Utf8Path::new("/ws/crates/member"),
+                root_workspace.as_ref(),
+            )
+            .map(|_| manifest.to_string())
+        }
+
+        #[test_log::test]
+        fn all() {
+            let manifest_text = indoc! {r#"
+                [package]
+                name = "member"
+                version.workspace = true
+                edition = { workspace = true }
+                readme.workspace = true
+
+                [dependencies]
+                either = { workspace = true, optional = true }
+                mockall.workspace = true
+                serde = { workspace = true, features = ["rc", "derive"], default-features = true } # serialization
+                local = { workspace = true, version = "0.2.0" }
+
+                [target.'cfg(unix)'.dev-dependencies.either]
+                workspace = true
+            "#};
+
+            let expected = indoc! {r#"
+                [package]
+                name = "member"
+                version = "1.2.3"
+                edition = "2021"
+                readme = "../../README.md"
+
+                [dependencies]
+                either = { version = "1.10.0", optional = true }
+                mockall = { version = "0.12.1" }
+                serde = { version = "1.0.197", features = ["derive", "rc"] } # serialization
+                local = { path = "../local", version = "0.1.0" }
+
+                [target.'cfg(unix)'.dev-dependencies.either]
+                version = "1.10.0"
+            "#};
+            assert_eq!(expected, perform_test(manifest_text).unwrap());
+        }
+
+        #[test_log::test]
+        fn missing_workspace_value() {
+            let manifest_text = "[dependencies]\nfoo.workspace = true\n";
+
+            assert_matches!(
+                perform_test(manifest_text),
+                Err(Error::MissingInheritedValue(what)) => {
+                    assert_eq!("dependency `foo`", what);
+                }
+            );
+        }
+
+        #[test_log::test]
+        fn nothing_inherited() {
+            let manifest_text = "[package]\nname = \"member\"\n\n[dependencies]\neither = \"1\"\n";
+
+            assert_eq!(manifest_text, perform_test(manifest_text).unwrap());
+        }
+    }
+
+    mod rebase_path {
+        use super::*;
+
+        #[test]
+        fn all() {
+            let rebase =
+                |path, root, dir| rebase_path(path, Utf8Path::new(root), Utf8Path::new(dir));
+
+            assert_eq!("../../README.md", rebase("README.md", "/ws", "/ws/crates/a"));
+            assert_eq!("../b", rebase("./crates/b", "/ws", "/ws/crates/a"));
+            assert_eq!(".", rebase("crates/a", "/ws", "/ws/crates/a"));
+        }
+    }
+}
@@ -5,7 +5,19 @@
 //! - `cargo-msrv-prep`
 //! - `cargo-msrv-unprep`
 //!
-//! This library is not meant for external use and makes no guarantee on API stability.
+//! # API stability
+//!
+//! The [`Preparer`] API can be used by other tools (e.g. an `xtask`) to prepare manifests for
+//! determining/verifying MSRV and restore them afterwards, like the commands do (see the
+//! [`preparer`] module for an example). It follows [semantic versioning]: breaking changes to it
+//! (and to the types it uses, like [`PrepReport`](prep::PrepReport),
+//! [`UnprepReport`](unprep::UnprepReport), [`Metadata`], [`BackupBackend`] and [`Error`]) are
+//! only made in new major versions.
+//!
+//! Other items are public because the commands use them; they are documented, but they may change
+//! in minor versions.
+//!
+//! [semantic versioning]: https://semver.org/
 //!
 //! To install `cargo-msrv-prep`, see [the project's GitHub page](https://github.com/clechasseur/msrv-prep).
 
@@ -13,28 +25,58 @@
 #![deny(rustdoc::private_intra_doc_links)]
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
 
+pub mod backup;
+pub mod cargo_config;
+pub mod clean;
 pub mod common_args;
+pub mod compat;
 mod detail;
+pub mod find;
+pub mod github;
+pub mod index;
+pub mod inheritance;
+pub mod lints;
+pub mod lock_pins;
+pub mod lockfile;
 pub mod metadata;
+pub mod minimal_versions;
+pub mod minimize_pins;
 pub(crate) mod mockable;
+pub mod pins;
+pub mod prep;
+pub mod preparer;
 pub mod result;
+pub mod strip_keys;
+pub mod suggest_pins;
+pub mod toolchain;
+pub mod unprep;
+pub mod verify;
 
 use std::fs;
 
 use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
-use log::{debug, error, info, trace, warn};
+use log::{debug, info, trace, warn};
+pub use preparer::Preparer;
 pub use result::Error;
 pub use result::Result;
-use toml_edit::{Document, Item, Table};
-
-use crate::detail::{merge_msrv_dependencies, PACKAGE_SECTION_NAME};
+use toml_edit::{Document, DocumentMut, Item, Table};
+
+use crate::backup::{BackupBackend, FileBackupBackend};
+use crate::detail::{
+    is_workspace_inherited, merge_msrv_dependencies, set_package_field, PACKAGE_SECTION_NAME,
+    WORKSPACE_SECTION_NAME,
+};
+use crate::metadata::Metadata;
 #[mockall_double::double]
 use crate::mockable::fs as mockable_fs;
-use crate::result::IoErrorContext;
+use crate::result::{IoErrorContext, TomlErrorContext};
 
 /// Default suffix used to backup manifest files before determining/verifying MSRV.
 pub const DEFAULT_MANIFEST_BACKUP_SUFFIX: &str = ".msrv-prep.bak";
 
+/// Default name of TOML file containing pinned crates used when determining/verifying MSRV.
+pub const DEFAULT_MSRV_PINS_FILE_NAME: &str = "msrv-pins.toml";
+
 /// Field in the `package` section of a manifest that stores the package's MSRV.
 pub const RUST_VERSION_SPECIFIER: &str = "rust-version";
 
@@ -64,147 +106,220 @@ pub fn remove_rust_version(manifest: &mut Table) -> bool {
     changed
 }
 
-/// Merges optional MSRV dependencies in a Cargo manifest if they exist.
+/// Checks whether a Cargo manifest's `package` section has a `rust-version` field.
+pub fn has_rust_version(manifest: &Table) -> bool {
+    manifest
+        .get(PACKAGE_SECTION_NAME)
+        .and_then(Item::as_table_like)
+        .is_some_and(|package| package.contains_key(RUST_VERSION_SPECIFIER))
+}
+
+/// Sets the `rust-version` field of a Cargo manifest.
 ///
-/// The optional pinned MSRV dependencies need to be stored in a file next to the Cargo manifest.
+/// The field is set in the manifest's `package` section, if present. If the package inherits
+/// its `rust-version` from the workspace (e.g. `rust-version.workspace = true`), the field is
+/// instead set in the `workspace.package` section (if present in the same manifest). The
+/// `workspace.package` section is also updated if it already has a `rust-version` field.
 ///
-/// Returns `Ok(true)` if the manifest was modified.
-pub fn maybe_merge_msrv_dependencies(
-    manifest: &mut Table,
-    manifest_path: &Utf8Path,
-    pins_file_name: &str,
-) -> Result<bool> {
-    trace!(
-        "Entering `maybe_merge_msrv_dependencies` (manifest_path: '{manifest_path}', pins_file_name: '{pins_file_name}')"
-    );
-    let mut changed = false;
-
-    let pins_file_path = manifest_path.parent().map(|par| par.join(pins_file_name));
+/// Existing fields are updated in place, preserving formatting and comments; new fields are
+/// inserted after the `edition` (or `version`) field.
+///
+/// Returns `true` if the manifest was modified.
+pub fn set_rust_version(manifest: &mut Table, rust_version: &str) -> bool {
+    trace!("Entering `set_rust_version` (rust_version: '{rust_version}')");
 
-    if let Some(pins_file_path) = pins_file_path {
-        debug!("Pinned MSRV dependencies file path: {pins_file_path}");
+    let mut changed = false;
+    let mut inherited = false;
+    if let Some(Item::Table(package)) = manifest.get_mut(PACKAGE_SECTION_NAME) {
+        inherited = package
+            .get(RUST_VERSION_SPECIFIER)
+            .is_some_and(is_workspace_inherited);
+        if inherited {
+            info!("'{RUST_VERSION_SPECIFIER}' field is inherited from the workspace");
+        } else {
+            changed = set_package_field(package, RUST_VERSION_SPECIFIER, rust_version);
+        }
+    }
 
-        if pins_file_path.is_file() {
-            info!(
-                "Pinned MSRV dependencies file found at '{pins_file_path}'; merging with manifest at '{manifest_path}'"
-            );
+    changed = set_workspace_package_rust_version(manifest, rust_version, inherited) || changed;
 
-            let pins_file_text = fs::read_to_string(&pins_file_path)
-                .with_io_context(|| format!("reading MSRV pins file '{pins_file_path}'"))?;
-            let pins_file = Document::parse(pins_file_text)?;
+    trace!("Exiting `set_rust_version` (changed: {changed})");
+    changed
+}
 
-            changed = merge_msrv_dependencies(manifest, &pins_file);
-        }
-    } else {
-        warn!("Pinned MSRV dependencies file path could not be determined; skipping");
+/// Sets the `rust-version` field in the `workspace.package` section of a root manifest,
+/// if the field is present (or if `force` is `true`).
+///
+/// The manifest's `package` section, if any, is left untouched.
+fn set_workspace_package_rust_version(
+    manifest: &mut Table,
+    rust_version: &str,
+    force: bool,
+) -> bool {
+    match manifest
+        .get_mut(WORKSPACE_SECTION_NAME)
+        .and_then(|workspace| workspace.get_mut(PACKAGE_SECTION_NAME))
+    {
+        Some(Item::Table(workspace_package))
+            if force || workspace_package.contains_key(RUST_VERSION_SPECIFIER) =>
+        {
+            set_package_field(workspace_package, RUST_VERSION_SPECIFIER, rust_version)
+        },
+        _ => false,
     }
-
-    trace!("Exiting `maybe_merge_msrv_dependencies` (changed: {changed})");
-    Ok(changed)
 }
 
-/// Backs up a manifest file by copying it to a new file next to it.
+/// Sets the `rust-version` field in the manifests of the selected packages
+/// (see [`set_rust_version`]), as well as in the workspace's root manifest.
 ///
-/// The new file's name is the same as the manifest, with the given backup suffix appended.
+/// If the root manifest's package is not selected, only its `workspace.package` section
+/// is updated.
 ///
-/// If a lockfile exists next to the manifest, it is also backed up in a similar manner.
-pub fn backup_manifest(manifest_path: &Utf8Path, backup_suffix: &str, force: bool) -> Result<()> {
-    trace!(
-        "Entering `backup_manifest` (manifest_path: '{manifest_path}', backup_suffix: '{backup_suffix}', force: {force})",
-    );
-
-    let lockfile_path = manifest_path.with_extension(LOCKFILE_EXT);
-
-    let manifest_backup_path = get_backup_path(manifest_path, backup_suffix)?;
-    let lockfile_backup_path = get_backup_path(&lockfile_path, backup_suffix)?;
+/// Refuses to modify manifests that are currently prepped (e.g. for which a backup exists),
+/// since the change would be lost when restoring them.
+///
+/// Returns the paths of the manifests that were modified.
+pub fn set_workspace_rust_version(
+    metadata: &Metadata,
+    backup_backend: &dyn BackupBackend,
+    rust_version: &str,
+    dry_run: bool,
+) -> Result<Vec<Utf8PathBuf>> {
+    trace!("Entering `set_workspace_rust_version` (rust_version: '{rust_version}')");
+
+    let root_manifest_path = metadata
+        .cargo_metadata
+        .workspace_root
+        .join(DEFAULT_MANIFEST_FILE_NAME);
+    let mut manifest_paths: Vec<_> = metadata
+        .selected_packages
+        .iter()
+        .map(|package| package.manifest_path.clone())
+        .collect();
+    let root_selected = manifest_paths.contains(&root_manifest_path);
+    if root_manifest_path.is_file() && !root_selected {
+        manifest_paths.push(root_manifest_path.clone());
+    }
 
-    validate_backup_file(&manifest_backup_path, force)?;
-    if lockfile_path.is_file() {
-        validate_backup_file(&lockfile_backup_path, force)?;
+    for manifest_path in &manifest_paths {
+        if backup_backend.has_backup(manifest_path)? {
+            return Err(Error::ManifestPrepped(manifest_path.clone()));
+        }
     }
 
-    backup_file(manifest_path, &manifest_backup_path)?;
-    if lockfile_path.is_file() {
-        backup_file(&lockfile_path, &lockfile_backup_path)?;
+    let mut changed_paths = Vec::new();
+    for manifest_path in manifest_paths {
+        let manifest_text = fs::read_to_string(&manifest_path)
+            .with_io_context(|| format!("reading manifest '{manifest_path}'"))?;
+        let mut manifest = manifest_text
+            .parse::<DocumentMut>()
+            .with_toml_file(&manifest_path, &manifest_text)?;
+
+        let changed = if manifest_path == root_manifest_path && !root_selected {
+            set_workspace_package_rust_version(&mut manifest, rust_version, false)
+        } else {
+            set_rust_version(&mut manifest, rust_version)
+        };
+        if !changed {
+            debug!("Manifest '{manifest_path}' not changed");
+        } else if dry_run {
+            info!("Manifest '{manifest_path}' needs changes; not persisting (dry-run mode)");
+            changed_paths.push(manifest_path);
+        } else {
+            info!("Setting '{RUST_VERSION_SPECIFIER}' to '{rust_version}' in '{manifest_path}'");
+            mockable_fs::write(&manifest_path, manifest.to_string()).with_io_context(|| {
+                format!("saving updated manifest content to '{manifest_path}'")
+            })?;
+            changed_paths.push(manifest_path);
+        }
     }
 
-    trace!("Exiting `backup_manifest`");
-    Ok(())
+    trace!("Exiting `set_workspace_rust_version` (changed: {changed_paths:?})");
+    Ok(changed_paths)
 }
 
-/// If a backup manifest exists next to the given manifest, restores it.
-///
-/// The backup manifest must've been created by calling [`backup_manifest`]
-/// (passing it the same `backup_suffix` value).
+/// Reads the pinned MSRV dependencies file stored next to a Cargo manifest, if it exists.
 ///
-/// If a lockfile was also backed up next to the manifest, it is also restored.
-pub fn maybe_restore_manifest(manifest_path: &Utf8Path, backup_suffix: &str) -> Result<()> {
+/// Returns `Ok(None)` if there is no pins file.
+pub fn maybe_read_pins_file(
+    manifest_path: &Utf8Path,
+    pins_file_name: &str,
+) -> Result<Option<Document<String>>> {
     trace!(
-        "Entering `maybe_restore_manifest` (manifest_path: '{manifest_path}', backup_suffix: '{backup_suffix}')"
+        "Entering `maybe_read_pins_file` (manifest_path: '{manifest_path}', pins_file_name: '{pins_file_name}')"
     );
 
-    let lockfile_path = manifest_path.with_extension(LOCKFILE_EXT);
+    let Some(pins_file_path) = manifest_path.parent().map(|par| par.join(pins_file_name)) else {
+        warn!("Pinned MSRV dependencies file path could not be determined; skipping");
+        return Ok(None);
+    };
+    debug!("Pinned MSRV dependencies file path: {pins_file_path}");
 
-    maybe_restore_file(manifest_path, backup_suffix)?;
+    let pins_file = if pins_file_path.is_file() {
+        info!("Pinned MSRV dependencies file found at '{pins_file_path}'");
 
-    if lockfile_path.is_file() {
-        maybe_restore_file(&lockfile_path, backup_suffix)?;
-    }
+        let pins_file_text = fs::read_to_string(&pins_file_path)
+            .with_io_context(|| format!("reading MSRV pins file '{pins_file_path}'"))?;
+        Some(
+            Document::parse(pins_file_text.clone())
+                .with_toml_file(&pins_file_path, &pins_file_text)?,
+        )
+    } else {
+        None
+    };
 
-    trace!("Exiting `maybe_restore_manifest`");
-    Ok(())
+    trace!("Exiting `maybe_read_pins_file` (found: {})", pins_file.is_some());
+    Ok(pins_file)
 }
 
-fn maybe_restore_file(file_path: &Utf8Path, backup_suffix: &str) -> Result<()> {
+/// Merges optional MSRV dependencies in a Cargo manifest if they exist.
+///
+/// The optional pinned MSRV dependencies need to be stored in a file next to the Cargo manifest
+/// (see [`maybe_read_pins_file`]).
+///
+/// Returns `Ok(true)` if the manifest was modified.
+pub fn maybe_merge_msrv_dependencies(
+    manifest: &mut Table,
+    manifest_path: &Utf8Path,
+    pins_file_name: &str,
+) -> Result<bool> {
     trace!(
-        "Entering `maybe_restore_file` (file_path: '{file_path}', backup_suffix: '{backup_suffix}')"
+        "Entering `maybe_merge_msrv_dependencies` (manifest_path: '{manifest_path}', pins_file_name: '{pins_file_name}')"
     );
 
-    let backup_path = get_backup_path(file_path, backup_suffix)?;
-    debug!("Backup path: {backup_path}");
-
-    if backup_path.is_file() {
-        info!("Backup file found at '{backup_path}'; restoring to '{file_path}'");
-
-        mockable_fs::rename(&backup_path, file_path).with_io_context(|| {
-            format!("restoring backup from '{backup_path}' to '{file_path}'")
-        })?;
-    }
-
-    trace!("Exiting `maybe_restore_file`");
-    Ok(())
-}
+    let changed = match maybe_read_pins_file(manifest_path, pins_file_name)? {
+        Some(pins_file) => {
+            info!("Merging pinned MSRV dependencies with manifest at '{manifest_path}'");
+            !merge_msrv_dependencies(manifest, &pins_file).is_empty()
+        },
+        None => false,
+    };
 
-fn get_backup_path(file_path: &Utf8Path, backup_suffix: &str) -> Result<Utf8PathBuf> {
-    file_path
-        .file_name()
-        .map(|name| name.to_string() + backup_suffix)
-        .and_then(|name| file_path.parent().map(|par| par.join(name)))
-        .ok_or_else(|| Error::InvalidPath(file_path.into()))
+    trace!("Exiting `maybe_merge_msrv_dependencies` (changed: {changed})");
+    Ok(changed)
 }
 
-fn validate_backup_file(backup_path: &Utf8Path, force: bool) -> Result<()> {
-    match (backup_path.is_file(), force) {
-        (true, true) => {
-            info!(
-                "Backup file already exists at '{backup_path}'; will be overwritten (forced backup)"
-            );
-            Ok(())
-        },
-        (true, false) => {
-            error!("Backup file already exists at '{backup_path}'; aborting");
-
-            Err(Error::BackupFileAlreadyExists(backup_path.into()))
-        },
-        (false, _) => Ok(()),
-    }
+/// Backs up a manifest file by copying it to a new file next to it.
+///
+/// The new file's name is the same as the manifest, with the given backup suffix appended.
+///
+/// If a lockfile exists next to the manifest, it is also backed up in a similar manner.
+///
+/// This uses the [`FileBackupBackend`]; see the [`backup`] module for other backends.
+pub fn backup_manifest(manifest_path: &Utf8Path, backup_suffix: &str, force: bool) -> Result<()> {
+    FileBackupBackend::new(backup_suffix).backup_manifest(manifest_path, force)
 }
 
-fn backup_file(file_path: &Utf8Path, backup_path: &Utf8Path) -> Result<()> {
-    info!("Backing up '{file_path}' to '{backup_path}'");
-    mockable_fs::copy(file_path, backup_path)
-        .map(|_| ())
-        .with_io_context(|| format!("backing up '{file_path}' to '{backup_path}'"))
+/// If a backup manifest exists next to the given manifest, restores it.
+///
+/// The backup manifest must've been created by calling [`backup_manifest`]
+/// (passing it the same `backup_suffix` value).
+///
+/// If a lockfile was also backed up next to the manifest, it is also restored.
+///
+/// Returns `Ok(true)` if the manifest was restored.
+pub fn maybe_restore_manifest(manifest_path: &Utf8Path, backup_suffix: &str) -> Result<bool> {
+    FileBackupBackend::new(backup_suffix).maybe_restore_manifest(manifest_path)
 }
 
 #[cfg(test)]
@@ -233,6 +348,168 @@ mod tests {
         }
     }
 
+    mod set_rust_version {
+        use indoc::indoc;
+
+        use super::*;
+
+        #[test_log::test]
+        fn existing() {
+            let mut manifest = indoc! {r#"
+                [package]
+                name = "foo"
+                rust-version = "1.80.0" # our MSRV
+                edition = "2021"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            assert!(set_rust_version(&mut manifest, "1.70.0"));
+
+            let expected = indoc! {r#"
+                [package]
+                name = "foo"
+                rust-version = "1.70.0" # our MSRV
+                edition = "2021"
+            "#};
+            assert_eq!(expected, manifest.to_string());
+            assert!(!set_rust_version(&mut manifest, "1.70.0"));
+        }
+
+        #[test_log::test]
+        fn missing() {
+            let mut manifest = "[package]\nname = \"foo\"\n"
+                .parse::<DocumentMut>()
+                .unwrap();
+
+            assert!(set_rust_version(&mut manifest, "1.70.0"));
+            assert_eq!(
+                "[package]\nname = \"foo\"\nrust-version = \"1.70.0\"\n",
+                manifest.to_string()
+            );
+        }
+
+        #[test_log::test]
+        fn missing_after_edition() {
+            let mut manifest = indoc! {r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+                # Our license
+                license = "MIT"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            assert!(set_rust_version(&mut manifest, "1.70"));
+
+            let expected = indoc! {r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+                edition = "2021"
+                rust-version = "1.70"
+                # Our license
+                license = "MIT"
+            "#};
+            assert_eq!(expected, manifest.to_string());
+        }
+
+        #[test_log::test]
+        fn inherited() {
+            let manifest_text = "[package]\nrust-version.workspace = true\n";
+            let mut manifest = manifest_text.parse::<DocumentMut>().unwrap();
+
+            assert!(!set_rust_version(&mut manifest, "1.70.0"));
+            assert_eq!(manifest_text, manifest.to_string());
+        }
+
+        #[test_log::test]
+        fn inherited_from_same_manifest() {
+            let mut manifest = indoc! {r#"
+                [workspace.package]
+                edition = "2021"
+                rust-version = "1.80.0"
+
+                [package]
+                name = "foo"
+                rust-version = { workspace = true }
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            assert!(set_rust_version(&mut manifest, "1.70.0"));
+
+            let expected = indoc! {r#"
+                [workspace.package]
+                edition = "2021"
+                rust-version = "1.70.0"
+
+                [package]
+                name = "foo"
+                rust-version = { workspace = true }
+            "#};
+            assert_eq!(expected, manifest.to_string());
+        }
+
+        #[test_log::test]
+        fn workspace_package() {
+            let mut manifest = indoc! {r#"
+                [workspace]
+                members = ["foo"]
+
+                [workspace.package]
+                rust-version = "1.80.0"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            assert!(set_rust_version(&mut manifest, "1.70.0"));
+            assert!(manifest.to_string().contains("rust-version = \"1.70.0\""));
+        }
+    }
+
+    mod maybe_read_pins_file {
+        use assert_matches::assert_matches;
+
+        use super::*;
+
+        fn perform_test(pins_file_text: Option<&str>) -> Result<Option<Document<String>>> {
+            let temp = assert_fs::TempDir::new().unwrap();
+            let manifest_path = Utf8PathBuf::from_path_buf(temp.path().join("Cargo.toml")).unwrap();
+            if let Some(pins_file_text) = pins_file_text {
+                fs::write(temp.path().join("msrv-pins.toml"), pins_file_text).unwrap();
+            }
+
+            maybe_read_pins_file(&manifest_path, "msrv-pins.toml")
+        }
+
+        #[test_log::test]
+        fn found() {
+            let pins_file = perform_test(Some("[dependencies]\nserde = \"=1.0.100\"\n"));
+
+            assert_matches!(pins_file, Ok(Some(pins_file)) => {
+                assert!(pins_file.contains_key("dependencies"));
+            });
+        }
+
+        #[test_log::test]
+        fn not_found() {
+            assert_matches!(perform_test(None), Ok(None));
+        }
+
+        #[test_log::test]
+        fn invalid() {
+            assert_matches!(perform_test(Some("[dependencies")), Err(Error::TomlFile { .. }));
+        }
+
+        #[test_log::test]
+        fn skip_parent_path() {
+            assert_matches!(maybe_read_pins_file("".into(), "msrv-pins.toml"), Ok(None));
+        }
+    }
+
     mod maybe_merge_msrv_dependencies {
         use assert_matches::assert_matches;
 
@@ -269,6 +546,7 @@ mod tests {
                 .iter()
                 .collect();
 
+                let _lock = crate::mockable::lock_fs_mock();
                 let ctx = mockable_fs::copy_context();
                 ctx.expect().returning(|_, _| {
                     Err(io::Error::new(io::ErrorKind::PermissionDenied, "permission denied"))
@@ -308,6 +586,7 @@ mod tests {
                 .iter()
                 .collect();
 
+                let _lock = crate::mockable::lock_fs_mock();
                 let ctx = mockable_fs::rename_context();
                 ctx.expect().returning(|_, _| {
                     Err(io::Error::new(io::ErrorKind::PermissionDenied, "permission denied"))
@@ -0,0 +1,183 @@
+//! Helpers to handle the `lints` section of manifests.
+//!
+//! Versions of Cargo before 1.74 do not support `lints` sections (nor `lints.workspace = true`),
+//! so they can be removed from manifests. To keep lint levels applying when determining/verifying
+//! MSRV, lints can also be translated into `rustc` flags (e.g. `-Dunsafe_code`) to be added
+//! to the workspace's Cargo configuration file (see
+//! [`CargoConfigChanges::rustflags`](crate::cargo_config::CargoConfigChanges::rustflags)).
+
+use log::{debug, trace, warn};
+use toml_edit::{Item, Table, TableLike};
+
+use crate::detail::{is_workspace_inherited, LINTS_SECTION_NAME, WORKSPACE_SECTION_NAME};
+
+/// Tool whose lints are not prefixed (e.g. `unsafe_code` instead of `rust::unsafe_code`).
+const RUST_LINT_TOOL: &str = "rust";
+
+const LEVEL_FIELD: &str = "level";
+const PRIORITY_FIELD: &str = "priority";
+
+/// Removes the `lints` section of a manifest, as well as its `workspace.lints` section.
+///
+/// Returns `true` if the manifest was modified.
+pub fn strip_lints(manifest: &mut Table) -> bool {
+    trace!("Entering `strip_lints`");
+
+    let mut changed = manifest.remove(LINTS_SECTION_NAME).is_some();
+
+    if let Some(workspace) = manifest
+        .get_mut(WORKSPACE_SECTION_NAME)
+        .and_then(Item::as_table_like_mut)
+    {
+        changed = workspace.remove(LINTS_SECTION_NAME).is_some() || changed;
+    }
+    if changed {
+        debug!("Removed '{LINTS_SECTION_NAME}' section(s)");
+    }
+
+    trace!("Exiting `strip_lints` (changed: {changed})");
+    changed
+}
+
+/// Translates a `lints` section into `rustc` flags (e.g. `-Dclippy::all`).
+///
+/// Flags are sorted by priority (then by name), so that lints with a higher priority
+/// override those with a lower priority, like Cargo does.
+pub fn lint_rustflags(lints: &dyn TableLike) -> Vec<String> {
+    let mut flags: Vec<_> = lints
+        .iter()
+        .filter_map(|(tool, tool_lints)| Some((tool, tool_lints.as_table_like()?)))
+        .flat_map(|(tool, tool_lints)| {
+            tool_lints.iter().filter_map(move |(name, lint)| {
+                let (level, priority) = match lint {
+                    Item::Value(level) if level.is_str() => (level.as_str()?, 0),
+                    _ => {
+                        let lint = lint.as_table_like()?;
+                        let level = lint.get(LEVEL_FIELD).and_then(Item::as_str)?;
+                        let priority = lint
+                            .get(PRIORITY_FIELD)
+                            .and_then(Item::as_integer)
+                            .unwrap_or_default();
+                        (level, priority)
+                    },
+                };
+                let flag = match level {
+                    "forbid" => "-F",
+                    "deny" => "-D",
+                    "warn" => "-W",
+                    "allow" => "-A",
+                    level => {
+                        warn!("Unknown level '{level}' for lint '{tool}::{name}'; skipping");
+                        return None;
+                    },
+                };
+
+                let name = match tool {
+                    RUST_LINT_TOOL => name.to_string(),
+                    tool => format!("{tool}::{name}"),
+                };
+                Some((priority, name, flag))
+            })
+        })
+        .collect();
+
+    flags.sort_by(|(priority_a, name_a, _), (priority_b, name_b, _)| {
+        priority_a.cmp(priority_b).then_with(|| name_a.cmp(name_b))
+    });
+    flags
+        .into_iter()
+        .map(|(_, name, flag)| format!("{flag}{name}"))
+        .collect()
+}
+
+/// Translates the lints that apply to a manifest into `rustc` flags (see [`lint_rustflags`]).
+///
+/// If the manifest inherits its lints from the workspace (`lints.workspace = true`), the lints
+/// in `root_workspace` (the `workspace` section of the root manifest) are used.
+pub fn manifest_lint_rustflags(manifest: &Table, root_workspace: Option<&Table>) -> Vec<String> {
+    let lints = match manifest.get(LINTS_SECTION_NAME) {
+        Some(lints) if is_workspace_inherited(lints) => {
+            root_workspace.and_then(|ws| ws.get(LINTS_SECTION_NAME))
+        },
+        lints => lints,
+    };
+
+    lints
+        .and_then(Item::as_table_like)
+        .map(lint_rustflags)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use indoc::indoc;
+    use toml_edit::DocumentMut;
+
+    use super::*;
+
+    mod strip_lints {
+        use super::*;
+
+        #[test]
+        fn all() {
+            let mut manifest = indoc! {r#"
+                [package]
+                name = "foo"
+
+                [lints]
+                workspace = true
+
+                [workspace.lints.rust]
+                unsafe_code = "forbid"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            assert!(strip_lints(&mut manifest));
+            assert_eq!("[package]\nname = \"foo\"\n", manifest.to_string());
+            assert!(!strip_lints(&mut manifest));
+        }
+    }
+
+    mod manifest_lint_rustflags {
+        use super::*;
+
+        #[test]
+        fn own_lints() {
+            let manifest = indoc! {r#"
+                [lints.rust]
+                unsafe_code = "forbid"
+                unused = { level = "warn", priority = -1 }
+
+                [lints.clippy]
+                all = { level = "deny", priority = -1 }
+                enum_glob_use = "allow"
+                bogus = "whatever"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            assert_eq!(
+                vec!["-Dclippy::all", "-Wunused", "-Aclippy::enum_glob_use", "-Funsafe_code"],
+                manifest_lint_rustflags(&manifest, None)
+            );
+        }
+
+        #[test]
+        fn inherited() {
+            let root_manifest = "[workspace.lints.rust]\nunsafe_code = \"deny\"\n"
+                .parse::<DocumentMut>()
+                .unwrap();
+            let root_workspace = root_manifest
+                .get(WORKSPACE_SECTION_NAME)
+                .and_then(Item::as_table);
+            let manifest = "[lints]\nworkspace = true\n"
+                .parse::<DocumentMut>()
+                .unwrap();
+
+            assert_eq!(vec!["-Dunsafe_code"], manifest_lint_rustflags(&manifest, root_workspace));
+            assert!(manifest_lint_rustflags(&manifest, None).is_empty());
+        }
+    }
+}
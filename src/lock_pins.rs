@@ -0,0 +1,197 @@
+//! Helpers to pin transitive dependencies in the workspace's lockfile.
+//!
+//! Pinning a transitive dependency by adding it to a manifest's dependencies changes the
+//! package's dependency graph (and can affect feature unification). Instead, the pinned MSRV
+//! dependencies file can contain a `lock` section mapping package specs to versions:
+//!
+//! ```toml
+//! [lock]
+//! proc-macro2 = "1.0.60"
+//! ```
+//!
+//! These are applied after preparation by running `cargo update -p <spec> --precise <version>`.
+//! The lockfile is backed up beforehand, so that it can be restored by `cargo msrv-unprep`.
+
+use cargo_metadata::camino::Utf8Path;
+use log::{debug, info, trace, warn};
+use toml_edit::{Item, Table};
+
+use crate::backup::BackupBackend;
+use crate::lockfile::backup_workspace_lockfile;
+use crate::verify::CheckCommand;
+use crate::{Error, Result};
+
+/// Name of the section of the pinned MSRV dependencies file storing lockfile pins.
+pub const LOCK_SECTION_NAME: &str = "lock";
+
+/// Version to which a package should be pinned in the lockfile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockPin {
+    /// Spec of the package to pin (e.g. `proc-macro2` or `proc-macro2@1.0.80`).
+    pub spec: String,
+
+    /// Version to pin the package to.
+    pub version: String,
+}
+
+/// Reads the lockfile pins stored in the `lock` section of a pinned MSRV dependencies file.
+pub fn read_lock_pins(pins_file: &Table) -> Result<Vec<LockPin>> {
+    let Some(lock) = pins_file.get(LOCK_SECTION_NAME) else {
+        return Ok(Vec::new());
+    };
+    let lock = lock.as_table_like().ok_or_else(|| Error::InvalidLockPin {
+        spec: LOCK_SECTION_NAME.into(),
+        reason: "section must be a table".into(),
+    })?;
+
+    lock.iter()
+        .map(|(spec, version)| match version {
+            Item::Value(version) if version.is_str() => Ok(LockPin {
+                spec: spec.into(),
+                version: version.as_str().unwrap_or_default().into(),
+            }),
+            _ => Err(Error::InvalidLockPin {
+                spec: spec.into(),
+                reason: "version must be a string".into(),
+            }),
+        })
+        .collect()
+}
+
+/// Applies lockfile pins to the lockfile of the given workspace.
+///
+/// The workspace's lockfile is backed up beforehand using the given backend (along with the
+/// root manifest, unless it was already backed up), then `cargo update -p <spec> --precise
+/// <version>` is run for each pin. If the lockfile did not exist, restoring the backup will
+/// delete it. If `dry_run` is `true`, nothing is actually modified.
+///
+/// Returns `Ok(true)` if there were pins to apply.
+pub fn apply_lock_pins(
+    workspace_root: &Utf8Path,
+    pins: &[LockPin],
+    backup_backend: &dyn BackupBackend,
+    force: bool,
+    dry_run: bool,
+) -> Result<bool> {
+    trace!("Entering `apply_lock_pins` (workspace_root: '{workspace_root}', pins: {pins:?})");
+
+    if pins.is_empty() {
+        debug!("No lockfile pins to apply");
+        return Ok(false);
+    }
+    if dry_run {
+        info!("{} lockfile pin(s) need to be applied; skipping (dry-run mode)", pins.len());
+        return Ok(true);
+    }
+
+    backup_workspace_lockfile(workspace_root, backup_backend, force)?;
+
+    for pin in pins {
+        info!("Pinning '{}' to version {} in lockfile", pin.spec, pin.version);
+
+        let command = CheckCommand {
+            program: "cargo".into(),
+            args: vec![
+                "update".into(),
+                "-p".into(),
+                pin.spec.clone(),
+                "--precise".into(),
+                pin.version.clone(),
+            ],
+        };
+        if !command.run(workspace_root)? {
+            return Err(Error::LockPinFailed {
+                spec: pin.spec.clone(),
+                version: pin.version.clone(),
+            });
+        }
+    }
+
+    trace!("Exiting `apply_lock_pins`");
+    Ok(true)
+}
+
+/// Merges lockfile pins read from multiple pinned MSRV dependencies files.
+///
+/// If the same package is pinned to different versions, the last pin wins.
+pub fn merge_lock_pins(pins: &mut Vec<LockPin>, new_pins: Vec<LockPin>) {
+    for new_pin in new_pins {
+        match pins.iter_mut().find(|pin| pin.spec == new_pin.spec) {
+            Some(pin) if pin.version != new_pin.version => {
+                warn!(
+                    "'{}' pinned to both versions {} and {} in lockfile; using {}",
+                    pin.spec, pin.version, new_pin.version, new_pin.version
+                );
+                pin.version = new_pin.version;
+            },
+            Some(_) => (),
+            None => pins.push(new_pin),
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod read_lock_pins {
+        use assert_matches::assert_matches;
+        use indoc::indoc;
+        use toml_edit::Document;
+
+        use super::*;
+
+        #[test]
+        fn lock_section() {
+            let pins_file = Document::parse(indoc! {r#"
+                [dependencies]
+                serde = "1.0.197"
+
+                [lock]
+                proc-macro2 = "1.0.60"
+                "syn@2.0.50" = "2.0.10"
+            "#})
+            .unwrap();
+
+            assert_eq!(
+                vec![
+                    LockPin { spec: "proc-macro2".into(), version: "1.0.60".into() },
+                    LockPin { spec: "syn@2.0.50".into(), version: "2.0.10".into() },
+                ],
+                read_lock_pins(&pins_file).unwrap()
+            );
+        }
+
+        #[test]
+        fn no_lock_section() {
+            let pins_file = Document::parse("[dependencies]\nserde = \"1.0.197\"\n").unwrap();
+
+            assert!(read_lock_pins(&pins_file).unwrap().is_empty());
+        }
+
+        #[test]
+        fn invalid_version() {
+            let pins_file = Document::parse("[lock]\nproc-macro2 = 1\n").unwrap();
+
+            assert_matches!(read_lock_pins(&pins_file), Err(Error::InvalidLockPin { spec, .. }) => {
+                assert_eq!("proc-macro2", spec);
+            });
+        }
+    }
+
+    mod merge_lock_pins {
+        use super::*;
+
+        #[test]
+        fn last_pin_wins() {
+            let pin =
+                |spec: &str, version: &str| LockPin { spec: spec.into(), version: version.into() };
+
+            let mut pins = vec![pin("a", "1.0.0"), pin("b", "2.0.0")];
+            merge_lock_pins(&mut pins, vec![pin("b", "2.1.0"), pin("c", "3.0.0")]);
+
+            assert_eq!(vec![pin("a", "1.0.0"), pin("b", "2.1.0"), pin("c", "3.0.0")], pins);
+        }
+    }
+}
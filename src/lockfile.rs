@@ -0,0 +1,371 @@
+//! Helpers to prepare the lockfile (`Cargo.lock`) of a workspace.
+//!
+//! Older versions of Cargo cannot read lockfiles using a newer format (e.g. `version = 4`
+//! lockfiles require Cargo 1.78+), so the lockfile can be downgraded to a format understood
+//! by the toolchain used to verify MSRV. The following lockfile versions are supported:
+//!
+//! - Version 4: readable by Cargo 1.78+; Git source URLs are percent-encoded
+//! - Version 3: readable by Cargo 1.47+
+//! - Version 2: readable by Cargo 1.38+; has no `version` field
+
+use std::fs;
+
+use cargo_metadata::camino::Utf8Path;
+use cargo_metadata::semver::Version;
+use log::{debug, info, trace};
+use toml_edit::{value, DocumentMut, Item, RawString, Table, Value};
+
+use crate::backup::BackupBackend;
+#[mockall_double::double]
+use crate::mockable::fs as mockable_fs;
+use crate::result::{IoErrorContext, TomlErrorContext};
+use crate::{Error, Result, DEFAULT_MANIFEST_FILE_NAME, LOCKFILE_EXT};
+
+/// Field of a lockfile storing its format version.
+const VERSION_FIELD: &str = "version";
+
+/// Array of tables of a lockfile storing locked packages.
+const PACKAGE_ARRAY_NAME: &str = "package";
+
+/// Table of a version 1 lockfile storing checksums.
+const METADATA_TABLE_NAME: &str = "metadata";
+
+/// Prefix of Git sources in lockfiles.
+const GIT_SOURCE_PREFIX: &str = "git+";
+
+/// Oldest lockfile version that can be produced by [`downgrade_lockfile`].
+pub const MIN_LOCKFILE_VERSION: u32 = 2;
+
+/// Returns the newest lockfile version that can be read by the given version of Cargo.
+pub fn max_lockfile_version(rust_version: &Version) -> u32 {
+    match (rust_version.major, rust_version.minor) {
+        (1, 78..) | (2.., _) => 4,
+        (1, 47..) => 3,
+        (1, 38..) => 2,
+        _ => 1,
+    }
+}
+
+/// Returns the format version of a lockfile.
+///
+/// Lockfiles without a `version` field are either version 2 or version 1 (if they have
+/// a `metadata` table).
+pub fn lockfile_version(lockfile: &Table) -> u32 {
+    match lockfile.get(VERSION_FIELD).and_then(Item::as_integer) {
+        Some(version) => version.try_into().unwrap_or_default(),
+        None if lockfile.contains_key(METADATA_TABLE_NAME) => 1,
+        None => 2,
+    }
+}
+
+/// Downgrades a lockfile to the given format version, if it uses a newer format.
+///
+/// Returns `Ok(true)` if the lockfile was modified.
+pub fn downgrade_lockfile(lockfile: &mut Table, target_version: u32) -> Result<bool> {
+    trace!("Entering `downgrade_lockfile` (target_version: {target_version})");
+
+    let current_version = lockfile_version(lockfile);
+    debug!("Lockfile version: {current_version}");
+    if current_version <= target_version {
+        trace!("Exiting `downgrade_lockfile` (lockfile already uses version {current_version})");
+        return Ok(false);
+    }
+    if target_version < MIN_LOCKFILE_VERSION {
+        return Err(Error::UnsupportedLockfileVersion(target_version));
+    }
+
+    if current_version >= 4 {
+        decode_git_sources(lockfile);
+    }
+
+    if target_version >= 3 {
+        let version = lockfile
+            .get_mut(VERSION_FIELD)
+            .and_then(Item::as_value_mut)
+            .expect("lockfile should have a version");
+        let decor = version.decor().clone();
+        *version = i64::from(target_version).into();
+        *version.decor_mut() = decor;
+    } else {
+        // The `version` field is usually preceded by the "@generated" comment, so keep it.
+        let prefix = lockfile
+            .key(VERSION_FIELD)
+            .and_then(|key| key.leaf_decor().prefix())
+            .and_then(RawString::as_str)
+            .unwrap_or_default()
+            .to_string();
+        lockfile.remove(VERSION_FIELD);
+
+        if let Some(first_package) = lockfile
+            .get_mut(PACKAGE_ARRAY_NAME)
+            .and_then(Item::as_array_of_tables_mut)
+            .and_then(|packages| packages.get_mut(0))
+        {
+            let existing_prefix = first_package
+                .decor()
+                .prefix()
+                .and_then(RawString::as_str)
+                .unwrap_or_default();
+            let prefix = prefix + existing_prefix.trim_start_matches('\n');
+            first_package.decor_mut().set_prefix(prefix);
+        }
+    }
+
+    trace!("Exiting `downgrade_lockfile`");
+    Ok(true)
+}
+
+/// Backs up the lockfile of the given workspace, if it was not already backed up.
+///
+/// The root manifest is backed up with the lockfile (unless it was already backed up), so that
+/// `cargo msrv-unprep` restores them together. If the lockfile does not exist, restoring the
+/// backup will delete it.
+pub fn backup_workspace_lockfile(
+    workspace_root: &Utf8Path,
+    backup_backend: &dyn BackupBackend,
+    force: bool,
+) -> Result<()> {
+    trace!("Entering `backup_workspace_lockfile` (workspace_root: '{workspace_root}')");
+
+    let root_manifest_path = workspace_root.join(DEFAULT_MANIFEST_FILE_NAME);
+    let lockfile_path = root_manifest_path.with_extension(LOCKFILE_EXT);
+    if !backup_backend.has_backup(&lockfile_path)? {
+        info!("Backing up lockfile '{lockfile_path}'");
+
+        if backup_backend.has_backup(&root_manifest_path)? {
+            backup_backend.backup_files(&[&lockfile_path], force)?;
+        } else {
+            backup_backend.backup_files(&[&root_manifest_path, &lockfile_path], force)?;
+        }
+    }
+
+    trace!("Exiting `backup_workspace_lockfile`");
+    Ok(())
+}
+
+/// Downgrades the lockfile of the given workspace to the given format version, if needed
+/// (see [`downgrade_lockfile`]).
+///
+/// The lockfile is backed up using the given backend before being modified.
+/// If `dry_run` is `true`, the lockfile is not actually modified.
+///
+/// Returns `Ok(true)` if the lockfile needed to be downgraded.
+pub fn prep_lockfile_version(
+    workspace_root: &Utf8Path,
+    target_version: u32,
+    backup_backend: &dyn BackupBackend,
+    force: bool,
+    dry_run: bool,
+) -> Result<bool> {
+    trace!(
+        "Entering `prep_lockfile_version` (workspace_root: '{workspace_root}', target_version: {target_version})"
+    );
+
+    let lockfile_path = workspace_root
+        .join(DEFAULT_MANIFEST_FILE_NAME)
+        .with_extension(LOCKFILE_EXT);
+    if !lockfile_path.is_file() {
+        info!("No lockfile found at '{lockfile_path}'; skipping");
+        return Ok(false);
+    }
+
+    let lockfile_text = fs::read_to_string(&lockfile_path)
+        .with_io_context(|| format!("reading lockfile '{lockfile_path}'"))?;
+    let mut lockfile = lockfile_text
+        .parse::<DocumentMut>()
+        .with_toml_file(&lockfile_path, &lockfile_text)?;
+
+    let changed = downgrade_lockfile(&mut lockfile, target_version)?;
+    if !changed {
+        info!("Lockfile '{lockfile_path}' does not need to be downgraded; skipping");
+    } else if dry_run {
+        info!("Lockfile '{lockfile_path}' needs to be downgraded; not persisting (dry-run mode)");
+    } else {
+        info!("Downgrading lockfile '{lockfile_path}' to version {target_version}");
+
+        backup_workspace_lockfile(workspace_root, backup_backend, force)?;
+        mockable_fs::write(&lockfile_path, lockfile.to_string())
+            .with_io_context(|| format!("saving updated lockfile to '{lockfile_path}'"))?;
+    }
+
+    trace!("Exiting `prep_lockfile_version` (changed: {changed})");
+    Ok(changed)
+}
+
+fn decode_git_sources(lockfile: &mut Table) {
+    let Some(packages) = lockfile
+        .get_mut(PACKAGE_ARRAY_NAME)
+        .and_then(Item::as_array_of_tables_mut)
+    else {
+        return;
+    };
+
+    for package in packages.iter_mut() {
+        if let Some(source) = package.get_mut("source").and_then(Item::as_value_mut) {
+            decode_git_source_value(source);
+        }
+        if let Some(dependencies) = package.get_mut("dependencies").and_then(Item::as_array_mut) {
+            for dependency in dependencies.iter_mut() {
+                decode_git_source_value(dependency);
+            }
+        }
+    }
+}
+
+fn decode_git_source_value(source: &mut Value) {
+    let Some(text) = source.as_str() else {
+        return;
+    };
+
+    // Sources can appear alone (e.g. `git+https://...`) or after a dependency's name and
+    // version (e.g. `foo 1.0.0 (git+https://...)`).
+    let decoded = match text.find(GIT_SOURCE_PREFIX) {
+        Some(start) => {
+            let (before, source_url) = text.split_at(start);
+            let query_start = source_url.find('?').unwrap_or(source_url.len());
+            let query_end = source_url.find('#').unwrap_or(source_url.len());
+            if query_start >= query_end {
+                return;
+            }
+
+            format!(
+                "{before}{}{}{}",
+                &source_url[..query_start],
+                percent_decode(&source_url[query_start..query_end]),
+                &source_url[query_end..]
+            )
+        },
+        None => return,
+    };
+
+    if decoded != text {
+        debug!("Decoding Git source '{text}' to '{decoded}'");
+
+        let decor = source.decor().clone();
+        *source = value(decoded).into_value().expect("item should be a value");
+        *source.decor_mut() = decor;
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+
+    String::from_utf8(decoded).unwrap_or_else(|_| text.into())
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod max_lockfile_version {
+        use super::*;
+
+        #[test]
+        fn all() {
+            let max = |minor| max_lockfile_version(&Version::new(1, minor, 0));
+
+            assert_eq!(4, max(85));
+            assert_eq!(4, max(78));
+            assert_eq!(3, max(77));
+            assert_eq!(3, max(47));
+            assert_eq!(2, max(46));
+            assert_eq!(2, max(38));
+            assert_eq!(1, max(37));
+        }
+    }
+
+    mod downgrade_lockfile {
+        use assert_matches::assert_matches;
+        use indoc::indoc;
+
+        use super::*;
+
+        const V4_LOCKFILE: &str = indoc! {r#"
+            # This file is automatically @generated by Cargo.
+            # It is not intended for manual editing.
+            version = 4
+
+            [[package]]
+            name = "foo"
+            version = "0.1.0"
+            dependencies = [
+             "bar",
+             "baz 0.2.0 (git+https://github.com/a/baz?branch=feat%2Fx#abc123)",
+            ]
+
+            [[package]]
+            name = "bar"
+            version = "1.0.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "0123456789abcdef"
+
+            [[package]]
+            name = "baz"
+            version = "0.2.0"
+            source = "git+https://github.com/a/baz?branch=feat%2Fx#abc123"
+        "#};
+
+        #[test_log::test]
+        fn to_version_3() {
+            let mut lockfile = V4_LOCKFILE.parse::<DocumentMut>().unwrap();
+
+            assert_matches!(downgrade_lockfile(&mut lockfile, 3), Ok(true));
+
+            let expected = V4_LOCKFILE
+                .replace("version = 4", "version = 3")
+                .replace("feat%2Fx", "feat/x");
+            assert_eq!(expected, lockfile.to_string());
+        }
+
+        #[test_log::test]
+        fn to_version_2() {
+            let mut lockfile = V4_LOCKFILE.parse::<DocumentMut>().unwrap();
+
+            assert_matches!(downgrade_lockfile(&mut lockfile, 2), Ok(true));
+
+            let expected = V4_LOCKFILE
+                .replace("version = 4\n\n", "")
+                .replace("feat%2Fx", "feat/x");
+            assert_eq!(expected, lockfile.to_string());
+            assert_eq!(2, lockfile_version(&lockfile));
+        }
+
+        #[test_log::test]
+        fn already_older() {
+            let mut lockfile = V4_LOCKFILE.parse::<DocumentMut>().unwrap();
+
+            assert_matches!(downgrade_lockfile(&mut lockfile, 4), Ok(false));
+            assert_eq!(V4_LOCKFILE, lockfile.to_string());
+        }
+
+        #[test_log::test]
+        fn unsupported_version() {
+            let mut lockfile = V4_LOCKFILE.parse::<DocumentMut>().unwrap();
+
+            assert_matches!(
+                downgrade_lockfile(&mut lockfile, 1),
+                Err(Error::UnsupportedLockfileVersion(1))
+            );
+        }
+    }
+}
@@ -11,6 +11,91 @@
 //! by copying it to a new file next to it. (If a lockfile is also present, it is
 //! backed up as well.)
 //!
+//! Once done, a summary table listing, for each package, the removed `rust-version`, the
+//! number of pins merged per section and the backup location is printed to stderr (unless
+//! `--quiet` is used).
+//!
+//! When run in GitHub Actions, `--message-format github` can be used to report errors as
+//! workflow annotations (pointing to the offending file and location when known) and to append
+//! the summary table to the job summary (the file named by `$GITHUB_STEP_SUMMARY`).
+//!
+//! On failure, an error message is printed to stderr and a distinct exit code is used for
+//! each category of failure: `3` if no manifest needed preparation (only with
+//! `--fail-if-unchanged`), `4` if a manifest is already prepped (a backup exists), `5` if a
+//! pinned MSRV dependencies file (or another TOML file) is invalid, `6` for I/O failures, `7` if
+//! `cargo metadata` failed and `1` for other errors (`2` is used for invalid arguments).
+//!
+//! Optionally, the workspace's toolchain file (`rust-toolchain.toml` or `rust-toolchain`)
+//! can also be updated to use a specific channel (via `--toolchain-channel`) or removed
+//! (via `--remove-toolchain-file`). It is backed up in the same way as manifests.
+//!
+//! The workspace's Cargo configuration file (`.cargo/config.toml`) can also be modified
+//! (or created) to enable MSRV-aware dependency resolution (via `--msrv-aware-resolver`),
+//! offline mode (via `--net-offline`) or vendored sources (via `--vendored-sources`).
+//!
+//! Values inherited from the workspace (e.g. `version.workspace = true`) can be replaced with
+//! concrete values from the root manifest (via `--inline-workspace`), merging `features` and
+//! `optional` like Cargo does and rebasing paths.
+//!
+//! `lints` sections can also be removed from manifests (via `--strip-lints`), optionally
+//! translating them into `rustc` flags in the workspace's Cargo configuration file
+//! (via `--lints-to-rustflags`) so that lint levels keep applying.
+//!
+//! Arbitrary keys (e.g. `cargo-features` or nightly-only keys) can also be removed from manifests,
+//! either via `--strip-key` (using TOML dotted key syntax, e.g. `package.autolib`) or by listing
+//! them in the pinned MSRV dependencies file (see below).
+//!
+//! When a target Rust version is given (via `--target-rust-version`, or `--toolchain-channel`
+//! if it is a version), manifest syntax not supported by that version of Cargo is down-leveled:
+//! workspace inheritance is inlined, `lints` sections are removed, `dep:` features are
+//! rewritten and `resolver = "3"` is replaced with `resolver = "2"`. Constructs that cannot be
+//! down-leveled safely (like an unsupported edition or weak `?/` features) result in an error.
+//!
+//! The workspace's lockfile (`Cargo.lock`) can also be downgraded to an older format version
+//! (via `--lockfile-version`), so that older versions of Cargo can read it. When a target Rust
+//! version is given (via `--target-rust-version`, or `--toolchain-channel` if it is a version),
+//! the newest format supported by that version is picked automatically.
+//!
+//! The `verify` subcommand can be used to verify each package's MSRV in one step: the
+//! packages' `rust-version` is read, manifests are prepared, a check command
+//! (`cargo +{msrv} check --workspace --lib --bins` by default) is run using each MSRV
+//! toolchain, then manifests are restored. The result is reported for each package. Manifest
+//! syntax is only down-leveled if a target Rust version is given (see above).
+//!
+//! The `find` subcommand can be used to determine MSRV: manifests are prepared, then a check
+//! command is run using a binary search over installed toolchains (or those specified via
+//! `--toolchains`) to find the lowest toolchain for which it succeeds. Manifests are then
+//! restored; the result can also be written to the packages' `rust-version` field
+//! (via `--write-rust-version`).
+//!
+//! The `suggest-pins` subcommand can be used to find which dependencies need to be pinned:
+//! manifests are prepared, then a check command is run (using `--message-format=json`). If it
+//! fails, the dependencies that failed to build are pinned to older semver-compatible versions
+//! (or newer ones, via `--newer`) in the pinned MSRV dependencies file, then the process is
+//! repeated until the command succeeds (or until `--max-attempts` is reached). Manifests are
+//! restored afterwards; the suggested pins are kept.
+//!
+//! The `minimize-pins` subcommand does the opposite: it runs a check command with all pins
+//! applied, then again while dropping pins one at a time, to report which pins are required.
+//! Pinned MSRV dependencies files are backed up beforehand and restored afterwards; if `--write`
+//! is used, unnecessary pins are then removed.
+//!
+//! The `pin` subcommand can be used to pin a dependency to the highest version compatible with
+//! an MSRV (via `--for`), according to a local copy of the crate registry index (Cargo's cache
+//! of the crates.io index by default, or a directory passed via `--index`). No network access
+//! is performed.
+//!
+//! Pins can also be edited via the `pin add` and `pin remove` subcommands (e.g.
+//! `cargo msrv-prep pin add foo@1.2.3 --build --target 'cfg(unix)'`), which update the pinned
+//! MSRV dependencies file of the selected packages while preserving its formatting.
+//!
+//! The `lint-pins` subcommand reports pins that are no longer needed because their
+//! `msrv-prep.until-rust-version` is at or below their package's `rust-version` (see below).
+//!
+//! The `set-rust-version` subcommand can be used to write an MSRV to the `rust-version` field
+//! of packages (or of the workspace's `workspace.package` section, if inherited), preserving
+//! formatting.
+//!
 //! Once MSRV has been determined or verified, this process can be undone (e.g. the original
 //! manifests restored) by calling `cargo msrv-unprep` (see `cargo-msrv-unprep` crate).
 //!
@@ -29,17 +114,30 @@
 //! build (most likely because it is too old, could be successfully built in the past
 //! but it no longer works today).
 //!
-//! The pinned MSRV dependencies file can contain three different types of dependencies:
+//! The pinned MSRV dependencies file can contain four different types of dependencies:
 //!
 //! - `dependencies`
+//! - `dev-dependencies`
 //! - `build-dependencies`
-//! - Target-specific versions of the two above (e.g. `target.'cfg(unix)'.dependencies`)
+//! - Target-specific versions of the three above (e.g. `target.'cfg(unix)'.dependencies`)
+//!
+//! It can also contain a `lock` section pinning transitive dependencies in the workspace's
+//! lockfile instead of the manifest; these are applied after preparation by running
+//! `cargo update -p <spec> --precise <version>` (the lockfile is backed up beforehand).
+//!
+//! Its `msrv-prep` section can list keys to remove from the manifest (in its `strip-keys`
+//! field).
+//!
+//! Finally, each pin can store metadata in an `msrv-prep` field, which is removed before the
+//! pin is merged: a `reason` explaining why the pin is needed and an `until-rust-version`
+//! from which the pin can be deleted.
 //!
 //! # Pinned MSRV dependencies file example
 //!
 //! ```toml
 //! [dependencies]
 //! foo = "1.0.0"
+//! memchr = { version = "=2.5.0", msrv-prep.reason = "2.6 needs 1.61", msrv-prep.until-rust-version = "1.61" }
 //!
 //! [build-dependencies]
 //! bar = "2.0.0"
@@ -49,63 +147,75 @@
 //!
 //! [target.'cfg(unix)'.build-dependencies]
 //! unix-specific-build-baz = "4.0.0"
+//!
+//! [lock]
+//! transitive-qux = "5.0.0"
+//!
+//! [msrv-prep]
+//! strip-keys = ["cargo-features", "package.autolib"]
 //! ```
 
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
 
-use std::fs;
+use std::process::ExitCode;
 
-use cargo_msrv_prep::common_args::CommonArgs;
+use cargo_metadata::camino::Utf8PathBuf;
+use cargo_metadata::semver::Version;
+use cargo_metadata::DependencyKind;
+use cargo_msrv_prep::common_args::{CommonArgs, MessageFormat};
+use cargo_msrv_prep::find::{find_msrv, installed_toolchains, parse_toolchain_version, Toolchain};
+use cargo_msrv_prep::github::{error_annotation, maybe_append_step_summary};
+use cargo_msrv_prep::index::CrateIndex;
 use cargo_msrv_prep::metadata::Metadata;
-use cargo_msrv_prep::result::IoErrorContext;
-use cargo_msrv_prep::{
-    backup_manifest, maybe_merge_msrv_dependencies, remove_rust_version,
-    DEFAULT_MANIFEST_FILE_NAME, RUST_VERSION_SPECIFIER,
+use cargo_msrv_prep::minimize_pins::{self, MinimizePinsOptions};
+use cargo_msrv_prep::pins::{
+    add_pin, expired_pins, parse_pin_spec, pin_for_rust_version, remove_pin, PinSpec,
 };
-use clap::{crate_name, Args, Parser};
-use log::{debug, info, trace};
-use toml_edit::DocumentMut;
-
-#[mockall_double::double]
-use crate::mockable::fs as mockable_fs;
+use cargo_msrv_prep::prep::PrepOptions;
+use cargo_msrv_prep::strip_keys::parse_key_path;
+use cargo_msrv_prep::suggest_pins::{
+    self, PinDirection, SuggestPinsOptions, SuggestPinsOutcome, DEFAULT_MAX_ATTEMPTS,
+};
+use cargo_msrv_prep::unprep::UnprepOptions;
+use cargo_msrv_prep::verify::{verify_msrv, VerifyStatus, DEFAULT_CHECK_COMMAND};
+use cargo_msrv_prep::{set_workspace_rust_version, Error, Preparer, DEFAULT_MSRV_PINS_FILE_NAME};
+use clap::{crate_name, Args, Parser, Subcommand};
+use log::{info, trace, warn};
 
-fn main() -> cargo_msrv_prep::Result<()> {
+fn main() -> ExitCode {
     let Cli::MsrvPrep(args) = Cli::parse();
 
     env_logger::Builder::new()
-        .filter_level(args.common.verbose.log_level_filter())
+        .filter_level(args.common().verbose.log_level_filter())
         .init();
 
     info!("{} started", crate_name!());
 
-    prep_for_msrv(&args)?;
-
-    info!("{} finished", crate_name!());
-    Ok(())
-}
-
-mod mockable {
-    #[allow(dead_code)]
-    #[cfg_attr(coverage_nightly, coverage(off))]
-    #[cfg_attr(test, mockall::automock)]
-    pub(super) mod fs {
-        use std::fs as real_fs;
-        use std::io;
-        use std::path::Path;
-
-        #[cfg_attr(test, mockall::concretize)]
-        pub fn write<P, C>(path: P, contents: C) -> io::Result<()>
-        where
-            P: AsRef<Path>,
-            C: AsRef<[u8]>,
-        {
-            real_fs::write(path, contents)
+    let result = match &args.command {
+        Some(MsrvPrepCommand::Verify(verify_args)) => verify(verify_args),
+        Some(MsrvPrepCommand::Find(find_args)) => find(find_args),
+        Some(MsrvPrepCommand::SuggestPins(suggest_args)) => suggest_pins(suggest_args),
+        Some(MsrvPrepCommand::MinimizePins(minimize_args)) => minimize_pins(minimize_args),
+        Some(MsrvPrepCommand::Pin(pin_args)) => match &pin_args.command {
+            Some(PinCommand::Add(add_args)) => pin_add(add_args),
+            Some(PinCommand::Remove(remove_args)) => pin_remove(remove_args),
+            None => pin(pin_args),
+        },
+        Some(MsrvPrepCommand::LintPins(lint_args)) => lint_pins(lint_args),
+        Some(MsrvPrepCommand::SetRustVersion(set_args)) => set_rust_version(set_args),
+        None => prep_for_msrv(&args),
+    };
+    if let Err(err) = result {
+        if args.common().message_format == MessageFormat::Github {
+            println!("{}", error_annotation(&err));
         }
+        eprintln!("error: {err}");
+        return ExitCode::from(err.exit_code());
     }
-}
 
-/// Default name of TOML file containing pinned crates used when determining/verifying MSRV.
-const DEFAULT_MSRV_PINS_FILE_NAME: &str = "msrv-pins.toml";
+    info!("{} finished", crate_name!());
+    ExitCode::SUCCESS
+}
 
 #[derive(Debug, Parser)]
 #[command(name = "cargo", bin_name = "cargo")]
@@ -124,9 +234,76 @@ enum Cli {
         - Remove each package's `rust-version` field (if found)\n\
         - Merge pinned MSRV dependencies in the package's manifest (if found)\n\
         \n\
-        To undo changes, run `cargo msrv-unprep`."
+        To undo changes, run `cargo msrv-unprep`.",
+    args_conflicts_with_subcommands = true
 )]
 struct MsrvPrepArgs {
+    #[command(subcommand)]
+    command: Option<MsrvPrepCommand>,
+
+    #[command(flatten)]
+    prep: PrepArgs,
+
+    /// Overwrite existing manifest backup files
+    #[arg(short, long, default_value_t = false)]
+    pub force: bool,
+
+    /// Determine if preparation is required without persisting resulting manifests
+    ///
+    /// To see result, increase verbosity to at least INFO (e.g. `-vv`)
+    #[arg(short = 'n', long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Exit with an error (exit code 3) if no manifest needed preparation
+    #[arg(long, default_value_t = false)]
+    pub fail_if_unchanged: bool,
+}
+
+impl MsrvPrepArgs {
+    fn common(&self) -> &CommonArgs {
+        match &self.command {
+            Some(MsrvPrepCommand::Verify(verify_args)) => &verify_args.prep.common,
+            Some(MsrvPrepCommand::Find(find_args)) => &find_args.prep.common,
+            Some(MsrvPrepCommand::SuggestPins(suggest_args)) => &suggest_args.prep.common,
+            Some(MsrvPrepCommand::MinimizePins(minimize_args)) => &minimize_args.prep.common,
+            Some(MsrvPrepCommand::Pin(pin_args)) => match &pin_args.command {
+                Some(PinCommand::Add(add_args)) => &add_args.pins.common,
+                Some(PinCommand::Remove(remove_args)) => &remove_args.pins.common,
+                None => &pin_args.pins.common,
+            },
+            Some(MsrvPrepCommand::LintPins(lint_args)) => &lint_args.common,
+            Some(MsrvPrepCommand::SetRustVersion(set_args)) => &set_args.common,
+            None => &self.prep.common,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum MsrvPrepCommand {
+    /// Verify each package's MSRV by preparing manifests, running a check command, then restoring manifests
+    Verify(VerifyArgs),
+
+    /// Find MSRV by preparing manifests, then bisecting toolchains using a check command
+    Find(FindArgs),
+
+    /// Suggest MSRV pins by running a check command and pinning dependencies that fail to build
+    SuggestPins(SuggestPinsArgs),
+
+    /// Find which MSRV pins are required by running a check command while dropping pins one at a time
+    MinimizePins(MinimizePinsArgs),
+
+    /// Pin a dependency to the highest version compatible with an MSRV, using a local crate registry index
+    Pin(PinArgs),
+
+    /// Report MSRV pins that are no longer needed, according to their `until-rust-version`
+    LintPins(LintPinsArgs),
+
+    /// Set the `rust-version` field of packages (or of the workspace, if inherited)
+    SetRustVersion(SetRustVersionArgs),
+}
+
+#[derive(Debug, Args)]
+struct PrepArgs {
     #[command(flatten)]
     common: CommonArgs,
 
@@ -142,171 +319,798 @@ struct MsrvPrepArgs {
     #[arg(long, default_value_t = false)]
     pub no_merge_pinned_dependencies: bool,
 
-    /// Overwrite existing manifest backup files
+    /// Remove a key from manifests (e.g. `cargo-features` or `package.autolib`); can be repeated
+    ///
+    /// Keys use TOML dotted key syntax. Keys can also be listed in the `strip-keys` field
+    /// of the `msrv-prep` section of pinned MSRV dependencies files
+    #[arg(long = "strip-key", value_name = "KEY", value_parser = parse_strip_key)]
+    pub strip_keys: Vec<String>,
+
+    /// Pin direct dependencies to the minimal versions satisfying their requirements
+    ///
+    /// Rewrites each dependency's version requirement to `=<min>`, then runs
+    /// `cargo generate-lockfile` so that direct dependencies are resolved to their
+    /// minimal versions (without requiring a nightly toolchain). The lockfile is backed up
+    /// beforehand.
+    ///
+    /// Minimal versions are computed across the workspace (one per crate and semver-compatible
+    /// range), using the lowest versions found in Cargo's local copy of the crates.io index
+    #[arg(long, default_value_t = false)]
+    pub direct_minimal_versions: bool,
+
+    /// Set the toolchain channel in the workspace's toolchain file (`rust-toolchain.toml` or `rust-toolchain`)
+    #[arg(long, value_name = "CHANNEL")]
+    pub toolchain_channel: Option<String>,
+
+    /// Remove the workspace's toolchain file (`rust-toolchain.toml` or `rust-toolchain`)
+    #[arg(long, default_value_t = false, conflicts_with = "toolchain_channel")]
+    pub remove_toolchain_file: bool,
+
+    /// Enable MSRV-aware dependency resolution in the workspace's `.cargo/config.toml`
+    ///
+    /// Sets `resolver.incompatible-rust-versions` to `"fallback"`, so that dependency
+    /// resolution takes `rust-version` into account (requires Cargo 1.84+)
+    #[arg(long, default_value_t = false)]
+    pub msrv_aware_resolver: bool,
+
+    /// Set `net.offline` to `true` in the workspace's `.cargo/config.toml`
+    #[arg(long, default_value_t = false)]
+    pub net_offline: bool,
+
+    /// Replace `crates-io` with vendored sources in the workspace's `.cargo/config.toml`
+    ///
+    /// The directory should contain sources produced by `cargo vendor`
+    #[arg(long, value_name = "DIR")]
+    pub vendored_sources: Option<Utf8PathBuf>,
+
+    /// Inline values inherited from the workspace (`workspace = true`) in manifests
+    ///
+    /// Cargo versions before 1.64 do not support workspace inheritance
+    #[arg(long, default_value_t = false)]
+    pub inline_workspace: bool,
+
+    /// Remove `lints` sections from manifests (and `workspace.lints` from the root manifest)
+    ///
+    /// Cargo versions before 1.74 do not support `lints` sections
+    #[arg(long, default_value_t = false)]
+    pub strip_lints: bool,
+
+    /// Translate lints into `rustc` flags in the workspace's `.cargo/config.toml` (implies `--strip-lints`)
+    ///
+    /// Flags (e.g. `-Dunsafe_code`) are appended to `build.rustflags`, so that lint levels
+    /// keep applying (note that these are ignored if the `RUSTFLAGS` environment variable is set)
+    #[arg(long, default_value_t = false)]
+    pub lints_to_rustflags: bool,
+
+    /// Rust version targeted when determining/verifying MSRV
+    ///
+    /// Manifest syntax not supported by this version of Cargo (e.g. workspace inheritance
+    /// before 1.64) is down-leveled, and the lockfile format version is picked accordingly
+    /// (see `--lockfile-version`). Defaults to the toolchain channel (see `--toolchain-channel`)
+    /// if it is a version
+    #[arg(long, value_name = "VERSION", value_parser = parse_toolchain)]
+    pub target_rust_version: Option<Version>,
+
+    /// Downgrade the workspace's lockfile to this format version, if it uses a newer format
+    ///
+    /// Defaults to the newest version supported by the target Rust version, if any
+    /// (see `--target-rust-version`)
+    #[arg(long, value_name = "VERSION", value_parser = clap::value_parser!(u32).range(2..=4))]
+    pub lockfile_version: Option<u32>,
+}
+
+impl PrepArgs {
+    fn preparer(&self) -> Preparer {
+        let mut preparer = Preparer::new()
+            .pins_file_name(self.pins_file_name.as_str())
+            .remove_rust_version(!self.no_remove_rust_version)
+            .merge_pinned_dependencies(!self.no_merge_pinned_dependencies)
+            .direct_minimal_versions(self.direct_minimal_versions)
+            .remove_toolchain_file(self.remove_toolchain_file)
+            .msrv_aware_resolver(self.msrv_aware_resolver)
+            .net_offline(self.net_offline)
+            .inline_workspace(self.inline_workspace)
+            .strip_lints(self.strip_lints)
+            .lints_to_rustflags(self.lints_to_rustflags)
+            .backup_root_manifest(self.common.backup_root_manifest);
+        for key_path in &self.strip_keys {
+            preparer = preparer.strip_key(key_path.as_str());
+        }
+        if let Some(channel) = &self.toolchain_channel {
+            preparer = preparer.toolchain_channel(channel.as_str());
+        }
+        if let Some(vendored_sources) = &self.vendored_sources {
+            preparer = preparer.vendored_sources(vendored_sources.clone());
+        }
+        if let Some(target_rust_version) = &self.target_rust_version {
+            preparer = preparer.target_rust_version(target_rust_version.clone());
+        }
+        if let Some(lockfile_version) = self.lockfile_version {
+            preparer = preparer.lockfile_version(lockfile_version);
+        }
+        if self.direct_minimal_versions {
+            match CrateIndex::crates_io() {
+                Ok(index) => preparer = preparer.minimal_versions_index(index),
+                Err(err) => {
+                    warn!("Minimal versions will not be checked against published versions: {err}")
+                },
+            }
+        }
+
+        preparer
+    }
+
+    fn prep_options(&self) -> PrepOptions {
+        self.preparer().prep_options()
+    }
+}
+
+#[derive(Debug, Args)]
+struct VerifyArgs {
+    #[command(flatten)]
+    prep: PrepArgs,
+
+    /// Command used to verify MSRV
+    ///
+    /// `{msrv}` is replaced by the package's `rust-version` and `{package}` by its name.
+    /// Packages resulting in the same command are only verified once.
+    #[arg(long, value_name = "COMMAND", default_value = DEFAULT_CHECK_COMMAND)]
+    pub check_command: String,
+}
+
+#[derive(Debug, Args)]
+struct FindArgs {
+    #[command(flatten)]
+    prep: PrepArgs,
+
+    /// Command used to check whether a toolchain works
+    ///
+    /// `{msrv}` is replaced by the name of the toolchain being checked (e.g. `1.70.0`, or
+    /// `1.70-x86_64-unknown-linux-gnu` for toolchains installed via `rustup`).
+    #[arg(long, value_name = "COMMAND", default_value = DEFAULT_CHECK_COMMAND)]
+    pub check_command: String,
+
+    /// Toolchains to check (e.g. `1.70.0,1.75.0`); defaults to toolchains installed via `rustup`
+    #[arg(long, value_name = "VERSIONS", value_delimiter = ',', value_parser = parse_toolchain)]
+    pub toolchains: Vec<Version>,
+
+    /// Ignore toolchains older than this version
+    #[arg(long, value_name = "VERSION", value_parser = parse_toolchain)]
+    pub min_toolchain: Option<Version>,
+
+    /// Ignore toolchains newer than this version
+    #[arg(long, value_name = "VERSION", value_parser = parse_toolchain)]
+    pub max_toolchain: Option<Version>,
+
+    /// Write the MSRV found to the `rust-version` field of the selected packages
+    #[arg(long, default_value_t = false)]
+    pub write_rust_version: bool,
+}
+
+impl FindArgs {
+    fn toolchains(&self) -> cargo_msrv_prep::Result<Vec<Toolchain>> {
+        let mut toolchains = if self.toolchains.is_empty() {
+            installed_toolchains()?
+        } else {
+            self.toolchains
+                .iter()
+                .cloned()
+                .map(Toolchain::from)
+                .collect()
+        };
+
+        toolchains.retain(|toolchain| {
+            self.min_toolchain
+                .as_ref()
+                .is_none_or(|min| toolchain.version >= *min)
+                && self
+                    .max_toolchain
+                    .as_ref()
+                    .is_none_or(|max| toolchain.version <= *max)
+        });
+        toolchains.sort();
+        toolchains.dedup_by(|a, b| a.version == b.version);
+
+        Ok(toolchains)
+    }
+}
+
+#[derive(Debug, Args)]
+struct SuggestPinsArgs {
+    #[command(flatten)]
+    prep: PrepArgs,
+
+    /// Command used to check whether dependencies build
+    ///
+    /// `{msrv}` is replaced by the Rust version (see `--rust-version`). `--message-format=json`
+    /// is added unless a message format is already specified.
+    #[arg(long, value_name = "COMMAND", default_value = DEFAULT_CHECK_COMMAND)]
+    pub check_command: String,
+
+    /// Rust version used to run the check command; defaults to the oldest MSRV of the selected packages
+    #[arg(long, value_name = "VERSION", value_parser = parse_toolchain)]
+    pub rust_version: Option<Version>,
+
+    /// Pin failing dependencies to newer versions instead of older ones
+    ///
+    /// Useful when dependencies fail to build because they are too old (e.g. when checking
+    /// minimal versions)
+    #[arg(long, default_value_t = false)]
+    pub newer: bool,
+
+    /// Maximum number of times the check command is run
+    #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_MAX_ATTEMPTS)]
+    pub max_attempts: usize,
+
+    /// Local copy of the crate registry index to use
+    ///
+    /// Used to find versions of transitive dependencies to pin in the lockfile. Can be
+    /// a registry's directory in `$CARGO_HOME/registry/index` or a directory in the index
+    /// format. Defaults to Cargo's local cache of the crates.io index, if any
+    #[arg(long, value_name = "DIR")]
+    pub index: Option<Utf8PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct MinimizePinsArgs {
+    #[command(flatten)]
+    prep: PrepArgs,
+
+    /// Command used to check whether the workspace builds
+    ///
+    /// `{msrv}` is replaced by the Rust version (see `--rust-version`).
+    #[arg(long, value_name = "COMMAND", default_value = DEFAULT_CHECK_COMMAND)]
+    pub check_command: String,
+
+    /// Rust version used to run the check command; defaults to the oldest MSRV of the selected packages
+    #[arg(long, value_name = "VERSION", value_parser = parse_toolchain)]
+    pub rust_version: Option<Version>,
+
+    /// Remove unnecessary pins from pinned MSRV dependencies files
     #[arg(short, long, default_value_t = false)]
-    pub force: bool,
+    pub write: bool,
+}
 
-    /// Determine if preparation is required without persisting resulting manifests
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct PinArgs {
+    #[command(subcommand)]
+    command: Option<PinCommand>,
+
+    #[command(flatten)]
+    pins: PinsFileArgs,
+
+    /// Name of the dependency to pin (direct or transitive)
+    #[arg(value_name = "CRATE", required = true)]
+    pub crate_name: Option<String>,
+
+    /// Rust version the pinned version must support; defaults to the oldest MSRV of the selected packages
+    #[arg(long = "for", value_name = "MSRV", value_parser = parse_toolchain)]
+    pub for_rust_version: Option<Version>,
+
+    /// Local copy of the crate registry index to use
+    ///
+    /// Can be a registry's directory in `$CARGO_HOME/registry/index` or a directory in the
+    /// index format. Defaults to Cargo's local cache of the crates.io index
+    #[arg(long, value_name = "DIR")]
+    pub index: Option<Utf8PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+enum PinCommand {
+    /// Add (or update) a pin in the pinned MSRV dependencies file of the selected packages
+    Add(PinAddArgs),
+
+    /// Remove a pin from the pinned MSRV dependencies file of the selected packages
+    Remove(PinRemoveArgs),
+}
+
+#[derive(Debug, Args)]
+struct PinsFileArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    /// Name of TOML file containing pinned dependencies
+    #[arg(long, default_value = DEFAULT_MSRV_PINS_FILE_NAME)]
+    pub pins_file_name: String,
+
+    /// Determine which pins files need to be changed without writing them
     ///
     /// To see result, increase verbosity to at least INFO (e.g. `-vv`)
     #[arg(short = 'n', long, default_value_t = false)]
     pub dry_run: bool,
 }
 
+#[derive(Debug, Args)]
+struct PinSectionArgs {
+    /// Pin a build dependency
+    #[arg(long, default_value_t = false, conflicts_with = "dev")]
+    pub build: bool,
+
+    /// Pin a dev dependency
+    #[arg(long, default_value_t = false)]
+    pub dev: bool,
+
+    /// Pin a target-specific dependency (e.g. `cfg(unix)`)
+    #[arg(long, value_name = "TARGET")]
+    pub target: Option<String>,
+}
+
+impl PinSectionArgs {
+    fn kind(&self) -> DependencyKind {
+        match (self.build, self.dev) {
+            (true, _) => DependencyKind::Build,
+            (_, true) => DependencyKind::Development,
+            _ => DependencyKind::Normal,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct PinAddArgs {
+    #[command(flatten)]
+    pins: PinsFileArgs,
+
+    #[command(flatten)]
+    section: PinSectionArgs,
+
+    /// Dependency to pin and its version requirement (e.g. `foo@1.2.3` or `foo@=1.2.3`)
+    #[arg(value_name = "CRATE@VERSION", value_parser = parse_pin)]
+    pub spec: (String, String),
+
+    /// Features to enable (space- or comma-separated); defaults to those of the manifest's dependency
+    #[arg(short = 'F', long, value_name = "FEATURES", value_delimiter = ',')]
+    pub features: Vec<String>,
+
+    /// Disable the dependency's default features
+    #[arg(long, default_value_t = false)]
+    pub no_default_features: bool,
+}
+
+#[derive(Debug, Args)]
+struct PinRemoveArgs {
+    #[command(flatten)]
+    pins: PinsFileArgs,
+
+    #[command(flatten)]
+    section: PinSectionArgs,
+
+    /// Name of the pinned dependency to remove
+    #[arg(value_name = "CRATE")]
+    pub crate_name: String,
+}
+
+#[derive(Debug, Args)]
+struct LintPinsArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    /// Name of TOML file containing pinned dependencies
+    #[arg(long, default_value = DEFAULT_MSRV_PINS_FILE_NAME)]
+    pub pins_file_name: String,
+}
+
+#[derive(Debug, Args)]
+struct SetRustVersionArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    /// Rust version to set (e.g. `1.70.0` or `1.70`)
+    #[arg(value_parser = parse_rust_version)]
+    pub rust_version: String,
+
+    /// Determine which manifests need to be changed without persisting them
+    ///
+    /// To see result, increase verbosity to at least INFO (e.g. `-vv`)
+    #[arg(short = 'n', long, default_value_t = false)]
+    pub dry_run: bool,
+}
+
+fn parse_rust_version(rust_version: &str) -> Result<String, String> {
+    parse_toolchain(rust_version).map(|_| rust_version.into())
+}
+
+fn parse_toolchain(toolchain: &str) -> Result<Version, String> {
+    parse_toolchain_version(toolchain)
+        .ok_or_else(|| format!("invalid toolchain version: {toolchain}"))
+}
+
+fn parse_pin(spec: &str) -> Result<(String, String), String> {
+    parse_pin_spec(spec).map_err(|err| err.to_string())
+}
+
+fn parse_strip_key(key_path: &str) -> Result<String, String> {
+    parse_key_path(key_path)
+        .map(|_| key_path.into())
+        .map_err(|err| err.to_string())
+}
+
+fn oldest_rust_version(metadata: &Metadata) -> Option<Version> {
+    metadata
+        .selected_packages()
+        .iter()
+        .filter_map(|package| package.rust_version.clone())
+        .min()
+}
+
 fn prep_for_msrv(args: &MsrvPrepArgs) -> cargo_msrv_prep::Result<()> {
     trace!("Entering `prep_for_msrv` (args: {args:?})");
 
-    let metadata: Metadata = (&args.common).try_into()?;
-    debug!("Workspace root: {}", metadata.cargo_metadata.workspace_root);
-    debug!("Selected packages: {}", metadata.selected_package_names());
+    let metadata = args.prep.common.metadata()?;
+    let backup_backend = args
+        .prep
+        .common
+        .backup_backend(&metadata.cargo_metadata().workspace_root)?;
 
-    let mut root_manifest_backed_up = false;
-    for package in &metadata.selected_packages {
-        info!("Preparing manifest '{}' (at '{}')", package.name, package.manifest_path);
-
-        let manifest_text = fs::read_to_string(&package.manifest_path)
-            .with_io_context(|| format!("reading manifest of package {}", package.name))?;
-        let mut manifest = manifest_text.parse::<DocumentMut>()?;
+    let report = args
+        .prep
+        .preparer()
+        .force(args.force)
+        .dry_run(args.dry_run)
+        .backup_backend(backup_backend)
+        .prepare(&metadata)?;
+    if !args.prep.common.verbose.is_silent() {
+        eprint!("{report}");
+    }
+    if args.prep.common.message_format == MessageFormat::Github {
+        maybe_append_step_summary(&format!("### `cargo msrv-prep`\n\n{}", report.to_markdown()))?;
+    }
+    if args.fail_if_unchanged && !report.changed() {
+        return Err(Error::NothingToPrep);
+    }
 
-        let rust_version_removed = if !args.no_remove_rust_version {
-            let removed = remove_rust_version(&mut manifest);
+    trace!("Exiting `prep_for_msrv`");
+    Ok(())
+}
 
-            debug!("'{RUST_VERSION_SPECIFIER}' field removed: {removed}");
-            removed
-        } else {
-            info!("Skipping removal of '{RUST_VERSION_SPECIFIER}' field");
-            false
-        };
+fn verify(args: &VerifyArgs) -> cargo_msrv_prep::Result<()> {
+    trace!("Entering `verify` (args: {args:?})");
 
-        let msrv_dependencies_merged = if !args.no_merge_pinned_dependencies {
-            let merged = maybe_merge_msrv_dependencies(
-                &mut manifest,
-                &package.manifest_path,
-                &args.pins_file_name,
-            )?;
+    let metadata = args.prep.common.metadata()?;
+    let workspace_root = metadata.cargo_metadata().workspace_root.clone();
+    let backup_backend = args.prep.common.backup_backend(&workspace_root)?;
 
-            debug!("Pinned MSRV dependencies merged: {merged}");
-            merged
-        } else {
-            info!("Skipping merging of pinned MSRV dependencies");
-            false
-        };
+    let prep_options = args.prep.prep_options();
+    let unprep_options = UnprepOptions {
+        backup_root_manifest: args.prep.common.backup_root_manifest,
+        ..Default::default()
+    };
+    let results = verify_msrv(
+        &metadata,
+        backup_backend.as_ref(),
+        &prep_options,
+        &unprep_options,
+        &args.check_command,
+        |command| command.run(&workspace_root),
+    )?;
 
-        if rust_version_removed || msrv_dependencies_merged {
-            if !args.dry_run {
-                info!("Manifest for '{}' changed after preparation; persisting", package.name);
-
-                backup_manifest(
-                    &package.manifest_path,
-                    &args.common.manifest_backup_suffix,
-                    args.force,
-                )?;
-                mockable_fs::write(&package.manifest_path, manifest.to_string()).with_io_context(
-                    || format!("saving updated manifest content to '{}'", package.manifest_path),
-                )?;
-            } else {
-                info!(
-                    "Manifest for '{}' changed after preparation; not persisting (dry-run mode)",
-                    package.name
-                );
-            }
+    let mut failed = Vec::new();
+    for result in &results {
+        match &result.rust_version {
+            Some(rust_version) => {
+                println!("{} (MSRV {rust_version}): {}", result.package_name, result.status)
+            },
+            None => println!("{}: {}", result.package_name, result.status),
+        }
 
-            root_manifest_backed_up = root_manifest_backed_up
-                || package.manifest_path
-                    == metadata
-                        .cargo_metadata
-                        .workspace_root
-                        .join(DEFAULT_MANIFEST_FILE_NAME);
-        } else {
-            info!("Manifest for '{}' not changed after preparation; skipping", package.name);
+        if result.status == VerifyStatus::Failed {
+            failed.push(result.package_name.clone());
         }
     }
 
-    if args.common.backup_root_manifest {
-        if !root_manifest_backed_up {
-            if !args.dry_run {
-                info!("Backing up root manifest (at '{}')", metadata.cargo_metadata.workspace_root);
-
-                // Note: this will fail if the root manifest has a non-standard name, but
-                // there doesn't seem to be an easy way to fetch the name of the root
-                // manifest when it doesn't contain a package itself, so we have no choice.
-                backup_manifest(
-                    &metadata
-                        .cargo_metadata
-                        .workspace_root
-                        .join(DEFAULT_MANIFEST_FILE_NAME),
-                    &args.common.manifest_backup_suffix,
-                    args.force,
-                )?;
-            } else {
-                info!("Root manifest needs backup; skipping (dry-run mode)");
-            }
-        } else {
-            info!("Root manifest already backed up; skipping");
-        }
+    trace!("Exiting `verify`");
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::MsrvVerificationFailed(failed))
     }
+}
 
-    trace!("Exiting `prep_for_msrv`");
+fn find(args: &FindArgs) -> cargo_msrv_prep::Result<()> {
+    trace!("Entering `find` (args: {args:?})");
+
+    let toolchains = args.toolchains()?;
+    if toolchains.is_empty() {
+        return Err(Error::NoToolchains);
+    }
+    info!(
+        "Toolchains to check: {}",
+        toolchains
+            .iter()
+            .map(Toolchain::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let metadata = args.prep.common.metadata()?;
+    let workspace_root = metadata.cargo_metadata().workspace_root.clone();
+    let backup_backend = args.prep.common.backup_backend(&workspace_root)?;
+
+    let prep_options = args.prep.prep_options();
+    let unprep_options = UnprepOptions {
+        backup_root_manifest: args.prep.common.backup_root_manifest,
+        ..Default::default()
+    };
+    let msrv = find_msrv(
+        &metadata,
+        backup_backend.as_ref(),
+        &prep_options,
+        &unprep_options,
+        &args.check_command,
+        &toolchains,
+        |command| command.run(&workspace_root),
+    )?
+    .ok_or(Error::MsrvNotFound)?;
+    println!("MSRV: {msrv}");
+
+    if args.write_rust_version {
+        set_workspace_rust_version(&metadata, backup_backend.as_ref(), &msrv.to_string(), false)?;
+    }
+
+    trace!("Exiting `find`");
     Ok(())
 }
 
-#[cfg(test)]
-#[cfg_attr(coverage_nightly, coverage(off))]
-mod tests {
-    use std::path::PathBuf;
+fn suggest_pins(args: &SuggestPinsArgs) -> cargo_msrv_prep::Result<()> {
+    trace!("Entering `suggest_pins` (args: {args:?})");
 
-    use assert_fs::fixture::PathCopy;
-    use assert_fs::TempDir;
+    let metadata = args.prep.common.metadata()?;
+    let workspace_root = metadata.cargo_metadata().workspace_root.clone();
+    let backup_backend = args.prep.common.backup_backend(&workspace_root)?;
 
-    use super::*;
+    let rust_version = args
+        .rust_version
+        .clone()
+        .or_else(|| oldest_rust_version(&metadata))
+        .ok_or(Error::MissingRustVersion)?;
+    let prep_options = args.prep.prep_options();
+    let unprep_options = UnprepOptions {
+        backup_root_manifest: args.prep.common.backup_root_manifest,
+        ..Default::default()
+    };
+    let options = SuggestPinsOptions {
+        check_command_template: args.check_command.clone(),
+        rust_version,
+        direction: if args.newer { PinDirection::Newer } else { PinDirection::Older },
+        max_attempts: args.max_attempts,
+        index: match &args.index {
+            Some(index) => Some(CrateIndex::open(index)?),
+            None => CrateIndex::crates_io()
+                .inspect_err(|err| warn!("Transitive dependencies will not be pinned: {err}"))
+                .ok(),
+        },
+    };
+    let result = suggest_pins::suggest_pins(
+        &metadata,
+        backup_backend.as_ref(),
+        &prep_options,
+        &unprep_options,
+        &options,
+        |command| {
+            command
+                .output(&workspace_root)
+                .inspect(|output| eprint!("{}", output.stderr))
+        },
+    )?;
 
-    fn project_path(project_name: &str) -> PathBuf {
-        [env!("CARGO_MANIFEST_DIR"), "resources", "tests", "cargo-msrv-prep", project_name]
-            .iter()
-            .collect()
+    for suggestion in &result.suggestions {
+        println!(
+            "{}: {} = \"{}\" ({} failed to build)",
+            suggestion.location.pins_file_path,
+            suggestion.location,
+            suggestion.requirement,
+            suggestion.package
+        );
     }
 
-    fn fork_project(project_name: &str) -> TempDir {
-        let temp = TempDir::new().unwrap();
+    trace!("Exiting `suggest_pins`");
+    match result.outcome {
+        SuggestPinsOutcome::Succeeded => Ok(()),
+        SuggestPinsOutcome::NoPinnableFailure => Err(Error::NoPinnableFailure),
+        SuggestPinsOutcome::LimitReached => {
+            Err(Error::PinSuggestionLimitReached(args.max_attempts))
+        },
+    }
+}
 
-        temp.copy_from(project_path(project_name), &["*.rs", "*.toml", "*.lock"])
-            .unwrap();
+fn minimize_pins(args: &MinimizePinsArgs) -> cargo_msrv_prep::Result<()> {
+    trace!("Entering `minimize_pins` (args: {args:?})");
 
-        temp
+    let metadata = args.prep.common.metadata()?;
+    let workspace_root = metadata.cargo_metadata().workspace_root.clone();
+    let backup_backend = args.prep.common.backup_backend(&workspace_root)?;
+
+    let rust_version = args
+        .rust_version
+        .clone()
+        .or_else(|| oldest_rust_version(&metadata))
+        .ok_or(Error::MissingRustVersion)?;
+    let prep_options = args.prep.prep_options();
+    let unprep_options = UnprepOptions {
+        backup_root_manifest: args.prep.common.backup_root_manifest,
+        ..Default::default()
+    };
+    let options = MinimizePinsOptions {
+        check_command_template: args.check_command.clone(),
+        rust_version,
+        write: args.write,
+    };
+    let result = minimize_pins::minimize_pins(
+        &metadata,
+        backup_backend.as_ref(),
+        &prep_options,
+        &unprep_options,
+        &options,
+        |command| command.run(&workspace_root),
+    )?;
+
+    for location in &result.required {
+        println!("{}: {} (required)", location.pins_file_path, location);
+    }
+    for location in &result.unnecessary {
+        println!("{}: {} (unnecessary)", location.pins_file_path, location);
     }
 
-    mod errors {
-        use std::io;
-
-        use assert_fs::fixture::PathChild;
-        use assert_matches::assert_matches;
-        use cargo_msrv_prep::Error;
-
-        use super::*;
-
-        #[test_log::test]
-        fn modified_manifest_write_error() {
-            let temp = fork_project("simple_project");
-
-            let Cli::MsrvPrep(args) = Cli::parse_from(
-                [
-                    "cargo".to_string(),
-                    "msrv-prep".to_string(),
-                    "--manifest-path".to_string(),
-                    temp.child("Cargo.toml").to_string_lossy().to_string(),
-                    "-vvvv".to_string(),
-                ]
-                .iter(),
-            );
-
-            let ctx = mockable_fs::write_context();
-            ctx.expect().returning(|_, _| {
-                Err(io::Error::new(io::ErrorKind::PermissionDenied, "permission denied"))
-            });
-
-            assert_matches!(prep_for_msrv(&args), Err(Error::Io { source, .. }) => {
-                assert_eq!(io::ErrorKind::PermissionDenied, source.kind());
-            });
+    trace!("Exiting `minimize_pins`");
+    Ok(())
+}
+
+fn pin(args: &PinArgs) -> cargo_msrv_prep::Result<()> {
+    trace!("Entering `pin` (args: {args:?})");
+
+    let metadata = args.pins.common.metadata()?;
+    let rust_version = args
+        .for_rust_version
+        .clone()
+        .or_else(|| oldest_rust_version(&metadata))
+        .ok_or(Error::MissingRustVersion)?;
+    let index = match &args.index {
+        Some(index) => CrateIndex::open(index)?,
+        None => CrateIndex::crates_io()?,
+    };
+
+    let pin = pin_for_rust_version(
+        &metadata,
+        &index,
+        &args.pins.pins_file_name,
+        args.crate_name.as_deref().unwrap_or_default(),
+        &rust_version,
+        args.pins.dry_run,
+    )?;
+    println!(
+        "{}: {} = \"{}\" (rust-version: {})",
+        pin.location.pins_file_path,
+        pin.location,
+        pin.requirement,
+        pin.entry
+            .rust_version
+            .as_ref()
+            .map_or_else(|| "unspecified".into(), Version::to_string)
+    );
+
+    trace!("Exiting `pin`");
+    Ok(())
+}
+
+fn pin_add(args: &PinAddArgs) -> cargo_msrv_prep::Result<()> {
+    trace!("Entering `pin_add` (args: {args:?})");
+
+    let metadata = args.pins.common.metadata()?;
+    let (name, requirement) = args.spec.clone();
+    let features = args
+        .features
+        .iter()
+        .flat_map(|features| features.split_whitespace())
+        .map(Into::into)
+        .collect();
+    let spec = PinSpec {
+        name,
+        requirement,
+        kind: args.section.kind(),
+        target: args.section.target.clone(),
+        features,
+        no_default_features: args.no_default_features,
+    };
+
+    for package in metadata.selected_packages() {
+        let (location, changed) =
+            add_pin(package, &args.pins.pins_file_name, &spec, args.pins.dry_run)?;
+        if changed {
+            println!("{}: added {location}", location.pins_file_path);
+        }
+    }
+
+    trace!("Exiting `pin_add`");
+    Ok(())
+}
+
+fn pin_remove(args: &PinRemoveArgs) -> cargo_msrv_prep::Result<()> {
+    trace!("Entering `pin_remove` (args: {args:?})");
+
+    let metadata = args.pins.common.metadata()?;
+
+    let mut removed = false;
+    for package in metadata.selected_packages() {
+        let (location, changed) = remove_pin(
+            package,
+            &args.pins.pins_file_name,
+            &args.crate_name,
+            args.section.kind(),
+            args.section.target.as_deref(),
+            args.pins.dry_run,
+        )?;
+        if changed {
+            println!("{}: removed {location}", location.pins_file_path);
+            removed = true;
+        }
+    }
+
+    trace!("Exiting `pin_remove`");
+    if removed {
+        Ok(())
+    } else {
+        Err(Error::PinNotFound(args.crate_name.clone()))
+    }
+}
+
+fn lint_pins(args: &LintPinsArgs) -> cargo_msrv_prep::Result<()> {
+    trace!("Entering `lint_pins` (args: {args:?})");
+
+    let metadata = args.common.metadata()?;
+    let expired = expired_pins(&metadata, &args.pins_file_name)?;
+
+    for pin in &expired {
+        let until_rust_version = pin
+            .metadata
+            .until_rust_version
+            .as_ref()
+            .expect("pin should have an `until-rust-version`");
+        print!(
+            "{}: {} is no longer needed (until Rust {until_rust_version}, MSRV is {})",
+            pin.location.pins_file_path, pin.location, pin.rust_version
+        );
+        match &pin.metadata.reason {
+            Some(reason) => println!(": {reason}"),
+            None => println!(),
         }
     }
+
+    trace!("Exiting `lint_pins` (expired: {})", expired.len());
+    match expired.len() {
+        0 => Ok(()),
+        count => Err(Error::ExpiredPins(count)),
+    }
+}
+
+fn set_rust_version(args: &SetRustVersionArgs) -> cargo_msrv_prep::Result<()> {
+    trace!("Entering `set_rust_version` (args: {args:?})");
+
+    let metadata = args.common.metadata()?;
+    let backup_backend = args
+        .common
+        .backup_backend(&metadata.cargo_metadata().workspace_root)?;
+
+    let changed = set_workspace_rust_version(
+        &metadata,
+        backup_backend.as_ref(),
+        &args.rust_version,
+        args.dry_run,
+    )?;
+    info!("Manifests changed: {}", changed.len());
+
+    trace!("Exiting `set_rust_version`");
+    Ok(())
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn verify_cli() {
+        Cli::command().debug_assert();
+    }
 }
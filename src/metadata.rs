@@ -1,13 +1,45 @@
-use cargo_metadata::Package;
+//! Metadata of the workspace being prepped.
 
-use crate::common_args::CommonArgs;
+use std::path::Path;
 
+use cargo_metadata::{MetadataCommand, Package};
+
+/// Metadata of a Cargo workspace, along with the packages selected for preparation.
+///
+/// Functions like [`prep_for_msrv`](crate::prep::prep_for_msrv) only process the selected
+/// packages (although they can also modify the workspace's root manifest).
 pub struct Metadata {
-    pub cargo_metadata: cargo_metadata::Metadata,
-    pub selected_packages: Vec<Package>,
+    pub(crate) cargo_metadata: cargo_metadata::Metadata,
+    pub(crate) selected_packages: Vec<Package>,
 }
 
 impl Metadata {
+    /// Loads metadata for the workspace of the given manifest, selecting all workspace members.
+    ///
+    /// Metadata is loaded by running `cargo metadata`.
+    pub fn for_manifest<P>(manifest_path: P) -> crate::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let metadata = MetadataCommand::new()
+            .manifest_path(manifest_path.as_ref())
+            .exec()?;
+        let selected_packages = metadata.workspace_packages().into_iter().cloned().collect();
+
+        Ok(Metadata { cargo_metadata: metadata, selected_packages })
+    }
+
+    /// Returns the metadata of the workspace, as returned by `cargo metadata`.
+    pub fn cargo_metadata(&self) -> &cargo_metadata::Metadata {
+        &self.cargo_metadata
+    }
+
+    /// Returns the packages selected for preparation.
+    pub fn selected_packages(&self) -> &[Package] {
+        &self.selected_packages
+    }
+
+    /// Returns the names of the selected packages, separated by commas (e.g. for logging).
     pub fn selected_package_names(&self) -> String {
         self.selected_packages
             .iter()
@@ -16,16 +48,3 @@ impl Metadata {
             .join(", ")
     }
 }
-
-impl TryFrom<&CommonArgs> for Metadata {
-    type Error = crate::Error;
-
-    fn try_from(value: &CommonArgs) -> Result<Self, Self::Error> {
-        let metadata = value.manifest.metadata().exec()?;
-
-        let (selected_packages, _) = value.workspace.partition_packages(&metadata);
-        let selected_packages: Vec<_> = selected_packages.into_iter().cloned().collect();
-
-        Ok(Metadata { cargo_metadata: metadata, selected_packages })
-    }
-}
@@ -0,0 +1,612 @@
+//! Helpers to pin a manifest's direct dependencies to their minimal versions.
+//!
+//! Each dependency's version requirement is rewritten to `=<min>`, where `<min>` is the lowest
+//! version satisfying the original requirement. When Cargo resolves dependencies afterwards
+//! (e.g. via `cargo generate-lockfile`), direct dependencies are thus resolved to their minimal
+//! versions, without requiring a nightly toolchain (like `-Z minimal-versions` does). When
+//! preparing manifests, the workspace's lockfile is generated that way afterwards (see
+//! [`generate_minimal_versions_lockfile`]).
+//!
+//! Since Cargo can only select one version per semver-compatible range of a crate, minimal
+//! versions are computed across the whole workspace (see [`MinimalVersions`]): if one package
+//! requires `^1.2` and another `^1.5`, both are pinned to `=1.5.0`. If an index is available,
+//! the lowest version actually published is used (e.g. `=1.2.1` if `1.2.0` was never published).
+//!
+//! Dependencies using a `path` or `git` source, as well as those inherited from the workspace
+//! (which are pinned in the workspace's `workspace.dependencies` section instead), are skipped.
+
+use std::collections::HashMap;
+
+use cargo_metadata::camino::Utf8Path;
+use cargo_metadata::semver::{Comparator, Op, Prerelease, Version, VersionReq};
+use log::{debug, info, trace, warn};
+use toml_edit::{Formatted, Item, Table, TableLike, Value};
+
+use crate::backup::BackupBackend;
+use crate::detail::{
+    BUILD_DEPENDENCIES_SECTION_NAME, DEPENDENCIES_SECTION_NAME, DEV_DEPENDENCIES_SECTION_NAME,
+    TARGET_SECTION_NAME, WORKSPACE_SECTION_NAME,
+};
+use crate::index::CrateIndex;
+use crate::lockfile::backup_workspace_lockfile;
+use crate::verify::CheckCommand;
+use crate::{Error, Result};
+
+/// Field of a dependency specifying its version requirement.
+const VERSION_FIELD: &str = "version";
+
+/// Field of a dependency specifying the name of the crate, if it was renamed.
+const PACKAGE_FIELD: &str = "package";
+
+/// Fields of a dependency specifying a source other than a registry.
+const NON_REGISTRY_SOURCE_FIELDS: &[&str] = &["path", "git"];
+
+/// Marker preceding the name of the crate that could not be resolved in Cargo's error messages.
+const RESOLUTION_FAILURE_MARKER: &str = "failed to select a version for ";
+
+/// Name of a crate and semver-compatible range of its versions (see [`compatibility`]).
+type CrateRange = (String, (u64, u64, u64));
+
+/// Returns the minimal version satisfying a version requirement (e.g. `1.2.0` for `^1.2`).
+///
+/// Returns `None` if the requirement is invalid or has no lower bound (e.g. `*` or `<2`).
+pub fn minimal_version(requirement: &str) -> Option<Version> {
+    VersionReq::parse(requirement)
+        .ok()?
+        .comparators
+        .iter()
+        .filter_map(lower_bound)
+        .max()
+}
+
+/// Minimal versions of the direct dependencies of a workspace.
+///
+/// Requirements are grouped per crate and per semver-compatible range (e.g. `1.x` or `0.3.x`),
+/// since Cargo can only select one version in each. The minimal version of a group is the
+/// largest lower bound of its requirements, or the lowest published version satisfying all of
+/// them if an index is available (see [`resolve`](Self::resolve)).
+#[derive(Debug, Clone, Default)]
+pub struct MinimalVersions {
+    requirements: HashMap<CrateRange, Vec<VersionReq>>,
+    versions: HashMap<CrateRange, Version>,
+}
+
+impl MinimalVersions {
+    /// Adds the requirements of all dependencies in a manifest (see
+    /// [`pin_direct_minimal_versions`] for the sections that are considered).
+    pub fn add_manifest(&mut self, manifest: &Table) {
+        self.add_dependencies_sections(manifest);
+
+        if let Some(targets) = manifest.get(TARGET_SECTION_NAME).and_then(Item::as_table) {
+            for (_, target) in targets.iter() {
+                if let Some(target) = target.as_table_like() {
+                    self.add_dependencies_sections(target);
+                }
+            }
+        }
+
+        if let Some(workspace) = manifest
+            .get(WORKSPACE_SECTION_NAME)
+            .and_then(Item::as_table_like)
+        {
+            self.add_workspace(workspace);
+        }
+    }
+
+    /// Adds the requirements of the dependencies in a `workspace` section.
+    pub fn add_workspace(&mut self, workspace: &dyn TableLike) {
+        if let Some(dependencies) = workspace
+            .get(DEPENDENCIES_SECTION_NAME)
+            .and_then(Item::as_table_like)
+        {
+            self.add_dependencies(dependencies);
+        }
+    }
+
+    /// Computes the minimal version of each crate, using the given index (if any) to pick
+    /// the lowest published version that satisfies all requirements.
+    ///
+    /// If a crate is not found in the index, or if none of its published versions satisfies
+    /// all requirements, the largest lower bound of its requirements is used instead.
+    pub fn resolve(&mut self, index: Option<&CrateIndex>) -> Result<()> {
+        trace!("Entering `MinimalVersions::resolve` (crates: {})", self.requirements.len());
+
+        for (key, requirements) in &self.requirements {
+            let Some(lower_bound) = requirements
+                .iter()
+                .flat_map(|requirement| requirement.comparators.iter().filter_map(lower_bound))
+                .max()
+            else {
+                continue;
+            };
+
+            let published = match index {
+                Some(index) => index
+                    .versions(&key.0)?
+                    .into_iter()
+                    .filter(|entry| !entry.yanked && entry.version >= lower_bound)
+                    .map(|entry| entry.version)
+                    .filter(|version| requirements.iter().all(|req| req.matches(version)))
+                    .min(),
+                None => None,
+            };
+            let version = published.unwrap_or(lower_bound);
+            debug!("Minimal version of '{}' in workspace: {version}", key.0);
+
+            self.versions.insert(key.clone(), version);
+        }
+
+        trace!("Exiting `MinimalVersions::resolve`");
+        Ok(())
+    }
+
+    /// Returns the version a dependency with the given crate name and version requirement
+    /// should be pinned to.
+    ///
+    /// If the minimal version computed for the workspace does not satisfy the requirement
+    /// (e.g. `~1.2` when another package requires `^1.5`), the requirement's own minimal
+    /// version is returned, leaving the conflict to Cargo.
+    pub fn pinned_version(&self, name: &str, requirement: &str) -> Option<Version> {
+        let own = minimal_version(requirement)?;
+        let workspace = self
+            .versions
+            .get(&(name.into(), compatibility(&own)))
+            .filter(|version| VersionReq::parse(requirement).is_ok_and(|req| req.matches(version)));
+
+        Some(workspace.cloned().unwrap_or(own))
+    }
+
+    fn add_dependencies_sections(&mut self, table: &dyn TableLike) {
+        for name in [
+            DEPENDENCIES_SECTION_NAME,
+            DEV_DEPENDENCIES_SECTION_NAME,
+            BUILD_DEPENDENCIES_SECTION_NAME,
+        ] {
+            if let Some(dependencies) = table.get(name).and_then(Item::as_table_like) {
+                self.add_dependencies(dependencies);
+            }
+        }
+    }
+
+    fn add_dependencies(&mut self, dependencies: &dyn TableLike) {
+        for (key, dependency) in dependencies.iter() {
+            let Some((name, requirement)) = registry_requirement(key, dependency) else {
+                continue;
+            };
+            let (Ok(req), Some(min)) =
+                (VersionReq::parse(requirement), minimal_version(requirement))
+            else {
+                continue;
+            };
+
+            self.requirements
+                .entry((name.into(), compatibility(&min)))
+                .or_default()
+                .push(req);
+        }
+    }
+}
+
+/// Rewrites the version requirements of all dependencies in a manifest to `=<min>`,
+/// where `<min>` is the minimal version of the dependency in the workspace
+/// (see [`MinimalVersions::pinned_version`]).
+///
+/// This includes normal, dev and build dependencies (including target-specific ones),
+/// as well as the dependencies in the `workspace.dependencies` section.
+///
+/// Returns `true` if the manifest was modified.
+pub fn pin_direct_minimal_versions(
+    manifest: &mut Table,
+    minimal_versions: &MinimalVersions,
+) -> bool {
+    trace!("Entering `pin_direct_minimal_versions`");
+
+    let mut changed = pin_dependencies_sections(manifest, minimal_versions);
+
+    if let Some(Item::Table(targets)) = manifest.get_mut(TARGET_SECTION_NAME) {
+        for (_, target) in targets.iter_mut() {
+            if let Some(target) = target.as_table_like_mut() {
+                changed = pin_dependencies_sections(target, minimal_versions) || changed;
+            }
+        }
+    }
+
+    if let Some(dependencies) = manifest
+        .get_mut(WORKSPACE_SECTION_NAME)
+        .and_then(|workspace| workspace.get_mut(DEPENDENCIES_SECTION_NAME))
+        .and_then(Item::as_table_like_mut)
+    {
+        changed = pin_dependencies(dependencies, minimal_versions) || changed;
+    }
+
+    trace!("Exiting `pin_direct_minimal_versions` (changed: {changed})");
+    changed
+}
+
+/// Generates the lockfile of the given workspace (via `cargo generate-lockfile`), so that
+/// direct dependencies pinned by [`pin_direct_minimal_versions`] are resolved to their minimal
+/// versions.
+///
+/// The workspace's lockfile is backed up beforehand using the given backend (along with the
+/// root manifest, unless it was already backed up). If the lockfile did not exist, restoring
+/// the backup will delete it. If `dry_run` is `true`, nothing is actually modified.
+pub fn generate_minimal_versions_lockfile(
+    workspace_root: &Utf8Path,
+    backup_backend: &dyn BackupBackend,
+    force: bool,
+    dry_run: bool,
+) -> Result<()> {
+    trace!("Entering `generate_minimal_versions_lockfile` (workspace_root: '{workspace_root}')");
+
+    if dry_run {
+        info!("Lockfile needs to be generated; skipping (dry-run mode)");
+        return Ok(());
+    }
+
+    backup_workspace_lockfile(workspace_root, backup_backend, force)?;
+
+    info!("Generating lockfile resolving direct dependencies to their minimal versions");
+    let command = CheckCommand { program: "cargo".into(), args: vec!["generate-lockfile".into()] };
+    let output = command.output(workspace_root)?;
+    if !output.success {
+        warn!("`{command}` failed:\n{}", output.stderr.trim_end());
+
+        return Err(match unresolved_crate(&output.stderr) {
+            Some(name) => Error::MinimalVersionUnresolved(name),
+            None => Error::GenerateLockfileFailed,
+        });
+    }
+
+    trace!("Exiting `generate_minimal_versions_lockfile`");
+    Ok(())
+}
+
+fn lower_bound(comparator: &Comparator) -> Option<Version> {
+    let minor = comparator.minor.unwrap_or(0);
+    let patch = comparator.patch.unwrap_or(0);
+
+    match comparator.op {
+        Op::Exact | Op::GreaterEq | Op::Tilde | Op::Caret | Op::Wildcard => {
+            let mut version = Version::new(comparator.major, minor, patch);
+            version.pre = comparator.pre.clone();
+            Some(version)
+        },
+        Op::Greater if comparator.pre == Prerelease::EMPTY => {
+            Some(match (comparator.minor, comparator.patch) {
+                (Some(minor), Some(patch)) => Version::new(comparator.major, minor, patch + 1),
+                (Some(minor), None) => Version::new(comparator.major, minor + 1, 0),
+                (None, _) => Version::new(comparator.major + 1, 0, 0),
+            })
+        },
+        _ => None,
+    }
+}
+
+/// Returns the semver-compatible range a version belongs to, as the `(major, minor, patch)`
+/// prefix that must stay the same (e.g. `(1, 0, 0)` for `1.2.3` or `(0, 3, 0)` for `0.3.1`).
+fn compatibility(version: &Version) -> (u64, u64, u64) {
+    match (version.major, version.minor) {
+        (0, 0) => (0, 0, version.patch),
+        (0, minor) => (0, minor, 0),
+        (major, _) => (major, 0, 0),
+    }
+}
+
+/// Returns the crate name and version requirement of a dependency, unless it uses a source
+/// other than a registry (or has no version requirement).
+fn registry_requirement<'a>(key: &'a str, dependency: &'a Item) -> Option<(&'a str, &'a str)> {
+    match dependency {
+        Item::Value(Value::String(requirement)) => Some((key, requirement.value())),
+        _ => {
+            let dependency = dependency.as_table_like()?;
+            if NON_REGISTRY_SOURCE_FIELDS
+                .iter()
+                .any(|field| dependency.contains_key(field))
+            {
+                return None;
+            }
+
+            let name = dependency
+                .get(PACKAGE_FIELD)
+                .and_then(Item::as_str)
+                .unwrap_or(key);
+            Some((name, dependency.get(VERSION_FIELD)?.as_str()?))
+        },
+    }
+}
+
+/// Returns the name of the crate Cargo failed to select a version for, from the output
+/// of `cargo generate-lockfile` (e.g. ``failed to select a version for `semver`.``).
+fn unresolved_crate(stderr: &str) -> Option<String> {
+    let (_, rest) = stderr.split_once(RESOLUTION_FAILURE_MARKER)?;
+    let (_, rest) = rest.split_once('`')?;
+    let (quoted, _) = rest.split_once('`')?;
+
+    // Note: for unmatched requirements, Cargo quotes the whole requirement
+    // (e.g. ``failed to select a version for the requirement `semver = "=1.0.99"` ``).
+    quoted.split_whitespace().next().map(Into::into)
+}
+
+fn pin_dependencies_sections(
+    table: &mut dyn TableLike,
+    minimal_versions: &MinimalVersions,
+) -> bool {
+    let mut changed = false;
+
+    for name in
+        [DEPENDENCIES_SECTION_NAME, DEV_DEPENDENCIES_SECTION_NAME, BUILD_DEPENDENCIES_SECTION_NAME]
+    {
+        if let Some(dependencies) = table.get_mut(name).and_then(Item::as_table_like_mut) {
+            changed = pin_dependencies(dependencies, minimal_versions) || changed;
+        }
+    }
+
+    changed
+}
+
+fn pin_dependencies(dependencies: &mut dyn TableLike, minimal_versions: &MinimalVersions) -> bool {
+    let mut changed = false;
+
+    for (key, dependency) in dependencies.iter_mut() {
+        let Some(pinned) = registry_requirement(key.get(), dependency)
+            .and_then(|(name, requirement)| pin_requirement(minimal_versions, name, requirement))
+        else {
+            continue;
+        };
+
+        let requirement = match dependency {
+            Item::Value(Value::String(requirement)) => requirement,
+            _ => match dependency
+                .as_table_like_mut()
+                .and_then(|dependency| dependency.get_mut(VERSION_FIELD))
+            {
+                Some(Item::Value(Value::String(requirement))) => requirement,
+                _ => continue,
+            },
+        };
+
+        debug!("Pinning dependency '{key}' to '{pinned}'");
+
+        let decor = requirement.decor().clone();
+        *requirement = Formatted::new(pinned);
+        *requirement.decor_mut() = decor;
+        changed = true;
+    }
+
+    changed
+}
+
+fn pin_requirement(
+    minimal_versions: &MinimalVersions,
+    name: &str,
+    requirement: &str,
+) -> Option<String> {
+    let pinned = format!("={}", minimal_versions.pinned_version(name, requirement)?);
+    (pinned != requirement.trim()).then_some(pinned)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod minimal_version {
+        use super::*;
+
+        #[test]
+        fn lower_bounds() {
+            let min = |req| minimal_version(req).map(|version| version.to_string());
+
+            assert_eq!(Some("1.2.0".into()), min("1.2"));
+            assert_eq!(Some("1.2.3".into()), min("^1.2.3"));
+            assert_eq!(Some("1.2.0".into()), min("~1.2"));
+            assert_eq!(Some("1.0.0".into()), min("1.*"));
+            assert_eq!(Some("1.2.3".into()), min("=1.2.3"));
+            assert_eq!(Some("1.5.0".into()), min(">=1.2, >=1.5, <2"));
+            assert_eq!(Some("1.2.4".into()), min(">1.2.3"));
+            assert_eq!(Some("1.3.0".into()), min(">1.2"));
+            assert_eq!(Some("2.0.0".into()), min(">1"));
+            assert_eq!(Some("0.16.2".into()), min("0.16.2+1.7.2"));
+            assert_eq!(Some("1.0.0-beta.1".into()), min("1.0.0-beta.1"));
+        }
+
+        #[test]
+        fn no_lower_bound() {
+            assert_eq!(None, minimal_version("*"));
+            assert_eq!(None, minimal_version("<2"));
+            assert_eq!(None, minimal_version("not a version"));
+        }
+    }
+
+    mod pin_direct_minimal_versions {
+        use cargo_metadata::camino::Utf8PathBuf;
+        use indoc::indoc;
+        use toml_edit::DocumentMut;
+
+        use super::*;
+
+        fn pin_workspace(manifests: &mut [DocumentMut], index: Option<&CrateIndex>) -> Vec<bool> {
+            let mut minimal_versions = MinimalVersions::default();
+            for manifest in manifests.iter() {
+                minimal_versions.add_manifest(manifest);
+            }
+            minimal_versions.resolve(index).unwrap();
+
+            manifests
+                .iter_mut()
+                .map(|manifest| pin_direct_minimal_versions(manifest, &minimal_versions))
+                .collect()
+        }
+
+        #[test_log::test]
+        fn all_sections() {
+            let mut manifest = indoc! {r#"
+                [dependencies]
+                serde = { version = "1.0", features = ["derive"] } # serialization
+                either = "1.10.0"
+                local = { path = "../local", version = "0.1" }
+                inherited.workspace = true
+
+                [dev-dependencies]
+                mockall = "=0.12.1"
+
+                [build-dependencies.rustc_version]
+                version = "~0.4"
+
+                [target.'cfg(windows)'.dependencies]
+                winapi = ">=0.3.5, <0.4"
+
+                [workspace.dependencies]
+                inherited = "2.1"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            assert_eq!(vec![true], pin_workspace(std::slice::from_mut(&mut manifest), None));
+
+            let expected = indoc! {r#"
+                [dependencies]
+                serde = { version = "=1.0.0", features = ["derive"] } # serialization
+                either = "=1.10.0"
+                local = { path = "../local", version = "0.1" }
+                inherited.workspace = true
+
+                [dev-dependencies]
+                mockall = "=0.12.1"
+
+                [build-dependencies.rustc_version]
+                version = "=0.4.0"
+
+                [target.'cfg(windows)'.dependencies]
+                winapi = "=0.3.5"
+
+                [workspace.dependencies]
+                inherited = "=2.1.0"
+            "#};
+            assert_eq!(expected, manifest.to_string());
+        }
+
+        #[test_log::test]
+        fn already_pinned() {
+            let manifest_text = indoc! {r#"
+                [dependencies]
+                either = "=1.10.0"
+                anything = "*"
+            "#};
+            let mut manifest = manifest_text.parse::<DocumentMut>().unwrap();
+
+            assert_eq!(vec![false], pin_workspace(std::slice::from_mut(&mut manifest), None));
+            assert_eq!(manifest_text, manifest.to_string());
+        }
+
+        #[test_log::test]
+        fn across_workspace() {
+            let mut manifests = [
+                indoc! {r#"
+                    [dependencies]
+                    either = "1.2"
+                    semver = "0.9"
+                    tilde = "~1.2"
+                "#},
+                indoc! {r#"
+                    [dependencies]
+                    other-either = { package = "either", version = "1.5" }
+                    semver = "1.0.3"
+                    tilde = "1.5"
+
+                    [workspace.dependencies]
+                    either = "1.4"
+                "#},
+            ]
+            .map(|manifest| manifest.parse::<DocumentMut>().unwrap());
+
+            assert_eq!(vec![true, true], pin_workspace(&mut manifests, None));
+
+            let expected = [
+                indoc! {r#"
+                    [dependencies]
+                    either = "=1.5.0"
+                    semver = "=0.9.0"
+                    tilde = "=1.2.0"
+                "#},
+                indoc! {r#"
+                    [dependencies]
+                    other-either = { package = "either", version = "=1.5.0" }
+                    semver = "=1.0.3"
+                    tilde = "=1.5.0"
+
+                    [workspace.dependencies]
+                    either = "=1.5.0"
+                "#},
+            ];
+            assert_eq!(expected, manifests.map(|manifest| manifest.to_string()));
+        }
+
+        #[test_log::test]
+        fn published_versions() {
+            let path: Utf8PathBuf = [env!("CARGO_MANIFEST_DIR"), "resources", "tests", "index"]
+                .iter()
+                .collect();
+            let index = CrateIndex::open(path).unwrap();
+            let mut manifest = indoc! {r#"
+                [dependencies]
+                semver = "1.0.10"
+                either = "1.11"
+                unknown = "0.1"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            assert_eq!(
+                vec![true],
+                pin_workspace(std::slice::from_mut(&mut manifest), Some(&index))
+            );
+
+            // Note: `either` 1.11.0 is yanked, and `unknown` is not found in the index (so the
+            // lower bound of its requirement is used).
+            let expected = indoc! {r#"
+                [dependencies]
+                semver = "=1.0.21"
+                either = "=1.12.0"
+                unknown = "=0.1.0"
+            "#};
+            assert_eq!(expected, manifest.to_string());
+        }
+    }
+
+    mod unresolved_crate {
+        use indoc::indoc;
+
+        use super::*;
+
+        #[test]
+        fn conflict() {
+            let stderr = indoc! {"
+                    Updating crates.io index
+                error: failed to select a version for `either`.
+                    ... required by package `b v0.1.0 (/tmp/workspace/b)`
+                versions that meet the requirements `=1.5.0` are: 1.5.0
+            "};
+
+            assert_eq!(Some("either".into()), unresolved_crate(stderr));
+        }
+
+        #[test]
+        fn unmatched_requirement() {
+            let stderr = indoc! {r#"
+                    Updating crates.io index
+                error: failed to select a version for the requirement `semver = "=1.0.99"`
+                candidate versions found which didn't match: 1.0.23, 1.0.22, 1.0.21
+            "#};
+
+            assert_eq!(Some("semver".into()), unresolved_crate(stderr));
+        }
+
+        #[test]
+        fn other_failure() {
+            let stderr = "error: could not find `Cargo.toml` in `/tmp` or any parent directory";
+
+            assert_eq!(None, unresolved_crate(stderr));
+        }
+    }
+}
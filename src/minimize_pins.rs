@@ -0,0 +1,434 @@
+//! Helpers to find which MSRV pins are actually required.
+//!
+//! Pins are dropped one at a time: for each pin, the pinned MSRV dependencies files are
+//! rewritten without it (and without the pins already found to be unnecessary), manifests are
+//! prepared (see [`prep_for_msrv`](crate::prep::prep_for_msrv)), a check command is run, then
+//! manifests are restored (see [`unprep_from_msrv`]). If the command still succeeds, the pin is
+//! unnecessary.
+//!
+//! Pins files are backed up before the first trial (using the same backend as manifests), so
+//! that the originals are not lost if trials are interrupted. They are restored afterwards,
+//! then rewritten if asked to keep only required pins.
+//!
+//! Since the check command can update the workspace's lockfile (and dropping pins can change
+//! the dependencies resolved), the root manifest and lockfile are always backed up and restored
+//! for each trial.
+
+use std::collections::HashSet;
+use std::fs;
+
+use cargo_metadata::camino::Utf8PathBuf;
+use cargo_metadata::semver::Version;
+use log::{debug, error, info, trace};
+use toml_edit::DocumentMut;
+
+use crate::backup::BackupBackend;
+use crate::metadata::Metadata;
+use crate::pins::{list_pins, pins_file_path, unset_pin, write_pins_file, PinLocation};
+use crate::prep::PrepOptions;
+use crate::result::{IoErrorContext, TomlErrorContext};
+use crate::unprep::{unprep_from_msrv, UnprepOptions};
+use crate::verify::{prep_for_check, CheckCommand, PACKAGE_PLACEHOLDER};
+use crate::{Error, Result};
+
+/// Options used by [`minimize_pins`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinimizePinsOptions {
+    /// Template of the check command (see [`CheckCommand::from_template`]).
+    pub check_command_template: String,
+
+    /// Rust version used to run the check command.
+    pub rust_version: Version,
+
+    /// Whether to rewrite pinned MSRV dependencies files to keep only required pins.
+    pub write: bool,
+}
+
+/// Result of [`minimize_pins`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MinimizePinsResult {
+    /// Pins required for the check command to succeed.
+    pub required: Vec<PinLocation>,
+
+    /// Pins that can be removed without affecting the check command.
+    pub unnecessary: Vec<PinLocation>,
+}
+
+struct PinsFile {
+    path: Utf8PathBuf,
+    original: DocumentMut,
+}
+
+/// Finds which pins of the selected packages are required for a check command to succeed.
+///
+/// The check command is built from [`MinimizePinsOptions::check_command_template`] and run
+/// via `runner` for each trial (see [module description](self) for details). It must succeed
+/// with all pins applied, otherwise [`Error::PinsBaselineFailed`] is returned. Pins files are
+/// backed up using `backup_backend` beforehand; manifests and pins files are always restored,
+/// even if preparing manifests or running a command fails.
+pub fn minimize_pins<R>(
+    metadata: &Metadata,
+    backup_backend: &dyn BackupBackend,
+    prep_options: &PrepOptions,
+    unprep_options: &UnprepOptions,
+    options: &MinimizePinsOptions,
+    mut runner: R,
+) -> Result<MinimizePinsResult>
+where
+    R: FnMut(&CheckCommand) -> Result<bool>,
+{
+    trace!("Entering `minimize_pins` (options: {options:?})");
+
+    if options.check_command_template.contains(PACKAGE_PLACEHOLDER) {
+        return Err(Error::InvalidCheckCommand(options.check_command_template.clone()));
+    }
+    let command = CheckCommand::from_template(
+        &options.check_command_template,
+        &options.rust_version.to_string(),
+        "",
+    )?;
+
+    let mut pins_files = Vec::new();
+    let mut pins = Vec::new();
+    for package in &metadata.selected_packages {
+        let pins_file_path = pins_file_path(package, &prep_options.pins_file_name);
+        if !pins_file_path.is_file() {
+            continue;
+        }
+
+        let original_text = fs::read_to_string(&pins_file_path)
+            .with_io_context(|| format!("reading MSRV pins file '{pins_file_path}'"))?;
+        let original: DocumentMut = original_text
+            .parse()
+            .with_toml_file(&pins_file_path, &original_text)?;
+        pins.extend(list_pins(&original).into_iter().map(|(section, key)| {
+            (
+                pins_files.len(),
+                PinLocation::new(package, &prep_options.pins_file_name, section, key),
+            )
+        }));
+        pins_files.push(PinsFile { path: pins_file_path, original });
+    }
+    debug!("Pins found: {}", pins.len());
+
+    let prep_options = PrepOptions { backup_root_manifest: true, ..prep_options.clone() };
+    let unprep_options = UnprepOptions { backup_root_manifest: true, ..unprep_options.clone() };
+
+    if !pins.is_empty() {
+        let pins_file_paths: Vec<_> = pins_files
+            .iter()
+            .map(|pins_file| pins_file.path.as_path())
+            .collect();
+        backup_backend.backup_files(&pins_file_paths, prep_options.force)?;
+    }
+
+    let mut dropped = HashSet::new();
+    let mut trial = |dropped: &HashSet<usize>| -> Result<bool> {
+        write_trial_pins_files(&pins_files, &pins, dropped)?;
+        prep_for_check(metadata, backup_backend, &prep_options, &unprep_options)?;
+
+        info!("Running check command '{command}' ({} pin(s) dropped)", dropped.len());
+        let run_result = runner(&command);
+        if let Err(err) = &run_result {
+            error!("Failed to run check command '{command}': {err}");
+        }
+
+        let unprep_result = unprep_from_msrv(metadata, backup_backend, &unprep_options);
+        let passed = run_result?;
+        unprep_result?;
+        Ok(passed)
+    };
+    let trials_result = (|| {
+        if pins.is_empty() {
+            return Ok(());
+        }
+        if !trial(&dropped)? {
+            return Err(Error::PinsBaselineFailed);
+        }
+
+        for (i, (_, location)) in pins.iter().enumerate() {
+            dropped.insert(i);
+            if trial(&dropped)? {
+                info!("Pin '{location}' is unnecessary");
+            } else {
+                info!("Pin '{location}' is required");
+                dropped.remove(&i);
+            }
+        }
+        Ok(())
+    })();
+
+    let restore_result = if pins.is_empty() {
+        Ok(())
+    } else {
+        restore_pins_files(&pins_files, backup_backend).and_then(|()| {
+            if options.write && trials_result.is_ok() {
+                write_trial_pins_files(&pins_files, &pins, &dropped)
+            } else {
+                Ok(())
+            }
+        })
+    };
+    trials_result?;
+    restore_result?;
+
+    let (unnecessary, required): (Vec<_>, Vec<_>) = pins
+        .into_iter()
+        .enumerate()
+        .partition(|(i, _)| dropped.contains(i));
+    let result = MinimizePinsResult {
+        required: required
+            .into_iter()
+            .map(|(_, (_, location))| location)
+            .collect(),
+        unnecessary: unnecessary
+            .into_iter()
+            .map(|(_, (_, location))| location)
+            .collect(),
+    };
+
+    trace!(
+        "Exiting `minimize_pins` (required: {}, unnecessary: {})",
+        result.required.len(),
+        result.unnecessary.len()
+    );
+    Ok(result)
+}
+
+fn write_trial_pins_files(
+    pins_files: &[PinsFile],
+    pins: &[(usize, PinLocation)],
+    dropped: &HashSet<usize>,
+) -> Result<()> {
+    for (file_index, pins_file) in pins_files.iter().enumerate() {
+        let mut trial = pins_file.original.clone();
+        for (_, (_, location)) in pins
+            .iter()
+            .enumerate()
+            .filter(|(i, (pin_file_index, _))| *pin_file_index == file_index && dropped.contains(i))
+        {
+            unset_pin(&mut trial, &location.section, &location.key);
+        }
+
+        write_pins_file(&pins_file.path, &trial)?;
+    }
+
+    Ok(())
+}
+
+/// Restores all pins files from their backups, even if one cannot be restored (the first error
+/// is returned).
+fn restore_pins_files(pins_files: &[PinsFile], backup_backend: &dyn BackupBackend) -> Result<()> {
+    let mut first_error = None;
+    for pins_file in pins_files {
+        if let Err(err) = backup_backend.maybe_restore_file(&pins_file.path) {
+            error!("Failed to restore MSRV pins file '{}': {err}", pins_file.path);
+            first_error.get_or_insert(err);
+        }
+    }
+
+    first_error.map_or(Ok(()), Err)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod minimize_pins {
+        use std::path::PathBuf;
+        use std::sync::{Arc, Mutex};
+
+        use assert_fs::assert::PathAssert;
+        use assert_fs::fixture::{FileWriteStr, PathChild, PathCopy};
+        use assert_fs::TempDir;
+        use assert_matches::assert_matches;
+        use indoc::indoc;
+        use predicates::path::missing;
+
+        use super::*;
+        use crate::backup::FileBackupBackend;
+        use crate::mockable::mock_fs as mockable_fs;
+        use crate::verify::DEFAULT_CHECK_COMMAND;
+        use crate::DEFAULT_MANIFEST_BACKUP_SUFFIX;
+
+        const ORIGINAL_PINS: &str = indoc! {r#"
+            [dependencies]
+            serde = "1.0.197"
+
+            [build-dependencies]
+            libgit2-sys = "0.16.2+1.7.2"
+
+            [target.'cfg(windows)'.dependencies]
+            winapi = "0.3.9"
+        "#};
+
+        /// Runs `minimize_pins` on a copy of `simple_project`, passing the content of the last
+        /// pins file written to `runner`. Returns the result, along with the content of all pins
+        /// files written (in order).
+        ///
+        /// `setup` is called on the copy of the project beforehand; afterwards, the pins file
+        /// is checked to have been restored from its backup.
+        fn perform_test<S, R>(
+            write: bool,
+            setup: S,
+            mut runner: R,
+        ) -> (Result<MinimizePinsResult>, Vec<String>)
+        where
+            S: FnOnce(&TempDir),
+            R: FnMut(&str) -> Result<bool>,
+        {
+            let project_path: PathBuf = [
+                env!("CARGO_MANIFEST_DIR"),
+                "resources",
+                "tests",
+                "cargo-msrv-prep",
+                "simple_project",
+            ]
+            .iter()
+            .collect();
+            let temp = TempDir::new().unwrap();
+            temp.copy_from(project_path, &["*.rs", "*.toml", "*.lock"])
+                .unwrap();
+            setup(&temp);
+
+            let metadata = Metadata::for_manifest(temp.child("Cargo.toml").path()).unwrap();
+            let backup_backend = FileBackupBackend::new(DEFAULT_MANIFEST_BACKUP_SUFFIX);
+
+            // Note: pins files are not actually written here, since our `fs` functions are
+            // mocked in unit tests; we record their content instead. Manifests are not modified
+            // either (see `dry_run`). Backups are actually made and restored, however.
+            let _lock = crate::mockable::lock_fs_mock();
+            let written = Arc::new(Mutex::new(Vec::new()));
+            let ctx = mockable_fs::write_context();
+            ctx.expect().returning({
+                let written = Arc::clone(&written);
+                move |_, contents| {
+                    let contents = String::from_utf8(contents.as_ref().to_vec()).unwrap();
+                    written.lock().unwrap().push(contents);
+                    Ok(())
+                }
+            });
+            let copy_ctx = mockable_fs::copy_context();
+            copy_ctx
+                .expect()
+                .returning(|from, to| std::fs::copy(from, to));
+            let rename_ctx = mockable_fs::rename_context();
+            rename_ctx
+                .expect()
+                .returning(|from, to| std::fs::rename(from, to));
+
+            let result = minimize_pins(
+                &metadata,
+                &backup_backend,
+                &PrepOptions { dry_run: true, ..Default::default() },
+                &UnprepOptions::default(),
+                &MinimizePinsOptions {
+                    check_command_template: DEFAULT_CHECK_COMMAND.into(),
+                    rust_version: Version::new(1, 70, 0),
+                    write,
+                },
+                |command| {
+                    assert_eq!("cargo +1.70.0 check --workspace --lib --bins", command.to_string());
+                    let last_written = written.lock().unwrap().last().cloned().unwrap_or_default();
+                    runner(&last_written)
+                },
+            );
+
+            temp.child("msrv-pins.toml").assert(ORIGINAL_PINS);
+            if !matches!(result, Err(Error::BackupFileAlreadyExists(_))) {
+                temp.child("msrv-pins.toml.msrv-prep.bak").assert(missing());
+            }
+
+            let written = written.lock().unwrap().clone();
+            (result, written)
+        }
+
+        fn locations(locations: &[PinLocation]) -> Vec<String> {
+            locations.iter().map(ToString::to_string).collect()
+        }
+
+        #[test_log::test]
+        fn report() {
+            let mut runs = 0;
+            let (result, written) = perform_test(
+                false,
+                |_| {},
+                |pins_file| {
+                    runs += 1;
+                    Ok(pins_file.contains("serde"))
+                },
+            );
+
+            assert_matches!(result, Ok(result) => {
+                assert_eq!(vec!["dependencies.serde"], locations(&result.required));
+                assert_eq!(
+                    vec![
+                        "build-dependencies.libgit2-sys",
+                        "target.\"cfg(windows)\".dependencies.winapi"
+                    ],
+                    locations(&result.unnecessary)
+                );
+            });
+            assert_eq!(4, runs);
+            assert_eq!(4, written.len());
+        }
+
+        #[test_log::test]
+        fn write() {
+            let (result, written) =
+                perform_test(true, |_| {}, |pins_file| Ok(pins_file.contains("serde")));
+
+            assert_matches!(result, Ok(result) => {
+                assert_eq!(1, result.required.len());
+                assert_eq!(2, result.unnecessary.len());
+            });
+            assert_eq!(
+                Some("[dependencies]\nserde = \"1.0.197\"\n"),
+                written.last().map(String::as_str)
+            );
+        }
+
+        #[test_log::test]
+        fn baseline_failed() {
+            let mut runs = 0;
+            let (result, written) = perform_test(
+                true,
+                |_| {},
+                |_| {
+                    runs += 1;
+                    Ok(false)
+                },
+            );
+
+            assert_matches!(result, Err(Error::PinsBaselineFailed));
+            assert_eq!(1, runs);
+            assert_eq!(1, written.len());
+        }
+
+        #[test_log::test]
+        fn runner_error() {
+            let (result, written) =
+                perform_test(true, |_| {}, |_| Err(Error::InvalidCheckCommand("oops".into())));
+
+            assert_matches!(result, Err(Error::InvalidCheckCommand(_)));
+            assert_eq!(1, written.len());
+        }
+
+        #[test_log::test]
+        fn backup_exists() {
+            let (result, written) = perform_test(
+                true,
+                |temp| {
+                    temp.child("msrv-pins.toml.msrv-prep.bak")
+                        .write_str("[dependencies]\n")
+                        .unwrap()
+                },
+                |_| panic!("no trial should be run"),
+            );
+
+            assert_matches!(result, Err(Error::BackupFileAlreadyExists(_)));
+            assert!(written.is_empty());
+        }
+    }
+}
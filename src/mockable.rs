@@ -23,4 +23,35 @@ pub mod fs {
     {
         real_fs::rename(from, to)
     }
+
+    #[cfg_attr(test, mockall::concretize)]
+    pub fn remove_file<P>(path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        real_fs::remove_file(path)
+    }
+
+    #[cfg_attr(test, mockall::concretize)]
+    pub fn write<P, C>(path: P, contents: C) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        C: AsRef<[u8]>,
+    {
+        real_fs::write(path, contents)
+    }
+}
+
+/// Lock held by tests setting expectations on [`fs`] functions, since mock contexts are global
+/// and do not provide any synchronization.
+#[cfg(test)]
+static FS_MOCK_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Acquires the lock that must be held while setting expectations on [`fs`] functions
+/// (see [`FS_MOCK_LOCK`]). It must be acquired before creating any mock context.
+#[cfg(test)]
+pub fn lock_fs_mock() -> std::sync::MutexGuard<'static, ()> {
+    FS_MOCK_LOCK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
 }
@@ -0,0 +1,1181 @@
+//! Helpers to edit pinned MSRV dependencies files.
+//!
+//! Dependencies are pinned by adding an entry to one of the dependencies sections of the
+//! pinned MSRV dependencies file next to a package's manifest (see
+//! [`maybe_merge_msrv_dependencies`](crate::maybe_merge_msrv_dependencies)). Since entries
+//! replace those of the manifest when merged, pins of direct dependencies keep the
+//! dependency's other fields (e.g. `features`).
+//!
+//! Pins can also store metadata in an `msrv-prep` field, which is removed before merging (see
+//! [`PinMetadata`]):
+//!
+//! ```toml
+//! [dependencies]
+//! memchr = { version = "=2.5.0", msrv-prep.reason = "2.6 needs 1.61", msrv-prep.until-rust-version = "1.61" }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::semver::{Version, VersionReq};
+use cargo_metadata::{Dependency, DependencyKind, Package, PackageId};
+use log::{debug, info, trace};
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Key, Table, TableLike, Value};
+
+use crate::detail::{
+    unformatted, BUILD_DEPENDENCIES_SECTION_NAME, DEPENDENCIES_SECTION_NAME,
+    DEV_DEPENDENCIES_SECTION_NAME, TARGET_SECTION_NAME,
+};
+use crate::find::parse_toolchain_version;
+use crate::index::{CrateIndex, IndexEntry};
+use crate::lock_pins::LOCK_SECTION_NAME;
+use crate::metadata::Metadata;
+#[mockall_double::double]
+use crate::mockable::fs as mockable_fs;
+use crate::result::{IoErrorContext, TomlErrorContext};
+use crate::strip_keys::MSRV_PREP_SECTION_NAME;
+use crate::{Error, Result};
+
+/// Field of a dependency specifying its version requirement.
+pub const VERSION_FIELD: &str = "version";
+
+/// Field of a pin storing its metadata (see [`PinMetadata`]).
+pub const PIN_METADATA_FIELD: &str = MSRV_PREP_SECTION_NAME;
+
+/// Field of a pin's metadata explaining why the pin is needed.
+pub const REASON_FIELD: &str = "reason";
+
+/// Field of a pin's metadata specifying the Rust version from which the pin is no longer needed.
+pub const UNTIL_RUST_VERSION_FIELD: &str = "until-rust-version";
+
+/// Names of the dependencies sections that can contain pins.
+const DEPENDENCIES_SECTION_NAMES: &[&str] =
+    &[DEPENDENCIES_SECTION_NAME, DEV_DEPENDENCIES_SECTION_NAME, BUILD_DEPENDENCIES_SECTION_NAME];
+
+/// Pin written by [`pin_for_rust_version`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RustVersionPin {
+    /// Where the pin was written.
+    pub location: PinLocation,
+
+    /// Version of the dependency that was pinned, as listed in the index.
+    pub entry: IndexEntry,
+
+    /// Version requirement the dependency was pinned to (e.g. `=1.2.3`), or version it was
+    /// pinned to in the lockfile (e.g. `1.2.3`, for transitive dependencies).
+    pub requirement: String,
+
+    /// Whether the pins file was modified.
+    pub changed: bool,
+}
+
+/// Metadata of a pin, stored in its `msrv-prep` field (see [`PIN_METADATA_FIELD`]).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PinMetadata {
+    /// Why the pin is needed (see [`REASON_FIELD`]).
+    pub reason: Option<String>,
+
+    /// Rust version from which the pin is no longer needed (see [`UNTIL_RUST_VERSION_FIELD`]).
+    pub until_rust_version: Option<Version>,
+}
+
+/// Pin that is no longer needed, as reported by [`expired_pins`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpiredPin {
+    /// Location of the pin.
+    pub location: PinLocation,
+
+    /// Metadata of the pin.
+    pub metadata: PinMetadata,
+
+    /// MSRV of the package the pin belongs to.
+    pub rust_version: Version,
+}
+
+/// Location of a dependency's pin in a pinned MSRV dependencies file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinLocation {
+    /// Path of the pinned MSRV dependencies file.
+    pub pins_file_path: Utf8PathBuf,
+
+    /// Path of the dependencies section (e.g. `["target", "cfg(unix)", "dependencies"]`).
+    pub section: Vec<String>,
+
+    /// Key of the dependency in its section (its name, unless it is renamed).
+    pub key: String,
+}
+
+impl PinLocation {
+    /// Determines where to pin a dependency of the selected packages.
+    ///
+    /// If a selected package depends directly on a registry dependency with this name,
+    /// the pin is stored in the same section of its pinned MSRV dependencies file (the
+    /// dependency is returned as well, see [`pin_value`]). Otherwise, the pin is added to the
+    /// `lock` section (see [`LOCK_SECTION_NAME`]) of the first selected package depending on it
+    /// transitively, so that the package's dependency graph is not changed; such pins must
+    /// specify an exact version.
+    ///
+    /// Returns `None` if no selected package depends on the dependency.
+    pub fn for_dependency<'a>(
+        metadata: &'a Metadata,
+        pins_file_name: &str,
+        dependency_name: &str,
+    ) -> Option<(Self, Option<&'a Dependency>)> {
+        let direct = metadata.selected_packages.iter().find_map(|package| {
+            package
+                .dependencies
+                .iter()
+                .find(|dependency| {
+                    dependency.name == dependency_name
+                        && is_registry_source(dependency.source.as_deref())
+                })
+                .map(|dependency| (package, dependency))
+        });
+        if let Some((package, dependency)) = direct {
+            let target = dependency.target.as_ref().map(ToString::to_string);
+            let section = pin_section(dependency.kind, target.as_deref());
+            let key = dependency
+                .rename
+                .as_ref()
+                .unwrap_or(&dependency.name)
+                .clone();
+
+            return Some((Self::new(package, pins_file_name, section, key), Some(dependency)));
+        }
+
+        let package = metadata
+            .selected_packages
+            .iter()
+            .find(|package| depends_on(&metadata.cargo_metadata, &package.id, dependency_name))?;
+        let section = vec![LOCK_SECTION_NAME.into()];
+
+        Some((Self::new(package, pins_file_name, section, dependency_name.into()), None))
+    }
+
+    /// Returns the location of a pin in the pinned MSRV dependencies file of a package.
+    pub fn new(package: &Package, pins_file_name: &str, section: Vec<String>, key: String) -> Self {
+        Self { pins_file_path: pins_file_path(package, pins_file_name), section, key }
+    }
+}
+
+impl Display for PinLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", dotted_key(self.section.iter().chain([&self.key])))
+    }
+}
+
+/// Dependency to pin via [`add_pin`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinSpec {
+    /// Name of the dependency.
+    pub name: String,
+
+    /// Version requirement to pin the dependency to.
+    pub requirement: String,
+
+    /// Kind of dependency (normal, dev or build).
+    pub kind: DependencyKind,
+
+    /// Target platform of the dependency (e.g. `cfg(unix)`), if any.
+    pub target: Option<String>,
+
+    /// Features to enable; if empty, those of the manifest's dependency are kept.
+    pub features: Vec<String>,
+
+    /// Whether to disable the dependency's default features.
+    pub no_default_features: bool,
+}
+
+/// Parses a pin spec of the form `<name>@<version requirement>` (e.g. `foo@1.2.3` or `foo@=1.2.3`),
+/// returning the name and version requirement.
+pub fn parse_pin_spec(spec: &str) -> Result<(String, String)> {
+    match spec.split_once('@') {
+        Some((name, requirement)) if !name.is_empty() && VersionReq::parse(requirement).is_ok() => {
+            Ok((name.into(), requirement.into()))
+        },
+        _ => Err(Error::InvalidPinSpec(spec.into())),
+    }
+}
+
+/// Returns the path of the dependencies section storing pins for dependencies of a given kind
+/// (e.g. `["target", "cfg(unix)", "build-dependencies"]`).
+pub fn pin_section(kind: DependencyKind, target: Option<&str>) -> Vec<String> {
+    let section_name = match kind {
+        DependencyKind::Development => DEV_DEPENDENCIES_SECTION_NAME,
+        DependencyKind::Build => BUILD_DEPENDENCIES_SECTION_NAME,
+        _ => DEPENDENCIES_SECTION_NAME,
+    };
+
+    match target {
+        Some(target) => vec![TARGET_SECTION_NAME.into(), target.into(), section_name.into()],
+        None => vec![section_name.into()],
+    }
+}
+
+/// Adds (or updates) a pin in the pinned MSRV dependencies file of a package.
+///
+/// If the package's manifest declares the dependency in the same section, the manifest
+/// dependency's other fields are kept (see [`pin_value`]), unless overridden.
+///
+/// Returns the pin's location and whether the pins file was modified.
+pub fn add_pin(
+    package: &Package,
+    pins_file_name: &str,
+    spec: &PinSpec,
+    dry_run: bool,
+) -> Result<(PinLocation, bool)> {
+    trace!("Entering `add_pin` (package: '{}', spec: {spec:?})", package.name);
+
+    let dependency = package.dependencies.iter().find(|dependency| {
+        dependency.rename.as_ref().unwrap_or(&dependency.name) == &spec.name
+            && dependency.kind == spec.kind
+            && dependency.target.as_ref().map(ToString::to_string) == spec.target
+    });
+    let mut value = pin_value(dependency, &spec.requirement);
+    if !spec.features.is_empty() || spec.no_default_features {
+        let mut inline = match value {
+            Value::InlineTable(inline) => inline,
+            value => {
+                let mut inline = InlineTable::new();
+                inline.insert(VERSION_FIELD, value);
+                inline
+            },
+        };
+        if !spec.features.is_empty() {
+            inline.insert("features", spec.features.iter().collect::<Array>().into());
+        }
+        if spec.no_default_features {
+            inline.insert("default-features", false.into());
+        }
+        value = inline.into();
+    }
+
+    let section = pin_section(spec.kind, spec.target.as_deref());
+    let location = PinLocation::new(package, pins_file_name, section, spec.name.clone());
+    let mut pins_file = read_pins_file(&location.pins_file_path)?;
+    let changed = set_pin(&mut pins_file, &location.section, &location.key, value)?;
+    if changed {
+        info!("Adding pin '{location}' to '{}'", location.pins_file_path);
+        if !dry_run {
+            write_pins_file(&location.pins_file_path, &pins_file)?;
+        }
+    }
+
+    trace!("Exiting `add_pin` (changed: {changed})");
+    Ok((location, changed))
+}
+
+/// Removes a pin from the pinned MSRV dependencies file of a package, if found.
+///
+/// Returns the pin's location and whether the pins file was modified.
+pub fn remove_pin(
+    package: &Package,
+    pins_file_name: &str,
+    name: &str,
+    kind: DependencyKind,
+    target: Option<&str>,
+    dry_run: bool,
+) -> Result<(PinLocation, bool)> {
+    trace!("Entering `remove_pin` (package: '{}', name: '{name}')", package.name);
+
+    let section = pin_section(kind, target);
+    let location = PinLocation::new(package, pins_file_name, section, name.into());
+    let changed = if location.pins_file_path.is_file() {
+        let mut pins_file = read_pins_file(&location.pins_file_path)?;
+        let changed = unset_pin(&mut pins_file, &location.section, &location.key);
+        if changed {
+            info!("Removing pin '{location}' from '{}'", location.pins_file_path);
+            if !dry_run {
+                write_pins_file(&location.pins_file_path, &pins_file)?;
+            }
+        }
+        changed
+    } else {
+        false
+    };
+
+    trace!("Exiting `remove_pin` (changed: {changed})");
+    Ok((location, changed))
+}
+
+/// Returns `true` if a package source is a registry (e.g. crates.io).
+pub fn is_registry_source(source: Option<&str>) -> bool {
+    source.is_some_and(|source| source.starts_with("registry+") || source.starts_with("sparse+"))
+}
+
+/// Returns the value to store in a pinned MSRV dependencies file to pin a dependency to the
+/// given version requirement.
+///
+/// For direct dependencies, the dependency's other fields (e.g. `features`) are kept, since
+/// the pin replaces the manifest's entry when merged.
+pub fn pin_value(dependency: Option<&Dependency>, requirement: &str) -> Value {
+    let Some(dependency) = dependency else {
+        return requirement.into();
+    };
+
+    let mut value = InlineTable::new();
+    value.insert(VERSION_FIELD, requirement.into());
+    if dependency.rename.is_some() {
+        value.insert("package", dependency.name.as_str().into());
+    }
+    if let Some(registry) = &dependency.registry {
+        value.insert("registry", registry.as_str().into());
+    }
+    if !dependency.features.is_empty() {
+        value.insert("features", dependency.features.iter().collect::<Array>().into());
+    }
+    if !dependency.uses_default_features {
+        value.insert("default-features", false.into());
+    }
+    if dependency.optional {
+        value.insert("optional", true.into());
+    }
+
+    if value.len() == 1 {
+        requirement.into()
+    } else {
+        value.into()
+    }
+}
+
+/// Returns a version requirement matching all versions that are semver-compatible with
+/// the given version (e.g. `^1` for `1.2.3`, `^0.3` for `0.3.5`).
+pub fn compatible_requirement(version: &Version) -> VersionReq {
+    let requirement = match (version.major, version.minor) {
+        (0, 0) => format!("=0.0.{}", version.patch),
+        (0, minor) => format!("^0.{minor}"),
+        (major, _) => format!("^{major}"),
+    };
+
+    VersionReq::parse(&requirement).expect("requirement should be valid")
+}
+
+/// Pins a dependency of the selected packages to the highest version compatible with the
+/// given Rust version, according to a crate registry index
+/// (see [`CrateIndex::highest_compatible`]).
+///
+/// The pin is written where [`PinLocation::for_dependency`] says. For direct dependencies,
+/// only versions matching the dependency's version requirement are considered, and the
+/// dependency is pinned to an exact requirement (e.g. `=1.2.3`); for transitive dependencies,
+/// only versions that are semver-compatible with the resolved version are, and the dependency
+/// is pinned in the lockfile (e.g. `1.2.3`).
+pub fn pin_for_rust_version(
+    metadata: &Metadata,
+    index: &CrateIndex,
+    pins_file_name: &str,
+    dependency_name: &str,
+    rust_version: &Version,
+    dry_run: bool,
+) -> Result<RustVersionPin> {
+    trace!(
+        "Entering `pin_for_rust_version` (dependency_name: '{dependency_name}', rust_version: {rust_version})"
+    );
+
+    let resolved = metadata
+        .cargo_metadata
+        .packages
+        .iter()
+        .filter(|package| package.name == dependency_name)
+        .map(|package| &package.version)
+        .max()
+        .ok_or_else(|| Error::UnknownDependency(dependency_name.into()))?;
+    let (location, dependency) =
+        PinLocation::for_dependency(metadata, pins_file_name, dependency_name)
+            .ok_or_else(|| Error::UnknownDependency(dependency_name.into()))?;
+
+    let requirement = match dependency {
+        Some(dependency) => dependency.req.clone(),
+        None => compatible_requirement(resolved),
+    };
+    let entry = index
+        .highest_compatible(dependency_name, rust_version, Some(&requirement))?
+        .ok_or_else(|| Error::NoCompatibleVersion {
+            name: dependency_name.into(),
+            rust_version: rust_version.clone(),
+        })?;
+
+    let requirement = match dependency {
+        Some(_) => format!("={}", entry.version),
+        None => entry.version.to_string(),
+    };
+    let mut pins_file = read_pins_file(&location.pins_file_path)?;
+    let changed = set_pin(
+        &mut pins_file,
+        &location.section,
+        &location.key,
+        pin_value(dependency, &requirement),
+    )?;
+    if changed {
+        info!("Pinning {dependency_name} to '{requirement}' in '{}'", location.pins_file_path);
+        if !dry_run {
+            write_pins_file(&location.pins_file_path, &pins_file)?;
+        }
+    }
+
+    trace!("Exiting `pin_for_rust_version` (changed: {changed})");
+    Ok(RustVersionPin { location, entry, requirement, changed })
+}
+
+/// Returns the version requirement of a pinned dependency, if any.
+pub fn pinned_requirement(pin: &Item) -> Option<&str> {
+    pin.as_str().or_else(|| {
+        pin.as_table_like()?
+            .get(VERSION_FIELD)
+            .and_then(Item::as_str)
+    })
+}
+
+/// Stores a pin in a pinned MSRV dependencies file, replacing any existing pin for the
+/// same dependency.
+///
+/// Returns `Ok(true)` if the pins file was modified (e.g. `Ok(false)` if the dependency was
+/// already pinned the same way; the pin's metadata is not compared, since it is preserved).
+/// If one of the section's tables exists but is not a table (e.g. `target.'cfg(unix)' = "x"`),
+/// an error is returned.
+pub fn set_pin(pins_file: &mut Table, section: &[String], key: &str, value: Value) -> Result<bool> {
+    let mut parent = pins_file;
+    for (i, name) in section.iter().enumerate() {
+        // Intermediate tables (e.g. `target.'cfg(unix)'`) don't need a header.
+        let implicit = i + 1 < section.len();
+        let item = parent.entry(name).or_insert_with(|| {
+            let mut new_table = Table::new();
+            new_table.set_implicit(implicit);
+            Item::Table(new_table)
+        });
+        if let Some(inline_table) = item.as_inline_table_mut() {
+            let mut new_table = std::mem::take(inline_table).into_table();
+            new_table.set_implicit(implicit);
+            *item = Item::Table(new_table);
+        }
+        parent = item
+            .as_table_mut()
+            .ok_or_else(|| Error::InvalidPinsSection(dotted_key(&section[..=i])))?;
+    }
+
+    if parent.get(key).and_then(pin_fields) == pin_fields(&Item::Value(value.clone())) {
+        return Ok(false);
+    }
+
+    let existing_metadata = parent
+        .get(key)
+        .and_then(Item::as_table_like)
+        .and_then(|pin| pin.get(PIN_METADATA_FIELD))
+        .and_then(|pin_metadata| pin_metadata.clone().into_value().ok());
+    let value = match (existing_metadata, value) {
+        (Some(pin_metadata), Value::InlineTable(mut pin)) => {
+            pin.insert(PIN_METADATA_FIELD, pin_metadata);
+            Value::InlineTable(pin)
+        },
+        (Some(pin_metadata), value) => {
+            let mut pin = InlineTable::new();
+            pin.insert(VERSION_FIELD, value);
+            pin.insert(PIN_METADATA_FIELD, pin_metadata);
+            Value::InlineTable(pin)
+        },
+        (None, value) => value,
+    };
+
+    parent.insert(key, Item::Value(value));
+    Ok(true)
+}
+
+/// Returns the fields of a pin (e.g. `version`, `features`) without its metadata, sorted by name
+/// so that pins can be compared. Values are stripped of their formatting (see [`unformatted`]).
+fn pin_fields(pin: &Item) -> Option<Vec<(String, String)>> {
+    let mut fields: Vec<_> = match pin.as_table_like() {
+        Some(pin) => pin
+            .iter()
+            .filter(|(name, _)| *name != PIN_METADATA_FIELD)
+            .map(|(name, field)| {
+                Some((name.to_string(), unformatted(&field.clone().into_value().ok()?)))
+            })
+            .collect::<Option<_>>()?,
+        None => vec![(VERSION_FIELD.to_string(), unformatted(pin.as_value()?))],
+    };
+    fields.sort();
+
+    Some(fields)
+}
+
+/// Lists the pins stored in a pinned MSRV dependencies file, in order, as pairs of section
+/// path and key (see [`PinLocation`]).
+///
+/// This includes dependencies pins (including target-specific ones) and lockfile pins
+/// (see [`LOCK_SECTION_NAME`]).
+pub fn list_pins(pins_file: &Table) -> Vec<(Vec<String>, String)> {
+    fn keys(section: &Item, path: Vec<String>) -> Vec<(Vec<String>, String)> {
+        section
+            .as_table_like()
+            .map(|section| {
+                section
+                    .iter()
+                    .map(|(key, _)| (path.clone(), key.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    let mut pins = Vec::new();
+    for (name, item) in pins_file.iter() {
+        if name == TARGET_SECTION_NAME {
+            let targets = item
+                .as_table_like()
+                .into_iter()
+                .flat_map(|targets| targets.iter());
+            for (target, target_item) in targets {
+                let sections = target_item
+                    .as_table_like()
+                    .into_iter()
+                    .flat_map(|sections| sections.iter());
+                for (section_name, section) in sections {
+                    if DEPENDENCIES_SECTION_NAMES.contains(&section_name) {
+                        let path = vec![name.into(), target.into(), section_name.into()];
+                        pins.extend(keys(section, path));
+                    }
+                }
+            }
+        } else if DEPENDENCIES_SECTION_NAMES.contains(&name) || name == LOCK_SECTION_NAME {
+            pins.extend(keys(item, vec![name.into()]));
+        }
+    }
+
+    pins
+}
+
+/// Removes a pin from a pinned MSRV dependencies file. Sections left empty are removed as well.
+///
+/// Returns `true` if the pins file was modified.
+pub fn unset_pin(pins_file: &mut Table, section: &[String], key: &str) -> bool {
+    fn unset(table: &mut dyn TableLike, section: &[String], key: &str) -> bool {
+        let Some((name, rest)) = section.split_first() else {
+            return table.remove(key).is_some();
+        };
+        let Some(child) = table.get_mut(name).and_then(Item::as_table_like_mut) else {
+            return false;
+        };
+
+        let removed = unset(child, rest, key);
+        if removed && child.is_empty() {
+            table.remove(name);
+        }
+        removed
+    }
+
+    unset(pins_file, section, key)
+}
+
+/// Reads the metadata of a pin (see [`PinMetadata`]).
+///
+/// `name` is used to report errors (e.g. the pin's [`PinLocation`]).
+pub fn pin_metadata(name: &str, pin: &Item) -> Result<PinMetadata> {
+    let invalid = |reason: String| Error::InvalidPinMetadata { pin: name.into(), reason };
+
+    let Some(pin_metadata) = pin
+        .as_table_like()
+        .and_then(|pin| pin.get(PIN_METADATA_FIELD))
+    else {
+        return Ok(PinMetadata::default());
+    };
+    let pin_metadata = pin_metadata
+        .as_table_like()
+        .ok_or_else(|| invalid(format!("'{PIN_METADATA_FIELD}' must be a table")))?;
+
+    let reason = match pin_metadata.get(REASON_FIELD) {
+        Some(reason) => Some(
+            reason
+                .as_str()
+                .ok_or_else(|| invalid(format!("'{REASON_FIELD}' must be a string")))?
+                .to_string(),
+        ),
+        None => None,
+    };
+    let until_rust_version = match pin_metadata.get(UNTIL_RUST_VERSION_FIELD) {
+        Some(until_rust_version) => Some(
+            until_rust_version
+                .as_str()
+                .and_then(parse_toolchain_version)
+                .ok_or_else(|| {
+                    invalid(format!("'{UNTIL_RUST_VERSION_FIELD}' must be a Rust version"))
+                })?,
+        ),
+        None => None,
+    };
+
+    Ok(PinMetadata { reason, until_rust_version })
+}
+
+/// Finds the pins of the selected packages that are no longer needed, because their
+/// `until-rust-version` (see [`PinMetadata`]) is at or below their package's `rust-version`.
+///
+/// Packages without a `rust-version` are skipped.
+pub fn expired_pins(metadata: &Metadata, pins_file_name: &str) -> Result<Vec<ExpiredPin>> {
+    trace!("Entering `expired_pins` (pins_file_name: '{pins_file_name}')");
+
+    let mut expired = Vec::new();
+    for package in &metadata.selected_packages {
+        let Some(rust_version) = &package.rust_version else {
+            debug!("Package '{}' has no rust-version; skipping", package.name);
+            continue;
+        };
+
+        let pins_file = read_pins_file(&pins_file_path(package, pins_file_name))?;
+        for (section, key) in list_pins(&pins_file) {
+            let Some(pin) = get_pin(&pins_file, &section, &key) else {
+                continue;
+            };
+            let location = PinLocation::new(package, pins_file_name, section, key);
+            let pin_metadata = pin_metadata(&location.to_string(), pin)?;
+
+            if pin_metadata
+                .until_rust_version
+                .as_ref()
+                .is_some_and(|until_rust_version| until_rust_version <= rust_version)
+            {
+                info!("Pin '{location}' is no longer needed for Rust {rust_version}");
+                expired.push(ExpiredPin {
+                    location,
+                    metadata: pin_metadata,
+                    rust_version: rust_version.clone(),
+                });
+            }
+        }
+    }
+
+    trace!("Exiting `expired_pins` (expired: {})", expired.len());
+    Ok(expired)
+}
+
+/// Returns the path of the pinned MSRV dependencies file of a package.
+pub fn pins_file_path(package: &Package, pins_file_name: &str) -> Utf8PathBuf {
+    package
+        .manifest_path
+        .parent()
+        .unwrap_or(&package.manifest_path)
+        .join(pins_file_name)
+}
+
+/// Reads a pinned MSRV dependencies file, returning an empty document if it does not exist.
+pub fn read_pins_file(pins_file_path: &Utf8Path) -> Result<DocumentMut> {
+    if !pins_file_path.is_file() {
+        return Ok(DocumentMut::new());
+    }
+
+    let pins_file_text = fs::read_to_string(pins_file_path)
+        .with_io_context(|| format!("reading MSRV pins file '{pins_file_path}'"))?;
+    pins_file_text
+        .parse()
+        .with_toml_file(pins_file_path, &pins_file_text)
+}
+
+/// Writes a pinned MSRV dependencies file.
+pub fn write_pins_file(pins_file_path: &Utf8Path, pins_file: &DocumentMut) -> Result<()> {
+    trace!("Entering `write_pins_file` (pins_file_path: '{pins_file_path}')");
+
+    mockable_fs::write(pins_file_path, pins_file.to_string())
+        .with_io_context(|| format!("writing MSRV pins file '{pins_file_path}'"))?;
+    debug!("MSRV pins file '{pins_file_path}' written");
+
+    trace!("Exiting `write_pins_file`");
+    Ok(())
+}
+
+fn dotted_key<'a, I>(keys: I) -> String
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    keys.into_iter()
+        .map(|key| Key::new(key.as_str()).to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn get_pin<'a>(pins_file: &'a Table, section: &[String], key: &str) -> Option<&'a Item> {
+    section
+        .iter()
+        .try_fold(pins_file as &dyn TableLike, |table, name| table.get(name)?.as_table_like())?
+        .get(key)
+}
+
+fn depends_on(metadata: &cargo_metadata::Metadata, root: &PackageId, name: &str) -> bool {
+    let Some(resolve) = &metadata.resolve else {
+        return false;
+    };
+    let nodes: HashMap<_, _> = resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+    let names: HashMap<_, _> = metadata
+        .packages
+        .iter()
+        .map(|package| (&package.id, package.name.as_str()))
+        .collect();
+
+    let mut visited = HashSet::new();
+    let mut to_visit = vec![root];
+    while let Some(id) = to_visit.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        if id != root && names.get(id) == Some(&name) {
+            return true;
+        }
+
+        // Note: only the root's dev-dependencies are built (not those of its dependencies).
+        if let Some(node) = nodes.get(id) {
+            to_visit.extend(
+                node.deps
+                    .iter()
+                    .filter(|dep| {
+                        id == root
+                            || dep
+                                .dep_kinds
+                                .iter()
+                                .any(|info| info.kind != DependencyKind::Development)
+                    })
+                    .map(|dep| &dep.pkg),
+            );
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod pin_location {
+        use super::*;
+
+        pub mod for_dependency {
+            use std::path::PathBuf;
+
+            use super::*;
+
+            pub fn metadata(project_name: &str) -> Metadata {
+                let manifest_path: PathBuf = [
+                    env!("CARGO_MANIFEST_DIR"),
+                    "resources",
+                    "tests",
+                    "cargo-msrv-prep",
+                    project_name,
+                    "Cargo.toml",
+                ]
+                .iter()
+                .collect();
+
+                Metadata::for_manifest(manifest_path).unwrap()
+            }
+
+            #[test]
+            fn direct() {
+                let metadata = metadata("simple_project");
+
+                let (location, dependency) =
+                    PinLocation::for_dependency(&metadata, "msrv-pins.toml", "either").unwrap();
+
+                assert_eq!("dependencies.either", location.to_string());
+                assert!(location
+                    .pins_file_path
+                    .ends_with("simple_project/msrv-pins.toml"));
+                assert_eq!("either", dependency.unwrap().name);
+
+                let (location, _) =
+                    PinLocation::for_dependency(&metadata, "msrv-pins.toml", "rustc_version")
+                        .unwrap();
+                assert_eq!("build-dependencies.rustc_version", location.to_string());
+            }
+
+            #[test]
+            fn dev_dependency() {
+                let metadata = metadata("simple_project");
+
+                let (location, dependency) =
+                    PinLocation::for_dependency(&metadata, "msrv-pins.toml", "mockall").unwrap();
+
+                assert_eq!("dev-dependencies.mockall", location.to_string());
+                assert_eq!("mockall", dependency.unwrap().name);
+            }
+
+            #[test]
+            fn transitive() {
+                let metadata = metadata("simple_project");
+
+                let (location, dependency) =
+                    PinLocation::for_dependency(&metadata, "msrv-pins.toml", "semver").unwrap();
+
+                assert_eq!("lock.semver", location.to_string());
+                assert!(dependency.is_none());
+
+                let (location, _) =
+                    PinLocation::for_dependency(&metadata, "msrv-pins.toml", "predicates").unwrap();
+                assert_eq!("lock.predicates", location.to_string());
+            }
+
+            #[test]
+            fn not_in_graph() {
+                let metadata = metadata("simple_project");
+
+                assert!(PinLocation::for_dependency(
+                    &metadata,
+                    "msrv-pins.toml",
+                    "not-a-dependency"
+                )
+                .is_none());
+            }
+        }
+    }
+
+    mod compatible_requirement {
+        use super::*;
+
+        #[test]
+        fn all() {
+            let req = |version: &str| compatible_requirement(&version.parse().unwrap()).to_string();
+
+            assert_eq!("^1", req("1.2.3"));
+            assert_eq!("^0.3", req("0.3.5"));
+            assert_eq!("=0.0.7", req("0.0.7"));
+        }
+    }
+
+    mod pin_for_rust_version {
+        use assert_matches::assert_matches;
+
+        use super::*;
+
+        fn fixture_index() -> CrateIndex {
+            let path: Utf8PathBuf = [env!("CARGO_MANIFEST_DIR"), "resources", "tests", "index"]
+                .iter()
+                .collect();
+
+            CrateIndex::open(path).unwrap()
+        }
+
+        // Note: pins files are not actually written here (see `dry_run`), since our `fs`
+        // functions are mocked in unit tests. Actual pinning is validated in integration tests.
+        #[test_log::test]
+        fn direct() {
+            let metadata = pin_location::for_dependency::metadata("simple_project");
+
+            let pin = pin_for_rust_version(
+                &metadata,
+                &fixture_index(),
+                "msrv-pins.toml",
+                "either",
+                &Version::new(1, 70, 0),
+                true,
+            )
+            .unwrap();
+
+            assert_eq!("dependencies.either", pin.location.to_string());
+            assert_eq!(Version::new(1, 12, 0), pin.entry.version);
+            assert_eq!("=1.12.0", pin.requirement);
+            assert!(pin.changed);
+        }
+
+        #[test_log::test]
+        fn transitive() {
+            let metadata = pin_location::for_dependency::metadata("simple_project");
+
+            let pin = pin_for_rust_version(
+                &metadata,
+                &fixture_index(),
+                "msrv-pins.toml",
+                "semver",
+                &Version::new(1, 70, 0),
+                true,
+            )
+            .unwrap();
+
+            assert_eq!("lock.semver", pin.location.to_string());
+            assert_eq!(Version::new(1, 0, 22), pin.entry.version);
+            assert_eq!("1.0.22", pin.requirement);
+            assert!(pin.changed);
+        }
+
+        #[test_log::test]
+        fn errors() {
+            let metadata = pin_location::for_dependency::metadata("simple_project");
+            let pin = |name: &str, rust_version: Version| {
+                pin_for_rust_version(
+                    &metadata,
+                    &fixture_index(),
+                    "msrv-pins.toml",
+                    name,
+                    &rust_version,
+                    true,
+                )
+            };
+
+            assert_matches!(pin("not-a-dependency", Version::new(1, 70, 0)), Err(Error::UnknownDependency(name)) => {
+                assert_eq!("not-a-dependency", name);
+            });
+            assert_matches!(
+                pin("either", Version::new(1, 60, 0)),
+                Err(Error::NoCompatibleVersion { .. })
+            );
+        }
+    }
+
+    mod parse_pin_spec {
+        use assert_matches::assert_matches;
+
+        use super::*;
+
+        #[test]
+        fn all() {
+            assert_eq!(("foo".into(), "1.2.3".into()), parse_pin_spec("foo@1.2.3").unwrap());
+            assert_eq!(("foo".into(), "=1.2.3".into()), parse_pin_spec("foo@=1.2.3").unwrap());
+            assert_matches!(parse_pin_spec("foo"), Err(Error::InvalidPinSpec(_)));
+            assert_matches!(parse_pin_spec("@1.2.3"), Err(Error::InvalidPinSpec(_)));
+            assert_matches!(parse_pin_spec("foo@bar"), Err(Error::InvalidPinSpec(_)));
+        }
+    }
+
+    mod list_pins {
+        use indoc::indoc;
+
+        use super::*;
+
+        #[test]
+        fn all() {
+            let pins_file = indoc! {r#"
+                [dependencies]
+                foo = "1.0.0"
+
+                [target.'cfg(unix)'.build-dependencies]
+                bar = "0.4.0"
+
+                [msrv-prep]
+                strip-keys = ["cargo-features"]
+
+                [lock]
+                baz = "2.0.0"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            let pins: Vec<_> = list_pins(&pins_file)
+                .into_iter()
+                .map(|(section, key)| format!("{}.{key}", section.join(".")))
+                .collect();
+            assert_eq!(
+                vec!["dependencies.foo", "target.cfg(unix).build-dependencies.bar", "lock.baz"],
+                pins
+            );
+        }
+    }
+
+    mod unset_pin {
+        use indoc::indoc;
+
+        use super::*;
+
+        #[test]
+        fn all() {
+            let mut pins_file = indoc! {r#"
+                # Pins needed for Rust 1.70
+                [dependencies]
+                foo = "1.0.0" # needs edition 2021
+                bar = "0.4.0"
+
+                [target.'cfg(unix)'.build-dependencies]
+                baz = "2.0.0"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+            let target_section: Vec<String> =
+                vec!["target".into(), "cfg(unix)".into(), "build-dependencies".into()];
+
+            assert!(unset_pin(&mut pins_file, &["dependencies".into()], "bar"));
+            assert!(unset_pin(&mut pins_file, &target_section, "baz"));
+            assert!(!unset_pin(&mut pins_file, &target_section, "baz"));
+            assert!(!unset_pin(&mut pins_file, &["dev-dependencies".into()], "foo"));
+
+            let expected = indoc! {r#"
+                # Pins needed for Rust 1.70
+                [dependencies]
+                foo = "1.0.0" # needs edition 2021
+            "#};
+            assert_eq!(expected, pins_file.to_string());
+        }
+    }
+
+    mod set_pin {
+        use assert_matches::assert_matches;
+        use indoc::indoc;
+
+        use super::*;
+
+        #[test]
+        fn all() {
+            let mut pins_file = indoc! {r#"
+                [dependencies]
+                foo = { version = "1.0.0", features = ["std"] }
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+            let target_section: Vec<String> =
+                vec!["target".into(), "cfg(unix)".into(), "build-dependencies".into()];
+
+            assert!(
+                set_pin(&mut pins_file, &["dependencies".into()], "foo", "<1.2.3".into()).unwrap()
+            );
+            assert!(set_pin(&mut pins_file, &target_section, "bar", "<0.4.0".into()).unwrap());
+            assert!(!set_pin(&mut pins_file, &target_section, "bar", "<0.4.0".into()).unwrap());
+
+            let expected = indoc! {r#"
+                [dependencies]
+                foo = "<1.2.3"
+
+                [target."cfg(unix)".build-dependencies]
+                bar = "<0.4.0"
+            "#};
+            assert_eq!(expected, pins_file.to_string());
+        }
+
+        #[test]
+        fn features_changed() {
+            let mut pins_file = indoc! {r#"
+                [dependencies]
+                foo = { version = "=1.2.3", features = ["std"], msrv-prep.reason = "1.3 needs 1.70" }
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+            let section: Vec<String> = vec!["dependencies".into()];
+            let pin = |features: &[&str]| {
+                let mut pin = InlineTable::new();
+                pin.insert(VERSION_FIELD, "=1.2.3".into());
+                pin.insert("features", features.iter().copied().collect::<Array>().into());
+                Value::InlineTable(pin)
+            };
+
+            assert!(!set_pin(&mut pins_file, &section, "foo", pin(&["std"])).unwrap());
+            assert!(set_pin(&mut pins_file, &section, "foo", pin(&["std", "alloc"])).unwrap());
+            assert!(set_pin(&mut pins_file, &section, "foo", "=1.2.3".into()).unwrap());
+
+            let expected = indoc! {r#"
+                [dependencies]
+                foo = { version = "=1.2.3", msrv-prep.reason = "1.3 needs 1.70" }
+            "#};
+            assert_eq!(expected, pins_file.to_string());
+        }
+
+        #[test]
+        fn inline_section() {
+            let mut pins_file = indoc! {r#"
+                target = { "cfg(unix)" = { dependencies = { foo = "1.0.0" } } }
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+            let section: Vec<String> =
+                vec!["target".into(), "cfg(unix)".into(), "dependencies".into()];
+
+            assert!(set_pin(&mut pins_file, &section, "bar", "<0.4.0".into()).unwrap());
+
+            let expected = indoc! {r#"
+                [target."cfg(unix)".dependencies]
+                foo = "1.0.0"
+                bar = "<0.4.0"
+            "#};
+            assert_eq!(expected, pins_file.to_string());
+        }
+
+        #[test]
+        fn not_a_table() {
+            let mut pins_file = indoc! {r#"
+                [target]
+                "cfg(unix)" = "x"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+            let section: Vec<String> =
+                vec!["target".into(), "cfg(unix)".into(), "dependencies".into()];
+
+            assert_matches!(
+                set_pin(&mut pins_file, &section, "bar", "<0.4.0".into()),
+                Err(Error::InvalidPinsSection(section)) if section == "target.\"cfg(unix)\""
+            );
+            assert!(pins_file.to_string().contains(r#""cfg(unix)" = "x""#));
+        }
+
+        #[test]
+        fn keeps_metadata() {
+            let mut pins_file = indoc! {r#"
+                [dependencies]
+                foo = { version = "1.0.0", msrv-prep.reason = "1.1 needs 1.70" }
+
+                [dependencies.bar.msrv-prep]
+                until-rust-version = "1.74"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            assert!(
+                set_pin(&mut pins_file, &["dependencies".into()], "foo", "<1.2.3".into()).unwrap()
+            );
+            assert!(
+                set_pin(&mut pins_file, &["dependencies".into()], "bar", "<0.4.0".into()).unwrap()
+            );
+
+            let expected = indoc! {r#"
+                [dependencies]
+                foo = { version = "<1.2.3", msrv-prep.reason = "1.1 needs 1.70" }
+                bar = { version = "<0.4.0", msrv-prep = { until-rust-version = "1.74" } }
+            "#};
+            assert_eq!(expected, pins_file.to_string());
+        }
+    }
+
+    mod pin_metadata {
+        use assert_matches::assert_matches;
+        use indoc::indoc;
+        use toml_edit::value;
+
+        use super::*;
+
+        #[test]
+        fn all() {
+            let pins_file = indoc! {r#"
+                [dependencies]
+                foo = { version = "=2.5.0", msrv-prep.reason = "2.6 needs 1.61", msrv-prep.until-rust-version = "1.61" }
+                bar = "1.0.0"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            assert_eq!(
+                PinMetadata {
+                    reason: Some("2.6 needs 1.61".into()),
+                    until_rust_version: Some(Version::new(1, 61, 0)),
+                },
+                pin_metadata("foo", &pins_file["dependencies"]["foo"]).unwrap()
+            );
+            assert_eq!(
+                PinMetadata::default(),
+                pin_metadata("bar", &pins_file["dependencies"]["bar"]).unwrap()
+            );
+        }
+
+        #[test]
+        fn errors() {
+            let pins_file = indoc! {r#"
+                [dependencies]
+                foo = { version = "1.0.0", msrv-prep = "oops" }
+                bar = { version = "1.0.0", msrv-prep.until-rust-version = "soon" }
+                baz = { version = "1.0.0", msrv-prep.reason = 42 }
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+
+            for name in ["foo", "bar", "baz"] {
+                assert_matches!(
+                    pin_metadata(name, &pins_file["dependencies"][name]),
+                    Err(Error::InvalidPinMetadata { pin, .. }) if pin == name
+                );
+            }
+            assert_matches!(pin_metadata("qux", &value("1.0.0")), Ok(_));
+        }
+    }
+}
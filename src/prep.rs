@@ -0,0 +1,706 @@
+//! Helpers to prepare manifests for determining/verifying MSRV.
+
+use std::fmt::{Display, Formatter};
+use std::fs;
+
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::semver::Version;
+use log::{debug, info, trace};
+use toml_edit::{DocumentMut, Table};
+
+use crate::backup::BackupBackend;
+use crate::cargo_config::{prep_cargo_config, CargoConfigChanges};
+use crate::compat::downgrade_manifest;
+use crate::detail::{
+    is_workspace_inherited, markdown_table, merge_msrv_dependencies, write_table,
+    PACKAGE_SECTION_NAME, WORKSPACE_SECTION_NAME,
+};
+use crate::index::CrateIndex;
+use crate::inheritance::{inline_workspace_inheritance, RootWorkspace};
+use crate::lints::{manifest_lint_rustflags, strip_lints};
+use crate::lock_pins::{apply_lock_pins, merge_lock_pins, read_lock_pins};
+use crate::lockfile::prep_lockfile_version;
+use crate::metadata::Metadata;
+use crate::minimal_versions::{
+    generate_minimal_versions_lockfile, pin_direct_minimal_versions, MinimalVersions,
+};
+#[mockall_double::double]
+use crate::mockable::fs as mockable_fs;
+use crate::result::{IoErrorContext, TomlErrorContext};
+use crate::strip_keys::{parse_key_path, read_strip_keys, strip_keys};
+use crate::toolchain::{prep_toolchain_file, ToolchainFileChange};
+use crate::{
+    maybe_read_pins_file, remove_rust_version, Result, DEFAULT_MANIFEST_FILE_NAME,
+    DEFAULT_MSRV_PINS_FILE_NAME, RUST_VERSION_SPECIFIER,
+};
+
+/// Options used by [`prep_for_msrv`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrepOptions {
+    /// Name of TOML file containing pinned dependencies.
+    pub pins_file_name: String,
+
+    /// Whether to remove the `rust-version` field of manifests.
+    pub remove_rust_version: bool,
+
+    /// Whether to merge pinned MSRV dependencies in manifests.
+    pub merge_pinned_dependencies: bool,
+
+    /// Paths of keys to remove from manifests (e.g. `package.autolib`; see [`parse_key_path`]).
+    ///
+    /// Keys listed in the pinned MSRV dependencies file next to a manifest are also removed
+    /// (see [`read_strip_keys`]).
+    pub strip_keys: Vec<String>,
+
+    /// Whether to pin direct dependencies to the minimal versions satisfying their requirements
+    /// (the workspace's lockfile is then generated using those pinned versions; see
+    /// [`generate_minimal_versions_lockfile`]).
+    pub direct_minimal_versions: bool,
+
+    /// Index used to pin direct dependencies to the lowest versions actually published, if any
+    /// (otherwise, the lower bounds of their requirements are used; see
+    /// [`MinimalVersions::resolve`]).
+    pub minimal_versions_index: Option<CrateIndex>,
+
+    /// Whether to inline values inherited from the workspace (`workspace = true`) in manifests
+    /// (see [`inline_workspace_inheritance`]).
+    pub inline_workspace: bool,
+
+    /// Rust version targeted when determining/verifying MSRV, if known.
+    ///
+    /// If set, manifest syntax not supported by this version of Cargo is down-leveled
+    /// (see [`downgrade_manifest`]).
+    pub target_rust_version: Option<Version>,
+
+    /// Whether to remove `lints` sections from manifests (and `workspace.lints` from the root manifest).
+    pub strip_lints: bool,
+
+    /// Whether to translate the lints of manifests into `rustc` flags in the workspace's
+    /// Cargo configuration file (see [`manifest_lint_rustflags`]).
+    pub lints_to_rustflags: bool,
+
+    /// Whether to always back up the root manifest (and its lockfile).
+    pub backup_root_manifest: bool,
+
+    /// Change to apply to the workspace's toolchain file, if any.
+    pub toolchain_file_change: Option<ToolchainFileChange>,
+
+    /// Changes to apply to the workspace's Cargo configuration file.
+    pub cargo_config_changes: CargoConfigChanges,
+
+    /// Lockfile format version to downgrade the workspace's lockfile to, if it uses a newer format
+    /// (see [`downgrade_lockfile`](crate::lockfile::downgrade_lockfile)).
+    pub lockfile_version: Option<u32>,
+
+    /// Whether to overwrite existing backups.
+    pub force: bool,
+
+    /// Whether to determine if preparation is required without persisting changes.
+    pub dry_run: bool,
+}
+
+impl Default for PrepOptions {
+    fn default() -> Self {
+        Self {
+            pins_file_name: DEFAULT_MSRV_PINS_FILE_NAME.into(),
+            remove_rust_version: true,
+            merge_pinned_dependencies: true,
+            strip_keys: Vec::new(),
+            direct_minimal_versions: false,
+            minimal_versions_index: None,
+            inline_workspace: false,
+            target_rust_version: None,
+            strip_lints: false,
+            lints_to_rustflags: false,
+            backup_root_manifest: false,
+            toolchain_file_change: None,
+            cargo_config_changes: CargoConfigChanges::default(),
+            lockfile_version: None,
+            force: false,
+            dry_run: false,
+        }
+    }
+}
+
+/// Changes made to the manifest of a package by [`prep_for_msrv`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PackagePrepReport {
+    /// Name of the package.
+    pub name: String,
+
+    /// Path of the package's manifest.
+    pub manifest_path: Utf8PathBuf,
+
+    /// Previous value of the `rust-version` field, if it was removed (`workspace` if it was
+    /// inherited from the workspace, or its TOML representation if it was not a string).
+    pub rust_version_removed: Option<String>,
+
+    /// Number of pins merged per dependencies section of the pinned MSRV dependencies file, in
+    /// order (see [`maybe_merge_msrv_dependencies`](crate::maybe_merge_msrv_dependencies)).
+    /// Lockfile pins are not included.
+    pub pins_merged: Vec<(String, usize)>,
+
+    /// Whether the manifest was changed.
+    pub changed: bool,
+
+    /// Where the manifest was backed up, if it was (see [`BackupBackend::backup_location`]).
+    pub backup: Option<String>,
+}
+
+/// Report of the changes made by [`prep_for_msrv`].
+///
+/// Its [`Display`] implementation renders a summary table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PrepReport {
+    /// Workspace root, used to shorten paths when displayed.
+    pub workspace_root: Utf8PathBuf,
+
+    /// Reports of the selected packages, in order.
+    pub packages: Vec<PackagePrepReport>,
+}
+
+impl PrepReport {
+    const HEADER: [&'static str; 4] = ["Package", "rust-version removed", "Pins merged", "Backup"];
+
+    /// Returns `true` if at least one manifest was changed.
+    pub fn changed(&self) -> bool {
+        self.packages.iter().any(|package| package.changed)
+    }
+
+    /// Returns a summary of the report as a Markdown table (e.g. for GitHub job summaries).
+    pub fn to_markdown(&self) -> String {
+        markdown_table(&Self::HEADER, &self.rows())
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.packages
+            .iter()
+            .map(|package| {
+                let pins_merged: Vec<_> = package
+                    .pins_merged
+                    .iter()
+                    .map(|(section, count)| format!("{section}: {count}"))
+                    .collect();
+
+                vec![
+                    package.name.clone(),
+                    package
+                        .rust_version_removed
+                        .as_deref()
+                        .unwrap_or("-")
+                        .into(),
+                    if pins_merged.is_empty() { "-".into() } else { pins_merged.join(", ") },
+                    package.backup.as_deref().map_or("-".into(), |backup| {
+                        relative_location(backup, &self.workspace_root)
+                    }),
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Display for PrepReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_table(f, &Self::HEADER, &self.rows())
+    }
+}
+
+/// Strips the workspace root from a location, if it starts with it.
+pub(crate) fn relative_location(location: &str, workspace_root: &Utf8Path) -> String {
+    Utf8Path::new(location)
+        .strip_prefix(workspace_root)
+        .map_or_else(|_| location.into(), |path| path.to_string())
+}
+
+/// Prepares the manifests of the selected packages for determining/verifying MSRV.
+///
+/// Modified manifests (and other files) are backed up using the given backend.
+///
+/// Returns a report of the changes made to each manifest.
+pub fn prep_for_msrv(
+    metadata: &Metadata,
+    backup_backend: &dyn BackupBackend,
+    options: &PrepOptions,
+) -> Result<PrepReport> {
+    trace!("Entering `prep_for_msrv` (options: {options:?})");
+
+    debug!("Workspace root: {}", metadata.cargo_metadata.workspace_root);
+    debug!("Selected packages: {}", metadata.selected_package_names());
+
+    let root_manifest_path = metadata
+        .cargo_metadata
+        .workspace_root
+        .join(DEFAULT_MANIFEST_FILE_NAME);
+
+    let root_workspace = if options.direct_minimal_versions
+        || options.inline_workspace
+        || options.target_rust_version.is_some()
+        || options.lints_to_rustflags
+    {
+        RootWorkspace::read(&root_manifest_path)?
+    } else {
+        None
+    };
+
+    let strip_key_paths = options
+        .strip_keys
+        .iter()
+        .map(|key_path| parse_key_path(key_path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut report = PrepReport {
+        workspace_root: metadata.cargo_metadata.workspace_root.clone(),
+        packages: Vec::new(),
+    };
+    let mut root_manifest_backed_up = false;
+    let mut lock_pins = Vec::new();
+    let mut lint_rustflags = Vec::new();
+    let mut prepped_manifests = Vec::new();
+    for package in &metadata.selected_packages {
+        info!("Preparing manifest '{}' (at '{}')", package.name, package.manifest_path);
+
+        let manifest_text = fs::read_to_string(&package.manifest_path)
+            .with_io_context(|| format!("reading manifest of package {}", package.name))?;
+        let mut manifest = manifest_text
+            .parse::<DocumentMut>()
+            .with_toml_file(&package.manifest_path, &manifest_text)?;
+        let pins_file = maybe_read_pins_file(&package.manifest_path, &options.pins_file_name)?;
+
+        let rust_version_removed = if options.remove_rust_version {
+            let rust_version = rust_version_value(&manifest);
+            let removed = remove_rust_version(&mut manifest);
+
+            debug!("'{RUST_VERSION_SPECIFIER}' field removed: {removed}");
+            rust_version.filter(|_| removed)
+        } else {
+            info!("Skipping removal of '{RUST_VERSION_SPECIFIER}' field");
+            None
+        };
+
+        let pins_merged = if options.merge_pinned_dependencies {
+            match &pins_file {
+                Some(pins_file) => {
+                    let merged = merge_msrv_dependencies(&mut manifest, pins_file);
+
+                    debug!("Pinned MSRV dependencies merged: {merged:?}");
+
+                    merge_lock_pins(&mut lock_pins, read_lock_pins(pins_file)?);
+                    merged
+                },
+                None => Vec::new(),
+            }
+        } else {
+            info!("Skipping merging of pinned MSRV dependencies");
+            Vec::new()
+        };
+
+        let mut package_strip_key_paths = strip_key_paths.clone();
+        if let Some(pins_file) = &pins_file {
+            package_strip_key_paths.extend(read_strip_keys(pins_file)?);
+        }
+        let keys_stripped = strip_keys(&mut manifest, &package_strip_key_paths);
+        debug!("Keys removed: {keys_stripped}");
+
+        // Note: lints are translated before being removed (including by `downgrade_manifest`).
+        if options.lints_to_rustflags {
+            let root_workspace = root_workspace.as_ref().map(|ws| &ws.workspace);
+            for flag in manifest_lint_rustflags(&manifest, root_workspace) {
+                if !lint_rustflags.contains(&flag) {
+                    lint_rustflags.push(flag);
+                }
+            }
+        }
+        let lints_stripped = if options.strip_lints {
+            let stripped = strip_lints(&mut manifest);
+
+            debug!("Lints removed: {stripped}");
+            stripped
+        } else {
+            false
+        };
+
+        // Note: this is done before pinning minimal versions, so that dependencies inlined
+        // from the workspace are pinned as well.
+        let manifest_dir = package
+            .manifest_path
+            .parent()
+            .unwrap_or(&metadata.cargo_metadata.workspace_root);
+        let workspace_inlined = if options.inline_workspace {
+            let inlined =
+                inline_workspace_inheritance(&mut manifest, manifest_dir, root_workspace.as_ref())?;
+
+            debug!("Values inherited from workspace inlined: {inlined}");
+            inlined
+        } else {
+            false
+        };
+        let manifest_downgraded = match &options.target_rust_version {
+            Some(target_rust_version) => {
+                let downgraded = downgrade_manifest(
+                    &mut manifest,
+                    manifest_dir,
+                    root_workspace.as_ref(),
+                    target_rust_version,
+                )?;
+
+                debug!("Manifest syntax down-leveled for Rust {target_rust_version}: {downgraded}");
+                downgraded
+            },
+            None => false,
+        };
+
+        let changed = rust_version_removed.is_some()
+            || !pins_merged.is_empty()
+            || keys_stripped
+            || lints_stripped
+            || workspace_inlined
+            || manifest_downgraded;
+        prepped_manifests.push((package, manifest, rust_version_removed, pins_merged, changed));
+    }
+
+    // Note: minimal versions are pinned once all manifests are prepared, since they are
+    // computed across the whole workspace (including dependencies merged from pins files).
+    let minimal_versions = if options.direct_minimal_versions {
+        let mut minimal_versions = MinimalVersions::default();
+        for (_, manifest, ..) in &prepped_manifests {
+            minimal_versions.add_manifest(manifest);
+        }
+        if let Some(root_workspace) = &root_workspace {
+            minimal_versions.add_workspace(&root_workspace.workspace);
+        }
+        minimal_versions.resolve(options.minimal_versions_index.as_ref())?;
+
+        Some(minimal_versions)
+    } else {
+        None
+    };
+
+    for (package, mut manifest, rust_version_removed, pins_merged, mut changed) in prepped_manifests
+    {
+        if let Some(minimal_versions) = &minimal_versions {
+            let pinned = pin_direct_minimal_versions(&mut manifest, minimal_versions);
+
+            debug!(
+                "Direct dependencies of '{}' pinned to minimal versions: {pinned}",
+                package.name
+            );
+            changed = pinned || changed;
+        }
+
+        let mut backup = None;
+        if changed {
+            if !options.dry_run {
+                info!("Manifest for '{}' changed after preparation; persisting", package.name);
+
+                backup_backend.backup_manifest(&package.manifest_path, options.force)?;
+                backup = Some(backup_backend.backup_location(&package.manifest_path)?);
+                mockable_fs::write(&package.manifest_path, manifest.to_string()).with_io_context(
+                    || format!("saving updated manifest content to '{}'", package.manifest_path),
+                )?;
+            } else {
+                info!(
+                    "Manifest for '{}' changed after preparation; not persisting (dry-run mode)",
+                    package.name
+                );
+            }
+
+            root_manifest_backed_up =
+                root_manifest_backed_up || package.manifest_path == root_manifest_path;
+        } else {
+            info!("Manifest for '{}' not changed after preparation; skipping", package.name);
+        }
+
+        report.packages.push(PackagePrepReport {
+            name: package.name.clone(),
+            manifest_path: package.manifest_path.clone(),
+            rust_version_removed,
+            pins_merged,
+            changed,
+            backup,
+        });
+    }
+
+    let root_manifest_selected = metadata
+        .selected_packages
+        .iter()
+        .any(|package| package.manifest_path == root_manifest_path);
+    if (options.direct_minimal_versions
+        || options.inline_workspace
+        || options.target_rust_version.is_some()
+        || options.strip_lints
+        || !strip_key_paths.is_empty())
+        && !root_manifest_selected
+        && root_manifest_path.is_file()
+    {
+        // Dependencies inherited from the workspace need to be pinned in the root manifest,
+        // and its syntax needs to be down-leveled (e.g. `workspace.lints` removed) for older Cargo.
+        root_manifest_backed_up = prep_root_manifest(
+            &root_manifest_path,
+            &strip_key_paths,
+            minimal_versions.as_ref(),
+            backup_backend,
+            options,
+        )?;
+    }
+
+    if options.backup_root_manifest {
+        if !root_manifest_backed_up {
+            if !options.dry_run {
+                info!("Backing up root manifest (at '{}')", metadata.cargo_metadata.workspace_root);
+
+                // Note: this will fail if the root manifest has a non-standard name, but
+                // there doesn't seem to be an easy way to fetch the name of the root
+                // manifest when it doesn't contain a package itself, so we have no choice.
+                backup_backend.backup_manifest(&root_manifest_path, options.force)?;
+            } else {
+                info!("Root manifest needs backup; skipping (dry-run mode)");
+            }
+        } else {
+            info!("Root manifest already backed up; skipping");
+        }
+    }
+
+    let mut cargo_config_changes = options.cargo_config_changes.clone();
+    cargo_config_changes.rustflags.extend(lint_rustflags);
+    if !cargo_config_changes.is_empty() {
+        let changed = prep_cargo_config(
+            &metadata.cargo_metadata.workspace_root,
+            &cargo_config_changes,
+            backup_backend,
+            options.force,
+            options.dry_run,
+        )?;
+        debug!("Cargo configuration file changed: {changed}");
+    }
+
+    if options.direct_minimal_versions {
+        generate_minimal_versions_lockfile(
+            &metadata.cargo_metadata.workspace_root,
+            backup_backend,
+            options.force,
+            options.dry_run,
+        )?;
+    }
+
+    // Note: lockfile pins are applied after preparing manifests, since they depend on them
+    // (and possibly on the Cargo configuration file).
+    let lock_pins_applied = apply_lock_pins(
+        &metadata.cargo_metadata.workspace_root,
+        &lock_pins,
+        backup_backend,
+        options.force,
+        options.dry_run,
+    )?;
+    debug!("Lockfile pins applied: {lock_pins_applied}");
+
+    // Note: the lockfile is downgraded after applying lockfile pins, since `cargo update`
+    // could rewrite it using a newer format.
+    if let Some(lockfile_version) = options.lockfile_version {
+        let changed = prep_lockfile_version(
+            &metadata.cargo_metadata.workspace_root,
+            lockfile_version,
+            backup_backend,
+            options.force,
+            options.dry_run,
+        )?;
+        debug!("Lockfile downgraded: {changed}");
+    }
+
+    // Note: the toolchain file is changed last, since commands run above (e.g. `cargo update`)
+    // would otherwise use the new toolchain, which might not be installed.
+    if let Some(change) = &options.toolchain_file_change {
+        let changed = prep_toolchain_file(
+            &metadata.cargo_metadata.workspace_root,
+            change,
+            backup_backend,
+            options.force,
+            options.dry_run,
+        )?;
+        debug!("Toolchain file changed: {changed}");
+    }
+
+    trace!("Exiting `prep_for_msrv` (changed: {})", report.changed());
+    Ok(report)
+}
+
+/// Returns the value of the `rust-version` field of a manifest, if any (`workspace` if it is
+/// inherited from the workspace, or its TOML representation if it is not a string).
+fn rust_version_value(manifest: &DocumentMut) -> Option<String> {
+    let rust_version = manifest
+        .get(PACKAGE_SECTION_NAME)?
+        .get(RUST_VERSION_SPECIFIER)?;
+    if is_workspace_inherited(rust_version) {
+        return Some(WORKSPACE_SECTION_NAME.into());
+    }
+
+    Some(
+        rust_version
+            .as_str()
+            .map_or_else(|| rust_version.to_string().trim().into(), Into::into),
+    )
+}
+
+fn prep_root_manifest(
+    root_manifest_path: &Utf8Path,
+    strip_key_paths: &[Vec<String>],
+    minimal_versions: Option<&MinimalVersions>,
+    backup_backend: &dyn BackupBackend,
+    options: &PrepOptions,
+) -> Result<bool> {
+    trace!("Entering `prep_root_manifest` (root_manifest_path: '{root_manifest_path}')");
+
+    let manifest_text = fs::read_to_string(root_manifest_path)
+        .with_io_context(|| format!("reading root manifest '{root_manifest_path}'"))?;
+    let mut manifest = manifest_text
+        .parse::<DocumentMut>()
+        .with_toml_file(root_manifest_path, &manifest_text)?;
+
+    let root_workspace = RootWorkspace::from_manifest(root_manifest_path, &manifest);
+    let root_dir = root_manifest_path.parent().unwrap_or(root_manifest_path);
+    let mut changed = strip_keys(&mut manifest, strip_key_paths);
+
+    // Note: the root manifest can also contain a package, but since it is not selected, only
+    // the `workspace` tables are prepared; we move them to a separate table to make sure
+    // the package's sections are left untouched.
+    let mut workspace_tables = Table::new();
+    if let Some(workspace) = manifest.get_mut(WORKSPACE_SECTION_NAME) {
+        workspace_tables.insert(WORKSPACE_SECTION_NAME, std::mem::take(workspace));
+    }
+    if options.inline_workspace {
+        changed =
+            inline_workspace_inheritance(&mut workspace_tables, root_dir, root_workspace.as_ref())?
+                || changed;
+    }
+    if let Some(target_rust_version) = &options.target_rust_version {
+        changed = downgrade_manifest(
+            &mut workspace_tables,
+            root_dir,
+            root_workspace.as_ref(),
+            target_rust_version,
+        )? || changed;
+    }
+    if options.strip_lints {
+        changed = strip_lints(&mut workspace_tables) || changed;
+    }
+    if let Some(minimal_versions) = minimal_versions {
+        changed = pin_direct_minimal_versions(&mut workspace_tables, minimal_versions) || changed;
+    }
+    if let Some(workspace) = workspace_tables.remove(WORKSPACE_SECTION_NAME) {
+        // Note: putting the tables back in place preserves their position in the manifest.
+        manifest[WORKSPACE_SECTION_NAME] = workspace;
+    }
+    if !changed {
+        info!("Root manifest not changed after preparation; skipping");
+    } else if options.dry_run {
+        info!("Root manifest changed after preparation; not persisting (dry-run mode)");
+    } else {
+        info!("Root manifest changed after preparation; persisting");
+
+        backup_backend.backup_manifest(root_manifest_path, options.force)?;
+        mockable_fs::write(root_manifest_path, manifest.to_string()).with_io_context(|| {
+            format!("saving updated manifest content to '{root_manifest_path}'")
+        })?;
+    }
+
+    trace!("Exiting `prep_root_manifest` (changed: {changed})");
+    Ok(changed)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::path::PathBuf;
+
+    use assert_fs::fixture::PathCopy;
+    use assert_fs::TempDir;
+
+    use super::*;
+
+    fn project_path(project_name: &str) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "resources", "tests", "cargo-msrv-prep", project_name]
+            .iter()
+            .collect()
+    }
+
+    fn fork_project(project_name: &str) -> TempDir {
+        let temp = TempDir::new().unwrap();
+
+        temp.copy_from(project_path(project_name), &["*.rs", "*.toml", "*.lock"])
+            .unwrap();
+
+        temp
+    }
+
+    mod rust_version_value {
+        use super::*;
+
+        fn perform_test(manifest: &str) -> Option<String> {
+            rust_version_value(&manifest.parse().unwrap())
+        }
+
+        #[test_log::test]
+        fn string() {
+            assert_eq!(
+                Some("1.70.0".to_string()),
+                perform_test("[package]\nrust-version = \"1.70.0\"\n")
+            );
+        }
+
+        #[test_log::test]
+        fn workspace_inherited() {
+            assert_eq!(
+                Some(WORKSPACE_SECTION_NAME.to_string()),
+                perform_test("[package]\nrust-version = { workspace = true }\n")
+            );
+        }
+
+        #[test_log::test]
+        fn not_a_string() {
+            assert_eq!(
+                Some("{ workspace = false }".to_string()),
+                perform_test("[package]\nrust-version = { workspace = false }\n")
+            );
+        }
+
+        #[test_log::test]
+        fn missing() {
+            assert_eq!(None, perform_test("[package]\n"));
+        }
+    }
+
+    mod errors {
+        use std::io;
+
+        use assert_fs::fixture::PathChild;
+        use assert_matches::assert_matches;
+
+        use super::*;
+        use crate::backup::FileBackupBackend;
+        use crate::{Error, DEFAULT_MANIFEST_BACKUP_SUFFIX};
+
+        #[test_log::test]
+        fn modified_manifest_write_error() {
+            let temp = fork_project("simple_project");
+
+            let metadata = Metadata::for_manifest(temp.child("Cargo.toml").path()).unwrap();
+            let backup_backend = FileBackupBackend::new(DEFAULT_MANIFEST_BACKUP_SUFFIX);
+
+            let _lock = crate::mockable::lock_fs_mock();
+            let copy_ctx = mockable_fs::copy_context();
+            copy_ctx.expect().returning(|_, _| Ok(0));
+            let ctx = mockable_fs::write_context();
+            ctx.expect().returning(|_, _| {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, "permission denied"))
+            });
+
+            assert_matches!(
+                prep_for_msrv(&metadata, &backup_backend, &PrepOptions::default()),
+                Err(Error::Io { source, .. }) => {
+                    assert_eq!(io::ErrorKind::PermissionDenied, source.kind());
+                }
+            );
+        }
+    }
+}
@@ -0,0 +1,438 @@
+//! Stable API to prepare manifests for determining/verifying MSRV, then restore them.
+//!
+//! [`Preparer`] offers the same options as `cargo msrv-prep` and `cargo msrv-unprep` (without
+//! depending on command-line parsing), so that other tools (e.g. an `xtask`) can embed them.
+//! Both commands are implemented using it.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cargo_msrv_prep::metadata::Metadata;
+//! use cargo_msrv_prep::Preparer;
+//!
+//! # fn check_msrv() -> cargo_msrv_prep::Result<()> {
+//! let metadata = Metadata::for_manifest("Cargo.toml")?;
+//! let preparer = Preparer::new()
+//!     .toolchain_channel("1.70.0")
+//!     .msrv_aware_resolver(true);
+//!
+//! let report = preparer.prepare(&metadata)?;
+//! eprint!("{report}");
+//!
+//! // ... determine/verify MSRV ...
+//!
+//! preparer.unprepare(&metadata)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use cargo_metadata::camino::Utf8PathBuf;
+use cargo_metadata::semver::Version;
+use log::trace;
+
+use crate::backup::{BackupBackend, FileBackupBackend};
+use crate::cargo_config::CargoConfigChanges;
+use crate::find::parse_toolchain_version;
+use crate::index::CrateIndex;
+use crate::lockfile::max_lockfile_version;
+use crate::metadata::Metadata;
+use crate::prep::{prep_for_msrv, PrepOptions, PrepReport};
+use crate::toolchain::ToolchainFileChange;
+use crate::unprep::{unprep_from_msrv, UnprepOptions, UnprepReport};
+use crate::{Result, DEFAULT_MANIFEST_BACKUP_SUFFIX, DEFAULT_MSRV_PINS_FILE_NAME};
+
+/// Prepares manifests for determining/verifying MSRV (see [`prepare`](Self::prepare)) and
+/// restores them afterwards (see [`unprepare`](Self::unprepare)).
+///
+/// Options mirror those of `cargo msrv-prep` and `cargo msrv-unprep` and default to the same
+/// values. Backups are stored next to modified files (see [`FileBackupBackend`]) unless another
+/// backend is specified.
+///
+/// See [module description](self) for an example.
+pub struct Preparer {
+    pins_file_name: String,
+    remove_rust_version: bool,
+    merge_pinned_dependencies: bool,
+    strip_keys: Vec<String>,
+    direct_minimal_versions: bool,
+    minimal_versions_index: Option<CrateIndex>,
+    toolchain_channel: Option<String>,
+    remove_toolchain_file: bool,
+    cargo_config_changes: CargoConfigChanges,
+    inline_workspace: bool,
+    strip_lints: bool,
+    lints_to_rustflags: bool,
+    target_rust_version: Option<Version>,
+    lockfile_version: Option<u32>,
+    backup_root_manifest: bool,
+    force: bool,
+    dry_run: bool,
+    expect_prepped: Vec<String>,
+    strict: bool,
+    backup_backend: Box<dyn BackupBackend>,
+}
+
+impl Preparer {
+    /// Creates a preparer using default options.
+    pub fn new() -> Self {
+        Self {
+            pins_file_name: DEFAULT_MSRV_PINS_FILE_NAME.into(),
+            remove_rust_version: true,
+            merge_pinned_dependencies: true,
+            strip_keys: Vec::new(),
+            direct_minimal_versions: false,
+            minimal_versions_index: None,
+            toolchain_channel: None,
+            remove_toolchain_file: false,
+            cargo_config_changes: CargoConfigChanges::default(),
+            inline_workspace: false,
+            strip_lints: false,
+            lints_to_rustflags: false,
+            target_rust_version: None,
+            lockfile_version: None,
+            backup_root_manifest: false,
+            force: false,
+            dry_run: false,
+            expect_prepped: Vec::new(),
+            strict: false,
+            backup_backend: Box::new(FileBackupBackend::new(DEFAULT_MANIFEST_BACKUP_SUFFIX)),
+        }
+    }
+
+    /// Sets the name of the TOML file containing pinned dependencies (`msrv-pins.toml` by default).
+    pub fn pins_file_name<N>(mut self, pins_file_name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        self.pins_file_name = pins_file_name.into();
+        self
+    }
+
+    /// Sets whether to remove the `rust-version` field of manifests (`true` by default).
+    pub fn remove_rust_version(mut self, remove_rust_version: bool) -> Self {
+        self.remove_rust_version = remove_rust_version;
+        self
+    }
+
+    /// Sets whether to merge pinned MSRV dependencies in manifests (`true` by default).
+    pub fn merge_pinned_dependencies(mut self, merge_pinned_dependencies: bool) -> Self {
+        self.merge_pinned_dependencies = merge_pinned_dependencies;
+        self
+    }
+
+    /// Adds a key to remove from manifests, using TOML dotted key syntax (e.g. `package.autolib`).
+    ///
+    /// Invalid keys are reported when preparing manifests.
+    pub fn strip_key<K>(mut self, key_path: K) -> Self
+    where
+        K: Into<String>,
+    {
+        self.strip_keys.push(key_path.into());
+        self
+    }
+
+    /// Sets whether to pin direct dependencies to the minimal versions satisfying their
+    /// requirements (and generate the workspace's lockfile using those versions).
+    pub fn direct_minimal_versions(mut self, direct_minimal_versions: bool) -> Self {
+        self.direct_minimal_versions = direct_minimal_versions;
+        self
+    }
+
+    /// Sets the index used to pin direct dependencies to the lowest versions actually published
+    /// (when pinning minimal versions; see
+    /// [`direct_minimal_versions`](Self::direct_minimal_versions)).
+    pub fn minimal_versions_index(mut self, index: CrateIndex) -> Self {
+        self.minimal_versions_index = Some(index);
+        self
+    }
+
+    /// Sets the toolchain channel in the workspace's toolchain file.
+    ///
+    /// If the channel is a version, it is also used as the default target Rust version
+    /// (see [`target_rust_version`](Self::target_rust_version)).
+    pub fn toolchain_channel<C>(mut self, channel: C) -> Self
+    where
+        C: Into<String>,
+    {
+        self.toolchain_channel = Some(channel.into());
+        self
+    }
+
+    /// Sets whether to remove the workspace's toolchain file (takes precedence over
+    /// [`toolchain_channel`](Self::toolchain_channel)).
+    pub fn remove_toolchain_file(mut self, remove_toolchain_file: bool) -> Self {
+        self.remove_toolchain_file = remove_toolchain_file;
+        self
+    }
+
+    /// Sets whether to enable MSRV-aware dependency resolution in the workspace's Cargo
+    /// configuration file.
+    pub fn msrv_aware_resolver(mut self, msrv_aware_resolver: bool) -> Self {
+        self.cargo_config_changes.msrv_aware_resolver = msrv_aware_resolver;
+        self
+    }
+
+    /// Sets whether to make Cargo run in offline mode via the workspace's Cargo configuration
+    /// file.
+    pub fn net_offline(mut self, net_offline: bool) -> Self {
+        self.cargo_config_changes.offline = net_offline;
+        self
+    }
+
+    /// Replaces `crates-io` with vendored sources stored in the given directory via the
+    /// workspace's Cargo configuration file.
+    pub fn vendored_sources<P>(mut self, vendored_sources: P) -> Self
+    where
+        P: Into<Utf8PathBuf>,
+    {
+        self.cargo_config_changes.vendored_sources = Some(vendored_sources.into());
+        self
+    }
+
+    /// Sets whether to inline values inherited from the workspace in manifests.
+    pub fn inline_workspace(mut self, inline_workspace: bool) -> Self {
+        self.inline_workspace = inline_workspace;
+        self
+    }
+
+    /// Sets whether to remove `lints` sections from manifests.
+    pub fn strip_lints(mut self, strip_lints: bool) -> Self {
+        self.strip_lints = strip_lints;
+        self
+    }
+
+    /// Sets whether to translate lints into `rustc` flags in the workspace's Cargo configuration
+    /// file (implies [`strip_lints`](Self::strip_lints)).
+    pub fn lints_to_rustflags(mut self, lints_to_rustflags: bool) -> Self {
+        self.lints_to_rustflags = lints_to_rustflags;
+        self
+    }
+
+    /// Sets the Rust version targeted when determining/verifying MSRV.
+    ///
+    /// Manifest syntax not supported by this version of Cargo is down-leveled, and the lockfile
+    /// format version is picked accordingly (see [`lockfile_version`](Self::lockfile_version)).
+    pub fn target_rust_version(mut self, target_rust_version: Version) -> Self {
+        self.target_rust_version = Some(target_rust_version);
+        self
+    }
+
+    /// Sets the lockfile format version to downgrade the workspace's lockfile to, if it uses a
+    /// newer format.
+    pub fn lockfile_version(mut self, lockfile_version: u32) -> Self {
+        self.lockfile_version = Some(lockfile_version);
+        self
+    }
+
+    /// Sets whether to always back up (and restore) the root manifest and its lockfile.
+    pub fn backup_root_manifest(mut self, backup_root_manifest: bool) -> Self {
+        self.backup_root_manifest = backup_root_manifest;
+        self
+    }
+
+    /// Sets whether to overwrite existing backups.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Sets whether to determine if preparation is required without persisting changes.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Adds the name of a package that is expected to have been prepped when restoring manifests.
+    pub fn expect_prepped<N>(mut self, package_name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        self.expect_prepped.push(package_name.into());
+        self
+    }
+
+    /// Sets whether to fail if a package expected to have been prepped has no backup when
+    /// restoring manifests (see [`expect_prepped`](Self::expect_prepped)).
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets the backend used to back up and restore files.
+    pub fn backup_backend(mut self, backup_backend: Box<dyn BackupBackend>) -> Self {
+        self.backup_backend = backup_backend;
+        self
+    }
+
+    /// Returns the options used to prepare manifests (see [`prep_for_msrv`]).
+    pub fn prep_options(&self) -> PrepOptions {
+        let target_rust_version = self.target_rust_version.clone().or_else(|| {
+            self.toolchain_channel
+                .as_deref()
+                .and_then(parse_toolchain_version)
+        });
+        let lockfile_version = self
+            .lockfile_version
+            .or_else(|| target_rust_version.as_ref().map(max_lockfile_version));
+        let toolchain_file_change = match (&self.toolchain_channel, self.remove_toolchain_file) {
+            (_, true) => Some(ToolchainFileChange::Remove),
+            (Some(channel), false) => Some(ToolchainFileChange::SetChannel(channel.clone())),
+            (None, false) => None,
+        };
+
+        PrepOptions {
+            pins_file_name: self.pins_file_name.clone(),
+            remove_rust_version: self.remove_rust_version,
+            merge_pinned_dependencies: self.merge_pinned_dependencies,
+            strip_keys: self.strip_keys.clone(),
+            direct_minimal_versions: self.direct_minimal_versions,
+            minimal_versions_index: self.minimal_versions_index.clone(),
+            inline_workspace: self.inline_workspace,
+            target_rust_version,
+            strip_lints: self.strip_lints || self.lints_to_rustflags,
+            lints_to_rustflags: self.lints_to_rustflags,
+            backup_root_manifest: self.backup_root_manifest,
+            toolchain_file_change,
+            cargo_config_changes: self.cargo_config_changes.clone(),
+            lockfile_version,
+            force: self.force,
+            dry_run: self.dry_run,
+        }
+    }
+
+    /// Returns the options used to restore manifests (see [`unprep_from_msrv`]).
+    pub fn unprep_options(&self) -> UnprepOptions {
+        UnprepOptions {
+            backup_root_manifest: self.backup_root_manifest,
+            expect_prepped: self.expect_prepped.clone(),
+            strict: self.strict,
+        }
+    }
+
+    /// Prepares the manifests of the selected packages (see [`prep_for_msrv`]).
+    pub fn prepare(&self, metadata: &Metadata) -> Result<PrepReport> {
+        trace!("Entering `Preparer::prepare`");
+
+        let report = prep_for_msrv(metadata, self.backup_backend.as_ref(), &self.prep_options())?;
+
+        trace!("Exiting `Preparer::prepare` (changed: {})", report.changed());
+        Ok(report)
+    }
+
+    /// Restores the manifests of the selected packages (see [`unprep_from_msrv`]).
+    pub fn unprepare(&self, metadata: &Metadata) -> Result<UnprepReport> {
+        trace!("Entering `Preparer::unprepare`");
+
+        let report =
+            unprep_from_msrv(metadata, self.backup_backend.as_ref(), &self.unprep_options())?;
+
+        trace!("Exiting `Preparer::unprepare`");
+        Ok(report)
+    }
+}
+
+impl Default for Preparer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod prep_options {
+        use super::*;
+
+        #[test]
+        fn default() {
+            assert_eq!(PrepOptions::default(), Preparer::new().prep_options());
+        }
+
+        #[test]
+        fn all() {
+            let options = Preparer::new()
+                .pins_file_name("pins.toml")
+                .remove_rust_version(false)
+                .merge_pinned_dependencies(false)
+                .strip_key("cargo-features")
+                .strip_key("package.autolib")
+                .direct_minimal_versions(true)
+                .toolchain_channel("1.70")
+                .msrv_aware_resolver(true)
+                .net_offline(true)
+                .vendored_sources("vendor")
+                .inline_workspace(true)
+                .lints_to_rustflags(true)
+                .backup_root_manifest(true)
+                .force(true)
+                .dry_run(true)
+                .prep_options();
+
+            assert_eq!(
+                PrepOptions {
+                    pins_file_name: "pins.toml".into(),
+                    remove_rust_version: false,
+                    merge_pinned_dependencies: false,
+                    strip_keys: vec!["cargo-features".into(), "package.autolib".into()],
+                    direct_minimal_versions: true,
+                    minimal_versions_index: None,
+                    inline_workspace: true,
+                    target_rust_version: Some(Version::new(1, 70, 0)),
+                    strip_lints: true,
+                    lints_to_rustflags: true,
+                    backup_root_manifest: true,
+                    toolchain_file_change: Some(ToolchainFileChange::SetChannel("1.70".into())),
+                    cargo_config_changes: CargoConfigChanges {
+                        msrv_aware_resolver: true,
+                        offline: true,
+                        vendored_sources: Some("vendor".into()),
+                        rustflags: Vec::new(),
+                    },
+                    lockfile_version: Some(3),
+                    force: true,
+                    dry_run: true,
+                },
+                options
+            );
+        }
+
+        #[test]
+        fn explicit_versions() {
+            let options = Preparer::new()
+                .toolchain_channel("stable")
+                .remove_toolchain_file(true)
+                .target_rust_version(Version::new(1, 56, 0))
+                .lockfile_version(2)
+                .prep_options();
+
+            assert_eq!(Some(ToolchainFileChange::Remove), options.toolchain_file_change);
+            assert_eq!(Some(Version::new(1, 56, 0)), options.target_rust_version);
+            assert_eq!(Some(2), options.lockfile_version);
+        }
+    }
+
+    mod unprep_options {
+        use super::*;
+
+        #[test]
+        fn all() {
+            let options = Preparer::new()
+                .backup_root_manifest(true)
+                .expect_prepped("foo")
+                .expect_prepped("bar")
+                .strict(true)
+                .unprep_options();
+
+            assert_eq!(
+                UnprepOptions {
+                    backup_root_manifest: true,
+                    expect_prepped: vec!["foo".into(), "bar".into()],
+                    strict: true,
+                },
+                options
+            );
+        }
+    }
+}
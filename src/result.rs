@@ -1,13 +1,19 @@
 use std::io;
 
-use cargo_metadata::camino::Utf8PathBuf;
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::semver::Version;
 use toml_edit::TomlError;
 
+use crate::detail::source_snippet;
+
 /// Result type used for our crate. Uses our [`Error`] type by default.
 pub type Result<T, E = Error> = core::result::Result<T, E>;
 
 /// Error type used by this crate.
+///
+/// New variants can be added in minor versions.
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
     #[error(transparent)]
     CargoMetadata(#[from] cargo_metadata::Error),
@@ -18,11 +24,184 @@ pub enum Error {
     #[error(transparent)]
     Toml(#[from] TomlError),
 
+    #[error("invalid TOML file '{path}': {}{}", .source.message(), toml_file_snippet(.path, .text, .source))]
+    TomlFile { path: Utf8PathBuf, text: String, source: Box<TomlError> },
+
     #[error("invalid path: {0}")]
     InvalidPath(Utf8PathBuf),
 
     #[error("backup file already exists: {0}")]
     BackupFileAlreadyExists(Utf8PathBuf),
+
+    #[error("Git command failed ({command}): {message}")]
+    Git { command: String, message: String },
+
+    #[error("restored manifest '{path}' is invalid: {reason}")]
+    InvalidRestoredManifest { path: Utf8PathBuf, reason: String },
+
+    #[error("invalid check command: '{0}'")]
+    InvalidCheckCommand(String),
+
+    #[error("MSRV verification failed for package(s): {}", .0.join(", "))]
+    MsrvVerificationFailed(Vec<String>),
+
+    #[error("failed to list installed toolchains: {0}")]
+    ToolchainList(String),
+
+    #[error("no toolchains to check")]
+    NoToolchains,
+
+    #[error("check command did not succeed with any toolchain")]
+    MsrvNotFound,
+
+    #[error(
+        "manifest '{0}' is currently prepped (a backup exists); run `cargo msrv-unprep` first"
+    )]
+    ManifestPrepped(Utf8PathBuf),
+
+    #[error("invalid lockfile pin for '{spec}': {reason}")]
+    InvalidLockPin { spec: String, reason: String },
+
+    #[error("invalid metadata for pin '{pin}': {reason}")]
+    InvalidPinMetadata { pin: String, reason: String },
+
+    #[error("invalid section '{0}' in MSRV pins file (expected a table)")]
+    InvalidPinsSection(String),
+
+    #[error("{0} pin(s) no longer needed")]
+    ExpiredPins(usize),
+
+    #[error("failed to pin '{spec}' to version {version} in lockfile")]
+    LockPinFailed { spec: String, version: String },
+
+    #[error("failed to generate lockfile (`cargo generate-lockfile` did not succeed)")]
+    GenerateLockfileFailed,
+
+    #[error(
+        "failed to resolve minimal version of '{0}' (`cargo generate-lockfile` did not succeed)"
+    )]
+    MinimalVersionUnresolved(String),
+
+    #[error("cannot downgrade lockfile to version {0} (only versions 2 and later are supported)")]
+    UnsupportedLockfileVersion(u32),
+
+    #[error("invalid key path '{0}' (expected a TOML dotted key, e.g. `package.autolib`)")]
+    InvalidKeyPath(String),
+
+    #[error("no value found in root manifest for {0} inherited from workspace")]
+    MissingInheritedValue(String),
+
+    #[error("manifest uses {construct}, which cannot be down-leveled for Rust {rust_version}")]
+    IncompatibleManifest { construct: String, rust_version: Version },
+
+    #[error("no Rust version to check with (none of the selected packages has a rust-version)")]
+    MissingRustVersion,
+
+    #[error("check command failed, but no dependency that could be pinned was found")]
+    NoPinnableFailure,
+
+    #[error("check command still failing after {0} attempt(s) to pin dependencies")]
+    PinSuggestionLimitReached(usize),
+
+    #[error("crate registry index not found at '{0}'")]
+    IndexNotFound(Utf8PathBuf),
+
+    #[error("invalid crate registry index file '{0}'")]
+    InvalidIndexFile(Utf8PathBuf),
+
+    #[error("invalid pin '{0}' (expected `<crate>@<version>`, e.g. `foo@1.2.3`)")]
+    InvalidPinSpec(String),
+
+    #[error("no pin found for '{0}' in the selected package(s)")]
+    PinNotFound(String),
+
+    #[error("check command fails even with all pins applied")]
+    PinsBaselineFailed,
+
+    #[error("'{0}' is not a dependency of the selected package(s)")]
+    UnknownDependency(String),
+
+    #[error("no version of '{name}' compatible with Rust {rust_version} found in index")]
+    NoCompatibleVersion { name: String, rust_version: Version },
+
+    #[error("no backup found for package(s) expected to be prepped: {}", .0.join(", "))]
+    PackagesNotPrepped(Vec<String>),
+
+    #[error("no manifest needed to be prepped")]
+    NothingToPrep,
+}
+
+/// Exit code used for errors that do not fall into a more specific category.
+pub const EXIT_FAILURE: u8 = 1;
+
+/// Exit code used when no manifest needed to be prepped (see [`Error::NothingToPrep`]).
+pub const EXIT_NOTHING_TO_PREP: u8 = 3;
+
+/// Exit code used when a manifest is already prepped (e.g. a backup file already exists).
+pub const EXIT_ALREADY_PREPPED: u8 = 4;
+
+/// Exit code used when a pinned MSRV dependencies file (or another TOML file, like a manifest)
+/// is invalid.
+pub const EXIT_INVALID_PINS_FILE: u8 = 5;
+
+/// Exit code used when an I/O operation fails.
+pub const EXIT_IO: u8 = 6;
+
+/// Exit code used when running `cargo metadata` fails.
+pub const EXIT_CARGO_METADATA: u8 = 7;
+
+impl Error {
+    /// Returns the exit code to use when a command fails with this error.
+    ///
+    /// | Exit code | Meaning                                                  |
+    /// |-----------|----------------------------------------------------------|
+    /// | 1         | Other failure ([`EXIT_FAILURE`])                         |
+    /// | 2         | Invalid command-line arguments (reported by `clap`)      |
+    /// | 3         | Nothing to prep ([`EXIT_NOTHING_TO_PREP`])               |
+    /// | 4         | Already prepped ([`EXIT_ALREADY_PREPPED`])               |
+    /// | 5         | Invalid pins file ([`EXIT_INVALID_PINS_FILE`])           |
+    /// | 6         | I/O failure ([`EXIT_IO`])                                |
+    /// | 7         | `cargo metadata` failed ([`EXIT_CARGO_METADATA`])        |
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::CargoMetadata(_) => EXIT_CARGO_METADATA,
+            Self::Io { .. } => EXIT_IO,
+            Self::Toml(_)
+            | Self::TomlFile { .. }
+            | Self::InvalidLockPin { .. }
+            | Self::InvalidPinMetadata { .. }
+            | Self::InvalidPinsSection(_)
+            | Self::InvalidKeyPath(_) => EXIT_INVALID_PINS_FILE,
+            Self::BackupFileAlreadyExists(_) | Self::ManifestPrepped(_) => EXIT_ALREADY_PREPPED,
+            Self::NothingToPrep => EXIT_NOTHING_TO_PREP,
+            Self::InvalidPath(_)
+            | Self::Git { .. }
+            | Self::InvalidRestoredManifest { .. }
+            | Self::InvalidCheckCommand(_)
+            | Self::MsrvVerificationFailed(_)
+            | Self::ToolchainList(_)
+            | Self::NoToolchains
+            | Self::MsrvNotFound
+            | Self::ExpiredPins(_)
+            | Self::LockPinFailed { .. }
+            | Self::GenerateLockfileFailed
+            | Self::MinimalVersionUnresolved(_)
+            | Self::UnsupportedLockfileVersion(_)
+            | Self::MissingInheritedValue(_)
+            | Self::IncompatibleManifest { .. }
+            | Self::MissingRustVersion
+            | Self::NoPinnableFailure
+            | Self::PinSuggestionLimitReached(_)
+            | Self::IndexNotFound(_)
+            | Self::InvalidIndexFile(_)
+            | Self::InvalidPinSpec(_)
+            | Self::PinNotFound(_)
+            | Self::PinsBaselineFailed
+            | Self::UnknownDependency(_)
+            | Self::NoCompatibleVersion { .. }
+            | Self::PackagesNotPrepped(_) => EXIT_FAILURE,
+        }
+    }
 }
 
 /// Trait used to provide context for I/O errors.
@@ -80,11 +259,124 @@ where
     }
 }
 
+/// Trait used to provide the path and content of the file that could not be parsed for TOML
+/// errors.
+///
+/// The resulting [`Error::TomlFile`] displays a snippet of the file pointing to the error, e.g.:
+///
+/// ```text
+/// invalid TOML file 'msrv-pins.toml': invalid float, expected nothing
+///  --> msrv-pins.toml:9:10
+///   |
+/// 9 | foo = 1.0.0
+///   |          ^^
+/// ```
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs;
+///
+/// use cargo_msrv_prep::result::{IoErrorContext, TomlErrorContext};
+/// use toml_edit::DocumentMut;
+///
+/// # fn read_pins_file() -> cargo_msrv_prep::Result<()> {
+/// let pins_file_text =
+///     fs::read_to_string("msrv-pins.toml").with_io_context(|| "reading 'msrv-pins.toml'")?;
+/// let pins_file = pins_file_text
+///     .parse::<DocumentMut>()
+///     .with_toml_file("msrv-pins.toml", &pins_file_text)?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait TomlErrorContext {
+    /// Type returned from [`with_toml_file`](Self::with_toml_file).
+    type Output;
+
+    /// Provides the path and content of the file that could not be parsed.
+    ///
+    /// See [trait description](Self) for details.
+    fn with_toml_file<P>(self, path: P, text: &str) -> Self::Output
+    where
+        P: Into<Utf8PathBuf>;
+}
+
+impl TomlErrorContext for TomlError {
+    type Output = Error;
+
+    fn with_toml_file<P>(self, path: P, text: &str) -> Self::Output
+    where
+        P: Into<Utf8PathBuf>,
+    {
+        Error::TomlFile { path: path.into(), text: text.into(), source: Box::new(self) }
+    }
+}
+
+impl<T, E> TomlErrorContext for core::result::Result<T, E>
+where
+    E: TomlErrorContext<Output = Error>,
+{
+    type Output = Result<T>;
+
+    fn with_toml_file<P>(self, path: P, text: &str) -> Self::Output
+    where
+        P: Into<Utf8PathBuf>,
+    {
+        self.map_err(|err| err.with_toml_file(path, text))
+    }
+}
+
+fn toml_file_snippet(path: &Utf8Path, text: &str, source: &TomlError) -> String {
+    source
+        .span()
+        .map(|span| format!("\n{}", source_snippet(path, text, span)))
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 #[cfg_attr(coverage_nightly, coverage(off))]
 mod tests {
     use super::*;
 
+    mod error {
+        use super::*;
+
+        #[test]
+        fn exit_code() {
+            assert_eq!(EXIT_NOTHING_TO_PREP, Error::NothingToPrep.exit_code());
+            assert_eq!(
+                EXIT_ALREADY_PREPPED,
+                Error::BackupFileAlreadyExists("Cargo.toml.msrv-prep.bak".into()).exit_code()
+            );
+            assert_eq!(
+                EXIT_ALREADY_PREPPED,
+                Error::ManifestPrepped("Cargo.toml".into()).exit_code()
+            );
+            assert_eq!(
+                EXIT_INVALID_PINS_FILE,
+                "[dependencies"
+                    .parse::<toml_edit::DocumentMut>()
+                    .with_toml_file("msrv-pins.toml", "[dependencies")
+                    .unwrap_err()
+                    .exit_code()
+            );
+            assert_eq!(
+                EXIT_IO,
+                io::Error::other("oh no")
+                    .with_io_context(|| "doing something")
+                    .exit_code()
+            );
+            assert_eq!(
+                EXIT_CARGO_METADATA,
+                Error::CargoMetadata(cargo_metadata::Error::CargoMetadata {
+                    stderr: "oh no".into()
+                })
+                .exit_code()
+            );
+            assert_eq!(EXIT_FAILURE, Error::PinsBaselineFailed.exit_code());
+        }
+    }
+
     mod impl_io_error_context_for_io_error {
         use assert_matches::assert_matches;
 
@@ -128,4 +420,40 @@ mod tests {
             }
         }
     }
+
+    mod impl_toml_error_context_for_core_result {
+        use assert_matches::assert_matches;
+        use toml_edit::DocumentMut;
+
+        use super::*;
+
+        #[test]
+        fn with_toml_file() {
+            let text = "[dependencies\n";
+            let result = text
+                .parse::<DocumentMut>()
+                .with_toml_file("msrv-pins.toml", text);
+
+            assert_matches!(result, Err(Error::TomlFile { path, text, source }) => {
+                assert_eq!("msrv-pins.toml", path);
+                assert_eq!("[dependencies\n", text);
+                assert!(source.span().is_some());
+            });
+        }
+
+        #[test]
+        fn display() {
+            let text = "[dependencies]\nfoo = 1.0.0\n";
+            let error = text
+                .parse::<DocumentMut>()
+                .with_toml_file("msrv-pins.toml", text)
+                .unwrap_err();
+
+            assert_eq!(
+                "invalid TOML file 'msrv-pins.toml': invalid float, expected nothing\n \
+                 --> msrv-pins.toml:2:10\n  |\n2 | foo = 1.0.0\n  |          ^^",
+                error.to_string()
+            );
+        }
+    }
 }
@@ -0,0 +1,194 @@
+//! Helpers to remove arbitrary keys from manifests.
+//!
+//! Some manifest keys make it impossible to determine/verify MSRV using a stable toolchain
+//! (e.g. `cargo-features` or other nightly-only keys). These can be removed by specifying their
+//! paths, using TOML dotted key syntax (e.g. `package.autolib` or
+//! `target.'cfg(unix)'.dependencies.foo`).
+//!
+//! Keys to remove can be passed on the command line or listed in the pinned MSRV dependencies
+//! file next to a manifest:
+//!
+//! ```toml
+//! [msrv-prep]
+//! strip-keys = ["cargo-features", "package.autolib"]
+//! ```
+
+use log::{debug, trace};
+use toml_edit::{Item, Key, Table, TableLike};
+
+use crate::{Error, Result};
+
+/// Name of the section of the pinned MSRV dependencies file storing `cargo-msrv-prep` settings.
+pub const MSRV_PREP_SECTION_NAME: &str = "msrv-prep";
+
+/// Field of the `msrv-prep` section listing keys to remove from the manifest.
+pub const STRIP_KEYS_FIELD: &str = "strip-keys";
+
+/// Parses the path of a key to remove (e.g. `package.autolib`).
+pub fn parse_key_path(key_path: &str) -> Result<Vec<String>> {
+    let keys = Key::parse(key_path).map_err(|_| Error::InvalidKeyPath(key_path.into()))?;
+
+    Ok(keys.into_iter().map(|key| key.get().to_string()).collect())
+}
+
+/// Removes the keys with the given paths from a manifest (see [`parse_key_path`]).
+///
+/// Returns `true` if the manifest was modified.
+pub fn strip_keys(manifest: &mut Table, key_paths: &[Vec<String>]) -> bool {
+    trace!("Entering `strip_keys` (key_paths: {key_paths:?})");
+
+    let mut changed = false;
+    for key_path in key_paths {
+        let Some((key, parents)) = key_path.split_last() else {
+            continue;
+        };
+
+        let mut table: Option<&mut dyn TableLike> = Some(manifest);
+        for parent in parents {
+            table = table
+                .and_then(|table| table.get_mut(parent))
+                .and_then(Item::as_table_like_mut);
+        }
+        if table.is_some_and(|table| table.remove(key).is_some()) {
+            debug!("Removed key '{}'", key_path.join("."));
+            changed = true;
+        }
+    }
+
+    trace!("Exiting `strip_keys` (changed: {changed})");
+    changed
+}
+
+/// Reads the paths of keys to remove stored in the `msrv-prep` section of a pinned MSRV
+/// dependencies file.
+pub fn read_strip_keys(pins_file: &Table) -> Result<Vec<Vec<String>>> {
+    let Some(strip_keys) = pins_file
+        .get(MSRV_PREP_SECTION_NAME)
+        .and_then(|section| section.get(STRIP_KEYS_FIELD))
+    else {
+        return Ok(Vec::new());
+    };
+
+    strip_keys
+        .as_array()
+        .ok_or_else(|| Error::InvalidKeyPath(strip_keys.to_string().trim().into()))?
+        .iter()
+        .map(|key_path| match key_path.as_str() {
+            Some(key_path) => parse_key_path(key_path),
+            None => Err(Error::InvalidKeyPath(key_path.to_string().trim().into())),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod parse_key_path {
+        use assert_matches::assert_matches;
+
+        use super::*;
+
+        #[test]
+        fn valid() {
+            assert_eq!(vec!["cargo-features"], parse_key_path("cargo-features").unwrap());
+            assert_eq!(vec!["package", "autolib"], parse_key_path("package.autolib").unwrap());
+            assert_eq!(
+                vec!["target", "cfg(unix)", "dependencies"],
+                parse_key_path("target.'cfg(unix)'.dependencies").unwrap()
+            );
+        }
+
+        #[test]
+        fn invalid() {
+            assert_matches!(parse_key_path("package..autolib"), Err(Error::InvalidKeyPath(_)));
+        }
+    }
+
+    mod strip_keys {
+        use indoc::indoc;
+        use toml_edit::DocumentMut;
+
+        use super::*;
+
+        #[test_log::test]
+        fn all() {
+            let mut manifest = indoc! {r#"
+                cargo-features = ["different-binary-name"]
+
+                [package]
+                name = "foo"
+                autolib = false
+                metadata = { docs = { rs = true }, other = 1 }
+
+                [target.'cfg(unix)'.dependencies]
+                nightly-only = "1.0.0"
+                either = "1.10.0"
+            "#}
+            .parse::<DocumentMut>()
+            .unwrap();
+            let key_paths: Vec<_> = [
+                "cargo-features",
+                "package.autolib",
+                "package.metadata.docs",
+                "target.'cfg(unix)'.dependencies.nightly-only",
+                "does.not.exist",
+            ]
+            .into_iter()
+            .map(|key_path| parse_key_path(key_path).unwrap())
+            .collect();
+
+            assert!(strip_keys(&mut manifest, &key_paths));
+            assert!(!strip_keys(&mut manifest, &key_paths));
+
+            // Note: the decor of the removed key is removed, but not that of the following table.
+            let expected = indoc! {r#"
+
+                [package]
+                name = "foo"
+                metadata = { other = 1 }
+
+                [target.'cfg(unix)'.dependencies]
+                either = "1.10.0"
+            "#};
+            assert_eq!(expected, manifest.to_string());
+        }
+    }
+
+    mod read_strip_keys {
+        use assert_matches::assert_matches;
+        use indoc::indoc;
+        use toml_edit::Document;
+
+        use super::*;
+
+        #[test]
+        fn msrv_prep_section() {
+            let pins_file = Document::parse(indoc! {r#"
+                [msrv-prep]
+                strip-keys = ["cargo-features", "package.autolib"]
+            "#})
+            .unwrap();
+
+            assert_eq!(
+                vec![vec!["cargo-features".to_string()], vec!["package".into(), "autolib".into()]],
+                read_strip_keys(&pins_file).unwrap()
+            );
+        }
+
+        #[test]
+        fn no_msrv_prep_section() {
+            let pins_file = Document::parse("[dependencies]\nserde = \"1.0.197\"\n").unwrap();
+
+            assert!(read_strip_keys(&pins_file).unwrap().is_empty());
+        }
+
+        #[test]
+        fn invalid() {
+            let pins_file = Document::parse("[msrv-prep]\nstrip-keys = [42]\n").unwrap();
+
+            assert_matches!(read_strip_keys(&pins_file), Err(Error::InvalidKeyPath(_)));
+        }
+    }
+}
@@ -0,0 +1,471 @@
+//! Helpers to suggest MSRV pins from a failing check command.
+//!
+//! Manifests are prepared (see [`prep_for_msrv`](crate::prep::prep_for_msrv)), then a check
+//! command is run using `--message-format=json`. If it fails, Cargo's JSON diagnostics (and its
+//! errors about packages requiring a newer `rustc`) are parsed to find which dependencies failed
+//! to build; each one is then pinned in the pinned MSRV dependencies file (see [`PinLocation`])
+//! to versions before (or after, see [`PinDirection`]) the one that failed, staying within
+//! semver-compatible versions. Manifests are restored (see [`unprep_from_msrv`]) and the
+//! process is repeated until the check command succeeds, or until a limit is reached.
+//!
+//! Transitive dependencies are pinned in the lockfile (see
+//! [`LOCK_SECTION_NAME`](crate::lock_pins::LOCK_SECTION_NAME)), which requires an exact
+//! version; it is looked up in a crate registry index (see [`SuggestPinsOptions::index`]).
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+use cargo_metadata::diagnostic::DiagnosticLevel;
+use cargo_metadata::semver::{Version, VersionReq};
+use cargo_metadata::Message;
+use log::{debug, error, info, trace, warn};
+
+use crate::backup::BackupBackend;
+use crate::index::CrateIndex;
+use crate::metadata::Metadata;
+use crate::pins::{pin_value, read_pins_file, set_pin, write_pins_file, PinLocation};
+use crate::prep::PrepOptions;
+use crate::unprep::{unprep_from_msrv, UnprepOptions};
+use crate::verify::{prep_for_check, CheckCommand, CheckOutput, PACKAGE_PLACEHOLDER};
+use crate::{Error, Result};
+
+/// Argument added to check commands so that Cargo emits JSON diagnostics.
+pub const MESSAGE_FORMAT_JSON_ARG: &str = "--message-format=json";
+
+/// Default maximum number of times the check command is run.
+pub const DEFAULT_MAX_ATTEMPTS: usize = 10;
+
+/// Prefix of the argument used to specify Cargo's message format.
+const MESSAGE_FORMAT_ARG_PREFIX: &str = "--message-format";
+
+/// Direction in which failing dependencies are pinned.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PinDirection {
+    /// Pin failing dependencies to older versions (e.g. when they require a newer `rustc`).
+    #[default]
+    Older,
+
+    /// Pin failing dependencies to newer versions (e.g. when checking minimal versions).
+    Newer,
+}
+
+/// A dependency that failed to build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailingPackage {
+    /// Name of the package.
+    pub name: String,
+
+    /// Version of the package that failed to build.
+    pub version: Version,
+}
+
+impl Display for FailingPackage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.name, self.version)
+    }
+}
+
+/// Pin suggested (and written) by [`suggest_pins`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinSuggestion {
+    /// Where the pin was written.
+    pub location: PinLocation,
+
+    /// Dependency that failed to build.
+    pub package: FailingPackage,
+
+    /// Version requirement the dependency was pinned to (or version it was pinned to in the
+    /// lockfile, for transitive dependencies).
+    pub requirement: String,
+}
+
+/// Options used by [`suggest_pins`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuggestPinsOptions {
+    /// Template of the check command (see [`CheckCommand::from_template`]).
+    ///
+    /// [`MESSAGE_FORMAT_JSON_ARG`] is added unless a message format is already specified.
+    pub check_command_template: String,
+
+    /// Rust version used to run the check command.
+    pub rust_version: Version,
+
+    /// Direction in which failing dependencies are pinned.
+    pub direction: PinDirection,
+
+    /// Maximum number of times the check command is run.
+    pub max_attempts: usize,
+
+    /// Crate registry index used to find versions of transitive dependencies to pin in the
+    /// lockfile. If `None`, failing transitive dependencies are not pinned.
+    pub index: Option<CrateIndex>,
+}
+
+/// Outcome of [`suggest_pins`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SuggestPinsOutcome {
+    /// The check command succeeded (possibly after pinning dependencies).
+    Succeeded,
+
+    /// The check command failed, but no dependency could be pinned (e.g. because the failure
+    /// is in a workspace package, or because a dependency cannot be pinned any further).
+    NoPinnableFailure,
+
+    /// The check command was still failing after the maximum number of attempts.
+    LimitReached,
+}
+
+/// Result of [`suggest_pins`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuggestPinsResult {
+    /// Pins suggested, in the order they were written.
+    pub suggestions: Vec<PinSuggestion>,
+
+    /// Outcome of the process.
+    pub outcome: SuggestPinsOutcome,
+}
+
+/// Parses a package ID, returning the package's name and version if it comes from a registry.
+///
+/// Both the current format (e.g. `registry+https://github.com/rust-lang/crates.io-index#foo@1.2.3`)
+/// and the format used before Cargo 1.77 (e.g. `foo 1.2.3 (registry+https://...)`) are supported.
+pub fn parse_package_id(package_id: &str) -> Option<FailingPackage> {
+    let (name, version) = match package_id.split_whitespace().collect::<Vec<_>>()[..] {
+        [name, version, source] => {
+            let source = source.strip_prefix('(')?;
+            (source.starts_with("registry+") || source.starts_with("sparse+"))
+                .then_some((name, version))?
+        },
+        [package_id] => {
+            if !package_id.starts_with("registry+") && !package_id.starts_with("sparse+") {
+                return None;
+            }
+            package_id.rsplit_once('#')?.1.split_once('@')?
+        },
+        _ => return None,
+    };
+
+    Some(FailingPackage { name: name.into(), version: version.parse().ok()? })
+}
+
+/// Finds the dependencies that failed to build, given a check command's output.
+///
+/// Packages are found in compiler errors (in JSON diagnostics) and in Cargo's errors about
+/// packages requiring a newer `rustc`. Packages named in `workspace_members` are ignored.
+pub fn failing_packages(output: &CheckOutput, workspace_members: &[&str]) -> Vec<FailingPackage> {
+    let from_diagnostics =
+        Message::parse_stream(output.stdout.as_bytes()).filter_map(|message| match message {
+            Ok(Message::CompilerMessage(message))
+                if message.message.level == DiagnosticLevel::Error =>
+            {
+                parse_package_id(&message.package_id.repr)
+            },
+            _ => None,
+        });
+    let from_rust_version = output.stderr.lines().filter_map(parse_rust_version_error);
+
+    let mut seen = HashSet::new();
+    from_diagnostics
+        .chain(from_rust_version)
+        .filter(|package| !workspace_members.contains(&package.name.as_str()))
+        .filter(|package| seen.insert(package.name.clone()))
+        .collect()
+}
+
+/// Returns the version requirement to which a failing dependency should be pinned.
+///
+/// The requirement only matches versions that are semver-compatible with the failing one
+/// (e.g. `>=1.0.0, <1.2.3` for `1.2.3`). Returns `None` if there are no such versions.
+pub fn pin_requirement(version: &Version, direction: PinDirection) -> Option<String> {
+    let version = Version::new(version.major, version.minor, version.patch);
+
+    match direction {
+        PinDirection::Older => {
+            let lower = match (version.major, version.minor) {
+                (0, 0) => version.clone(),
+                (0, minor) => Version::new(0, minor, 0),
+                (major, _) => Version::new(major, 0, 0),
+            };
+            (lower < version).then(|| format!(">={lower}, <{version}"))
+        },
+        PinDirection::Newer => {
+            let upper = match (version.major, version.minor) {
+                (0, 0) => return None,
+                (0, minor) => Version::new(0, minor + 1, 0),
+                (major, _) => Version::new(major + 1, 0, 0),
+            };
+            Some(format!(">{version}, <{upper}"))
+        },
+    }
+}
+
+/// Suggests pins for dependencies that prevent a check command from succeeding.
+///
+/// The check command is built from [`SuggestPinsOptions::check_command_template`] and run
+/// via `runner` while manifests are prepared (see [module description](self) for details).
+/// Suggested pins are written to the pinned MSRV dependencies files as they are found, so
+/// that they are merged in manifests when preparing them again. Manifests are always restored
+/// after running the command, even if preparing them fails or the command fails to run.
+pub fn suggest_pins<R>(
+    metadata: &Metadata,
+    backup_backend: &dyn BackupBackend,
+    prep_options: &PrepOptions,
+    unprep_options: &UnprepOptions,
+    options: &SuggestPinsOptions,
+    mut runner: R,
+) -> Result<SuggestPinsResult>
+where
+    R: FnMut(&CheckCommand) -> Result<CheckOutput>,
+{
+    trace!("Entering `suggest_pins` (options: {options:?})");
+
+    if options.check_command_template.contains(PACKAGE_PLACEHOLDER) {
+        return Err(Error::InvalidCheckCommand(options.check_command_template.clone()));
+    }
+    let mut command = CheckCommand::from_template(
+        &options.check_command_template,
+        &options.rust_version.to_string(),
+        "",
+    )?;
+    if !command
+        .args
+        .iter()
+        .any(|arg| arg.starts_with(MESSAGE_FORMAT_ARG_PREFIX))
+    {
+        command.args.push(MESSAGE_FORMAT_JSON_ARG.into());
+    }
+
+    let workspace_packages = metadata.cargo_metadata.workspace_packages();
+    let workspace_members: Vec<_> = workspace_packages
+        .iter()
+        .map(|package| package.name.as_str())
+        .collect();
+
+    let mut suggestions = Vec::new();
+    let mut outcome = SuggestPinsOutcome::LimitReached;
+    for attempt in 1..=options.max_attempts {
+        prep_for_check(metadata, backup_backend, prep_options, unprep_options)?;
+
+        info!("Running check command '{command}' (attempt {attempt})");
+        let run_result = runner(&command);
+        if let Err(err) = &run_result {
+            error!("Failed to run check command '{command}': {err}");
+        }
+
+        let unprep_result = unprep_from_msrv(metadata, backup_backend, unprep_options);
+        let output = run_result?;
+        unprep_result?;
+
+        if output.success {
+            info!("Check command succeeded");
+            outcome = SuggestPinsOutcome::Succeeded;
+            break;
+        }
+
+        let failing = failing_packages(&output, &workspace_members);
+        debug!("Failing dependencies: {failing:?}");
+
+        let mut pinned = false;
+        for package in failing {
+            if let Some(suggestion) =
+                pin_failing_package(metadata, &prep_options.pins_file_name, package, options)?
+            {
+                info!(
+                    "Pinned {} to '{}' in '{}' ({})",
+                    suggestion.package,
+                    suggestion.requirement,
+                    suggestion.location.pins_file_path,
+                    suggestion.location
+                );
+                suggestions.push(suggestion);
+                pinned = true;
+            }
+        }
+        if !pinned {
+            outcome = SuggestPinsOutcome::NoPinnableFailure;
+            break;
+        }
+    }
+
+    trace!("Exiting `suggest_pins` (outcome: {outcome:?})");
+    Ok(SuggestPinsResult { suggestions, outcome })
+}
+
+fn pin_failing_package(
+    metadata: &Metadata,
+    pins_file_name: &str,
+    package: FailingPackage,
+    options: &SuggestPinsOptions,
+) -> Result<Option<PinSuggestion>> {
+    let Some(requirement) = pin_requirement(&package.version, options.direction) else {
+        warn!("No semver-compatible version of {package} to pin to; skipping");
+        return Ok(None);
+    };
+    let Some((location, dependency)) =
+        PinLocation::for_dependency(metadata, pins_file_name, &package.name)
+    else {
+        warn!("Could not determine where to pin {package}; skipping");
+        return Ok(None);
+    };
+
+    let requirement = match dependency {
+        Some(_) => requirement,
+        None => match lock_pin_version(&package, &requirement, options)? {
+            Some(version) => version.to_string(),
+            None => {
+                warn!("No version of {package} matching '{requirement}' found in index; skipping");
+                return Ok(None);
+            },
+        },
+    };
+
+    let mut pins_file = read_pins_file(&location.pins_file_path)?;
+    let value = pin_value(dependency, &requirement);
+    if !set_pin(&mut pins_file, &location.section, &location.key, value)? {
+        warn!("{package} is already pinned to '{requirement}' but still fails; skipping");
+        return Ok(None);
+    }
+    write_pins_file(&location.pins_file_path, &pins_file)?;
+
+    Ok(Some(PinSuggestion { location, package, requirement }))
+}
+
+/// Returns the version to which a failing transitive dependency should be pinned in the
+/// lockfile: the highest version matching `requirement` that can be used with the Rust version
+/// (or the lowest matching one, when pinning to newer versions).
+fn lock_pin_version(
+    package: &FailingPackage,
+    requirement: &str,
+    options: &SuggestPinsOptions,
+) -> Result<Option<Version>> {
+    let Some(index) = &options.index else {
+        return Ok(None);
+    };
+    let requirement = VersionReq::parse(requirement).expect("requirement should be valid");
+
+    let version = match options.direction {
+        PinDirection::Older => index
+            .highest_compatible(&package.name, &options.rust_version, Some(&requirement))?
+            .map(|entry| entry.version),
+        PinDirection::Newer => index
+            .versions(&package.name)?
+            .into_iter()
+            .filter(|entry| !entry.yanked && entry.version.pre.is_empty())
+            .filter(|entry| requirement.matches(&entry.version))
+            .map(|entry| entry.version)
+            .min(),
+    };
+
+    Ok(version)
+}
+
+fn parse_rust_version_error(line: &str) -> Option<FailingPackage> {
+    let line = line.trim();
+
+    let (name, version) = if let Some(package) = line.strip_prefix("error: package `") {
+        // Cargo 1.56 to 1.79: "package `foo v1.2.3` cannot be built because it requires rustc..."
+        package.split_once('`')?.0.split_once(" v")?
+    } else {
+        // Cargo 1.80+: "foo@1.2.3 requires rustc 1.74"
+        line.split_once(" requires rustc ")?.0.split_once('@')?
+    };
+
+    Some(FailingPackage { name: name.into(), version: version.parse().ok()? })
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod parse_package_id {
+        use super::*;
+
+        #[test]
+        fn registry() {
+            let expected = FailingPackage { name: "foo".into(), version: Version::new(1, 2, 3) };
+
+            assert_eq!(
+                Some(expected.clone()),
+                parse_package_id("registry+https://github.com/rust-lang/crates.io-index#foo@1.2.3")
+            );
+            assert_eq!(
+                Some(expected.clone()),
+                parse_package_id("sparse+https://index.crates.io/#foo@1.2.3")
+            );
+            assert_eq!(
+                Some(expected),
+                parse_package_id(
+                    "foo 1.2.3 (registry+https://github.com/rust-lang/crates.io-index)"
+                )
+            );
+        }
+
+        #[test]
+        fn not_registry() {
+            assert_eq!(None, parse_package_id("path+file:///work/foo#0.1.0"));
+            assert_eq!(None, parse_package_id("git+https://github.com/foo/bar#baz@0.1.0"));
+            assert_eq!(None, parse_package_id("foo 0.1.0 (path+file:///work/foo)"));
+        }
+    }
+
+    mod failing_packages {
+        use indoc::indoc;
+
+        use super::*;
+
+        #[test]
+        fn all() {
+            let output = CheckOutput {
+                success: false,
+                stdout: indoc! {r#"
+                    {"reason":"compiler-message","package_id":"registry+https://github.com/rust-lang/crates.io-index#foo@1.2.3","manifest_path":"/foo/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"foo","src_path":"/foo/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"message":{"rendered":"error","$message_type":"diagnostic","children":[],"code":null,"level":"error","message":"oops","spans":[]}}
+                    {"reason":"compiler-message","package_id":"registry+https://github.com/rust-lang/crates.io-index#bar@0.4.0","manifest_path":"/bar/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"bar","src_path":"/bar/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"message":{"rendered":"warning","$message_type":"diagnostic","children":[],"code":null,"level":"warning","message":"hmm","spans":[]}}
+                    {"reason":"compiler-message","package_id":"path+file:///work/my-crate#0.1.0","manifest_path":"/work/my-crate/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"my-crate","src_path":"/work/my-crate/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"message":{"rendered":"error","$message_type":"diagnostic","children":[],"code":null,"level":"error","message":"oops","spans":[]}}
+                    {"reason":"build-finished","success":false}
+                "#}
+                .into(),
+                stderr: indoc! {"
+                    error: package `baz v2.0.1` cannot be built because it requires rustc 1.74 or newer, while the currently active rustc version is 1.70.0
+                    error: rustc 1.70.0 is not supported by the following packages:
+                      qux@0.3.5 requires rustc 1.72
+                      my-crate@0.1.0 requires rustc 1.75
+                "}
+                .into(),
+            };
+
+            let names: Vec<_> = failing_packages(&output, &["my-crate"])
+                .into_iter()
+                .map(|package| package.to_string())
+                .collect();
+            assert_eq!(vec!["foo@1.2.3", "baz@2.0.1", "qux@0.3.5"], names);
+        }
+    }
+
+    mod pin_requirement {
+        use super::*;
+
+        #[test]
+        fn older() {
+            let pin =
+                |version: &str| pin_requirement(&version.parse().unwrap(), PinDirection::Older);
+
+            assert_eq!(Some(">=1.0.0, <1.2.3".into()), pin("1.2.3"));
+            assert_eq!(Some(">=0.3.0, <0.3.5".into()), pin("0.3.5"));
+            assert_eq!(Some(">=2.0.0, <2.0.1".into()), pin("2.0.1+extra"));
+            assert_eq!(None, pin("1.0.0"));
+            assert_eq!(None, pin("0.3.0"));
+            assert_eq!(None, pin("0.0.7"));
+        }
+
+        #[test]
+        fn newer() {
+            let pin =
+                |version: &str| pin_requirement(&version.parse().unwrap(), PinDirection::Newer);
+
+            assert_eq!(Some(">1.2.3, <2.0.0".into()), pin("1.2.3"));
+            assert_eq!(Some(">0.3.5, <0.4.0".into()), pin("0.3.5"));
+            assert_eq!(None, pin("0.0.7"));
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Helpers to prepare the `rust-toolchain.toml` (or `rust-toolchain`) file of a workspace.
+//!
+//! A toolchain file pinning a recent toolchain can override the toolchain used when
+//! determining/verifying MSRV, so it can either be rewritten to use a specific channel
+//! or removed altogether.
+
+use std::fs;
+
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, info, trace};
+use toml_edit::{table, value, DocumentMut, Item};
+
+use crate::backup::BackupBackend;
+#[mockall_double::double]
+use crate::mockable::fs as mockable_fs;
+use crate::result::IoErrorContext;
+use crate::Result;
+
+/// Names of toolchain files, in the order in which they are looked up by `rustup`.
+pub const TOOLCHAIN_FILE_NAMES: &[&str] = &["rust-toolchain", "rust-toolchain.toml"];
+
+/// Name of the table storing toolchain information in a toolchain file.
+const TOOLCHAIN_SECTION_NAME: &str = "toolchain";
+
+/// Field in the `toolchain` section of a toolchain file that stores the toolchain channel.
+const CHANNEL_SPECIFIER: &str = "channel";
+
+/// Change to apply to a workspace's toolchain file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolchainFileChange {
+    /// Set the toolchain channel (e.g. `1.70.0`).
+    SetChannel(String),
+
+    /// Remove the toolchain file.
+    Remove,
+}
+
+/// Returns the path of the toolchain file at the given workspace root, if there is one.
+pub fn find_toolchain_file(workspace_root: &Utf8Path) -> Option<Utf8PathBuf> {
+    TOOLCHAIN_FILE_NAMES
+        .iter()
+        .map(|name| workspace_root.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Sets the toolchain channel in the content of a toolchain file.
+///
+/// If the content is in TOML format, the `channel` field of the `toolchain` table is
+/// updated (preserving formatting). Otherwise, the content is assumed to be in the legacy
+/// format (e.g. only a channel name) and is replaced.
+pub fn set_toolchain_channel(toolchain_file_text: &str, channel: &str) -> String {
+    match toolchain_file_text.parse::<DocumentMut>() {
+        Ok(mut toolchain_file) if !toolchain_file.is_empty() => {
+            let toolchain = toolchain_file
+                .entry(TOOLCHAIN_SECTION_NAME)
+                .or_insert_with(table);
+            match toolchain
+                .get_mut(CHANNEL_SPECIFIER)
+                .and_then(Item::as_value_mut)
+            {
+                Some(existing) => {
+                    let decor = existing.decor().clone();
+                    *existing = channel.into();
+                    *existing.decor_mut() = decor;
+                },
+                None => toolchain[CHANNEL_SPECIFIER] = value(channel),
+            }
+
+            toolchain_file.to_string()
+        },
+        _ => format!("{channel}\n"),
+    }
+}
+
+/// Applies a change to the toolchain file at the given workspace root, if there is one.
+///
+/// The toolchain file is backed up using the given backend before being modified.
+/// If `dry_run` is `true`, the toolchain file is not actually modified.
+///
+/// Returns `Ok(true)` if a toolchain file needed to be changed.
+pub fn prep_toolchain_file(
+    workspace_root: &Utf8Path,
+    change: &ToolchainFileChange,
+    backup_backend: &dyn BackupBackend,
+    force: bool,
+    dry_run: bool,
+) -> Result<bool> {
+    trace!(
+        "Entering `prep_toolchain_file` (workspace_root: '{workspace_root}', change: {change:?})"
+    );
+
+    let Some(toolchain_file_path) = find_toolchain_file(workspace_root) else {
+        info!("No toolchain file found in '{workspace_root}'; skipping");
+        return Ok(false);
+    };
+    debug!("Toolchain file path: {toolchain_file_path}");
+
+    let new_text = match change {
+        ToolchainFileChange::SetChannel(channel) => {
+            let text = fs::read_to_string(&toolchain_file_path)
+                .with_io_context(|| format!("reading toolchain file '{toolchain_file_path}'"))?;
+            let new_text = set_toolchain_channel(&text, channel);
+            if new_text == text {
+                info!("Toolchain file '{toolchain_file_path}' already uses channel '{channel}'");
+                return Ok(false);
+            }
+
+            Some(new_text)
+        },
+        ToolchainFileChange::Remove => None,
+    };
+
+    if dry_run {
+        info!(
+            "Toolchain file '{toolchain_file_path}' needs changes; not persisting (dry-run mode)"
+        );
+    } else {
+        backup_backend.backup_files(&[&toolchain_file_path], force)?;
+
+        match new_text {
+            Some(new_text) => {
+                info!("Updating toolchain file '{toolchain_file_path}'");
+                mockable_fs::write(&toolchain_file_path, new_text).with_io_context(|| {
+                    format!("saving updated toolchain file to '{toolchain_file_path}'")
+                })?;
+            },
+            None => {
+                info!("Removing toolchain file '{toolchain_file_path}'");
+                mockable_fs::remove_file(&toolchain_file_path).with_io_context(|| {
+                    format!("removing toolchain file '{toolchain_file_path}'")
+                })?;
+            },
+        }
+    }
+
+    trace!("Exiting `prep_toolchain_file`");
+    Ok(true)
+}
+
+/// Restores the toolchain file at the given workspace root, if it was backed up
+/// by [`prep_toolchain_file`].
+///
+/// Returns `Ok(true)` if a toolchain file was restored.
+pub fn maybe_restore_toolchain_file(
+    workspace_root: &Utf8Path,
+    backup_backend: &dyn BackupBackend,
+) -> Result<bool> {
+    trace!("Entering `maybe_restore_toolchain_file` (workspace_root: '{workspace_root}')");
+
+    let mut restored = false;
+    for name in TOOLCHAIN_FILE_NAMES {
+        restored = backup_backend.maybe_restore_file(&workspace_root.join(name))? || restored;
+    }
+
+    trace!("Exiting `maybe_restore_toolchain_file` (restored: {restored})");
+    Ok(restored)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod set_toolchain_channel {
+        use indoc::indoc;
+
+        use super::*;
+
+        #[test]
+        fn toml_format() {
+            let text = indoc! {r#"
+                # Our toolchain
+                [toolchain]
+                channel = "1.85.1" # recent
+                components = ["clippy"]
+            "#};
+
+            let expected = indoc! {r#"
+                # Our toolchain
+                [toolchain]
+                channel = "1.70.0" # recent
+                components = ["clippy"]
+            "#};
+            assert_eq!(expected, set_toolchain_channel(text, "1.70.0"));
+        }
+
+        #[test]
+        fn toml_format_without_channel() {
+            let text = indoc! {r#"
+                [toolchain]
+                profile = "minimal"
+            "#};
+
+            let expected = indoc! {r#"
+                [toolchain]
+                profile = "minimal"
+                channel = "1.70.0"
+            "#};
+            assert_eq!(expected, set_toolchain_channel(text, "1.70.0"));
+        }
+
+        #[test]
+        fn legacy_format() {
+            assert_eq!("1.70.0\n", set_toolchain_channel("nightly-2024-01-01\n", "1.70.0"));
+            assert_eq!("1.70.0\n", set_toolchain_channel("", "1.70.0"));
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Helpers to restore manifests backed up by `cargo msrv-prep`.
+
+use std::fmt::{Display, Formatter};
+use std::fs;
+
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, error, info, trace, warn};
+use toml_edit::DocumentMut;
+
+use crate::backup::BackupBackend;
+use crate::cargo_config::maybe_restore_cargo_config;
+use crate::detail::{markdown_table, write_table};
+use crate::metadata::Metadata;
+use crate::prep::relative_location;
+use crate::result::IoErrorContext;
+use crate::toolchain::maybe_restore_toolchain_file;
+use crate::{has_rust_version, Error, Result, DEFAULT_MANIFEST_FILE_NAME, RUST_VERSION_SPECIFIER};
+
+/// Outcome of restoring a manifest via [`restore_manifest`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RestoreStatus {
+    /// Manifest was restored from its backup.
+    Restored,
+
+    /// No backup was found for the manifest.
+    NothingToRestore,
+}
+
+impl Display for RestoreStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Restored => write!(f, "restored"),
+            Self::NothingToRestore => write!(f, "nothing to restore"),
+        }
+    }
+}
+
+/// Restores a manifest (and its lockfile, if any) from its backup, then verifies the result.
+///
+/// After restoring, the manifest is parsed to make sure it is valid. If the backup had a
+/// `rust-version` field that was missing from the prepped manifest (e.g. it was removed by
+/// `cargo msrv-prep`), the restored manifest must have it again.
+pub fn restore_manifest(
+    manifest_path: &Utf8Path,
+    backup_backend: &dyn BackupBackend,
+) -> Result<RestoreStatus> {
+    trace!("Entering `restore_manifest` (manifest_path: '{manifest_path}')");
+
+    let Some(backup) = backup_backend.read_backup(manifest_path)? else {
+        info!("No backup found for manifest '{manifest_path}'");
+        backup_backend.maybe_restore_manifest(manifest_path)?;
+
+        trace!("Exiting `restore_manifest` (nothing to restore)");
+        return Ok(RestoreStatus::NothingToRestore);
+    };
+
+    let backup_had_rust_version = String::from_utf8_lossy(&backup)
+        .parse::<DocumentMut>()
+        .is_ok_and(|backup| has_rust_version(&backup));
+    let prepped_has_rust_version = fs::read_to_string(manifest_path)
+        .ok()
+        .and_then(|text| text.parse::<DocumentMut>().ok())
+        .is_some_and(|prepped| has_rust_version(&prepped));
+    let rust_version_removed = backup_had_rust_version && !prepped_has_rust_version;
+    debug!("'{RUST_VERSION_SPECIFIER}' field removed during prep: {rust_version_removed}");
+
+    backup_backend.maybe_restore_manifest(manifest_path)?;
+
+    let invalid =
+        |reason: String| Error::InvalidRestoredManifest { path: manifest_path.into(), reason };
+    let restored_text = fs::read_to_string(manifest_path)
+        .with_io_context(|| format!("reading restored manifest '{manifest_path}'"))?;
+    let restored = restored_text
+        .parse::<DocumentMut>()
+        .map_err(|err| invalid(err.to_string()))?;
+    if rust_version_removed && !has_rust_version(&restored) {
+        return Err(invalid(format!("'{RUST_VERSION_SPECIFIER}' field was not restored")));
+    }
+
+    trace!("Exiting `restore_manifest` (restored)");
+    Ok(RestoreStatus::Restored)
+}
+
+/// Options used by [`unprep_from_msrv`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnprepOptions {
+    /// Whether to always restore the root manifest (and its lockfile).
+    pub backup_root_manifest: bool,
+
+    /// Names of packages that are expected to have been prepped.
+    pub expect_prepped: Vec<String>,
+
+    /// Whether to fail if a package in [`expect_prepped`](Self::expect_prepped) has no backup.
+    pub strict: bool,
+}
+
+/// Outcome of restoring the manifest of a package via [`unprep_from_msrv`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PackageUnprepReport {
+    /// Name of the package.
+    pub name: String,
+
+    /// Path of the package's manifest.
+    pub manifest_path: Utf8PathBuf,
+
+    /// Whether the manifest was restored.
+    pub status: RestoreStatus,
+
+    /// Where the manifest was restored from, if it was (see [`BackupBackend::backup_location`]).
+    pub backup: Option<String>,
+}
+
+/// Report of the manifests restored by [`unprep_from_msrv`].
+///
+/// Its [`Display`] implementation renders a summary table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct UnprepReport {
+    /// Workspace root, used to shorten paths when displayed.
+    pub workspace_root: Utf8PathBuf,
+
+    /// Reports of the selected packages, in order.
+    pub packages: Vec<PackageUnprepReport>,
+}
+
+impl UnprepReport {
+    const HEADER: [&'static str; 3] = ["Package", "Status", "Backup"];
+
+    /// Returns a summary of the report as a Markdown table (e.g. for GitHub job summaries).
+    pub fn to_markdown(&self) -> String {
+        markdown_table(&Self::HEADER, &self.rows())
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.packages
+            .iter()
+            .map(|package| {
+                vec![
+                    package.name.clone(),
+                    package.status.to_string(),
+                    package.backup.as_deref().map_or("-".into(), |backup| {
+                        relative_location(backup, &self.workspace_root)
+                    }),
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Display for UnprepReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_table(f, &Self::HEADER, &self.rows())
+    }
+}
+
+/// Restores the manifests of the selected packages (as well as other files modified
+/// during preparation) from backups created by [`prep_for_msrv`](crate::prep::prep_for_msrv).
+///
+/// Restoring continues even if a file cannot be restored; the first error is returned.
+/// Otherwise, returns a report of the manifests restored.
+pub fn unprep_from_msrv(
+    metadata: &Metadata,
+    backup_backend: &dyn BackupBackend,
+    options: &UnprepOptions,
+) -> Result<UnprepReport> {
+    trace!("Entering `unprep_from_msrv` (options: {options:?})");
+
+    debug!("Workspace root: {}", metadata.cargo_metadata.workspace_root);
+    debug!("Selected packages: {}", metadata.selected_package_names());
+
+    let root_manifest_path = metadata
+        .cargo_metadata
+        .workspace_root
+        .join(DEFAULT_MANIFEST_FILE_NAME);
+
+    let mut report = UnprepReport {
+        workspace_root: metadata.cargo_metadata.workspace_root.clone(),
+        packages: Vec::new(),
+    };
+    let mut first_error = None;
+    let mut not_prepped: Vec<_> = options
+        .expect_prepped
+        .iter()
+        .filter(|name| !metadata.selected_packages.iter().any(|p| p.name == **name))
+        .cloned()
+        .collect();
+    let mut root_manifest_restored = false;
+    for package in &metadata.selected_packages {
+        info!("Restoring manifest '{}' (at '{}')", package.name, package.manifest_path);
+
+        // Note: the backup location is determined beforehand, since restoring removes the backup.
+        let restored = (|| {
+            let backup = if backup_backend.has_backup(&package.manifest_path)? {
+                Some(backup_backend.backup_location(&package.manifest_path)?)
+            } else {
+                None
+            };
+
+            Ok((restore_manifest(&package.manifest_path, backup_backend)?, backup))
+        })();
+        match restored {
+            Ok((status, backup)) => {
+                info!("Package '{}': {status}", package.name);
+
+                if status == RestoreStatus::NothingToRestore
+                    && options.expect_prepped.contains(&package.name)
+                {
+                    not_prepped.push(package.name.clone());
+                }
+                report.packages.push(PackageUnprepReport {
+                    name: package.name.clone(),
+                    manifest_path: package.manifest_path.clone(),
+                    status,
+                    backup: backup.filter(|_| status == RestoreStatus::Restored),
+                });
+            },
+            Err(err) => {
+                error!("Package '{}': error: {err}", package.name);
+                first_error.get_or_insert(err);
+            },
+        }
+
+        root_manifest_restored =
+            root_manifest_restored || package.manifest_path == root_manifest_path;
+    }
+
+    // Note: the root manifest can also be modified when it is not selected (for example
+    // to pin its workspace dependencies), so we restore it if a backup exists.
+    if root_manifest_restored {
+        info!("Root manifest already restored; skipping");
+    } else {
+        let restored = (|| {
+            if !options.backup_root_manifest && !backup_backend.has_backup(&root_manifest_path)? {
+                return Ok(None);
+            }
+
+            info!("Restoring root manifest (at '{}')", metadata.cargo_metadata.workspace_root);
+
+            // Note: we do the same assumption here as in `prep_for_msrv`
+            // (see the corresponding note).
+            restore_manifest(&root_manifest_path, backup_backend).map(Some)
+        })();
+        match restored {
+            Ok(Some(status)) => info!("Root manifest: {status}"),
+            Ok(None) => (),
+            Err(err) => {
+                error!("Root manifest: error: {err}");
+                first_error.get_or_insert(err);
+            },
+        }
+    }
+
+    match maybe_restore_toolchain_file(&metadata.cargo_metadata.workspace_root, backup_backend) {
+        Ok(restored) => debug!("Toolchain file restored: {restored}"),
+        Err(err) => {
+            error!("Toolchain file: error: {err}");
+            first_error.get_or_insert(err);
+        },
+    }
+
+    match maybe_restore_cargo_config(&metadata.cargo_metadata.workspace_root, backup_backend) {
+        Ok(restored) => debug!("Cargo configuration file restored: {restored}"),
+        Err(err) => {
+            error!("Cargo configuration file: error: {err}");
+            first_error.get_or_insert(err);
+        },
+    }
+
+    if let Some(err) = first_error {
+        return Err(err);
+    }
+    if !not_prepped.is_empty() {
+        if options.strict {
+            error!(
+                "No backup found for packages expected to be prepped: {}",
+                not_prepped.join(", ")
+            );
+            return Err(Error::PackagesNotPrepped(not_prepped));
+        }
+
+        warn!("No backup found for packages expected to be prepped: {}", not_prepped.join(", "));
+    }
+
+    trace!("Exiting `unprep_from_msrv`");
+    Ok(report)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod restore_manifest {
+        use std::io;
+
+        use assert_matches::assert_matches;
+        use cargo_metadata::camino::Utf8PathBuf;
+
+        use super::*;
+
+        /// Backend that "restores" a fixed content, to validate verification.
+        struct FakeBackupBackend {
+            backup: Option<&'static str>,
+            restored: &'static str,
+        }
+
+        impl FakeBackupBackend {
+            fn unsupported<T>(operation: &str) -> Result<T> {
+                Err(io::Error::from(io::ErrorKind::Unsupported))
+                    .with_io_context(|| format!("{operation} (not supported by fake backend)"))
+            }
+        }
+
+        impl BackupBackend for FakeBackupBackend {
+            fn has_backup(&self, _file_path: &Utf8Path) -> Result<bool> {
+                Ok(self.backup.is_some())
+            }
+
+            fn read_backup(&self, _file_path: &Utf8Path) -> Result<Option<Vec<u8>>> {
+                Ok(self.backup.map(|backup| backup.as_bytes().to_vec()))
+            }
+
+            fn backup_files(&self, _file_paths: &[&Utf8Path], _force: bool) -> Result<()> {
+                Self::unsupported("backing up files")
+            }
+
+            fn maybe_restore_file(&self, file_path: &Utf8Path) -> Result<bool> {
+                if self.backup.is_none() {
+                    return Ok(false);
+                }
+
+                fs::write(file_path, self.restored).unwrap();
+                Ok(true)
+            }
+
+            fn backup_location(&self, _file_path: &Utf8Path) -> Result<String> {
+                Self::unsupported("getting backup location")
+            }
+        }
+
+        fn perform_test(
+            prepped: &str,
+            backup: Option<&'static str>,
+            restored: &'static str,
+        ) -> Result<RestoreStatus> {
+            let temp = assert_fs::TempDir::new().unwrap();
+            let manifest_path = Utf8PathBuf::from_path_buf(temp.path().join("Cargo.toml")).unwrap();
+            fs::write(&manifest_path, prepped).unwrap();
+
+            restore_manifest(&manifest_path, &FakeBackupBackend { backup, restored })
+        }
+
+        const WITH_RUST_VERSION: &str = "[package]\nrust-version = \"1.70.0\"\n";
+        const WITHOUT_RUST_VERSION: &str = "[package]\n";
+
+        #[test_log::test]
+        fn restored() {
+            let result =
+                perform_test(WITHOUT_RUST_VERSION, Some(WITH_RUST_VERSION), WITH_RUST_VERSION);
+
+            assert_matches!(result, Ok(RestoreStatus::Restored));
+        }
+
+        #[test_log::test]
+        fn nothing_to_restore() {
+            let result = perform_test(WITH_RUST_VERSION, None, WITH_RUST_VERSION);
+
+            assert_matches!(result, Ok(RestoreStatus::NothingToRestore));
+        }
+
+        #[test_log::test]
+        fn rust_version_not_restored() {
+            let result =
+                perform_test(WITHOUT_RUST_VERSION, Some(WITH_RUST_VERSION), WITHOUT_RUST_VERSION);
+
+            assert_matches!(result, Err(Error::InvalidRestoredManifest { reason, .. }) => {
+                assert!(reason.contains(RUST_VERSION_SPECIFIER));
+            });
+        }
+
+        #[test_log::test]
+        fn invalid_restored_manifest() {
+            let result = perform_test(WITHOUT_RUST_VERSION, Some(WITHOUT_RUST_VERSION), "[package");
+
+            assert_matches!(result, Err(Error::InvalidRestoredManifest { .. }));
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! Helpers to verify the MSRV of packages.
+//!
+//! Verification reads the `rust-version` of each selected package, prepares the manifests
+//! (see [`prep_for_msrv`]), runs a check command using the package's MSRV toolchain, then
+//! restores the manifests (see [`unprep_from_msrv`]).
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::process::Command;
+
+use cargo_metadata::camino::Utf8Path;
+use cargo_metadata::semver::Version;
+use log::{debug, error, info, trace};
+
+use crate::backup::BackupBackend;
+use crate::metadata::Metadata;
+use crate::prep::{prep_for_msrv, PrepOptions};
+use crate::result::IoErrorContext;
+use crate::unprep::{unprep_from_msrv, UnprepOptions};
+use crate::{Error, Result};
+
+/// Default command used to verify a package's MSRV.
+pub const DEFAULT_CHECK_COMMAND: &str = "cargo +{msrv} check --workspace --lib --bins";
+
+/// Placeholder replaced by the package's MSRV in check commands.
+pub const MSRV_PLACEHOLDER: &str = "{msrv}";
+
+/// Placeholder replaced by the package's name in check commands.
+pub const PACKAGE_PLACEHOLDER: &str = "{package}";
+
+/// Environment variables cleared when running check commands, so that the toolchain
+/// selected via the command (e.g. `cargo +1.70.0`) is not overridden.
+const CLEARED_ENV_VARS: &[&str] = &["CARGO", "RUSTC", "RUSTDOC", "RUSTUP_TOOLCHAIN"];
+
+/// Command run to verify a package's MSRV.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CheckCommand {
+    /// Program to run (e.g. `cargo`).
+    pub program: String,
+
+    /// Arguments passed to the program.
+    pub args: Vec<String>,
+}
+
+impl CheckCommand {
+    /// Creates a check command from a template, replacing placeholders
+    /// ([`MSRV_PLACEHOLDER`] and [`PACKAGE_PLACEHOLDER`]) with their values.
+    ///
+    /// The template is split on whitespace; quoting is not supported.
+    pub fn from_template(template: &str, msrv: &str, package_name: &str) -> Result<Self> {
+        let mut parts = template.split_whitespace().map(|part| {
+            part.replace(MSRV_PLACEHOLDER, msrv)
+                .replace(PACKAGE_PLACEHOLDER, package_name)
+        });
+
+        let program = parts
+            .next()
+            .ok_or_else(|| Error::InvalidCheckCommand(template.into()))?;
+        Ok(Self { program, args: parts.collect() })
+    }
+
+    /// Runs the check command in the given directory.
+    ///
+    /// Returns `Ok(true)` if the command succeeded.
+    pub fn run(&self, working_dir: &Utf8Path) -> Result<bool> {
+        trace!("Entering `CheckCommand::run` (command: '{self}', working_dir: '{working_dir}')");
+
+        let mut command = Command::new(&self.program);
+        command.args(&self.args).current_dir(working_dir);
+        for var in CLEARED_ENV_VARS {
+            command.env_remove(var);
+        }
+
+        let status = command
+            .status()
+            .with_io_context(|| format!("running check command '{self}'"))?;
+        debug!("Check command '{self}' exited with status {status}");
+
+        trace!("Exiting `CheckCommand::run`");
+        Ok(status.success())
+    }
+
+    /// Runs the check command in the given directory, capturing its output.
+    pub fn output(&self, working_dir: &Utf8Path) -> Result<CheckOutput> {
+        trace!("Entering `CheckCommand::output` (command: '{self}', working_dir: '{working_dir}')");
+
+        let mut command = Command::new(&self.program);
+        command.args(&self.args).current_dir(working_dir);
+        for var in CLEARED_ENV_VARS {
+            command.env_remove(var);
+        }
+
+        let output = command
+            .output()
+            .with_io_context(|| format!("running check command '{self}'"))?;
+        debug!("Check command '{self}' exited with status {}", output.status);
+
+        trace!("Exiting `CheckCommand::output`");
+        Ok(CheckOutput {
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).into(),
+            stderr: String::from_utf8_lossy(&output.stderr).into(),
+        })
+    }
+}
+
+/// Captured output of a check command (see [`CheckCommand::output`]).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CheckOutput {
+    /// Whether the command succeeded.
+    pub success: bool,
+
+    /// Standard output of the command.
+    pub stdout: String,
+
+    /// Standard error of the command.
+    pub stderr: String,
+}
+
+impl Display for CheckCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {arg}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of verifying a package's MSRV.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VerifyStatus {
+    /// Check command succeeded using the package's MSRV.
+    Passed,
+
+    /// Check command failed using the package's MSRV.
+    Failed,
+
+    /// Package does not declare a `rust-version`, so it was not verified.
+    Skipped,
+}
+
+impl Display for VerifyStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Passed => write!(f, "passed"),
+            Self::Failed => write!(f, "failed"),
+            Self::Skipped => write!(f, "skipped (no rust-version)"),
+        }
+    }
+}
+
+/// Result of verifying a package's MSRV via [`verify_msrv`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageVerifyResult {
+    /// Name of the package.
+    pub package_name: String,
+
+    /// MSRV declared by the package, if any.
+    pub rust_version: Option<Version>,
+
+    /// Outcome of the verification.
+    pub status: VerifyStatus,
+}
+
+/// Prepares manifests before running check commands (see [`prep_for_msrv`]).
+///
+/// If preparation fails, files that were already modified are restored (see
+/// [`unprep_from_msrv`]) before returning the error, unless they were already prepped
+/// beforehand (in which case existing backups are left untouched).
+pub(crate) fn prep_for_check(
+    metadata: &Metadata,
+    backup_backend: &dyn BackupBackend,
+    prep_options: &PrepOptions,
+    unprep_options: &UnprepOptions,
+) -> Result<()> {
+    let Err(err) = prep_for_msrv(metadata, backup_backend, prep_options) else {
+        return Ok(());
+    };
+
+    error!("Failed to prepare manifests: {err}");
+    if !matches!(err, Error::BackupFileAlreadyExists(_) | Error::ManifestPrepped(_)) {
+        if let Err(unprep_err) = unprep_from_msrv(metadata, backup_backend, unprep_options) {
+            error!("Failed to restore manifests: {unprep_err}");
+        }
+    }
+
+    Err(err)
+}
+
+/// Verifies the MSRV of the selected packages.
+///
+/// The `rust-version` of each package is read before preparing the manifests. Then, for each
+/// package declaring an MSRV, a check command is built from `check_command_template` and run
+/// via `runner`. Identical commands (e.g. for packages with the same MSRV, if the template
+/// does not use [`PACKAGE_PLACEHOLDER`]) are only run once. Manifests are always restored
+/// afterwards, even if preparing them or running a command fails.
+pub fn verify_msrv<R>(
+    metadata: &Metadata,
+    backup_backend: &dyn BackupBackend,
+    prep_options: &PrepOptions,
+    unprep_options: &UnprepOptions,
+    check_command_template: &str,
+    mut runner: R,
+) -> Result<Vec<PackageVerifyResult>>
+where
+    R: FnMut(&CheckCommand) -> Result<bool>,
+{
+    trace!("Entering `verify_msrv` (check_command_template: '{check_command_template}')");
+
+    let commands = metadata
+        .selected_packages
+        .iter()
+        .map(|package| {
+            package
+                .rust_version
+                .as_ref()
+                .map(|rust_version| {
+                    CheckCommand::from_template(
+                        check_command_template,
+                        &rust_version.to_string(),
+                        &package.name,
+                    )
+                })
+                .transpose()
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    prep_for_check(metadata, backup_backend, prep_options, unprep_options)?;
+
+    let mut outcomes = HashMap::new();
+    let mut run_result = Ok(());
+    for command in commands.iter().flatten() {
+        if outcomes.contains_key(command) {
+            debug!("Check command '{command}' already run; skipping");
+            continue;
+        }
+
+        info!("Running check command '{command}'");
+        match runner(command) {
+            Ok(passed) => {
+                outcomes.insert(command.clone(), passed);
+            },
+            Err(err) => {
+                error!("Failed to run check command '{command}': {err}");
+                run_result = Err(err);
+                break;
+            },
+        }
+    }
+
+    let unprep_result = unprep_from_msrv(metadata, backup_backend, unprep_options);
+    run_result?;
+    unprep_result?;
+
+    let results = metadata
+        .selected_packages
+        .iter()
+        .zip(commands)
+        .map(|(package, command)| {
+            let status = match command.and_then(|command| outcomes.get(&command).copied()) {
+                Some(true) => VerifyStatus::Passed,
+                Some(false) => VerifyStatus::Failed,
+                None => VerifyStatus::Skipped,
+            };
+
+            PackageVerifyResult {
+                package_name: package.name.clone(),
+                rust_version: package.rust_version.clone(),
+                status,
+            }
+        })
+        .collect();
+
+    trace!("Exiting `verify_msrv`");
+    Ok(results)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    mod check_command {
+        use super::*;
+
+        mod from_template {
+            use assert_matches::assert_matches;
+
+            use super::*;
+
+            #[test]
+            fn default_template() {
+                let command =
+                    CheckCommand::from_template(DEFAULT_CHECK_COMMAND, "1.70.0", "foo").unwrap();
+
+                assert_eq!("cargo", command.program);
+                assert_eq!(["+1.70.0", "check", "--workspace", "--lib", "--bins"], *command.args);
+            }
+
+            #[test]
+            fn package_placeholder() {
+                let command = CheckCommand::from_template(
+                    "cargo +{msrv} minimal-versions check -p {package}",
+                    "1.70.0",
+                    "foo",
+                )
+                .unwrap();
+
+                assert_eq!("cargo +1.70.0 minimal-versions check -p foo", command.to_string());
+            }
+
+            #[test]
+            fn empty_template() {
+                assert_matches!(
+                    CheckCommand::from_template("  ", "1.70.0", "foo"),
+                    Err(Error::InvalidCheckCommand(_))
+                );
+            }
+        }
+    }
+
+    mod verify_msrv {
+        use std::path::PathBuf;
+
+        use assert_fs::fixture::{PathChild, PathCopy};
+        use assert_fs::TempDir;
+        use assert_matches::assert_matches;
+
+        use super::*;
+        use crate::backup::FileBackupBackend;
+        use crate::DEFAULT_MANIFEST_BACKUP_SUFFIX;
+
+        fn fork_project(project_name: &str) -> TempDir {
+            let project_path: PathBuf =
+                [env!("CARGO_MANIFEST_DIR"), "resources", "tests", "cargo-msrv-prep", project_name]
+                    .iter()
+                    .collect();
+
+            let temp = TempDir::new().unwrap();
+            temp.copy_from(project_path, &["*.rs", "*.toml", "*.lock"])
+                .unwrap();
+            temp
+        }
+
+        // Note: files are not actually modified here (see `dry_run`), since our `fs` functions
+        // are mocked in unit tests. Actual preparation is validated in integration tests.
+        #[test_log::test]
+        fn deduplicates_commands() {
+            let temp = fork_project("workspace");
+            let metadata = Metadata::for_manifest(temp.child("Cargo.toml").path()).unwrap();
+            let backup_backend = FileBackupBackend::new(DEFAULT_MANIFEST_BACKUP_SUFFIX);
+
+            let mut commands = Vec::new();
+            let results = verify_msrv(
+                &metadata,
+                &backup_backend,
+                &PrepOptions { dry_run: true, ..Default::default() },
+                &UnprepOptions::default(),
+                DEFAULT_CHECK_COMMAND,
+                |command| {
+                    commands.push(command.to_string());
+                    Ok(false)
+                },
+            )
+            .unwrap();
+
+            assert_eq!(vec!["cargo +1.70.0 check --workspace --lib --bins"], commands);
+            assert_eq!(4, results.len());
+            assert!(results
+                .iter()
+                .all(|result| result.status == VerifyStatus::Failed));
+        }
+
+        #[test_log::test]
+        fn runner_error() {
+            let temp = fork_project("simple_project");
+            let metadata = Metadata::for_manifest(temp.child("Cargo.toml").path()).unwrap();
+            let backup_backend = FileBackupBackend::new(DEFAULT_MANIFEST_BACKUP_SUFFIX);
+
+            let result = verify_msrv(
+                &metadata,
+                &backup_backend,
+                &PrepOptions { dry_run: true, ..Default::default() },
+                &UnprepOptions::default(),
+                DEFAULT_CHECK_COMMAND,
+                |_| Err(Error::InvalidCheckCommand("oops".into())),
+            );
+
+            assert_matches!(result, Err(Error::InvalidCheckCommand(_)));
+        }
+    }
+}
@@ -6,6 +6,7 @@ use assert_fs::TempDir;
 use toml::Table;
 
 const MSRV_PREP_BIN_EXE: &str = env!("CARGO_BIN_EXE_cargo-msrv-prep");
+const MSRV_UNPREP_BIN_EXE: &str = env!("CARGO_BIN_EXE_cargo-msrv-unprep");
 
 fn project_path(project_name: &str) -> PathBuf {
     [env!("CARGO_MANIFEST_DIR"), "resources", "tests", "cargo-msrv-prep", project_name]
@@ -13,6 +14,12 @@ fn project_path(project_name: &str) -> PathBuf {
         .collect()
 }
 
+fn index_path() -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "resources", "tests", "index"]
+        .iter()
+        .collect()
+}
+
 fn fork_project(project_name: &str) -> TempDir {
     let temp = TempDir::new().unwrap();
 
@@ -22,6 +29,17 @@ fn fork_project(project_name: &str) -> TempDir {
     temp
 }
 
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = std::process::Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {args:?} failed");
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
 fn toml_files_equal<A, B>(file_a: A, file_b: B) -> bool
 where
     A: AsRef<Path>,
@@ -65,6 +83,182 @@ mod simple_project {
             .assert(eq_file(project_path("simple_project").join("Cargo.lock")));
     }
 
+    mod summary {
+        use predicates::str::contains;
+
+        use super::*;
+
+        #[test_log::test]
+        fn printed() {
+            let temp = fork_project("simple_project");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .assert()
+                .success()
+                .stderr(contains(
+                    "Package              rust-version removed  Pins merged  ",
+                ))
+                .stderr(contains(
+                    "test-simple-project  1.70.0                dependencies: 1, build-dependencies: 1, \
+                     target.\"cfg(windows)\".dependencies: 1  Cargo.toml.msrv-prep.bak\n",
+                ));
+        }
+
+        #[test_log::test]
+        fn dry_run() {
+            let temp = fork_project("simple_project");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--no-merge-pinned-dependencies")
+                .arg("--dry-run")
+                .assert()
+                .success()
+                .stderr(contains("test-simple-project  1.70.0                -            -\n"));
+        }
+
+        #[test_log::test]
+        fn lock_pins_not_counted() {
+            let temp = fork_project("simple_project");
+
+            let pins_file_path = temp.path().join("msrv-pins.toml");
+            let mut pins_file = fs::read_to_string(&pins_file_path).unwrap();
+            pins_file.push_str("\n[lock]\nproc-macro2 = \"1.0.60\"\n");
+            fs::write(&pins_file_path, pins_file).unwrap();
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--dry-run")
+                .assert()
+                .success()
+                .stderr(contains(
+                    "test-simple-project  1.70.0                dependencies: 1, build-dependencies: 1, \
+                     target.\"cfg(windows)\".dependencies: 1  -\n",
+                ));
+        }
+
+        #[test_log::test]
+        fn quiet() {
+            let temp = fork_project("simple_project");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--quiet")
+                .assert()
+                .success()
+                .stderr("");
+        }
+    }
+
+    mod message_format_github {
+        use assert_fs::fixture::{FileWriteStr, PathChild};
+        use predicates::str::contains;
+
+        use super::*;
+
+        #[test_log::test]
+        fn step_summary() {
+            let temp = fork_project("simple_project");
+            let summary = temp.child("summary.md");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("GITHUB_STEP_SUMMARY", summary.path())
+                .arg("msrv-prep")
+                .arg("--message-format")
+                .arg("github")
+                .assert()
+                .success();
+
+            summary.assert(predicates::str::starts_with("### `cargo msrv-prep`\n\n"));
+            summary.assert(contains("| test-simple-project | 1.70.0 |"));
+        }
+
+        #[test_log::test]
+        fn no_step_summary() {
+            let temp = fork_project("simple_project");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env_remove("GITHUB_STEP_SUMMARY")
+                .arg("msrv-prep")
+                .arg("--message-format")
+                .arg("github")
+                .assert()
+                .success()
+                .stdout("");
+        }
+
+        #[test_log::test]
+        fn error_annotation() {
+            let temp = fork_project("simple_project");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .assert()
+                .success();
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env_remove("GITHUB_STEP_SUMMARY")
+                .arg("msrv-prep")
+                .arg("--message-format")
+                .arg("github")
+                .assert()
+                .failure()
+                .stdout(contains(
+                    "::error file=Cargo.toml.msrv-prep.bak::backup file already exists: ",
+                ));
+        }
+
+        #[test_log::test]
+        fn toml_error_annotation() {
+            let temp = fork_project("simple_project");
+            let pins_file = temp.child("msrv-pins.toml");
+            let pins = fs::read_to_string(pins_file.path()).unwrap();
+            pins_file
+                .write_str(&format!("{pins}foo = 1.0.0\n"))
+                .unwrap();
+            let line = pins.lines().count() + 1;
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env_remove("GITHUB_STEP_SUMMARY")
+                .arg("msrv-prep")
+                .arg("--message-format")
+                .arg("github")
+                .assert()
+                .failure()
+                .stdout(contains(format!(
+                    "::error file=msrv-pins.toml,line={line},col=10::invalid TOML file"
+                )));
+        }
+
+        #[test_log::test]
+        fn human() {
+            let temp = fork_project("simple_project");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .assert()
+                .success();
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .assert()
+                .failure()
+                .stdout("");
+        }
+    }
+
     #[test_log::test]
     fn no_remove_rust_version() {
         let temp = fork_project("simple_project");
@@ -281,239 +475,489 @@ mod simple_project {
         temp.child("Cargo.toml.msrv-prep.bak").assert(missing());
         temp.child("Cargo.lock.msrv-prep.bak").assert(missing());
     }
-}
 
-mod with_workspaces {
-    use assert_cmd::Command;
-    use assert_fs::assert::PathAssert;
-    use assert_fs::fixture::PathChild;
-    use predicates::path::{eq_file, missing};
+    #[test_log::test]
+    fn git_backup_backend() {
+        let temp = fork_project("simple_project");
+        git(temp.path(), &["init", "--quiet"]);
 
-    use super::*;
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("--backup-backend")
+            .arg("git")
+            .arg("-vvvv")
+            .assert()
+            .success();
 
-    fn validate_workspace_result<'a, 'b, C, U>(
-        project_name: &str,
-        temp: &TempDir,
-        changed: C,
-        unchanged: U,
-    ) where
-        C: IntoIterator<Item = &'a str>,
-        U: IntoIterator<Item = &'b str>,
-    {
-        let project_path = project_path(project_name);
+        assert!(toml_files_equal(
+            temp.child("expected").child("all.toml").path(),
+            temp.child("Cargo.toml").path()
+        ));
+        temp.child("Cargo.toml.msrv-prep.bak").assert(missing());
+        temp.child("Cargo.lock.msrv-prep.bak").assert(missing());
+        assert_eq!(
+            fs::read_to_string(project_path("simple_project").join("Cargo.toml")).unwrap(),
+            git(temp.path(), &["cat-file", "blob", "refs/msrv-prep/backup:Cargo.toml"])
+        );
 
-        for package in changed {
-            assert!(toml_files_equal(
-                temp.child(package).child("expected.toml").path(),
-                temp.child(package).child("Cargo.toml").path()
-            ));
-            temp.child(package)
-                .child("Cargo.toml.msrv-prep.bak")
-                .assert(eq_file(project_path.join(package).join("Cargo.toml")));
-            if project_path.join(package).join("Cargo.lock").is_file() {
-                temp.child(package)
-                    .child("Cargo.lock.msrv-prep.bak")
-                    .assert(eq_file(project_path.join(package).join("Cargo.lock")));
-            } else {
-                temp.child(package)
-                    .child("Cargo.lock.msrv-prep.bak")
-                    .assert(missing());
-            }
-        }
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .arg("--backup-backend")
+            .arg("git")
+            .arg("-vvvv")
+            .assert()
+            .success();
 
-        for package in unchanged {
-            assert!(toml_files_equal(
-                temp.child(package).child("Cargo.toml").path(),
-                project_path.join(package).join("Cargo.toml")
-            ));
-            temp.child(package)
-                .child("Cargo.toml.msrv-prep.bak")
-                .assert(missing());
-            if project_path.join(package).join("Cargo.lock").is_file() {
-                temp.child(package)
-                    .child("Cargo.lock")
-                    .assert(eq_file(project_path.join(package).join("Cargo.lock")));
-            }
-            temp.child(package)
-                .child("Cargo.lock.msrv-prep.bak")
-                .assert(missing());
-        }
+        temp.child("Cargo.toml")
+            .assert(eq_file(project_path("simple_project").join("Cargo.toml")));
+        temp.child("Cargo.lock")
+            .assert(eq_file(project_path("simple_project").join("Cargo.lock")));
+        Command::new("git")
+            .current_dir(temp.path())
+            .args(["rev-parse", "--verify", "--quiet", "refs/msrv-prep/backup"])
+            .assert()
+            .failure();
     }
 
-    mod workspace {
+    mod toolchain_file {
+        use assert_fs::fixture::FileWriteStr;
+        use predicates::str::{contains, diff};
+
         use super::*;
 
+        const TOOLCHAIN_FILE_TEXT: &str = "[toolchain]\nchannel = \"1.85.1\"\n";
+
         #[test_log::test]
-        fn all() {
-            let temp = fork_project("workspace");
+        fn set_channel() {
+            let temp = fork_project("simple_project");
+            temp.child("rust-toolchain.toml")
+                .write_str(TOOLCHAIN_FILE_TEXT)
+                .unwrap();
 
             Command::new(MSRV_PREP_BIN_EXE)
                 .current_dir(temp.path())
                 .arg("msrv-prep")
-                .arg("--workspace")
+                .arg("--toolchain-channel")
+                .arg("1.70.0")
                 .arg("-vvvv")
                 .assert()
                 .success();
 
-            validate_workspace_result(
-                "workspace",
-                &temp,
-                ["", "member_a", "member_b", "member_c"],
-                [],
-            );
+            temp.child("rust-toolchain.toml")
+                .assert(diff("[toolchain]\nchannel = \"1.70.0\"\n"));
+            temp.child("rust-toolchain.toml.msrv-prep.bak")
+                .assert(diff(TOOLCHAIN_FILE_TEXT));
+
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child("rust-toolchain.toml")
+                .assert(diff(TOOLCHAIN_FILE_TEXT));
+            temp.child("rust-toolchain.toml.msrv-prep.bak")
+                .assert(missing());
         }
 
         #[test_log::test]
-        fn backup_root_manifest() {
-            let temp = fork_project("workspace");
+        fn remove() {
+            let temp = fork_project("simple_project");
+            temp.child("rust-toolchain").write_str("1.85.1\n").unwrap();
 
             Command::new(MSRV_PREP_BIN_EXE)
                 .current_dir(temp.path())
                 .arg("msrv-prep")
-                .arg("--workspace")
-                .arg("--backup-root-manifest")
+                .arg("--remove-toolchain-file")
                 .arg("-vvvv")
                 .assert()
                 .success();
 
-            validate_workspace_result(
-                "workspace",
-                &temp,
-                ["", "member_a", "member_b", "member_c"],
-                [],
-            );
-        }
+            temp.child("rust-toolchain").assert(missing());
+            temp.child("rust-toolchain.msrv-prep.bak")
+                .assert(diff("1.85.1\n"));
 
-        mod specific_packages_tests {
-            use super::*;
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("-vvvv")
+                .assert()
+                .success();
 
-            fn test_with_package(package: &str, package_dir: &str) {
-                let temp = fork_project("workspace");
+            temp.child("rust-toolchain").assert(diff("1.85.1\n"));
+            temp.child("rust-toolchain.msrv-prep.bak").assert(missing());
+        }
 
-                Command::new(MSRV_PREP_BIN_EXE)
-                    .current_dir(temp.path())
-                    .arg("msrv-prep")
-                    .arg("--package")
-                    .arg(package)
-                    .arg("-vvvv")
-                    .assert()
-                    .success();
+        #[test_log::test]
+        fn removed_backup_kept_by_clean() {
+            let temp = fork_project("simple_project");
+            temp.child("rust-toolchain.toml")
+                .write_str(TOOLCHAIN_FILE_TEXT)
+                .unwrap();
 
-                let unchanged = ["", "member_a", "member_b", "member_c"]
-                    .iter()
-                    .copied()
-                    .filter(|&dir| dir != package_dir)
-                    .collect::<Vec<_>>();
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--remove-toolchain-file")
+                .arg("-vvvv")
+                .assert()
+                .success();
 
-                validate_workspace_result("workspace", &temp, [package_dir], unchanged);
-            }
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("--clean")
+                .arg("--yes")
+                .arg("-vvvv")
+                .assert()
+                .success()
+                .stdout(contains("Kept 1 orphaned backup file(s)"));
 
-            #[test_log::test]
-            fn root_package() {
-                test_with_package("test-workspace", "");
-            }
+            temp.child("rust-toolchain.toml.msrv-prep.bak")
+                .assert(diff(TOOLCHAIN_FILE_TEXT));
 
-            #[test_log::test]
-            fn member_a() {
-                test_with_package("test-workspace-member-a", "member_a");
-            }
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("--clean")
+                .arg("--yes")
+                .arg("--include-orphaned")
+                .arg("-vvvv")
+                .assert()
+                .success();
 
-            #[test_log::test]
-            fn member_b() {
-                test_with_package("test-workspace-member-b", "member_b");
-            }
+            temp.child("rust-toolchain.toml.msrv-prep.bak")
+                .assert(missing());
+        }
 
-            #[test_log::test]
-            fn member_c() {
-                test_with_package("test-workspace-member-c", "member_c");
-            }
+        #[test_log::test]
+        fn dry_run() {
+            let temp = fork_project("simple_project");
+            temp.child("rust-toolchain.toml")
+                .write_str(TOOLCHAIN_FILE_TEXT)
+                .unwrap();
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--remove-toolchain-file")
+                .arg("--dry-run")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child("rust-toolchain.toml")
+                .assert(diff(TOOLCHAIN_FILE_TEXT));
+            temp.child("rust-toolchain.toml.msrv-prep.bak")
+                .assert(missing());
         }
+    }
 
-        mod excluded_packages_tests {
-            use super::*;
+    mod cargo_config {
+        use assert_fs::fixture::FileWriteStr;
+        use predicates::str::{contains, diff};
 
-            fn test_without_package(package: &str, package_dir: &str) {
-                let temp = fork_project("workspace");
+        use super::*;
 
-                Command::new(MSRV_PREP_BIN_EXE)
-                    .current_dir(temp.path())
-                    .arg("msrv-prep")
-                    .arg("--workspace")
-                    .arg("--exclude")
-                    .arg(package)
-                    .arg("-vvvv")
-                    .assert()
-                    .success();
+        #[test_log::test]
+        fn create() {
+            let temp = fork_project("simple_project");
 
-                let changed = ["", "member_a", "member_b", "member_c"]
-                    .iter()
-                    .copied()
-                    .filter(|&dir| dir != package_dir)
-                    .collect::<Vec<_>>();
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--msrv-aware-resolver")
+                .arg("-vvvv")
+                .assert()
+                .success();
 
-                validate_workspace_result("workspace", &temp, changed, [package_dir]);
-            }
+            temp.child(".cargo")
+                .child("config.toml")
+                .assert(diff("[resolver]\nincompatible-rust-versions = \"fallback\"\n"));
+            temp.child(".cargo")
+                .child("config.toml.msrv-prep.bak")
+                .assert(contains("did not exist"));
 
-            #[test_log::test]
-            fn without_root_package() {
-                test_without_package("test-workspace", "");
-            }
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("-vvvv")
+                .assert()
+                .success();
 
-            #[test_log::test]
-            fn without_member_a() {
-                test_without_package("test-workspace-member-a", "member_a");
-            }
+            temp.child(".cargo").child("config.toml").assert(missing());
+            temp.child(".cargo")
+                .child("config.toml.msrv-prep.bak")
+                .assert(missing());
+        }
 
-            #[test_log::test]
-            fn without_member_b() {
-                test_without_package("test-workspace-member-b", "member_b");
-            }
+        #[test_log::test]
+        fn update() {
+            const CONFIG_TEXT: &str = "[build]\nrustflags = [\"-Dwarnings\"]\n";
 
-            #[test_log::test]
-            fn without_member_c() {
-                test_without_package("test-workspace-member-c", "member_c");
-            }
+            let temp = fork_project("simple_project");
+            temp.child(".cargo")
+                .child("config.toml")
+                .write_str(CONFIG_TEXT)
+                .unwrap();
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--msrv-aware-resolver")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child(".cargo").child("config.toml").assert(diff(
+                "[build]\nrustflags = [\"-Dwarnings\"]\n\n[resolver]\nincompatible-rust-versions = \"fallback\"\n",
+            ));
+            temp.child(".cargo")
+                .child("config.toml.msrv-prep.bak")
+                .assert(diff(CONFIG_TEXT));
+
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child(".cargo")
+                .child("config.toml")
+                .assert(diff(CONFIG_TEXT));
+            temp.child(".cargo")
+                .child("config.toml.msrv-prep.bak")
+                .assert(missing());
         }
     }
 
-    mod rootless_workspace {
+    mod strip_keys {
+        use assert_fs::fixture::FileWriteStr;
+        use predicates::prelude::PredicateBooleanExt;
+        use predicates::str::contains;
+
         use super::*;
 
         #[test_log::test]
-        fn all() {
-            let temp = fork_project("rootless_workspace");
+        fn cli_and_pins_file() {
+            let temp = fork_project("simple_project");
+            let manifest_text = fs::read_to_string(temp.child("Cargo.toml").path()).unwrap();
+            let manifest_text = format!(
+                "unstable-key = true\n\n{manifest_text}\n[package.metadata.nightly]\nfoo = 42\n"
+            );
+            temp.child("Cargo.toml").write_str(&manifest_text).unwrap();
+            let pins_text = fs::read_to_string(temp.child("msrv-pins.toml").path()).unwrap();
+            temp.child("msrv-pins.toml")
+                .write_str(&format!("{pins_text}\n[msrv-prep]\nstrip-keys = [\"unstable-key\"]\n"))
+                .unwrap();
 
             Command::new(MSRV_PREP_BIN_EXE)
                 .current_dir(temp.path())
                 .arg("msrv-prep")
-                .arg("--workspace")
+                .arg("--strip-key")
+                .arg("package.metadata.nightly")
                 .arg("-vvvv")
                 .assert()
                 .success();
 
-            validate_workspace_result(
-                "rootless_workspace",
-                &temp,
-                ["member_a", "member_b", "member_c"],
-                [""],
+            temp.child("Cargo.toml").assert(
+                contains("unstable-key")
+                    .not()
+                    .and(contains("nightly").not())
+                    .and(contains("[package]")),
             );
+
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child("Cargo.toml").assert(manifest_text.as_str());
         }
 
         #[test_log::test]
-        fn backup_root_manifest() {
-            let temp = fork_project("rootless_workspace");
+        fn invalid_key() {
+            let temp = fork_project("simple_project");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--strip-key")
+                .arg("package..autolib")
+                .assert()
+                .failure()
+                .stderr(contains("invalid key path"));
+        }
+    }
+
+    mod lockfile_version {
+        use predicates::str::contains;
+
+        use super::*;
+
+        fn fork_project_with_v4_lockfile() -> (TempDir, String) {
+            let temp = fork_project("simple_project");
+
+            let lockfile_path = temp.child("Cargo.lock");
+            let lockfile = fs::read_to_string(lockfile_path.path())
+                .unwrap()
+                .replace("version = 3", "version = 4");
+            fs::write(lockfile_path.path(), &lockfile).unwrap();
+
+            (temp, lockfile)
+        }
+
+        fn perform_test(prep_args: &[&str]) {
+            let (temp, lockfile) = fork_project_with_v4_lockfile();
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .args(prep_args)
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child("Cargo.lock")
+                .assert(lockfile.replace("version = 4", "version = 3"));
+            temp.child("Cargo.lock.msrv-prep.bak")
+                .assert(lockfile.as_str());
+
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child("Cargo.lock").assert(lockfile.as_str());
+            temp.child("Cargo.lock.msrv-prep.bak").assert(missing());
+        }
+
+        #[test_log::test]
+        fn explicit_version() {
+            perform_test(&["--lockfile-version", "3"]);
+        }
+
+        #[test_log::test]
+        fn from_target_rust_version() {
+            perform_test(&["--target-rust-version", "1.70"]);
+        }
+
+        #[test_log::test]
+        fn from_toolchain_channel() {
+            let (temp, _) = fork_project_with_v4_lockfile();
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--toolchain-channel")
+                .arg("1.70.0")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child("Cargo.lock").assert(contains("version = 3"));
+        }
+
+        #[test_log::test]
+        fn not_needed() {
+            let (temp, lockfile) = fork_project_with_v4_lockfile();
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--target-rust-version")
+                .arg("1.80.0")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child("Cargo.lock").assert(lockfile.as_str());
+        }
+    }
+}
+
+mod with_workspaces {
+    use assert_cmd::Command;
+    use assert_fs::assert::PathAssert;
+    use assert_fs::fixture::PathChild;
+    use predicates::path::{eq_file, missing};
+
+    use super::*;
+
+    fn validate_workspace_result<'a, 'b, C, U>(
+        project_name: &str,
+        temp: &TempDir,
+        changed: C,
+        unchanged: U,
+    ) where
+        C: IntoIterator<Item = &'a str>,
+        U: IntoIterator<Item = &'b str>,
+    {
+        let project_path = project_path(project_name);
+
+        for package in changed {
+            assert!(toml_files_equal(
+                temp.child(package).child("expected.toml").path(),
+                temp.child(package).child("Cargo.toml").path()
+            ));
+            temp.child(package)
+                .child("Cargo.toml.msrv-prep.bak")
+                .assert(eq_file(project_path.join(package).join("Cargo.toml")));
+            if project_path.join(package).join("Cargo.lock").is_file() {
+                temp.child(package)
+                    .child("Cargo.lock.msrv-prep.bak")
+                    .assert(eq_file(project_path.join(package).join("Cargo.lock")));
+            } else {
+                temp.child(package)
+                    .child("Cargo.lock.msrv-prep.bak")
+                    .assert(missing());
+            }
+        }
+
+        for package in unchanged {
+            assert!(toml_files_equal(
+                temp.child(package).child("Cargo.toml").path(),
+                project_path.join(package).join("Cargo.toml")
+            ));
+            temp.child(package)
+                .child("Cargo.toml.msrv-prep.bak")
+                .assert(missing());
+            if project_path.join(package).join("Cargo.lock").is_file() {
+                temp.child(package)
+                    .child("Cargo.lock")
+                    .assert(eq_file(project_path.join(package).join("Cargo.lock")));
+            }
+            temp.child(package)
+                .child("Cargo.lock.msrv-prep.bak")
+                .assert(missing());
+        }
+    }
+
+    mod workspace {
+        use super::*;
+
+        #[test_log::test]
+        fn all() {
+            let temp = fork_project("workspace");
 
             Command::new(MSRV_PREP_BIN_EXE)
                 .current_dir(temp.path())
                 .arg("msrv-prep")
                 .arg("--workspace")
-                .arg("--backup-root-manifest")
                 .arg("-vvvv")
                 .assert()
                 .success();
 
             validate_workspace_result(
-                "rootless_workspace",
+                "workspace",
                 &temp,
                 ["", "member_a", "member_b", "member_c"],
                 [],
@@ -521,53 +965,1576 @@ mod with_workspaces {
         }
 
         #[test_log::test]
-        fn dry_run_with_backup_root_manifest() {
-            let temp = fork_project("rootless_workspace");
+        fn backup_root_manifest() {
+            let temp = fork_project("workspace");
 
             Command::new(MSRV_PREP_BIN_EXE)
                 .current_dir(temp.path())
                 .arg("msrv-prep")
                 .arg("--workspace")
                 .arg("--backup-root-manifest")
-                .arg("--dry-run")
                 .arg("-vvvv")
                 .assert()
                 .success();
 
             validate_workspace_result(
-                "rootless_workspace",
+                "workspace",
                 &temp,
-                [],
                 ["", "member_a", "member_b", "member_c"],
+                [],
             );
         }
-    }
-}
 
-mod no_changes {
-    use assert_cmd::Command;
-    use assert_fs::assert::PathAssert;
-    use assert_fs::fixture::PathChild;
-    use predicates::path::missing;
+        mod specific_packages_tests {
+            use super::*;
 
-    use super::*;
+            fn test_with_package(package: &str, package_dir: &str) {
+                let temp = fork_project("workspace");
+
+                Command::new(MSRV_PREP_BIN_EXE)
+                    .current_dir(temp.path())
+                    .arg("msrv-prep")
+                    .arg("--package")
+                    .arg(package)
+                    .arg("-vvvv")
+                    .assert()
+                    .success();
+
+                let unchanged = ["", "member_a", "member_b", "member_c"]
+                    .iter()
+                    .copied()
+                    .filter(|&dir| dir != package_dir)
+                    .collect::<Vec<_>>();
+
+                validate_workspace_result("workspace", &temp, [package_dir], unchanged);
+            }
+
+            #[test_log::test]
+            fn root_package() {
+                test_with_package("test-workspace", "");
+            }
+
+            #[test_log::test]
+            fn member_a() {
+                test_with_package("test-workspace-member-a", "member_a");
+            }
+
+            #[test_log::test]
+            fn member_b() {
+                test_with_package("test-workspace-member-b", "member_b");
+            }
+
+            #[test_log::test]
+            fn member_c() {
+                test_with_package("test-workspace-member-c", "member_c");
+            }
+        }
+
+        mod excluded_packages_tests {
+            use super::*;
+
+            fn test_without_package(package: &str, package_dir: &str) {
+                let temp = fork_project("workspace");
+
+                Command::new(MSRV_PREP_BIN_EXE)
+                    .current_dir(temp.path())
+                    .arg("msrv-prep")
+                    .arg("--workspace")
+                    .arg("--exclude")
+                    .arg(package)
+                    .arg("-vvvv")
+                    .assert()
+                    .success();
+
+                let changed = ["", "member_a", "member_b", "member_c"]
+                    .iter()
+                    .copied()
+                    .filter(|&dir| dir != package_dir)
+                    .collect::<Vec<_>>();
+
+                validate_workspace_result("workspace", &temp, changed, [package_dir]);
+            }
+
+            #[test_log::test]
+            fn without_root_package() {
+                test_without_package("test-workspace", "");
+            }
+
+            #[test_log::test]
+            fn without_member_a() {
+                test_without_package("test-workspace-member-a", "member_a");
+            }
+
+            #[test_log::test]
+            fn without_member_b() {
+                test_without_package("test-workspace-member-b", "member_b");
+            }
+
+            #[test_log::test]
+            fn without_member_c() {
+                test_without_package("test-workspace-member-c", "member_c");
+            }
+        }
+    }
+
+    mod inline_workspace {
+        use super::*;
+
+        #[test_log::test]
+        fn all() {
+            let temp = fork_project("workspace");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--workspace")
+                .arg("--inline-workspace")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            for package in ["", "member_a"] {
+                let manifest =
+                    fs::read_to_string(temp.child(package).child("Cargo.toml").path()).unwrap();
+                assert!(manifest.contains("either = { version = \"1.10.0\" }"), "{manifest}");
+                assert!(manifest.contains("mockall = { version = \"0.12.1\" }"), "{manifest}");
+                assert!(!manifest.contains("workspace = true"), "{manifest}");
+            }
+
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("--workspace")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            for package in ["", "member_a"] {
+                temp.child(package)
+                    .child("Cargo.toml")
+                    .assert(eq_file(project_path("workspace").join(package).join("Cargo.toml")));
+            }
+        }
+
+        #[test_log::test]
+        fn unselected_root_package() {
+            let temp = fork_project("workspace");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--package")
+                .arg("test-workspace-member-a")
+                .arg("--inline-workspace")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            let member_manifest =
+                fs::read_to_string(temp.child("member_a").child("Cargo.toml").path()).unwrap();
+            assert!(member_manifest.contains("either = { version = \"1.10.0\" }"));
+
+            // Root package is not selected, so its manifest is left untouched.
+            temp.child("Cargo.toml")
+                .assert(eq_file(project_path("workspace").join("Cargo.toml")));
+            temp.child("Cargo.toml.msrv-prep.bak").assert(missing());
+        }
+    }
+
+    mod lints {
+        use predicates::str::diff;
+
+        use super::*;
+
+        #[test_log::test]
+        fn lints_to_rustflags() {
+            let temp = fork_project("workspace");
+
+            let append = |path: &Path, text: &str| {
+                let mut content = fs::read_to_string(path).unwrap();
+                content.push_str(text);
+                fs::write(path, content).unwrap();
+            };
+            append(
+                temp.child("Cargo.toml").path(),
+                "\n[workspace.lints.rust]\nunsafe_code = \"forbid\"\n",
+            );
+            append(
+                temp.child("member_a").child("Cargo.toml").path(),
+                "\n[lints]\nworkspace = true\n",
+            );
+            let original_root_manifest =
+                fs::read_to_string(temp.child("Cargo.toml").path()).unwrap();
+            let original_member_manifest =
+                fs::read_to_string(temp.child("member_a").child("Cargo.toml").path()).unwrap();
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--package")
+                .arg("test-workspace-member-a")
+                .arg("--lints-to-rustflags")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            for manifest in [temp.child("Cargo.toml"), temp.child("member_a").child("Cargo.toml")] {
+                assert!(!fs::read_to_string(manifest.path())
+                    .unwrap()
+                    .contains("lints"));
+            }
+            temp.child(".cargo")
+                .child("config.toml")
+                .assert(diff("[build]\nrustflags = [\"-Funsafe_code\"]\n"));
+
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("--package")
+                .arg("test-workspace-member-a")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child("Cargo.toml").assert(original_root_manifest);
+            temp.child("member_a")
+                .child("Cargo.toml")
+                .assert(original_member_manifest);
+            temp.child(".cargo").child("config.toml").assert(missing());
+        }
+    }
+
+    mod target_rust_version {
+        use super::*;
+
+        #[test_log::test]
+        fn workspace_inheritance() {
+            let temp = fork_project("workspace");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--package")
+                .arg("test-workspace-member-a")
+                .arg("--target-rust-version")
+                .arg("1.63.0")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            let member_manifest =
+                fs::read_to_string(temp.child("member_a").child("Cargo.toml").path()).unwrap();
+            assert!(member_manifest.contains("either = { version = \"1.10.0\" }"));
+            assert!(member_manifest.contains("rustc_version = { version = \"0.4.0\" }"));
+            assert!(!member_manifest.contains("workspace = true"));
+
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("--package")
+                .arg("test-workspace-member-a")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child("member_a").child("Cargo.toml").assert(eq_file(
+                project_path("workspace")
+                    .join("member_a")
+                    .join("Cargo.toml"),
+            ));
+        }
+
+        #[test_log::test]
+        fn unsupported_edition() {
+            let temp = fork_project("workspace");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--package")
+                .arg("test-workspace-member-a")
+                .arg("--target-rust-version")
+                .arg("1.50.0")
+                .arg("-vvvv")
+                .assert()
+                .failure()
+                .stderr(predicates::str::contains("edition 2021"));
+
+            temp.child("member_a").child("Cargo.toml").assert(eq_file(
+                project_path("workspace")
+                    .join("member_a")
+                    .join("Cargo.toml"),
+            ));
+        }
+    }
+
+    mod rootless_workspace {
+        use super::*;
+
+        #[test_log::test]
+        fn all() {
+            let temp = fork_project("rootless_workspace");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--workspace")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            validate_workspace_result(
+                "rootless_workspace",
+                &temp,
+                ["member_a", "member_b", "member_c"],
+                [""],
+            );
+        }
+
+        #[test_log::test]
+        fn backup_root_manifest() {
+            let temp = fork_project("rootless_workspace");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--workspace")
+                .arg("--backup-root-manifest")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            validate_workspace_result(
+                "rootless_workspace",
+                &temp,
+                ["", "member_a", "member_b", "member_c"],
+                [],
+            );
+        }
+
+        #[test_log::test]
+        fn dry_run_with_backup_root_manifest() {
+            let temp = fork_project("rootless_workspace");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("--workspace")
+                .arg("--backup-root-manifest")
+                .arg("--dry-run")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            validate_workspace_result(
+                "rootless_workspace",
+                &temp,
+                [],
+                ["", "member_a", "member_b", "member_c"],
+            );
+        }
+    }
+}
+
+mod no_changes {
+    use assert_cmd::Command;
+    use assert_fs::assert::PathAssert;
+    use assert_fs::fixture::PathChild;
+    use predicates::path::missing;
+
+    use super::*;
+
+    #[test_log::test]
+    fn no_op() {
+        let temp = fork_project("no_changes");
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("-vvvv")
+            .assert()
+            .success();
+
+        assert!(toml_files_equal(
+            temp.child("expected.toml").path(),
+            temp.child("Cargo.toml").path()
+        ));
+        temp.child("Cargo.toml.msrv-prep.bak").assert(missing());
+        temp.child("Cargo.lock.msrv-prep.bak").assert(missing());
+    }
+}
+
+mod exit_codes {
+    use assert_cmd::Command;
+    use assert_fs::fixture::{FileWriteStr, PathChild};
+    use predicates::str::contains;
+
+    use super::*;
+
+    fn append(temp: &TempDir, file_name: &str, text: &str) {
+        let file = temp.child(file_name);
+        let existing = fs::read_to_string(file.path()).unwrap();
+        file.write_str(&format!("{existing}{text}")).unwrap();
+    }
+
+    #[test_log::test]
+    fn nothing_to_prep() {
+        let temp = fork_project("no_changes");
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("--fail-if-unchanged")
+            .assert()
+            .code(3)
+            .stderr(contains("error: no manifest needed to be prepped"));
+    }
+
+    #[test_log::test]
+    fn changed() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("--fail-if-unchanged")
+            .assert()
+            .success();
+    }
+
+    #[test_log::test]
+    fn already_prepped() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .assert()
+            .success();
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .assert()
+            .code(4)
+            .stderr(contains("error: backup file already exists: "));
+    }
+
+    #[test_log::test]
+    fn invalid_pins_file() {
+        let temp = fork_project("simple_project");
+        append(&temp, "msrv-pins.toml", "foo = 1.0.0\n");
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .assert()
+            .code(5)
+            .stderr(contains("msrv-pins.toml': invalid float, expected nothing\n"))
+            .stderr(contains("msrv-pins.toml:9:10\n  |\n9 | foo = 1.0.0\n  |          ^^\n"));
+    }
+
+    #[test_log::test]
+    fn cargo_metadata_failed() {
+        let temp = fork_project("simple_project");
+        append(&temp, "Cargo.toml", "bar = [\n");
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .assert()
+            .code(7);
+    }
+}
+
+#[cfg(unix)]
+mod with_stub_cargo {
+    use std::env;
+    use std::os::unix::fs::PermissionsExt;
+
+    use assert_cmd::Command;
+    use assert_fs::assert::PathAssert;
+    use assert_fs::fixture::PathChild;
+    use predicates::path::{eq_file, missing};
+    use predicates::str::contains;
+
+    use super::*;
+
+    /// Creates a stub `cargo` executable that logs its arguments (and whether the manifest
+    /// was prepped when it was called), then runs the given script to determine its exit code.
+    fn stub_cargo_script(script: &str) -> TempDir {
+        let bin = TempDir::new().unwrap();
+
+        let stub_path = bin.child("cargo");
+        fs::write(
+            stub_path.path(),
+            format!(
+                "#!/bin/sh\n\
+                 echo \"$@\" >> stub-cargo.log\n\
+                 grep -q rust-version Cargo.toml || echo prepped >> stub-cargo.log\n\
+                 {script}\n"
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(stub_path.path(), fs::Permissions::from_mode(0o755)).unwrap();
+
+        bin
+    }
+
+    fn stub_cargo(exit_code: i32) -> TempDir {
+        stub_cargo_script(&format!("exit {exit_code}"))
+    }
+
+    fn path_with(bin: &TempDir) -> String {
+        let mut paths = vec![bin.path().to_path_buf()];
+        paths.extend(env::split_paths(&env::var_os("PATH").unwrap_or_default()));
+        env::join_paths(paths).unwrap().into_string().unwrap()
+    }
+
+    mod verify {
+        use super::*;
+
+        #[test_log::test]
+        fn passed() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo(0);
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("verify")
+                .arg("-vvvv")
+                .assert()
+                .success()
+                .stdout(contains("test-simple-project (MSRV 1.70.0): passed"));
+
+            temp.child("stub-cargo.log")
+                .assert("+1.70.0 check --workspace --lib --bins\nprepped\n");
+            temp.child("Cargo.toml")
+                .assert(eq_file(project_path("simple_project").join("Cargo.toml")));
+            temp.child("Cargo.toml.msrv-prep.bak").assert(missing());
+        }
+
+        #[test_log::test]
+        fn failed() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo(101);
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("verify")
+                .arg("--check-command")
+                .arg("cargo +{msrv} minimal-versions check -p {package}")
+                .arg("-vvvv")
+                .assert()
+                .failure()
+                .stdout(contains("test-simple-project (MSRV 1.70.0): failed"));
+
+            temp.child("stub-cargo.log")
+                .assert("+1.70.0 minimal-versions check -p test-simple-project\nprepped\n");
+            temp.child("Cargo.toml")
+                .assert(eq_file(project_path("simple_project").join("Cargo.toml")));
+        }
+
+        #[test_log::test]
+        fn workspace() {
+            let temp = fork_project("workspace");
+            let bin = stub_cargo(0);
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("verify")
+                .arg("--workspace")
+                .arg("-vvvv")
+                .assert()
+                .success()
+                .stdout(contains("test-workspace-member-a (MSRV 1.70.0): passed"));
+
+            // All packages have the same MSRV, so the check command is only run once.
+            temp.child("stub-cargo.log")
+                .assert("+1.70.0 check --workspace --lib --bins\nprepped\n");
+            for member in ["", "member_a", "member_b", "member_c"] {
+                temp.child(member)
+                    .child("Cargo.toml")
+                    .assert(eq_file(project_path("workspace").join(member).join("Cargo.toml")));
+            }
+        }
+
+        #[test_log::test]
+        fn prep_failure() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo_script("[ \"$1\" = generate-lockfile ] && exit 101\nexit 0");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("verify")
+                .arg("--direct-minimal-versions")
+                .arg("-vvvv")
+                .assert()
+                .failure()
+                .stderr(contains("failed to generate lockfile"));
+
+            // The manifest was prepared before generating the lockfile failed; it is restored.
+            for file in ["Cargo.toml", "Cargo.lock"] {
+                temp.child(file)
+                    .assert(eq_file(project_path("simple_project").join(file)));
+                temp.child(format!("{file}.msrv-prep.bak"))
+                    .assert(missing());
+            }
+        }
+    }
+
+    mod find {
+        use super::*;
+
+        /// Stub `cargo` that only succeeds with toolchains 1.65.0 and later.
+        const STUB_SCRIPT: &str =
+            "case \"$1\" in +1.6[5-9].*|+1.[7-9]?.*) exit 0;; *) exit 1;; esac";
+
+        #[test_log::test]
+        fn found() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo_script(STUB_SCRIPT);
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("find")
+                .arg("--toolchains")
+                .arg("1.60.0,1.63.0,1.65.0,1.70.0,1.75")
+                .arg("-vvvv")
+                .assert()
+                .success()
+                .stdout(contains("MSRV: 1.65.0"));
+
+            let log = fs::read_to_string(temp.child("stub-cargo.log").path()).unwrap();
+            assert!(log.contains("+1.65.0 check --workspace --lib --bins\nprepped\n"));
+            assert!(!log.contains("+1.75.0"), "unexpected toolchain checked: {log}");
+            temp.child("Cargo.toml")
+                .assert(eq_file(project_path("simple_project").join("Cargo.toml")));
+            temp.child("Cargo.toml.msrv-prep.bak").assert(missing());
+        }
+
+        #[test_log::test]
+        fn write_rust_version() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo_script(STUB_SCRIPT);
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("find")
+                .arg("--toolchains")
+                .arg("1.60.0,1.63.0,1.65.0,1.70.0,1.75.0")
+                .arg("--min-toolchain")
+                .arg("1.63")
+                .arg("--write-rust-version")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            let manifest = fs::read_to_string(temp.child("Cargo.toml").path()).unwrap();
+            assert!(manifest.contains("rust-version = \"1.65.0\""));
+            assert!(!fs::read_to_string(temp.child("stub-cargo.log").path())
+                .unwrap()
+                .contains("+1.60.0"));
+        }
+
+        #[test_log::test]
+        fn not_found() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo(1);
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("find")
+                .arg("--toolchains")
+                .arg("1.60.0,1.70.0")
+                .arg("-vvvv")
+                .assert()
+                .failure();
+
+            temp.child("Cargo.toml")
+                .assert(eq_file(project_path("simple_project").join("Cargo.toml")));
+        }
+    }
+
+    mod lock_pins {
+        use super::*;
+
+        #[test_log::test]
+        fn prep_and_unprep() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo_script("echo '# updated' >> Cargo.lock");
+
+            let pins_file_path = temp.child("msrv-pins.toml");
+            let mut pins_file = fs::read_to_string(pins_file_path.path()).unwrap();
+            pins_file.push_str("\n[lock]\nproc-macro2 = \"1.0.60\"\n");
+            fs::write(pins_file_path.path(), pins_file).unwrap();
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child("stub-cargo.log")
+                .assert("update -p proc-macro2 --precise 1.0.60\nprepped\n");
+            assert!(!toml_files_equal(
+                temp.child("Cargo.toml").path(),
+                project_path("simple_project").join("Cargo.toml")
+            ));
+            assert!(fs::read_to_string(temp.child("Cargo.lock").path())
+                .unwrap()
+                .ends_with("# updated\n"));
+            temp.child("Cargo.lock.msrv-prep.bak")
+                .assert(eq_file(project_path("simple_project").join("Cargo.lock")));
+
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            for file in ["Cargo.toml", "Cargo.lock"] {
+                temp.child(file)
+                    .assert(eq_file(project_path("simple_project").join(file)));
+            }
+        }
+
+        #[test_log::test]
+        fn update_failure() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo(101);
+
+            fs::write(temp.child("msrv-pins.toml").path(), "[lock]\nproc-macro2 = \"0.0.1\"\n")
+                .unwrap();
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("-vvvv")
+                .assert()
+                .failure();
+        }
+    }
+
+    mod direct_minimal_versions {
+        use assert_fs::fixture::FileWriteStr;
+
+        use super::*;
+
+        #[test_log::test]
+        fn workspace() {
+            let temp = fork_project("workspace");
+            let bin = stub_cargo_script("echo '# generated' >> Cargo.lock");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("--package")
+                .arg("test-workspace-member-a")
+                .arg("--direct-minimal-versions")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            temp.child("stub-cargo.log").assert("generate-lockfile\n");
+
+            let member_manifest =
+                fs::read_to_string(temp.child("member_a").child("Cargo.toml").path()).unwrap();
+            assert!(member_manifest.contains("serde_json = \"=1.0.114\""));
+            assert!(member_manifest.contains("either.workspace = true"));
+
+            // Root manifest is not selected, but workspace dependencies are pinned there
+            // (the root package's own sections are left untouched).
+            let root_manifest = fs::read_to_string(temp.child("Cargo.toml").path()).unwrap();
+            assert!(root_manifest.contains("either = \"=1.10.0\""));
+            assert!(root_manifest.contains("either.workspace = true"));
+            assert!(root_manifest.contains("rust-version = \"1.70.0\""));
+            temp.child("Cargo.toml.msrv-prep.bak")
+                .assert(eq_file(project_path("workspace").join("Cargo.toml")));
+            assert!(fs::read_to_string(temp.child("Cargo.lock").path())
+                .unwrap()
+                .ends_with("# generated\n"));
+            temp.child("Cargo.lock.msrv-prep.bak")
+                .assert(eq_file(project_path("workspace").join("Cargo.lock")));
+
+            Command::new(MSRV_UNPREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-unprep")
+                .arg("--package")
+                .arg("test-workspace-member-a")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            for file in ["Cargo.toml", "Cargo.lock", "member_a/Cargo.toml"] {
+                temp.child(file)
+                    .assert(eq_file(project_path("workspace").join(file)));
+                temp.child(format!("{file}.msrv-prep.bak"))
+                    .assert(missing());
+            }
+        }
+
+        #[test_log::test]
+        fn generate_lockfile_failure() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo(101);
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("--direct-minimal-versions")
+                .arg("-vvvv")
+                .assert()
+                .failure()
+                .stderr(contains("failed to generate lockfile"));
+        }
+
+        #[test_log::test]
+        fn toolchain_file_changed_afterwards() {
+            let temp = fork_project("simple_project");
+            temp.child("rust-toolchain.toml")
+                .write_str("[toolchain]\nchannel = \"stable\"\n")
+                .unwrap();
+            let bin = stub_cargo_script("cat rust-toolchain.toml >> stub-cargo.log");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("--direct-minimal-versions")
+                .arg("--toolchain-channel")
+                .arg("1.70.0")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            // `cargo generate-lockfile` must run using the current toolchain.
+            temp.child("stub-cargo.log")
+                .assert("generate-lockfile\nprepped\n[toolchain]\nchannel = \"stable\"\n");
+            temp.child("rust-toolchain.toml")
+                .assert("[toolchain]\nchannel = \"1.70.0\"\n");
+        }
+
+        #[test_log::test]
+        fn unresolved_crate() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo_script(
+                "echo 'error: failed to select a version for `either`.' >&2\nexit 101",
+            );
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("--direct-minimal-versions")
+                .arg("-vvvv")
+                .assert()
+                .failure()
+                .stderr(contains("failed to resolve minimal version of 'either'"));
+        }
+    }
+
+    mod suggest_pins {
+        use super::*;
+
+        /// Fails with a compiler error in `either` 1.10.0 unless it has been pinned.
+        const STUB_SCRIPT: &str = r#"grep -q '<1.10.0' Cargo.toml && exit 0
+echo '{"reason":"compiler-message","package_id":"registry+https://github.com/rust-lang/crates.io-index#either@1.10.0","manifest_path":"/either/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"either","src_path":"/either/src/lib.rs","edition":"2018","doc":true,"doctest":true,"test":true},"message":{"rendered":"error","$message_type":"diagnostic","children":[],"code":null,"level":"error","message":"oops","spans":[]}}'
+exit 101"#;
+
+        #[test_log::test]
+        fn pinned() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo_script(STUB_SCRIPT);
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("suggest-pins")
+                .arg("-vvvv")
+                .assert()
+                .success()
+                .stdout(contains(
+                    "dependencies.either = \">=1.0.0, <1.10.0\" (either@1.10.0 failed to build)",
+                ));
+
+            let log = fs::read_to_string(temp.child("stub-cargo.log").path()).unwrap();
+            assert_eq!(
+                "+1.70.0 check --workspace --lib --bins --message-format=json\nprepped\n".repeat(2),
+                log
+            );
+            let pins_file = fs::read_to_string(temp.child("msrv-pins.toml").path()).unwrap();
+            assert!(pins_file.contains("either = \">=1.0.0, <1.10.0\""), "{pins_file}");
+            temp.child("Cargo.toml")
+                .assert(eq_file(project_path("simple_project").join("Cargo.toml")));
+            temp.child("Cargo.toml.msrv-prep.bak").assert(missing());
+        }
+
+        #[test_log::test]
+        fn transitive() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo_script(
+                &STUB_SCRIPT
+                    .replace(
+                        "grep -q '<1.10.0' Cargo.toml",
+                        "grep -q 'semver = \"1.0.21\"' msrv-pins.toml",
+                    )
+                    .replace("either@1.10.0", "semver@1.0.22")
+                    .replace("exit 101", "[ \"$1\" = update ] || exit 101"),
+            );
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("suggest-pins")
+                .arg("--index")
+                .arg(index_path())
+                .arg("-vvvv")
+                .assert()
+                .success()
+                .stdout(contains("lock.semver = \"1.0.21\" (semver@1.0.22 failed to build)"));
+
+            let log = fs::read_to_string(temp.child("stub-cargo.log").path()).unwrap();
+            assert!(log.contains("update -p semver --precise 1.0.21\n"), "{log}");
+            let pins_file = fs::read_to_string(temp.child("msrv-pins.toml").path()).unwrap();
+            assert!(pins_file.contains("[lock]\nsemver = \"1.0.21\"\n"), "{pins_file}");
+            for file in ["Cargo.toml", "Cargo.lock"] {
+                temp.child(file)
+                    .assert(eq_file(project_path("simple_project").join(file)));
+            }
+        }
+
+        #[test_log::test]
+        fn no_pinnable_failure() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo(101);
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("suggest-pins")
+                .arg("-vvvv")
+                .assert()
+                .failure()
+                .stderr(contains(
+                    "error: check command failed, but no dependency that could be pinned was found",
+                ));
+
+            temp.child("msrv-pins.toml")
+                .assert(eq_file(project_path("simple_project").join("msrv-pins.toml")));
+        }
+
+        #[test_log::test]
+        fn limit_reached() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo_script(&STUB_SCRIPT.replace("<1.10.0", "never"));
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("suggest-pins")
+                .arg("--max-attempts")
+                .arg("1")
+                .arg("-vvvv")
+                .assert()
+                .failure()
+                .stderr(contains(
+                    "error: check command still failing after 1 attempt(s) to pin dependencies",
+                ));
+        }
+    }
+
+    mod minimize_pins {
+        use super::*;
+
+        /// Fails unless `serde` has been pinned.
+        const STUB_SCRIPT: &str = r#"grep -q 'serde = "1.0.197"' Cargo.toml && exit 0
+exit 101"#;
+
+        #[test_log::test]
+        fn report() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo_script(STUB_SCRIPT);
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("minimize-pins")
+                .arg("-vvvv")
+                .assert()
+                .success()
+                .stdout(contains("dependencies.serde (required)"))
+                .stdout(contains("build-dependencies.libgit2-sys (unnecessary)"))
+                .stdout(contains("target.\"cfg(windows)\".dependencies.winapi (unnecessary)"));
+
+            let log = fs::read_to_string(temp.child("stub-cargo.log").path()).unwrap();
+            assert_eq!("+1.70.0 check --workspace --lib --bins\nprepped\n".repeat(4), log);
+            temp.child("msrv-pins.toml")
+                .assert(eq_file(project_path("simple_project").join("msrv-pins.toml")));
+            temp.child("Cargo.toml")
+                .assert(eq_file(project_path("simple_project").join("Cargo.toml")));
+            temp.child("Cargo.toml.msrv-prep.bak").assert(missing());
+        }
+
+        #[test_log::test]
+        fn write() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo_script(STUB_SCRIPT);
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("minimize-pins")
+                .arg("--write")
+                .arg("-vvvv")
+                .assert()
+                .success();
+
+            let pins_file = fs::read_to_string(temp.child("msrv-pins.toml").path()).unwrap();
+            assert!(pins_file.contains("serde = \"1.0.197\""), "{pins_file}");
+            assert!(!pins_file.contains("libgit2-sys"), "{pins_file}");
+            assert!(!pins_file.contains("winapi"), "{pins_file}");
+        }
+
+        #[test_log::test]
+        fn pins_file_backed_up() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo_script(&format!(
+                "grep -q winapi msrv-pins.toml.msrv-prep.bak || exit 102\n{STUB_SCRIPT}"
+            ));
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("minimize-pins")
+                .arg("-vvvv")
+                .assert()
+                .success()
+                .stdout(contains("dependencies.serde (required)"));
+
+            // The original pins file is backed up during trials, then restored.
+            temp.child("msrv-pins.toml")
+                .assert(eq_file(project_path("simple_project").join("msrv-pins.toml")));
+            temp.child("msrv-pins.toml.msrv-prep.bak").assert(missing());
+        }
+
+        #[test_log::test]
+        fn lockfile_restored() {
+            let temp = fork_project("workspace");
+            let bin = stub_cargo_script("echo '# updated' >> Cargo.lock");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("minimize-pins")
+                .arg("--package")
+                .arg("test-workspace-member-a")
+                .arg("-vvvv")
+                .assert()
+                .success()
+                .stdout(contains("dependencies.serde (unnecessary)"));
+
+            // The check command updates the lockfile, which must be restored after each trial.
+            for file in ["Cargo.toml", "Cargo.lock", "member_a/Cargo.toml"] {
+                temp.child(file)
+                    .assert(eq_file(project_path("workspace").join(file)));
+                temp.child(format!("{file}.msrv-prep.bak"))
+                    .assert(missing());
+            }
+        }
+
+        #[test_log::test]
+        fn baseline_failed() {
+            let temp = fork_project("simple_project");
+            let bin = stub_cargo(101);
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .env("PATH", path_with(&bin))
+                .arg("msrv-prep")
+                .arg("minimize-pins")
+                .arg("--write")
+                .arg("-vvvv")
+                .assert()
+                .failure()
+                .stderr(contains("error: check command fails even with all pins applied"));
+
+            temp.child("msrv-pins.toml")
+                .assert(eq_file(project_path("simple_project").join("msrv-pins.toml")));
+        }
+    }
+}
+
+mod set_rust_version {
+    use assert_cmd::Command;
+    use assert_fs::assert::PathAssert;
+    use assert_fs::fixture::PathChild;
+    use predicates::path::eq_file;
+
+    use super::*;
+
+    const MEMBERS: [&str; 4] = ["", "member_a", "member_b", "member_c"];
 
     #[test_log::test]
-    fn no_op() {
-        let temp = fork_project("no_changes");
+    fn workspace() {
+        let temp = fork_project("workspace");
 
         Command::new(MSRV_PREP_BIN_EXE)
             .current_dir(temp.path())
             .arg("msrv-prep")
+            .arg("set-rust-version")
+            .arg("1.65")
+            .arg("--workspace")
             .arg("-vvvv")
             .assert()
             .success();
 
-        assert!(toml_files_equal(
-            temp.child("expected.toml").path(),
-            temp.child("Cargo.toml").path()
-        ));
-        temp.child("Cargo.toml.msrv-prep.bak").assert(missing());
-        temp.child("Cargo.lock.msrv-prep.bak").assert(missing());
+        for member in MEMBERS {
+            let manifest =
+                fs::read_to_string(temp.child(member).child("Cargo.toml").path()).unwrap();
+            let original =
+                fs::read_to_string(project_path("workspace").join(member).join("Cargo.toml"))
+                    .unwrap();
+
+            assert_eq!(
+                original.replace("rust-version = \"1.70.0\"", "rust-version = \"1.65\""),
+                manifest
+            );
+        }
+    }
+
+    #[test_log::test]
+    fn selected_package() {
+        let temp = fork_project("workspace");
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("set-rust-version")
+            .arg("1.65")
+            .arg("-p")
+            .arg("test-workspace-member-a")
+            .arg("-vvvv")
+            .assert()
+            .success();
+
+        for member in MEMBERS {
+            let manifest =
+                fs::read_to_string(temp.child(member).child("Cargo.toml").path()).unwrap();
+            let original =
+                fs::read_to_string(project_path("workspace").join(member).join("Cargo.toml"))
+                    .unwrap();
+
+            if member == "member_a" {
+                assert_eq!(
+                    original.replace("rust-version = \"1.70.0\"", "rust-version = \"1.65\""),
+                    manifest
+                );
+            } else {
+                assert_eq!(original, manifest, "{member}");
+            }
+        }
+    }
+
+    #[test_log::test]
+    fn selected_package_with_workspace_package() {
+        let temp = fork_project("workspace");
+        let root_manifest = temp.child("Cargo.toml");
+        let original = fs::read_to_string(root_manifest.path()).unwrap();
+        let original = original
+            .replace("[package]", "[workspace.package]\nrust-version = \"1.70.0\"\n\n[package]");
+        fs::write(root_manifest.path(), &original).unwrap();
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("set-rust-version")
+            .arg("1.65")
+            .arg("-p")
+            .arg("test-workspace-member-a")
+            .arg("-vvvv")
+            .assert()
+            .success();
+
+        assert_eq!(
+            original.replacen("rust-version = \"1.70.0\"", "rust-version = \"1.65\"", 1),
+            fs::read_to_string(root_manifest.path()).unwrap()
+        );
+    }
+
+    #[test_log::test]
+    fn prepped() {
+        let temp = fork_project("workspace");
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("--workspace")
+            .assert()
+            .success();
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("set-rust-version")
+            .arg("1.65")
+            .arg("--workspace")
+            .arg("-vvvv")
+            .assert()
+            .failure();
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .arg("--workspace")
+            .assert()
+            .success();
+
+        for member in MEMBERS {
+            temp.child(member)
+                .child("Cargo.toml")
+                .assert(eq_file(project_path("workspace").join(member).join("Cargo.toml")));
+        }
+    }
+
+    #[test_log::test]
+    fn invalid_version() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("set-rust-version")
+            .arg("stable")
+            .assert()
+            .failure();
+    }
+}
+
+mod lint_pins {
+    use assert_cmd::Command;
+    use assert_fs::fixture::{FileWriteStr, PathChild};
+    use predicates::prelude::PredicateBooleanExt;
+    use predicates::str::contains;
+
+    use super::*;
+
+    const PINS_FILE: &str = r#"[dependencies]
+serde = { version = "1.0.197", msrv-prep.reason = "1.0.198 needs 1.71", msrv-prep.until-rust-version = "1.71" }
+either = { version = "=1.10.0", msrv-prep.until-rust-version = "1.70" }
+"#;
+
+    #[test_log::test]
+    fn expired() {
+        let temp = fork_project("simple_project");
+        temp.child("msrv-pins.toml").write_str(PINS_FILE).unwrap();
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("lint-pins")
+            .arg("-vvvv")
+            .assert()
+            .failure()
+            .stdout(contains(
+                "dependencies.either is no longer needed (until Rust 1.70.0, MSRV is 1.70.0)\n",
+            ))
+            .stdout(contains("serde").not())
+            .stderr(contains("error: 1 pin(s) no longer needed"));
+    }
+
+    #[test_log::test]
+    fn none_expired() {
+        let temp = fork_project("simple_project");
+        temp.child("msrv-pins.toml")
+            .write_str(&PINS_FILE.replace("\"1.70\"", "\"1.74\""))
+            .unwrap();
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("lint-pins")
+            .arg("-vvvv")
+            .assert()
+            .success()
+            .stdout("");
+    }
+
+    #[test_log::test]
+    fn invalid_metadata() {
+        let temp = fork_project("simple_project");
+        temp.child("msrv-pins.toml")
+            .write_str(&PINS_FILE.replace("\"1.70\"", "\"someday\""))
+            .unwrap();
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("lint-pins")
+            .arg("-vvvv")
+            .assert()
+            .failure()
+            .code(5)
+            .stderr(contains("error: invalid metadata for pin 'dependencies.either'"));
+    }
+
+    #[test_log::test]
+    fn stripped_during_prep() {
+        let temp = fork_project("simple_project");
+        temp.child("msrv-pins.toml").write_str(PINS_FILE).unwrap();
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("-vvvv")
+            .assert()
+            .success();
+
+        let manifest = fs::read_to_string(temp.child("Cargo.toml").path()).unwrap();
+        assert!(!manifest.contains("msrv-prep"), "{manifest}");
+        assert!(manifest.contains("either = { version = \"=1.10.0\" }"), "{manifest}");
+    }
+}
+
+mod pin {
+    use assert_cmd::Command;
+    use assert_fs::assert::PathAssert;
+    use assert_fs::fixture::PathChild;
+    use predicates::path::eq_file;
+    use predicates::str::contains;
+
+    use super::*;
+
+    #[test_log::test]
+    fn for_msrv() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("pin")
+            .arg("either")
+            .arg("--index")
+            .arg(index_path())
+            .arg("-vvvv")
+            .assert()
+            .success()
+            .stdout(contains("dependencies.either = \"=1.12.0\" (rust-version: 1.65.0)"));
+
+        let pins_file = fs::read_to_string(temp.child("msrv-pins.toml").path()).unwrap();
+        let original =
+            fs::read_to_string(project_path("simple_project").join("msrv-pins.toml")).unwrap();
+        assert_eq!(
+            original
+                .replace("serde = \"1.0.197\"\n", "serde = \"1.0.197\"\neither = \"=1.12.0\"\n"),
+            pins_file
+        );
+    }
+
+    #[test_log::test]
+    fn dry_run() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("pin")
+            .arg("either")
+            .arg("--for")
+            .arg("1.74")
+            .arg("--index")
+            .arg(index_path())
+            .arg("--dry-run")
+            .assert()
+            .success()
+            .stdout(contains("\"=1.13.0\""));
+
+        temp.child("msrv-pins.toml")
+            .assert(eq_file(project_path("simple_project").join("msrv-pins.toml")));
+    }
+
+    mod add {
+        use super::*;
+
+        #[test_log::test]
+        fn new_sections() {
+            let temp = fork_project("simple_project");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("pin")
+                .arg("add")
+                .arg("libc@=0.2.150")
+                .arg("--build")
+                .arg("--target")
+                .arg("cfg(unix)")
+                .arg("--features")
+                .arg("std,extra_traits")
+                .arg("-vvvv")
+                .assert()
+                .success()
+                .stdout(contains("added target.\"cfg(unix)\".build-dependencies.libc"));
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("pin")
+                .arg("add")
+                .arg("mockall@0.11.4")
+                .arg("--dev")
+                .assert()
+                .success();
+
+            let pins_file = fs::read_to_string(temp.child("msrv-pins.toml").path()).unwrap();
+            let original =
+                fs::read_to_string(project_path("simple_project").join("msrv-pins.toml")).unwrap();
+            assert_eq!(
+                format!(
+                    "{original}\n\
+                     [target.\"cfg(unix)\".build-dependencies]\n\
+                     libc = {{ version = \"=0.2.150\", features = [\"std\", \"extra_traits\"] }}\n\
+                     \n\
+                     [dev-dependencies]\n\
+                     mockall = \"0.11.4\"\n"
+                ),
+                pins_file
+            );
+
+            // Dev dependencies pins are merged like other pins.
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .assert()
+                .success();
+            temp.child("Cargo.toml")
+                .assert(contains("mockall = \"0.11.4\""));
+        }
+
+        #[test_log::test]
+        fn existing_pin() {
+            let temp = fork_project("simple_project");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("pin")
+                .arg("add")
+                .arg("winapi@0.3.8")
+                .arg("--target")
+                .arg("cfg(windows)")
+                .assert()
+                .success();
+
+            let pins_file = fs::read_to_string(temp.child("msrv-pins.toml").path()).unwrap();
+            let original =
+                fs::read_to_string(project_path("simple_project").join("msrv-pins.toml")).unwrap();
+            assert_eq!(original.replace("0.3.9", "0.3.8"), pins_file);
+        }
+
+        #[test_log::test]
+        fn invalid_spec() {
+            let temp = fork_project("simple_project");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("pin")
+                .arg("add")
+                .arg("winapi")
+                .assert()
+                .failure()
+                .stderr(contains("invalid pin 'winapi'"));
+        }
+    }
+
+    mod remove {
+        use super::*;
+
+        #[test_log::test]
+        fn existing_pin() {
+            let temp = fork_project("simple_project");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("pin")
+                .arg("remove")
+                .arg("libgit2-sys")
+                .arg("--build")
+                .arg("-vvvv")
+                .assert()
+                .success()
+                .stdout(contains("removed build-dependencies.libgit2-sys"));
+
+            let pins_file = fs::read_to_string(temp.child("msrv-pins.toml").path()).unwrap();
+            let original =
+                fs::read_to_string(project_path("simple_project").join("msrv-pins.toml")).unwrap();
+            assert_eq!(
+                original.replace("[build-dependencies]\nlibgit2-sys = \"0.16.2+1.7.2\"\n\n", ""),
+                pins_file
+            );
+        }
+
+        #[test_log::test]
+        fn not_found() {
+            let temp = fork_project("simple_project");
+
+            Command::new(MSRV_PREP_BIN_EXE)
+                .current_dir(temp.path())
+                .arg("msrv-prep")
+                .arg("pin")
+                .arg("remove")
+                .arg("libgit2-sys")
+                .assert()
+                .failure()
+                .stderr(contains("error: no pin found for '"));
+
+            temp.child("msrv-pins.toml")
+                .assert(eq_file(project_path("simple_project").join("msrv-pins.toml")));
+        }
+    }
+
+    #[test_log::test]
+    fn no_compatible_version() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_PREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-prep")
+            .arg("pin")
+            .arg("either")
+            .arg("--for")
+            .arg("1.60")
+            .arg("--index")
+            .arg(index_path())
+            .assert()
+            .failure()
+            .stderr(contains("error: no version of '"));
     }
 }
@@ -150,3 +150,252 @@ mod custom_values {
         );
     }
 }
+
+mod summary {
+    use predicates::str::contains;
+
+    use super::*;
+
+    #[test_log::test]
+    fn printed() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .assert()
+            .success()
+            .stderr(contains(
+                "Package              Status    Backup\n\
+                 test-simple-project  restored  Cargo.toml.msrv-prep.bak\n",
+            ));
+    }
+
+    #[test_log::test]
+    fn quiet() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .arg("-q")
+            .assert()
+            .success()
+            .stderr("");
+    }
+
+    #[test_log::test]
+    fn github_step_summary() {
+        let temp = fork_project("simple_project");
+        let summary = temp.child("summary.md");
+
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .env("GITHUB_STEP_SUMMARY", summary.path())
+            .arg("msrv-unprep")
+            .arg("--message-format")
+            .arg("github")
+            .assert()
+            .success();
+
+        summary.assert(predicates::str::starts_with("### `cargo msrv-unprep`\n\n"));
+        summary.assert(contains("| test-simple-project | restored | Cargo.toml.msrv-prep.bak |"));
+    }
+}
+
+mod clean {
+    use assert_fs::fixture::FileWriteStr;
+    use predicates::prelude::PredicateBooleanExt;
+    use predicates::str::contains;
+
+    use super::*;
+
+    #[test_log::test]
+    fn report_only() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .arg("--clean")
+            .arg("-vvvv")
+            .assert()
+            .success()
+            .stdout(contains("stale: "))
+            .stdout(contains("identical: "))
+            .stdout(contains("--yes"));
+
+        temp.child("Cargo.toml.msrv-prep.bak")
+            .assert(eq_file(project_path("simple_project").join("Cargo.toml.msrv-prep.bak")));
+        temp.child("Cargo.lock.msrv-prep.bak")
+            .assert(eq_file(project_path("simple_project").join("Cargo.lock.msrv-prep.bak")));
+        temp.child("Cargo.toml")
+            .assert(eq_file(project_path("simple_project").join("Cargo.toml")));
+    }
+
+    #[test_log::test]
+    fn delete() {
+        let temp = fork_project("simple_project");
+        temp.child("Other.toml.old.bak")
+            .write_str("[package]")
+            .unwrap();
+
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .arg("--clean")
+            .arg("--clean-suffix")
+            .arg(".old.bak")
+            .arg("--yes")
+            .arg("--include-orphaned")
+            .arg("-vvvv")
+            .assert()
+            .success()
+            .stdout(contains("Deleted 2 backup file(s)"))
+            .stdout(contains("Kept 1 stale backup file(s)"));
+
+        temp.child("Cargo.toml.msrv-prep.bak")
+            .assert(eq_file(project_path("simple_project").join("Cargo.toml.msrv-prep.bak")));
+        temp.child("Cargo.lock.msrv-prep.bak").assert(missing());
+        temp.child("Other.toml.old.bak").assert(missing());
+        temp.child("Cargo.toml")
+            .assert(eq_file(project_path("simple_project").join("Cargo.toml")));
+    }
+
+    #[test_log::test]
+    fn keep_orphaned() {
+        let temp = fork_project("simple_project");
+        temp.child("Other.toml.old.bak")
+            .write_str("[package]")
+            .unwrap();
+
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .arg("--clean")
+            .arg("--clean-suffix")
+            .arg(".old.bak")
+            .arg("--yes")
+            .arg("-vvvv")
+            .assert()
+            .success()
+            .stdout(contains("Deleted 1 backup file(s)"))
+            .stdout(contains("Kept 1 orphaned backup file(s)"));
+
+        temp.child("Other.toml.old.bak").assert("[package]");
+    }
+
+    #[test_log::test]
+    fn delete_include_stale() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .arg("--clean")
+            .arg("--yes")
+            .arg("--include-stale")
+            .arg("-vvvv")
+            .assert()
+            .success()
+            .stdout(contains("Deleted 2 backup file(s)"))
+            .stdout(contains("stale backup file(s)").not());
+
+        temp.child("Cargo.toml.msrv-prep.bak").assert(missing());
+        temp.child("Cargo.lock.msrv-prep.bak").assert(missing());
+        temp.child("Cargo.toml")
+            .assert(eq_file(project_path("simple_project").join("Cargo.toml")));
+    }
+
+    #[test_log::test]
+    fn git_backup_backend() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .arg("--clean")
+            .arg("--backup-backend")
+            .arg("git")
+            .assert()
+            .code(2)
+            .stderr(contains("`--clean` only supports the `file` backup backend"));
+    }
+
+    #[test_log::test]
+    fn invalid_suffix() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .arg("--clean")
+            .arg("--clean-suffix")
+            .arg(".old")
+            .assert()
+            .failure();
+
+        temp.child("Cargo.toml.msrv-prep.bak")
+            .assert(eq_file(project_path("simple_project").join("Cargo.toml.msrv-prep.bak")));
+    }
+}
+
+mod expect_prepped {
+    use predicates::str::contains;
+
+    use super::*;
+
+    #[test_log::test]
+    fn prepped() {
+        let temp = fork_project("simple_project");
+
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .arg("--expect-prepped")
+            .arg("test-simple-project")
+            .arg("--strict")
+            .arg("-vvvv")
+            .assert()
+            .success()
+            .stderr(contains("Package 'test-simple-project': restored"));
+
+        validate_unprep_result(
+            &ChildPath::new(temp.path()),
+            &ChildPath::new(project_path("simple_project")),
+        );
+    }
+
+    #[test_log::test]
+    fn not_prepped() {
+        let temp = fork_project("no_changes");
+
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .arg("--expect-prepped")
+            .arg("test-simple-project-with-no-msrv-changes")
+            .arg("-vvvv")
+            .assert()
+            .success()
+            .stderr(contains("nothing to restore"));
+    }
+
+    #[test_log::test]
+    fn not_prepped_strict() {
+        let temp = fork_project("no_changes");
+
+        Command::new(MSRV_UNPREP_BIN_EXE)
+            .current_dir(temp.path())
+            .arg("msrv-unprep")
+            .arg("--expect-prepped")
+            .arg("test-simple-project-with-no-msrv-changes")
+            .arg("--strict")
+            .assert()
+            .code(1)
+            .stderr(contains(
+                "error: no backup found for package(s) expected to be prepped: \
+                 test-simple-project-with-no-msrv-changes\n",
+            ));
+    }
+}